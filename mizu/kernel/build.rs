@@ -9,7 +9,7 @@ fn main() {
         // Reduce the prefix region sized `KERNEL_OFFSET` to avoid overlapping with the
         // firmware.
 
-        let ram_size = config::RAM_SIZE - config::KERNEL_OFFSET;
+        let ram_size = config::MAX_RAM_SIZE - config::KERNEL_OFFSET;
 
         let new = ldscript.replace("%RAM_START%", &config::KERNEL_START_PHYS.to_string());
         let new = new.replace("%VIRT_START%", &config::KERNEL_START.to_string());
@@ -0,0 +1,290 @@
+//! The kernel command line, read from `/chosen/bootargs` of the device tree.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::iter;
+
+use log::LevelFilter;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+use spin::Once;
+
+/// The device holding the root file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Root {
+    /// The first block device with a valid file system.
+    #[default]
+    Any,
+    /// The block device at `/dev/block/<index>`.
+    Block(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RootFsType {
+    #[default]
+    Fat32,
+    /// The root file system is in memory, and no block device is used.
+    Tmpfs,
+}
+
+/// The device of the kernel console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    /// The serial port discovered in the device tree at the index, named
+    /// `ttyS<index>`.
+    Serial(usize),
+    /// The console provided by the SBI firmware, named `hvc0` or `sbi`.
+    Sbi,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console::Serial(0)
+    }
+}
+
+/// The IPv4 configuration of the network interfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ip {
+    #[default]
+    Dhcp,
+    Static {
+        address: Ipv4Cidr,
+        gateway: Option<Ipv4Address>,
+    },
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmdline {
+    /// `root=`: `/dev/block/<index>`, `/dev/vd<letter>` or
+    /// `/dev/mmcblk<index>`.
+    pub root: Root,
+    /// `rootfstype=`: `vfat`, `fat32` or `tmpfs`.
+    pub rootfstype: RootFsType,
+    /// `init=`: the path of the init process. If not specified, `/init` and
+    /// `/sbin/init` are tried, and the built-in test suites are run if neither
+    /// exists.
+    pub init: Option<String>,
+    /// `loglevel=`: either a number from 0 to 8 or a name of the level.
+    pub loglevel: LevelFilter,
+    /// `console=`: `ttyS<index>`, `hvc0` or `sbi`.
+    pub console: Console,
+    /// `ip=`: `dhcp`, `off`, or `<client>:<server>:<gateway>:<netmask>[:...]`.
+    pub ip: Ip,
+    /// The unknown parameters without values and the ones after `--`, passed
+    /// to `init` as arguments.
+    pub init_args: Vec<String>,
+    /// The unknown parameters with values, passed to `init` as environment
+    /// variables.
+    pub init_envs: Vec<String>,
+}
+
+impl Default for Cmdline {
+    fn default() -> Self {
+        Cmdline {
+            root: Default::default(),
+            rootfstype: Default::default(),
+            init: None,
+            loglevel: default_loglevel(),
+            console: Default::default(),
+            ip: Default::default(),
+            init_args: Vec::new(),
+            init_envs: Vec::new(),
+        }
+    }
+}
+
+pub fn default_loglevel() -> LevelFilter {
+    match option_env!("RUST_LOG") {
+        Some(level) => parse_loglevel(level).unwrap_or(LevelFilter::Warn),
+        None => LevelFilter::Warn,
+    }
+}
+
+fn parse_loglevel(value: &str) -> Option<LevelFilter> {
+    Some(match value {
+        "0" | "1" | "2" | "3" | "error" => LevelFilter::Error,
+        "4" | "warn" => LevelFilter::Warn,
+        "5" | "6" | "info" => LevelFilter::Info,
+        "7" | "debug" => LevelFilter::Debug,
+        "8" | "trace" => LevelFilter::Trace,
+        "off" => LevelFilter::Off,
+        _ => return None,
+    })
+}
+
+fn parse_root(value: &str) -> Option<Root> {
+    let dev = value.strip_prefix("/dev/")?;
+    let index = if let Some(index) = dev.strip_prefix("block/") {
+        index.parse().ok()?
+    } else if let Some(index) = dev.strip_prefix("mmcblk") {
+        index.parse().ok()?
+    } else {
+        match dev.strip_prefix("vd")?.as_bytes() {
+            &[letter @ b'a'..=b'z'] => (letter - b'a').into(),
+            _ => return None,
+        }
+    };
+    Some(Root::Block(index))
+}
+
+fn parse_rootfstype(value: &str) -> Option<RootFsType> {
+    Some(match value {
+        "vfat" | "fat" | "fat32" => RootFsType::Fat32,
+        "tmpfs" | "ramfs" => RootFsType::Tmpfs,
+        _ => return None,
+    })
+}
+
+fn parse_console(value: &str) -> Option<Console> {
+    // Options like baud rates after the comma are ignored.
+    let name = value.split(',').next()?;
+    Some(match name {
+        "hvc0" | "sbi" => Console::Sbi,
+        _ => Console::Serial(name.strip_prefix("ttyS")?.parse().ok()?),
+    })
+}
+
+fn parse_ip(value: &str) -> Option<Ip> {
+    Some(match value {
+        "dhcp" | "on" | "any" => Ip::Dhcp,
+        "off" | "none" => Ip::Off,
+        _ => {
+            let mut fields = value.split(':');
+            let client = fields.next()?.parse::<Ipv4Address>().ok()?;
+            let _server = fields.next();
+            let gateway = match fields.next() {
+                Some("") | None => None,
+                Some(gateway) => Some(gateway.parse().ok()?),
+            };
+            let address = match fields.next() {
+                Some("") | None => Ipv4Cidr::new(client, 24),
+                Some(netmask) => Ipv4Cidr::from_netmask(client, netmask.parse().ok()?).ok()?,
+            };
+            Ip::Static { address, gateway }
+        }
+    })
+}
+
+/// Splits the command line by whitespaces, keeping the ones in double quotes.
+fn split(cmdline: &str) -> impl Iterator<Item = String> + '_ {
+    let mut chars = cmdline.chars().peekable();
+    iter::from_fn(move || {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        chars.peek()?;
+
+        let mut quoted = false;
+        let mut param = String::new();
+        while let Some(c) = chars.next_if(|&c| quoted || !c.is_whitespace()) {
+            match c {
+                '"' => quoted = !quoted,
+                c => param.push(c),
+            }
+        }
+        Some(param)
+    })
+}
+
+fn set<T>(slot: &mut T, value: Option<T>) -> bool {
+    value.map(|value| *slot = value).is_some()
+}
+
+impl Cmdline {
+    pub fn parse(cmdline: &str) -> Self {
+        let mut ret = Cmdline::default();
+        let mut params = split(cmdline);
+        for param in params.by_ref() {
+            if param == "--" {
+                break;
+            }
+            let Some((key, value)) = param.split_once('=') else {
+                ret.init_args.push(param);
+                continue;
+            };
+            let valid = match key {
+                "root" => set(&mut ret.root, parse_root(value)),
+                "rootfstype" => set(&mut ret.rootfstype, parse_rootfstype(value)),
+                "init" => set(&mut ret.init, Some(Some(value.to_string()))),
+                "loglevel" => set(&mut ret.loglevel, parse_loglevel(value)),
+                "console" => set(&mut ret.console, parse_console(value)),
+                "ip" => set(&mut ret.ip, parse_ip(value)),
+                _ => {
+                    ret.init_envs.push(param.clone());
+                    true
+                }
+            };
+            if !valid {
+                log::warn!("cmdline: ignoring invalid parameter {param:?}");
+            }
+        }
+        ret.init_args.extend(params);
+        ret
+    }
+}
+
+static CMDLINE: Once<Cmdline> = Once::new();
+
+/// Initializes the kernel command line from `/chosen/bootargs`.
+pub fn init(bootargs: Option<&str>) -> &'static Cmdline {
+    CMDLINE.call_once(|| {
+        let cmdline = bootargs.map_or_else(Cmdline::default, Cmdline::parse);
+        log::set_max_level(cmdline.loglevel);
+        cmdline
+    })
+}
+
+/// Returns the kernel command line, or the default one if not initialized.
+pub fn cmdline() -> &'static Cmdline {
+    CMDLINE.call_once(Cmdline::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        let params = split(r#"  a "b c"  d"e f"g "" "#).collect::<Vec<_>>();
+        assert_eq!(params, ["a", "b c", "de fg", ""]);
+
+        let cmdline = Cmdline::parse(r#"init="/bin/sh -l" FOO="x y""#);
+        assert_eq!(cmdline.init.as_deref(), Some("/bin/sh -l"));
+        assert_eq!(cmdline.init_envs, ["FOO=x y"]);
+    }
+
+    #[test]
+    fn key_value() {
+        let cmdline = Cmdline::parse(
+            "root=/dev/vdb rootfstype=tmpfs loglevel=7 console=ttyS1,115200 \
+             ip=10.0.2.15::10.0.2.2:255.255.255.0",
+        );
+        assert_eq!(cmdline.root, Root::Block(1));
+        assert_eq!(cmdline.rootfstype, RootFsType::Tmpfs);
+        assert_eq!(cmdline.loglevel, LevelFilter::Debug);
+        assert_eq!(cmdline.console, Console::Serial(1));
+        assert_eq!(
+            cmdline.ip,
+            Ip::Static {
+                address: Ipv4Cidr::new(Ipv4Address::new(10, 0, 2, 15), 24),
+                gateway: Some(Ipv4Address::new(10, 0, 2, 2)),
+            }
+        );
+        assert!(cmdline.init_args.is_empty() && cmdline.init_envs.is_empty());
+
+        // Invalid values keep the defaults.
+        let cmdline = Cmdline::parse("root=/dev/sda console=sbi,9600 loglevel=9");
+        assert_eq!(cmdline.root, Root::Any);
+        assert_eq!(cmdline.console, Console::Sbi);
+        assert_eq!(cmdline.loglevel, default_loglevel());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let cmdline = Cmdline::parse("single quiet=1 HOME=/root -- root=/dev/vda -v");
+        assert_eq!(cmdline.root, Root::Any);
+        assert_eq!(cmdline.init_args, ["single", "root=/dev/vda", "-v"]);
+        assert_eq!(cmdline.init_envs, ["quiet=1", "HOME=/root"]);
+    }
+}
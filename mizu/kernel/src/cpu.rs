@@ -1,4 +1,9 @@
-use core::sync::atomic::{self, AtomicUsize, Ordering::*};
+use core::{
+    sync::atomic::{self, AtomicUsize, Ordering::*},
+    time::Duration,
+};
+
+use crate::executor;
 
 pub struct IpiComm {
     cmd: AtomicUsize,
@@ -50,3 +55,47 @@ pub static IPI: IpiComm = IpiComm {
     cmd: AtomicUsize::new(0),
     result: AtomicUsize::new(0),
 };
+
+/// The number of the fractional bits of the load averages.
+pub const FSHIFT: u32 = 11;
+const FIXED_1: usize = 1 << FSHIFT;
+/// The decay factors of the load averages over 1, 5 and 15 minutes, sampled
+/// every [`LOAD_FREQ`].
+const EXP: [usize; 3] = [1884, 2014, 2037];
+const LOAD_FREQ: Duration = Duration::from_secs(5);
+
+static LOADAVG: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+fn calc_load(load: usize, exp: usize, active: usize) -> usize {
+    let new = load * exp + active * (FIXED_1 - exp);
+    // Round up when the load is increasing.
+    let new = if active >= load {
+        new + FIXED_1 - 1
+    } else {
+        new
+    };
+    new / FIXED_1
+}
+
+/// Returns the load averages over 1, 5 and 15 minutes, in fixed point with
+/// [`FSHIFT`] fractional bits.
+pub fn loadavg() -> [usize; 3] {
+    LOADAVG.each_ref().map(|load| load.load(Relaxed))
+}
+
+/// Samples the number of the tasks running or ready to run periodically,
+/// updating the load averages.
+pub async fn sample_load() {
+    loop {
+        ktime::sleep(LOAD_FREQ).await;
+        // The sampler itself is not counted.
+        let active = executor().load().saturating_sub(1) * FIXED_1;
+        for (load, exp) in LOADAVG.iter().zip(EXP) {
+            load.store(calc_load(load.load(Relaxed), exp, active), Relaxed);
+        }
+    }
+}
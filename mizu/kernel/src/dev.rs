@@ -1,6 +1,7 @@
 mod block;
 mod intr;
 mod net;
+mod rtc;
 mod sdmmc;
 mod serial;
 mod virtio;
@@ -16,6 +17,7 @@ pub use self::{
     block::{block, blocks},
     intr::INTR,
     net::{net, nets},
+    rtc::{realtime, set_realtime, timestamp, uptime, RtcTimeProvider},
     serial::{init_logger, Stdin, Stdout},
 };
 
@@ -26,6 +28,7 @@ static DEV_INIT: Lazy<Handlers<&str, (&FdtNode, &Fdt), bool>> = Lazy::new(|| {
         .map("riscv,plic0", intr::init_plic)
         .map("virtio,mmio", virtio::init_mmio)
         .map("cvitek,mars-sd", sdmmc::init)
+        .map("google,goldfish-rtc", rtc::init_goldfish_rtc)
 });
 
 fn interrupts<'a>(node: &'a FdtNode) -> impl Iterator<Item = u32> + 'a {
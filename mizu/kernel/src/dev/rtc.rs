@@ -0,0 +1,81 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering::SeqCst},
+    time::Duration,
+};
+
+use afat32::{Date, DateTime, TimeProvider};
+use fdt::{node::FdtNode, Fdt};
+use ktime::{Instant, InstantExt};
+use rv39_paging::{PAddr, ID_OFFSET};
+
+/// The wall-clock time of boot since the UNIX epoch, in microseconds.
+///
+/// It stays at the epoch until an RTC device is found or someone sets the
+/// wall clock.
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the time since boot.
+pub fn uptime() -> Duration {
+    let (secs, micros) = Instant::now().to_su();
+    Duration::from_secs(secs) + Duration::from_micros(micros)
+}
+
+/// Returns the wall-clock time since the UNIX epoch.
+pub fn realtime() -> Duration {
+    Duration::from_micros(BOOT_TIME.load(SeqCst)) + uptime()
+}
+
+/// Sets the wall-clock time since the UNIX epoch.
+pub fn set_realtime(now: Duration) {
+    let boot_time = now.saturating_sub(uptime());
+    BOOT_TIME.store(boot_time.as_micros() as u64, SeqCst);
+}
+
+/// Returns the wall-clock time as a timestamp of file metadata.
+pub fn timestamp() -> Instant {
+    let now = realtime();
+    Instant::from_su(now.as_secs(), now.subsec_micros().into())
+}
+
+/// Provides the wall-clock time to FAT file systems.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RtcTimeProvider;
+
+impl TimeProvider for RtcTimeProvider {
+    fn get_current_date(&self) -> Date {
+        self.get_current_date_time().date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        DateTime::from_unix(realtime())
+    }
+}
+
+const GOLDFISH_TIME_LOW: usize = 0x00;
+const GOLDFISH_TIME_HIGH: usize = 0x04;
+
+pub fn init_goldfish_rtc(node: &FdtNode, _: &Fdt) -> bool {
+    let Some(reg) = node.reg().and_then(|mut reg| reg.next()) else {
+        log::warn!("Skip invalid goldfish RTC: should have memory registers");
+        return false;
+    };
+    let base = PAddr::new(reg.starting_address as usize);
+
+    // SAFETY: The memory is statically mapped. Reading the lower half latches
+    // the higher half, so they must be read in order.
+    let nanos = unsafe {
+        let base = base
+            .to_laddr(ID_OFFSET)
+            .as_non_null_unchecked()
+            .cast::<u32>();
+        let low = base.as_ptr().add(GOLDFISH_TIME_LOW / 4).read_volatile();
+        let high = base.as_ptr().add(GOLDFISH_TIME_HIGH / 4).read_volatile();
+        (u64::from(high) << 32) | u64::from(low)
+    };
+    set_realtime(Duration::from_nanos(nanos));
+    log::info!(
+        "RTC: wall clock set to {}s since the epoch",
+        nanos / 1_000_000_000
+    );
+    true
+}
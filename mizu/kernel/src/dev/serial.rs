@@ -13,13 +13,16 @@ use fdt::{node::FdtNode, Fdt};
 use futures_util::{FutureExt, Stream};
 use ksync::event::{Event, EventListener};
 use ktime::Instant;
-use log::{Level, LevelFilter};
+use log::Level;
 use rv39_paging::{PAddr, ID_OFFSET};
 use spin::{Mutex, MutexGuard, Once};
 use uart::Uart;
 
 use super::{interrupts, intr::intr_man};
-use crate::{someb, tryb};
+use crate::{
+    cmdline::{cmdline, Console},
+    someb, tryb,
+};
 
 struct Serial {
     device: Mutex<Uart>,
@@ -55,6 +58,11 @@ impl Stdout<'_> {
     pub fn write_bytes(&mut self, buffer: &[u8]) {
         if let Some(serial) = &mut self.0 {
             buffer.iter().for_each(|&b| serial.send(b))
+        } else {
+            buffer.iter().for_each(|&b| {
+                #[allow(deprecated)]
+                sbi_rt::legacy::console_putchar(b.into());
+            })
         }
     }
 }
@@ -63,21 +71,6 @@ impl Stdin {
     pub fn new() -> Self {
         Stdin(None)
     }
-
-    pub async fn event() -> umio::Event {
-        let Some(serial) = SERIAL.get() else { return umio::Event::HANG_UP };
-
-        let mut listener = None;
-        loop {
-            if !serial.input.is_empty() {
-                break umio::Event::READABLE;
-            }
-            match listener.take() {
-                Some(listener) => listener.await,
-                None => listener = Some(serial.input_ready.listen()),
-            }
-        }
-    }
 }
 
 impl Stream for Stdin {
@@ -138,11 +131,13 @@ impl core::fmt::Display for OptionU32Display {
     }
 }
 
-struct Logger(LevelFilter, Mutex<()>);
+/// The logger printing to the console, whose level is set by `loglevel=` in the
+/// command line.
+struct Logger(Mutex<()>);
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= self.0
+        metadata.level() <= log::max_level()
     }
 
     fn log(&self, record: &log::Record) {
@@ -151,7 +146,7 @@ impl log::Log for Logger {
         }
 
         ksync::critical(|| {
-            let _guard = self.1.lock();
+            let _guard = self.0.lock();
 
             let time = Instant::now();
             let id = hart_id::hart_id();
@@ -190,23 +185,35 @@ fn ack_interrupt(completion: &Completion) -> bool {
 }
 
 pub unsafe fn init_logger() {
-    let level = match option_env!("RUST_LOG") {
-        Some("error") => LevelFilter::Error,
-        Some("warn") => LevelFilter::Warn,
-        Some("info") => LevelFilter::Info,
-        Some("debug") => LevelFilter::Debug,
-        Some("trace") => LevelFilter::Trace,
-        _ => LevelFilter::Warn,
-    };
+    let level = crate::cmdline::default_loglevel();
     unsafe {
-        let logger = LOGGER.write(Logger(level, Mutex::new(())));
+        let logger = LOGGER.write(Logger(Mutex::new(())));
         log::set_logger(logger).unwrap();
         log::set_max_level(level);
     }
 }
 
-fn init(node: &FdtNode, stride: usize) -> bool {
-    if SERIAL.is_completed() {
+/// Returns whether the serial port is the console selected by `console=` in
+/// the command line.
+fn is_console(node: &FdtNode, fdt: &Fdt) -> bool {
+    let Console::Serial(index) = cmdline().console else {
+        return false
+    };
+    let mut ports = fdt.all_nodes().filter(|node| {
+        let compat = node.compatible();
+        compat.map_or(false, |compat| {
+            compat
+                .all()
+                .any(|c| c == "ns16550a" || c == "snps,dw-apb-uart")
+        })
+    });
+    ports
+        .nth(index)
+        .map_or(false, |port| port.name == node.name)
+}
+
+fn init(node: &FdtNode, fdt: &Fdt, stride: usize) -> bool {
+    if SERIAL.is_completed() || !is_console(node, fdt) {
         return false;
     }
     let mut regs = someb!(node.reg());
@@ -234,10 +241,10 @@ fn init(node: &FdtNode, stride: usize) -> bool {
     true
 }
 
-pub fn init_ns16550a(node: &FdtNode, _: &Fdt) -> bool {
-    init(node, 1)
+pub fn init_ns16550a(node: &FdtNode, fdt: &Fdt) -> bool {
+    init(node, fdt, 1)
 }
 
-pub fn init_dw_apb_uart(node: &FdtNode, _: &Fdt) -> bool {
-    init(node, 4)
+pub fn init_dw_apb_uart(node: &FdtNode, fdt: &Fdt) -> bool {
+    init(node, fdt, 4)
 }
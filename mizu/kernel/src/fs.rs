@@ -1,34 +1,48 @@
 mod cache;
 mod debug;
 mod dev;
+pub mod epoll;
+mod mount;
 mod pipe;
 mod proc;
+mod pty;
 mod serial;
 pub mod socket;
 mod tmp;
+mod tty;
+pub mod unix;
 
-use alloc::{borrow::Cow, collections::BTreeMap, format, sync::Arc};
+use alloc::{borrow::Cow, boxed::Box, collections::BTreeMap, format, sync::Arc, vec::Vec};
 use core::{fmt, time::Duration};
 
-use afat32::NullTimeProvider;
 use arsc_rs::Arsc;
 use crossbeam_queue::ArrayQueue;
-use ksc::Error::{self, EACCES, ENOENT};
+use kmem::Phys;
+use ksc::Error::{self, EACCES, EBUSY, EINVAL, ELOOP, ENODEV, ENOENT};
 use ksync::channel::mpmc::{Sender, TryRecvError};
 use ktime::sleep;
 use spin::RwLock;
 use umifs::{
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     traits::{Entry, FileSystem},
     types::{OpenOptions, Permissions},
 };
 use umio::{IntoAnyExt, IoExt};
 
+use self::mount::{MountEntry, Usage};
 pub use self::{
     debug::{coverage, Coverage, CoverageFile, COVERAGE},
+    mount::{flags_of, inner, MountFlags},
     pipe::pipe,
+    pty::PtyMaster,
+    tty::{tty_of, Termios, Tty, WinSize},
+};
+use crate::{
+    cmdline::{cmdline, Root, RootFsType},
+    dev::{blocks, RtcTimeProvider},
+    executor,
+    task::{Access, Credentials},
 };
-use crate::{dev::blocks, executor};
 
 type FsCollection = BTreeMap<PathBuf, FsHandle>;
 
@@ -36,7 +50,37 @@ type FsCollection = BTreeMap<PathBuf, FsHandle>;
 struct FsHandle {
     dev: Cow<'static, str>,
     fs: Arsc<dyn FileSystem>,
+    /// The root directory of the mount, which is not necessarily the one of
+    /// `fs` for bind mounts.
+    root: Arc<dyn Entry>,
+    flags: MountFlags,
+    /// Counts the entries opened from the mount.
+    usage: Arc<Usage>,
+    /// The page cache of the block device beneath `fs`, if any.
+    device: Option<Arc<Phys>>,
     flush: Sender<ArrayQueue<()>>,
+    /// The mount at the same path hidden by this one, which is brought back
+    /// on unmounting.
+    covered: Option<Box<FsHandle>>,
+}
+
+impl FsHandle {
+    fn root(&self) -> Arc<dyn Entry> {
+        MountEntry::wrap(self.root.clone(), self.flags, self.usage.clone())
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        flush_fs(&*self.fs, self.device.as_deref()).await
+    }
+}
+
+/// Flushes the metadata of `fs` and then the dirty pages of its block device.
+async fn flush_fs(fs: &dyn FileSystem, device: Option<&Phys>) -> Result<(), Error> {
+    fs.flush().await?;
+    match device {
+        Some(device) => device.flush_all().await,
+        None => Ok(()),
+    }
 }
 
 impl fmt::Debug for FsHandle {
@@ -47,26 +91,104 @@ impl fmt::Debug for FsHandle {
 
 static FS: RwLock<FsCollection> = RwLock::new(BTreeMap::new());
 
-pub fn mount(path: PathBuf, dev: Cow<'static, str>, fs: Arsc<dyn FileSystem>) {
+fn insert(
+    path: PathBuf,
+    dev: Cow<'static, str>,
+    fs: Arsc<dyn FileSystem>,
+    root: Arc<dyn Entry>,
+    device: Option<Arc<Phys>>,
+    flags: MountFlags,
+) {
     let fs2 = fs.clone();
+    let device2 = device.clone();
     let (tx, rx) = ksync::channel::bounded(1);
     let task = async move {
         loop {
             sleep(Duration::from_secs(1)).await;
             if let Err(TryRecvError::Closed(_)) = rx.try_recv() {
-                let _ = fs2.flush().await;
+                let _ = flush_fs(&*fs2, device2.as_deref()).await;
                 break;
             }
-            let _ = fs2.flush().await;
+            let _ = flush_fs(&*fs2, device2.as_deref()).await;
         }
     };
     executor().spawn(task).detach();
-    let handle = FsHandle { dev, fs, flush: tx };
+    let handle = FsHandle {
+        dev,
+        fs,
+        root,
+        flags: flags & MountFlags::ATTRS,
+        usage: Default::default(),
+        device,
+        flush: tx,
+        covered: None,
+    };
 
-    let old = ksync::critical(|| FS.write().insert(path, handle));
-    if let Some(old) = old {
-        let _ = old.flush.try_send(());
-    }
+    ksync::critical(|| {
+        let mut fs = FS.write();
+        let covered = fs.remove(&path).map(Box::new);
+        fs.insert(path, FsHandle { covered, ..handle });
+    })
+}
+
+/// Mounts `fs` at `path`, with `device` being the page cache of its block
+/// device to be flushed along with it.
+pub async fn mount(
+    path: PathBuf,
+    dev: Cow<'static, str>,
+    fs: Arsc<dyn FileSystem>,
+    device: Option<Arc<Phys>>,
+    flags: MountFlags,
+) -> Result<(), Error> {
+    let root = fs.clone().root_dir().await?;
+    insert(path, dev, fs, root, device, flags);
+    Ok(())
+}
+
+/// Mounts the directory `dir` at `path`, which is opened from `src`.
+pub fn bind(
+    path: PathBuf,
+    src: &Path,
+    dir: Arc<dyn Entry>,
+    flags: MountFlags,
+) -> Result<(), Error> {
+    let (fs, _) = get(src).ok_or(ENOENT)?;
+    let dev = format!("/{src}").into();
+    // The device is flushed by the original mount, which is not kept busy by
+    // this one.
+    insert(path, dev, fs, inner(&dir), None, flags);
+    Ok(())
+}
+
+pub fn remount(path: &Path, flags: MountFlags) -> Result<(), Error> {
+    ksync::critical(|| {
+        let mut fs = FS.write();
+        let handle = fs.get_mut(path).ok_or(EINVAL)?;
+        handle.flags = flags & MountFlags::ATTRS;
+        Ok(())
+    })
+}
+
+/// The types of the supported file systems, and whether they need block
+/// devices.
+pub const FS_TYPES: [(&str, bool); 5] = [
+    ("vfat", true),
+    ("tmpfs", false),
+    ("proc", false),
+    ("devtmpfs", false),
+    ("devpts", false),
+];
+
+/// Creates a new virtual file system by its type name.
+pub fn new_virtual(ty: &str) -> Result<Arsc<dyn FileSystem>, Error> {
+    let fs: Arsc<dyn FileSystem> = match ty {
+        "tmpfs" => Arsc::new(tmp::TmpFs::new()),
+        "proc" => Arsc::new(proc::ProcFs),
+        "devtmpfs" | "devfs" => Arsc::new(dev::DevFs),
+        "devpts" => Arsc::new(pty::DevPts),
+        _ => return Err(ENODEV),
+    };
+    Ok(fs)
 }
 
 pub fn sync() {
@@ -74,37 +196,248 @@ pub fn sync() {
     fs.values().for_each(|fs| drop(fs.flush.try_send(())))
 }
 
-pub fn unmount(path: &Path) {
-    let handle = ksync::critical(|| FS.write().remove(path));
-    if let Some(fs_handle) = handle {
-        let _ = fs_handle.flush.try_send(());
+/// Flushes all the mounted file systems along with their block devices, and
+/// waits for their completion.
+pub async fn flush_all() {
+    let fs = ksync::critical(|| FS.read().clone());
+    for (path, handle) in fs {
+        // Let the background flushers know as well.
+        let _ = handle.flush.try_send(());
+        if let Err(err) = handle.flush().await {
+            log::warn!("failed to flush the file system at {path:?}: {err:?}");
+        }
     }
 }
 
-pub fn get(path: &Path) -> Option<(Arsc<dyn FileSystem>, &Path)> {
+/// Unmounts the file system at `path`, bringing back the one it covers if
+/// any.
+///
+/// The mount is busy if there are mounts beneath it or entries opened from it.
+/// If `detach` is set, they are detached along with it instead of failing with
+/// `EBUSY`. Opened files stay valid until they are closed.
+pub fn unmount(path: &Path, detach: bool) -> Result<(), Error> {
+    let handles = ksync::critical(|| {
+        let mut fs = FS.write();
+        let handle = fs.get(path).ok_or(EINVAL)?;
+        let used = handle.usage.is_busy();
+        let beneath = (fs.keys())
+            .filter(|p| p.starts_with(path) && *p != path)
+            .cloned()
+            .collect::<Vec<_>>();
+        if !detach && (used || !beneath.is_empty()) {
+            return Err(EBUSY);
+        }
+        let mut handles = (beneath.iter())
+            .filter_map(|p| fs.remove(p))
+            .collect::<Vec<_>>();
+        let mut handle = fs.remove(path).ok_or(EINVAL)?;
+        if let Some(covered) = handle.covered.take() {
+            fs.insert(path.to_path_buf(), *covered);
+        }
+        handles.push(handle);
+        Ok(handles)
+    })?;
+    for handle in handles {
+        let _ = handle.flush.try_send(());
+    }
+    Ok(())
+}
+
+fn find<T>(path: &Path, f: impl Fn(&FsHandle) -> T) -> Option<(PathBuf, T, &Path)> {
     ksync::critical(|| {
         let fs = FS.read();
         let mut iter = fs.iter().rev(); // Reverse the iterator for longest-prefix matching.
         iter.find_map(|(p, handle)| match path.strip_prefix(p) {
-            Ok(path) => Some((handle.fs.clone(), path)),
+            Ok(path) => Some((p.clone(), f(handle), path)),
             Err(_) => None,
         })
     })
 }
 
+pub fn get(path: &Path) -> Option<(Arsc<dyn FileSystem>, &Path)> {
+    find(path, |handle| handle.fs.clone()).map(|(_, fs, path)| (fs, path))
+}
+
+/// The maximum number of symbolic links followed in a single lookup.
+pub const MAX_SYMLINKS: usize = 40;
+
+enum Lookup {
+    Found(Arc<dyn Entry>, bool),
+    /// The lookup should restart from the root with the path.
+    Absolute(PathBuf),
+    /// The lookup should restart from the same directory with the path.
+    Relative(PathBuf),
+}
+
+/// Normalizes a path from the root, where `..` of the root is itself.
+fn normalize(path: &Path) -> PathBuf {
+    let path = path.normalize();
+    let iter = path.components();
+    iter.skip_while(|comp| *comp == Component::ParentDir)
+        .collect()
+}
+
+fn redirect(link: &Path, target: &Path, rest: &Path) -> Lookup {
+    match target.as_str().strip_prefix('/') {
+        Some(target) => Lookup::Absolute(normalize(&Path::new(target).join(rest))),
+        None => {
+            let parent = link.parent().unwrap_or(Path::new(""));
+            Lookup::Relative(parent.join(target).join(rest).normalize())
+        }
+    }
+}
+
+/// Checks whether `cred` may access `entry`, where `None` stands for the
+/// kernel itself.
+async fn check(
+    cred: Option<&Credentials>,
+    entry: &Arc<dyn Entry>,
+    access: Access,
+) -> Result<(), Error> {
+    match cred {
+        Some(cred) if !cred.is_root() => cred.check(&entry.metadata().await, access),
+        _ => Ok(()),
+    }
+}
+
+/// Opens the last component `name` of a path in `dir`, checking the access to
+/// the entry, or the permission to create it.
+async fn open_last(
+    dir: Arc<dyn Entry>,
+    name: &Path,
+    options: OpenOptions,
+    perm: Permissions,
+    cred: Option<&Credentials>,
+) -> Result<(Arc<dyn Entry>, bool), Error> {
+    let access = Access::from_options(options);
+    // Symbolic links are checked where they lead to.
+    if options.intersects(OpenOptions::CREAT | OpenOptions::TRUNC) {
+        // Nothing is created or truncated before the checks.
+        let lookup = OpenOptions::PATH | OpenOptions::NOFOLLOW;
+        match dir.clone().open(name, lookup, Default::default()).await {
+            Ok((entry, _)) if entry.read_link().await.is_err() => {
+                check(cred, &entry, access).await?
+            }
+            Err(ENOENT) if options.contains(OpenOptions::CREAT) => {
+                check(cred, &dir, Access::WRITE | Access::EXEC).await?
+            }
+            // Leave other errors to the opening itself.
+            _ => {}
+        }
+        return dir.open(name, options, perm).await;
+    }
+    let (entry, created) = dir.open(name, options, perm).await?;
+    if entry.read_link().await.is_err() {
+        check(cred, &entry, access).await?;
+    }
+    Ok((entry, created))
+}
+
+/// Looks up `path` from `dir` a component at a time, checking the search
+/// permission of every directory on the way, until a symbolic link is met.
+async fn lookup(
+    mut dir: Arc<dyn Entry>,
+    path: &Path,
+    options: OpenOptions,
+    perm: Permissions,
+    cred: Option<&Credentials>,
+) -> Result<Lookup, Error> {
+    let path = path.normalize();
+    let mut walked = PathBuf::new();
+    let mut iter = path.components();
+    while let Some(comp) = iter.next() {
+        let rest = iter.as_path();
+        check(cred, &dir, Access::EXEC).await?;
+        walked.push(comp);
+
+        if rest != "" {
+            let options = OpenOptions::PATH | OpenOptions::NOFOLLOW;
+            let (entry, _) = dir.open(comp.as_ref(), options, Default::default()).await?;
+            if let Ok(target) = entry.read_link().await {
+                return Ok(redirect(&walked, &target, rest));
+            }
+            dir = entry;
+            continue;
+        }
+
+        let (entry, created) = open_last(dir, comp.as_ref(), options, perm, cred).await?;
+        return match entry.read_link().await {
+            Ok(target) if !options.contains(OpenOptions::NOFOLLOW) => {
+                Ok(redirect(&walked, &target, Path::new("")))
+            }
+            Ok(_) if !options.contains(OpenOptions::PATH) => Err(ELOOP),
+            _ => Ok(Lookup::Found(entry, created)),
+        };
+    }
+    Ok(Lookup::Found(dir, false))
+}
+
+async fn open_from(
+    mut base: Option<Arc<dyn Entry>>,
+    path: &Path,
+    options: OpenOptions,
+    perm: Permissions,
+    cred: Option<&Credentials>,
+) -> Result<(Arc<dyn Entry>, bool), Error> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        let (mount, dir, rel) = match base {
+            Some(ref base) => (None, base.clone(), path.as_path()),
+            None => {
+                let (mount, root, rel) = find(&path, FsHandle::root).ok_or(ENOENT)?;
+                (Some(mount), root, rel)
+            }
+        };
+        path = match lookup(dir, rel, options, perm, cred).await? {
+            Lookup::Found(entry, created) => return Ok((entry, created)),
+            Lookup::Absolute(target) => {
+                base = None;
+                target
+            }
+            Lookup::Relative(target) => match mount {
+                Some(mount) => normalize(&mount.join(target)),
+                None => target,
+            },
+        };
+    }
+    Err(ELOOP)
+}
+
+/// Opens an entry at `path` from the root, following symbolic links.
 #[inline]
 pub async fn open(
     path: &Path,
     options: OpenOptions,
     perm: Permissions,
 ) -> Result<(Arc<dyn Entry>, bool), Error> {
-    let (fs, path) = get(path).ok_or(ENOENT)?;
-    let root_dir = fs.root_dir().await?;
-    if path == "" || path == "." {
-        Ok((root_dir, false))
-    } else {
-        root_dir.open(path, options, perm).await
-    }
+    open_from(None, path, options, perm, None).await
+}
+
+/// Opens an entry at `path` relative to the directory `base`, following
+/// symbolic links.
+#[inline]
+pub async fn open_at(
+    base: Arc<dyn Entry>,
+    path: &Path,
+    options: OpenOptions,
+    perm: Permissions,
+) -> Result<(Arc<dyn Entry>, bool), Error> {
+    open_from(Some(base), path, options, perm, None).await
+}
+
+/// Opens an entry at `path` from the root, or relative to the directory `base`,
+/// on behalf of `cred`, following symbolic links.
+///
+/// The search permission of every directory on the way is checked, as well as
+/// the access to the entry, or the permission to create it.
+pub async fn open_as(
+    cred: &Credentials,
+    base: Option<Arc<dyn Entry>>,
+    path: &Path,
+    options: OpenOptions,
+    perm: Permissions,
+) -> Result<(Arc<dyn Entry>, bool), Error> {
+    open_from(base, path, options, perm, Some(cred)).await
 }
 
 #[inline]
@@ -118,48 +451,69 @@ pub async fn open_dir(
 }
 
 pub async fn unlink(path: &Path) -> Result<(), Error> {
-    let (entry, _) = open(
-        path.parent().ok_or(ENOENT)?,
-        OpenOptions::DIRECTORY | OpenOptions::RDWR,
-        Permissions::all_same(true, true, false),
-    )
-    .await?;
-    let Some(dir) = entry.to_dir_mut() else {
+    let (_, root, path) = find(path, FsHandle::root).ok_or(ENOENT)?;
+    let Some(dir) = root.to_dir_mut() else {
         return Err(EACCES)
     };
     dir.unlink(path, None).await
 }
 
-pub async fn fs_init() {
-    mount(
-        "dev/shm".into(),
-        "tmpfs".into(),
-        Arsc::new(tmp::TmpFs::new()),
-    );
-    mount("dev".into(), "devfs".into(), Arsc::new(dev::DevFs));
-    mount("proc".into(), "procfs".into(), Arsc::new(proc::ProcFs));
-    mount("tmp".into(), "tmpfs".into(), Arsc::new(tmp::TmpFs::new()));
-    mount(
-        "sys/kernel/debug".into(),
-        "debugfs".into(),
-        Arsc::new(debug::DebugFs),
-    );
+async fn mount_root(root: Root) {
     for (index, block) in blocks().into_iter().enumerate() {
+        if matches!(root, Root::Block(i) if i != index) {
+            continue;
+        }
         if let Some(sdmmc) = block.clone().downcast::<sdmmc::Sdmmc>() {
             sdmmc.init().await.expect("Failed to initialize SD card")
         }
         let block_shift = block.block_shift();
-        let phys = crate::mem::new_phys(block.to_io().unwrap(), false);
-        if let Ok(fs) =
-            afat32::FatFileSystem::new(Arc::new(phys), block_shift, NullTimeProvider).await
+        let phys = Arc::new(crate::mem::new_phys(block.to_io().unwrap(), false));
+        if let Ok(fs) = afat32::FatFileSystem::new(phys.clone(), block_shift, RtcTimeProvider).await
         {
             mount(
                 "".into(),
                 format!("/dev/block/{index}").into(),
                 cache::CachedFs::new(fs).await.unwrap(),
-            );
-            break;
+                Some(phys),
+                MountFlags::empty(),
+            )
+            .await
+            .expect("Failed to mount the root file system");
+            return;
+        }
+    }
+    log::error!("No root file system found on {root:?}");
+}
+
+pub async fn fs_init() {
+    let none = MountFlags::empty();
+    let nodev = MountFlags::NOSUID | MountFlags::NODEV;
+    let noexec = MountFlags::NOSUID | MountFlags::NOEXEC;
+    let virtual_fs: [(&str, &str, Arsc<dyn FileSystem>, _); 6] = [
+        ("dev/shm", "tmpfs", Arsc::new(tmp::TmpFs::new()), nodev),
+        ("dev", "devfs", Arsc::new(dev::DevFs), none),
+        // The terminals are devices themselves, so they are not `nodev`.
+        ("dev/pts", "devpts", Arsc::new(pty::DevPts), noexec),
+        ("proc", "procfs", Arsc::new(proc::ProcFs), nodev | noexec),
+        ("tmp", "tmpfs", Arsc::new(tmp::TmpFs::new()), none),
+        (
+            "sys/kernel/debug",
+            "debugfs",
+            Arsc::new(debug::DebugFs),
+            none,
+        ),
+    ];
+    for (path, dev, fs, flags) in virtual_fs {
+        let res = mount(path.into(), dev.into(), fs, None, flags).await;
+        res.expect("Failed to mount virtual file systems");
+    }
+    match cmdline().rootfstype {
+        RootFsType::Tmpfs => {
+            let fs = Arsc::new(tmp::TmpFs::new());
+            let res = mount("".into(), "tmpfs".into(), fs, None, none).await;
+            res.expect("Failed to mount the root file system");
         }
+        RootFsType::Fat32 => mount_root(cmdline().root).await,
     }
     self::socket::init_stack();
 }
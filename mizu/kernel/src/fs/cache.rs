@@ -1,4 +1,7 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
 use core::num::NonZeroUsize;
 
 use arsc_rs::Arsc;
@@ -11,7 +14,7 @@ use ksc::{
 use rand_riscv::RandomState;
 use spin::Mutex;
 use umifs::{path::*, traits::*, types::*};
-use umio::{Event, IoPoll, SeekFrom};
+use umio::{Event, IoPoll, SeekFrom, Watcher};
 
 pub struct CachedFs {
     inner: Arsc<dyn FileSystem>,
@@ -131,6 +134,10 @@ impl Entry for CachedDir {
         self.entry.metadata()
     }
 
+    fn read_link<'a: 'b, 'b>(&'a self) -> Boxed<'b, Result<PathBuf, Error>> {
+        self.entry.read_link()
+    }
+
     fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
         Some(self)
     }
@@ -143,6 +150,14 @@ impl IoPoll for CachedDir {
     fn event<'s: 'r, 'r>(&'s self, expected: Event) -> Boxed<'r, Option<Event>> {
         self.entry.event(expected)
     }
+
+    fn poll(&self) -> Event {
+        self.entry.poll()
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.entry.watch(watcher, exclusive)
+    }
 }
 
 #[async_trait]
@@ -183,6 +198,11 @@ impl DirectoryMut for CachedDir {
         let dir = self.entry.clone().to_dir_mut().ok_or(EPERM)?;
         dir.unlink(path, expect_dir).await
     }
+
+    async fn symlink(&self, path: &Path, target: &Path) -> Result<(), Error> {
+        let dir = self.entry.clone().to_dir_mut().ok_or(EPERM)?;
+        dir.symlink(path, target).await
+    }
 }
 
 impl ToIo for CachedFile {
@@ -213,10 +233,22 @@ impl Entry for CachedFile {
         }
         self.entry.set_metadata(metadata)
     }
+
+    fn read_link<'a: 'b, 'b>(&'a self) -> Boxed<'b, Result<PathBuf, Error>> {
+        self.entry.read_link()
+    }
 }
 
 impl IoPoll for CachedFile {
     fn event<'s: 'r, 'r>(&'s self, expected: Event) -> Boxed<'r, Option<Event>> {
         self.entry.event(expected)
     }
+
+    fn poll(&self) -> Event {
+        self.entry.poll()
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.entry.watch(watcher, exclusive)
+    }
 }
@@ -125,7 +125,18 @@ impl Entry for DebugRoot {
     }
 
     async fn metadata(&self) -> Metadata {
-        todo!()
+        Metadata {
+            ty: FileType::DIR,
+            len: 0,
+            offset: 0,
+            link_count: 1,
+            perm: Permissions::from_bits_truncate(0o700),
+            uid: 0,
+            gid: 0,
+            block_size: 0,
+            block_count: 0,
+            times: Default::default(),
+        }
     }
 }
 
@@ -168,7 +179,10 @@ impl Entry for CoverageFile {
             ty: FileType::FILE,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(true, false, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
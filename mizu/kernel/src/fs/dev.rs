@@ -13,10 +13,25 @@ use umifs::{
 };
 use umio::{IoPoll, IoSlice, IoSliceMut, SeekFrom};
 
-use super::serial::Serial;
+use super::{pty::PtyMaster, serial::Serial};
 
 pub struct DevFs;
 
+fn dir_metadata() -> Metadata {
+    Metadata {
+        ty: FileType::DIR,
+        len: 0,
+        offset: 0,
+        link_count: 1,
+        perm: Permissions::from_bits_truncate(0o755),
+        uid: 0,
+        gid: 0,
+        block_size: 0,
+        block_count: 0,
+        times: Default::default(),
+    }
+}
+
 #[async_trait]
 impl FileSystem for DevFs {
     async fn root_dir(self: Arsc<Self>) -> Result<Arc<dyn Entry>, Error> {
@@ -33,7 +48,7 @@ impl FileSystem for DevFs {
             block_size: PAGE_SIZE,
             block_count: 0,
             block_free: 0,
-            file_count: 3 + crate::dev::blocks().len(),
+            file_count: 5 + crate::dev::blocks().len(),
         }
     }
 }
@@ -67,6 +82,7 @@ impl Entry for DevRoot {
                 let random = Arc::new(Random);
                 random.open(Path::new(""), options, perm).await
             }
+            "ptmx" => PtyMaster::new().open(Path::new(""), options, perm).await,
             _ => {
                 let (dir, next) = {
                     let mut comp = path.components();
@@ -77,7 +93,7 @@ impl Entry for DevRoot {
                         let dev_blocks = Arc::new(DevBlocks);
                         dev_blocks.open(next, options, perm).await
                     }
-                    "null" | "zero" | "serial" | "urandom" => Err(ENOTDIR),
+                    "null" | "zero" | "serial" | "urandom" | "ptmx" => Err(ENOTDIR),
                     _ => Err(ENOENT),
                 }
             }
@@ -85,7 +101,7 @@ impl Entry for DevRoot {
     }
 
     async fn metadata(&self) -> Metadata {
-        todo!()
+        dir_metadata()
     }
 }
 
@@ -121,7 +137,7 @@ impl Entry for DevBlocks {
     }
 
     async fn metadata(&self) -> Metadata {
-        todo!()
+        dir_metadata()
     }
 }
 impl IoPoll for DevBlocks {}
@@ -157,7 +173,10 @@ impl Entry for BlockEntry {
             ty: FileType::BLK,
             len: 0,
             offset: 0xdeadbeef,
+            link_count: 1,
             perm: Permissions::all_same(true, true, true),
+            uid: 0,
+            gid: 0,
             block_size: 1 << self.block_shift,
             block_count: self.block_count,
             times: Default::default(),
@@ -198,7 +217,10 @@ impl Entry for Random {
             ty: FileType::FILE,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(true, false, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
@@ -0,0 +1,349 @@
+//! The event poll, which keeps a persistent interest list of files and
+//! collects their readiness as they push it through [`umio::Watcher`]s.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::mem;
+
+use async_trait::async_trait;
+use bitflags::bitflags;
+use ksc::Error::{self, EEXIST, EINVAL, ENOENT, ENOSYS, EPERM, ESPIPE};
+use ksync::event::Event;
+use spin::Mutex;
+use umifs::{
+    path::Path,
+    traits::Entry,
+    types::{FileType, Metadata, OpenOptions, Permissions},
+};
+use umio::{Io, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher, Watchers};
+
+bitflags! {
+    /// The flags in the upper bits of `epoll_event::events`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Mode: u32 {
+        const EXCLUSIVE = 1 << 28;
+        const WAKEUP    = 1 << 29;
+        const ONESHOT   = 1 << 30;
+        const EDGE      = 1 << 31;
+    }
+}
+
+/// The events always reported, even if not requested.
+const ALWAYS: umio::Event = umio::Event::ERROR.union(umio::Event::HANG_UP);
+
+/// An item is identified by both the file descriptor and the file, so that the
+/// same file can be added again through its duplicates.
+type Key = (i32, usize);
+
+fn key_of(fd: i32, entry: &Arc<dyn Entry>) -> Key {
+    (fd, Arc::as_ptr(entry) as *const () as usize)
+}
+
+struct State {
+    events: umio::Event,
+    mode: Mode,
+    data: u64,
+    /// Whether the item is in the ready list.
+    queued: bool,
+    /// Set after an `EPOLLONESHOT` item is reported, until it is modified.
+    disabled: bool,
+}
+
+struct Item {
+    epoll: Weak<Epoll>,
+    key: Key,
+    entry: Weak<dyn Entry>,
+    state: Mutex<State>,
+}
+
+impl Watcher for Item {
+    fn notify(&self, events: umio::Event) -> bool {
+        let queue = ksync::critical(|| {
+            let mut state = self.state.lock();
+            if state.disabled || !events.intersects(state.events | ALWAYS) {
+                return None;
+            }
+            Some(!mem::replace(&mut state.queued, true))
+        });
+        match queue {
+            Some(true) => {
+                if let Some(epoll) = self.epoll.upgrade() {
+                    epoll.enqueue(self.key);
+                }
+                true
+            }
+            Some(false) => true,
+            None => false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    items: BTreeMap<Key, Arc<Item>>,
+    /// The items which may be ready, checked on the next harvest.
+    ready: VecDeque<Key>,
+}
+
+pub struct Epoll {
+    inner: Mutex<Inner>,
+    /// Notified when any item is queued to the ready list.
+    event: Event,
+    /// The watchers of the epoll itself, which can be added to another one.
+    watchers: Watchers,
+}
+
+impl Epoll {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Epoll {
+            inner: Default::default(),
+            event: Event::new(),
+            watchers: Watchers::new(),
+        })
+    }
+
+    fn enqueue(&self, key: Key) {
+        ksync::critical(|| self.inner.lock().ready.push_back(key));
+        self.event.notify(usize::MAX);
+        self.watchers.notify(umio::Event::READABLE);
+    }
+
+    pub fn add(
+        self: &Arc<Self>,
+        fd: i32,
+        entry: &Arc<dyn Entry>,
+        events: umio::Event,
+        mode: Mode,
+        data: u64,
+    ) -> Result<(), Error> {
+        if Arc::as_ptr(entry) as *const () == Arc::as_ptr(self) as *const () {
+            return Err(EINVAL);
+        }
+        let key = key_of(fd, entry);
+        let item = Arc::new(Item {
+            epoll: Arc::downgrade(self),
+            key,
+            entry: Arc::downgrade(entry),
+            // Queued for the initial check of its readiness.
+            state: Mutex::new(State {
+                events,
+                mode,
+                data,
+                queued: true,
+                disabled: false,
+            }),
+        });
+        let watcher = Arc::downgrade(&item) as Weak<dyn Watcher>;
+        if !entry.watch(watcher, mode.contains(Mode::EXCLUSIVE)) {
+            return Err(EPERM);
+        }
+        ksync::critical(|| {
+            let mut inner = self.inner.lock();
+            if inner.items.contains_key(&key) {
+                return Err(EEXIST);
+            }
+            // Closed files never get harvested, so they are removed here.
+            inner.items.retain(|_, item| item.entry.strong_count() > 0);
+            inner.items.insert(key, item);
+            Ok(())
+        })?;
+        self.enqueue(key);
+        Ok(())
+    }
+
+    pub fn modify(
+        &self,
+        fd: i32,
+        entry: &Arc<dyn Entry>,
+        events: umio::Event,
+        mode: Mode,
+        data: u64,
+    ) -> Result<(), Error> {
+        let key = key_of(fd, entry);
+        let item = ksync::critical(|| self.inner.lock().items.get(&key).cloned());
+        let item = item.ok_or(ENOENT)?;
+        let queue = ksync::critical(|| {
+            let mut state = item.state.lock();
+            if mode.contains(Mode::EXCLUSIVE) || state.mode.contains(Mode::EXCLUSIVE) {
+                return Err(EINVAL);
+            }
+            state.events = events;
+            state.mode = mode;
+            state.data = data;
+            state.disabled = false;
+            Ok(!mem::replace(&mut state.queued, true))
+        })?;
+        if queue {
+            self.enqueue(key);
+        }
+        Ok(())
+    }
+
+    pub fn delete(&self, fd: i32, entry: &Arc<dyn Entry>) -> Result<(), Error> {
+        let key = key_of(fd, entry);
+        let item = ksync::critical(|| self.inner.lock().items.remove(&key));
+        item.map(drop).ok_or(ENOENT)
+    }
+
+    /// Collects at most `max` ready items with their events and user data
+    /// without waiting.
+    pub fn harvest(&self, max: usize) -> Vec<(umio::Event, u64)> {
+        let mut ret = Vec::new();
+        let mut requeue = Vec::new();
+        // Only the items queued before are checked, so that every item is
+        // reported once at most.
+        let count = ksync::critical(|| self.inner.lock().ready.len());
+        for _ in 0..count {
+            if ret.len() >= max {
+                break;
+            }
+            let item = ksync::critical(|| {
+                let mut inner = self.inner.lock();
+                let key = inner.ready.pop_front()?;
+                Some((key, inner.items.get(&key).cloned()))
+            });
+            let Some((key, item)) = item else { break };
+            let Some(item) = item else { continue };
+            let Some(entry) = item.entry.upgrade() else {
+                let old = ksync::critical(|| self.inner.lock().items.remove(&key));
+                drop(old);
+                continue;
+            };
+
+            // Any notification from now on queues the item again.
+            ksync::critical(|| item.state.lock().queued = false);
+            let events = entry.poll();
+
+            let res = ksync::critical(|| {
+                let mut state = item.state.lock();
+                let events = events & (state.events | ALWAYS);
+                if state.disabled || events.is_empty() {
+                    return None;
+                }
+                if state.mode.contains(Mode::ONESHOT) {
+                    state.disabled = true;
+                } else if !state.mode.contains(Mode::EDGE) && !state.queued {
+                    // Level-triggered items are checked again on the next
+                    // harvest, until they are no longer ready.
+                    state.queued = true;
+                    requeue.push(key);
+                }
+                Some((events, state.data))
+            });
+            ret.extend(res);
+        }
+        if !requeue.is_empty() {
+            ksync::critical(|| self.inner.lock().ready.extend(requeue));
+        }
+        ret
+    }
+
+    /// Waits until at least one item is ready, returning at most `max` of
+    /// them.
+    pub async fn wait(&self, max: usize) -> Vec<(umio::Event, u64)> {
+        let mut listener = None;
+        loop {
+            let ret = self.harvest(max);
+            if !ret.is_empty() {
+                break ret;
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(self.event.listen()),
+            }
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        ksync::critical(|| !self.inner.lock().ready.is_empty())
+    }
+}
+
+#[async_trait]
+impl Entry for Epoll {
+    async fn open(
+        self: Arc<Self>,
+        _: &Path,
+        _: OpenOptions,
+        _: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        Err(ENOSYS)
+    }
+
+    async fn metadata(&self) -> Metadata {
+        Metadata {
+            ty: FileType::UNKNOWN,
+            len: 0,
+            offset: 0,
+            link_count: 1,
+            perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
+            block_size: 0,
+            block_count: 0,
+            times: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl IoPoll for Epoll {
+    async fn event(&self, expected: umio::Event) -> Option<umio::Event> {
+        if !expected.contains(umio::Event::READABLE) {
+            return None;
+        }
+        let mut listener = None;
+        loop {
+            if self.is_ready() {
+                break Some(umio::Event::READABLE);
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(self.event.listen()),
+            }
+        }
+    }
+
+    fn poll(&self) -> umio::Event {
+        match self.is_ready() {
+            true => umio::Event::READABLE,
+            false => umio::Event::empty(),
+        }
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.watchers.push(watcher, exclusive);
+        true
+    }
+}
+
+#[async_trait]
+impl Io for Epoll {
+    async fn read(&self, _: &mut [IoSliceMut]) -> Result<usize, Error> {
+        Err(EINVAL)
+    }
+
+    async fn write(&self, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(EINVAL)
+    }
+
+    async fn seek(&self, _: SeekFrom) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn read_at(&self, _: usize, _: &mut [IoSliceMut]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn write_at(&self, _: usize, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use async_trait::async_trait;
+use bitflags::bitflags;
+use ksc::{
+    Boxed,
+    Error::{self, *},
+};
+use umifs::{path::*, traits::*, types::*};
+use umio::{Event, IntoAnyExt, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher};
+
+use crate::task::Access;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct MountFlags: usize {
+        const RDONLY  = 1;
+        const NOSUID  = 2;
+        const NODEV   = 4;
+        const NOEXEC  = 8;
+        const REMOUNT = 32;
+        const BIND    = 4096;
+        const REC     = 16384;
+
+        /// The flags that stay with a mount, instead of describing the mount
+        /// operation itself.
+        const ATTRS = Self::RDONLY.bits()
+            | Self::NOSUID.bits()
+            | Self::NODEV.bits()
+            | Self::NOEXEC.bits();
+    }
+}
+
+/// The count of the entries opened from a mount, which tells whether the
+/// mount is in use.
+#[derive(Debug, Default)]
+pub struct Usage(AtomicUsize);
+
+impl Usage {
+    pub fn is_busy(&self) -> bool {
+        self.0.load(SeqCst) > 0
+    }
+}
+
+/// An entry opened from a mount, along with its restrictions.
+///
+/// Everything opened from it is wrapped again, so the restrictions cover the
+/// whole mount, and the mount stays busy until all of them are dropped.
+pub struct MountEntry {
+    entry: Arc<dyn Entry>,
+    flags: MountFlags,
+    usage: Arc<Usage>,
+}
+
+impl MountEntry {
+    /// Wraps `entry` as opened from the mount of `usage`, replacing the mount
+    /// it is opened from if any.
+    pub fn wrap(entry: Arc<dyn Entry>, flags: MountFlags, usage: Arc<Usage>) -> Arc<dyn Entry> {
+        usage.0.fetch_add(1, SeqCst);
+        Arc::new(MountEntry {
+            entry: inner(&entry),
+            flags: flags & MountFlags::ATTRS,
+            usage,
+        })
+    }
+
+    fn wrap_again(&self, entry: Arc<dyn Entry>) -> Arc<dyn Entry> {
+        Self::wrap(entry, self.flags, self.usage.clone())
+    }
+
+    fn check_write(&self) -> Result<(), Error> {
+        match self.flags.contains(MountFlags::RDONLY) {
+            true => Err(EROFS),
+            false => Ok(()),
+        }
+    }
+
+    fn unwrap_dir(dir: Arc<dyn DirectoryMut>) -> Result<Arc<dyn DirectoryMut>, Error> {
+        match dir.clone().downcast::<Self>() {
+            Some(me) => {
+                me.check_write()?;
+                me.entry.clone().to_dir_mut().ok_or(ENOTDIR)
+            }
+            None => Ok(dir),
+        }
+    }
+}
+
+/// Returns the entry itself regardless of the mount it is opened from, whose
+/// actual type can be checked.
+pub fn inner(entry: &Arc<dyn Entry>) -> Arc<dyn Entry> {
+    match entry.clone().downcast::<MountEntry>() {
+        Some(me) => me.entry.clone(),
+        None => entry.clone(),
+    }
+}
+
+/// Returns the restrictions of the mount which `entry` is opened from.
+pub fn flags_of(entry: &Arc<dyn Entry>) -> MountFlags {
+    match entry.clone().downcast::<MountEntry>() {
+        Some(me) => me.flags,
+        None => MountFlags::empty(),
+    }
+}
+
+fn is_write(options: OpenOptions) -> bool {
+    options.intersects(OpenOptions::CREAT | OpenOptions::TRUNC | OpenOptions::APPEND)
+        || Access::from_options(options).contains(Access::WRITE)
+}
+
+impl Drop for MountEntry {
+    fn drop(&mut self) {
+        self.usage.0.fetch_sub(1, SeqCst);
+    }
+}
+
+impl ToIo for MountEntry {
+    fn to_io(self: Arc<Self>) -> Option<Arc<dyn Io>> {
+        let io = self.entry.clone().to_io()?;
+        Some(match self.flags.contains(MountFlags::RDONLY) {
+            true => Arc::new(ReadOnlyIo(io)),
+            false => io,
+        })
+    }
+}
+
+#[async_trait]
+impl Entry for MountEntry {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if is_write(options) {
+            self.check_write()?;
+        }
+        let (entry, created) = self.entry.clone().open(path, options, perm).await?;
+        if self.flags.contains(MountFlags::NODEV) && !options.contains(OpenOptions::PATH) {
+            let ty = entry.metadata().await.ty;
+            if ty == FileType::CHR || ty == FileType::BLK {
+                return Err(EACCES);
+            }
+        }
+        Ok((self.wrap_again(entry), created))
+    }
+
+    fn metadata<'a: 'b, 'b>(&'a self) -> Boxed<'b, Metadata> {
+        self.entry.metadata()
+    }
+
+    async fn set_metadata(&self, metadata: SetMetadata) -> Result<(), Error> {
+        self.check_write()?;
+        self.entry.set_metadata(metadata).await
+    }
+
+    fn read_link<'a: 'b, 'b>(&'a self) -> Boxed<'b, Result<PathBuf, Error>> {
+        self.entry.read_link()
+    }
+
+    fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
+        self.entry.clone().to_dir()?;
+        Some(self)
+    }
+
+    fn to_dir_mut(self: Arc<Self>) -> Option<Arc<dyn DirectoryMut>> {
+        self.entry.clone().to_dir_mut()?;
+        Some(self)
+    }
+}
+
+impl IoPoll for MountEntry {
+    fn event<'s: 'r, 'r>(&'s self, expected: Event) -> Boxed<'r, Option<Event>> {
+        self.entry.event(expected)
+    }
+
+    fn poll(&self) -> Event {
+        self.entry.poll()
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.entry.watch(watcher, exclusive)
+    }
+}
+
+#[async_trait]
+impl Directory for MountEntry {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        let dir = self.entry.clone().to_dir().ok_or(ENOTDIR)?;
+        dir.next_dirent(last).await
+    }
+}
+
+#[async_trait]
+impl DirectoryMut for MountEntry {
+    async fn rename(
+        self: Arc<Self>,
+        src_path: &Path,
+        dst_parent: Arc<dyn DirectoryMut>,
+        dst_path: &Path,
+    ) -> Result<(), Error> {
+        self.check_write()?;
+        let dir = self.entry.clone().to_dir_mut().ok_or(ENOTDIR)?;
+        let dst_parent = Self::unwrap_dir(dst_parent)?;
+        dir.rename(src_path, dst_parent, dst_path).await
+    }
+
+    async fn link(
+        self: Arc<Self>,
+        src_path: &Path,
+        dst_parent: Arc<dyn DirectoryMut>,
+        dst_path: &Path,
+    ) -> Result<(), Error> {
+        self.check_write()?;
+        let dir = self.entry.clone().to_dir_mut().ok_or(ENOTDIR)?;
+        let dst_parent = Self::unwrap_dir(dst_parent)?;
+        dir.link(src_path, dst_parent, dst_path).await
+    }
+
+    async fn unlink(&self, path: &Path, expect_dir: Option<bool>) -> Result<(), Error> {
+        self.check_write()?;
+        let dir = self.entry.clone().to_dir_mut().ok_or(ENOTDIR)?;
+        dir.unlink(path, expect_dir).await
+    }
+
+    async fn symlink(&self, path: &Path, target: &Path) -> Result<(), Error> {
+        self.check_write()?;
+        let dir = self.entry.clone().to_dir_mut().ok_or(ENOTDIR)?;
+        dir.symlink(path, target).await
+    }
+}
+
+struct ReadOnlyIo(Arc<dyn Io>);
+
+#[async_trait]
+impl Io for ReadOnlyIo {
+    fn read<'a: 'r, 'b: 'r, 'r>(
+        &'a self,
+        buffer: &'b mut [IoSliceMut],
+    ) -> Boxed<'r, Result<usize, Error>> {
+        self.0.read(buffer)
+    }
+
+    async fn write(&self, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(EROFS)
+    }
+
+    fn seek<'a: 'r, 'r>(&'a self, whence: SeekFrom) -> Boxed<'r, Result<usize, Error>> {
+        self.0.seek(whence)
+    }
+
+    fn stream_len<'a: 'r, 'r>(&'a self) -> Boxed<'r, Result<usize, Error>> {
+        self.0.stream_len()
+    }
+
+    fn read_at<'a: 'r, 'b: 'r, 'r>(
+        &'a self,
+        offset: usize,
+        buffer: &'b mut [IoSliceMut],
+    ) -> Boxed<'r, Result<usize, Error>> {
+        self.0.read_at(offset, buffer)
+    }
+
+    async fn write_at(&self, _: usize, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(EROFS)
+    }
+
+    fn flush<'a: 'r, 'r>(&'a self) -> Boxed<'r, Result<(), Error>> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(bits: i32) -> OpenOptions {
+        OpenOptions::from_bits_truncate(bits)
+    }
+
+    #[test]
+    fn write_access_modes() {
+        // `O_RDONLY`, `O_WRONLY` and `O_RDWR` from the user.
+        assert!(!is_write(user(0)));
+        assert!(is_write(user(1)));
+        assert!(is_write(user(2)));
+        assert!(is_write(OpenOptions::CREAT));
+        assert!(is_write(OpenOptions::APPEND));
+    }
+
+    #[test]
+    fn rdwr_on_read_only_mount() {
+        let entry = MountEntry::wrap(
+            Arc::new(umifs::misc::Null),
+            MountFlags::RDONLY,
+            Default::default(),
+        );
+        let open = |options| {
+            let open = entry
+                .clone()
+                .open(Path::new(""), options, Permissions::empty());
+            futures_util::FutureExt::now_or_never(open).unwrap().err()
+        };
+        assert_eq!(open(user(0)), None);
+        assert_eq!(open(user(2)), Some(EROFS));
+    }
+}
@@ -1,4 +1,7 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
 use core::sync::atomic::{
     AtomicUsize,
     Ordering::{Relaxed, SeqCst},
@@ -19,7 +22,7 @@ use umifs::{
     traits::Entry,
     types::{FileType, Metadata, OpenOptions, Permissions},
 };
-use umio::{Io, IoPoll, IoSlice, IoSliceMut, SeekFrom};
+use umio::{Io, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher, Watchers};
 
 struct End {
     frame: Frame,
@@ -35,9 +38,23 @@ struct Pipe {
 
     read_event: Event,
     write_event: Event,
+    read_watchers: Watchers,
+    write_watchers: Watchers,
     max_buffers: AtomicUsize,
 }
 
+impl Pipe {
+    fn wake_reader(&self, events: umio::Event) {
+        self.read_event.notify(usize::MAX);
+        self.read_watchers.notify(events);
+    }
+
+    fn wake_writer(&self, events: umio::Event) {
+        self.write_event.notify(usize::MAX);
+        self.write_watchers.notify(events);
+    }
+}
+
 struct Sender(Arsc<Pipe>);
 
 struct Receiver(Arsc<Pipe>);
@@ -102,7 +119,7 @@ impl Receiver {
                 let mut me = self.0.read_end.lock();
                 if let Some(mut end) = me.take().or_else(|| {
                     let new = self.0.buffer.pop();
-                    new.inspect(|_| self.0.write_event.notify(usize::MAX))
+                    new.inspect(|_| self.0.wake_writer(umio::Event::WRITABLE))
                 }) {
                     assert!(end.is_buffer_full());
                     let len = end.read(buffer);
@@ -120,7 +137,7 @@ impl Receiver {
                 let mut writer = self.0.write_end.lock();
 
                 let new = self.0.buffer.pop();
-                if let Some(mut end) = new.inspect(|_| self.0.write_event.notify(usize::MAX)) {
+                if let Some(mut end) = new.inspect(|_| self.0.wake_writer(umio::Event::WRITABLE)) {
                     assert!(end.is_buffer_full());
                     let len = end.read(buffer);
                     if !end.should_discard() {
@@ -174,7 +191,7 @@ impl Receiver {
 
 impl Drop for Receiver {
     fn drop(&mut self) {
-        self.0.write_event.notify(usize::MAX);
+        self.0.wake_writer(umio::Event::ERROR);
     }
 }
 
@@ -233,7 +250,10 @@ impl Entry for Receiver {
             ty: FileType::FIFO,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(true, false, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
@@ -245,6 +265,32 @@ impl IoPoll for Receiver {
     fn event<'a: 'r, 'r>(&'a self, expected: umio::Event) -> Boxed<'r, Option<umio::Event>> {
         Box::pin(self.event(expected))
     }
+
+    fn poll(&self) -> umio::Event {
+        let readable = ksync::critical(|| {
+            let pipe = &self.0;
+            pipe.read_end.lock().is_some()
+                || !pipe.buffer.is_empty()
+                || pipe
+                    .write_end
+                    .lock()
+                    .as_ref()
+                    .map_or(false, |end| !end.is_empty())
+        });
+        let mut events = umio::Event::empty();
+        if readable {
+            events |= umio::Event::READABLE;
+        }
+        if Arsc::count(&self.0) == 1 {
+            events |= umio::Event::HANG_UP;
+        }
+        events
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.0.read_watchers.push(watcher, exclusive);
+        true
+    }
 }
 
 impl Sender {
@@ -280,7 +326,7 @@ impl Sender {
             });
 
             if let Some(len) = trial {
-                self.0.read_event.notify(usize::MAX);
+                self.0.wake_reader(umio::Event::READABLE);
                 break Ok(len);
             }
 
@@ -308,7 +354,7 @@ impl Sender {
 
 impl Drop for Sender {
     fn drop(&mut self) {
-        self.0.read_event.notify(usize::MAX);
+        self.0.wake_reader(umio::Event::HANG_UP);
     }
 }
 
@@ -367,7 +413,10 @@ impl Entry for Sender {
             ty: FileType::FIFO,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(false, true, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
@@ -379,6 +428,24 @@ impl IoPoll for Sender {
     fn event<'a: 'r, 'r>(&'a self, expected: umio::Event) -> Boxed<'r, Option<umio::Event>> {
         Box::pin(self.event(expected))
     }
+
+    fn poll(&self) -> umio::Event {
+        let pipe = &self.0;
+        if Arsc::count(pipe) == 1 {
+            return umio::Event::ERROR;
+        }
+        let writable = ksync::critical(|| pipe.write_end.lock().is_some())
+            || pipe.buffer.len() < pipe.max_buffers.load(SeqCst);
+        match writable {
+            true => umio::Event::WRITABLE,
+            false => umio::Event::empty(),
+        }
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.0.write_watchers.push(watcher, exclusive);
+        true
+    }
 }
 
 pub fn pipe() -> (Arc<dyn Entry>, Arc<dyn Entry>) {
@@ -1,30 +1,49 @@
-use alloc::{boxed::Box, string::String, sync::Arc};
+mod pid;
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use core::{
     fmt::Write,
+    iter,
     sync::atomic::{
         AtomicUsize,
         Ordering::{Relaxed, SeqCst},
     },
+    time::Duration,
 };
 
 use arsc_rs::Arsc;
 use async_trait::async_trait;
-use ksc::Error::{self, ENOENT, ENOTDIR, EPERM, ESPIPE};
+use ksc::Error::{self, EACCES, EINVAL, ENOENT, ENOTDIR, EPERM, ESPIPE};
 use ksync::Mutex;
 use rv39_paging::PAGE_SIZE;
 use umifs::{
     path::Path,
-    traits::{Entry, FileSystem},
-    types::{FileType, FsStat, Metadata, OpenOptions, Permissions},
+    traits::{Directory, Entry, FileSystem},
+    types::{DirEntry, FileType, FsStat, Metadata, OpenOptions, Permissions},
 };
 use umio::*;
 
+use self::pid::{parse_id, TaskDir, TaskLink};
+use super::{MountFlags, FS_TYPES};
+use crate::{
+    cpu::{self, FSHIFT},
+    dev, executor, rxx,
+    syscall::UTS_NAME,
+    task::{self, pid},
+};
+
 pub struct ProcFs;
 
 #[async_trait]
 impl FileSystem for ProcFs {
     async fn root_dir(self: Arsc<Self>) -> Result<Arc<dyn Entry>, Error> {
-        Ok(Arc::new(ProcRoot::default()))
+        Ok(Arc::new(ProcRoot))
     }
 
     async fn flush(&self) -> Result<(), Error> {
@@ -33,7 +52,7 @@ impl FileSystem for ProcFs {
 
     async fn stat(&self) -> FsStat {
         FsStat {
-            ty: "devfs",
+            ty: "proc",
             block_size: PAGE_SIZE,
             block_count: 0,
             block_free: 0,
@@ -42,13 +61,43 @@ impl FileSystem for ProcFs {
     }
 }
 
-#[derive(Default)]
-pub struct ProcRoot {
-    minfo: Arc<MemInfo>,
-    mounts: Arc<Mounts>,
-    intrs: Arc<Interrupts>,
+/// Converts the time to clock ticks, with `USER_HZ` being 100.
+fn ticks(time: Duration) -> u128 {
+    time.as_millis() / 10
 }
 
+/// The entries of `/proc` other than the directories of the processes.
+static ENTRIES: [(&str, FileType); 11] = [
+    ("cpuinfo", FileType::FILE),
+    ("filesystems", FileType::FILE),
+    ("interrupts", FileType::FILE),
+    ("loadavg", FileType::FILE),
+    ("meminfo", FileType::FILE),
+    ("mounts", FileType::FILE),
+    ("self", FileType::LNK),
+    ("stat", FileType::FILE),
+    ("swaps", FileType::FILE),
+    ("uptime", FileType::FILE),
+    ("version", FileType::FILE),
+];
+
+fn dir_metadata(offset: usize) -> Metadata {
+    Metadata {
+        ty: FileType::DIR,
+        len: 0,
+        offset,
+        link_count: 1,
+        perm: Permissions::from_bits_truncate(0o555),
+        uid: 0,
+        gid: 0,
+        block_size: 1024,
+        block_count: 0,
+        times: Default::default(),
+    }
+}
+
+pub struct ProcRoot;
+
 impl ToIo for ProcRoot {}
 
 #[async_trait]
@@ -59,29 +108,324 @@ impl Entry for ProcRoot {
         options: OpenOptions,
         perm: Permissions,
     ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if let Some(kind) = Kind::from_name(path.as_str()) {
+            return SysFile::new(kind).open(Path::new(""), options, perm).await;
+        }
         match path.as_str() {
-            "meminfo" => self.minfo.clone().open(Path::new(""), options, perm).await,
-            "mounts" => self.mounts.clone().open(Path::new(""), options, perm).await,
-            "interrupts" => self.intrs.clone().open(Path::new(""), options, perm).await,
+            "" | "." => Ok((self, false)),
+            "meminfo" => {
+                let minfo = Arc::new(MemInfo::default());
+                minfo.open(Path::new(""), options, perm).await
+            }
+            "mounts" => {
+                let mounts = Arc::new(Mounts::default());
+                mounts.open(Path::new(""), options, perm).await
+            }
+            "interrupts" => {
+                let intrs = Arc::new(Interrupts::default());
+                intrs.open(Path::new(""), options, perm).await
+            }
             _ => {
-                let (dir, _next) = {
+                let (dir, next) = {
                     let mut comp = path.components();
                     (comp.next().ok_or(ENOENT)?.as_str(), comp.as_path())
                 };
                 match dir {
-                    "meminfo" | "mounts" => Err(ENOTDIR),
-                    _ => Err(ENOENT),
+                    "cpuinfo" | "filesystems" | "interrupts" | "loadavg" | "meminfo" | "mounts"
+                    | "stat" | "swaps" | "uptime" | "version" => Err(ENOTDIR),
+                    // `/proc/self` is a link to the directory of the current process.
+                    "self" => {
+                        let task = task::current().ok_or(ENOENT)?;
+                        let process = task::pid::find(task.tgid()).ok_or(ENOENT)?;
+                        match next.as_str() {
+                            "" => {
+                                let target = format!("{}", task.tgid()).into();
+                                Ok((TaskLink::new(process, target), false))
+                            }
+                            _ => TaskDir::new(process, false).open(next, options, perm).await,
+                        }
+                    }
+                    _ => {
+                        let task = parse_id(dir).and_then(task::pid::find);
+                        // Threads are hidden from the listing but still accessible.
+                        let task = task.ok_or(ENOENT)?;
+                        let thread = task.tid() != task.tgid();
+                        TaskDir::new(task, thread).open(next, options, perm).await
+                    }
                 }
             }
         }
     }
 
     async fn metadata(&self) -> Metadata {
-        todo!()
+        dir_metadata(0)
+    }
+
+    fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
+        Some(self)
     }
 }
 impl IoPoll for ProcRoot {}
 
+#[async_trait]
+impl Directory for ProcRoot {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        // The offsets of the fixed entries are their indices plus one, followed
+        // by the ones of the processes, which are their PIDs plus one.
+        let offset = last.map_or(0, |last| last.metadata.offset);
+        if let Some(&(name, ty)) = ENTRIES.get(offset) {
+            return Ok(Some(DirEntry {
+                name: name.to_string(),
+                metadata: Metadata {
+                    ty,
+                    offset: offset + 1,
+                    perm: Permissions::all_same(true, false, false),
+                    ..dir_metadata(0)
+                },
+            }));
+        }
+        let next = pid::next_process(offset - ENTRIES.len());
+        Ok(next.map(|task| DirEntry {
+            name: format!("{}", task.tid()),
+            metadata: dir_metadata(ENTRIES.len() + task.tid() + 1),
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    CpuInfo,
+    Filesystems,
+    LoadAvg,
+    RandomizeVaSpace,
+    Stat,
+    Swaps,
+    Uptime,
+    Version,
+}
+
+impl Kind {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "cpuinfo" => Kind::CpuInfo,
+            "filesystems" => Kind::Filesystems,
+            "loadavg" => Kind::LoadAvg,
+            // The only entry of `/proc/sys` for now, accessible without listing.
+            "sys/kernel/randomize_va_space" => Kind::RandomizeVaSpace,
+            "stat" => Kind::Stat,
+            "swaps" => Kind::Swaps,
+            "uptime" => Kind::Uptime,
+            "version" => Kind::Version,
+            _ => return None,
+        })
+    }
+}
+
+/// A system-wide file, whose content is generated on every read.
+struct SysFile {
+    kind: Kind,
+    pos: AtomicUsize,
+}
+
+/// Returns the IDs of the harts in the order of the runners of the executor,
+/// which starts from the boot hart.
+fn hart_ids() -> impl Iterator<Item = usize> {
+    let (harts, bsp) = (rxx::harts(), hart_id::bsp_id());
+    let others = (0..usize::BITS as usize).filter(move |&id| harts & (1 << id) != 0 && id != bsp);
+    iter::once(bsp).chain(others)
+}
+
+/// Formats the time in seconds with 2 decimal places.
+fn secs(time: Duration) -> String {
+    format!("{}.{:02}", time.as_secs(), time.subsec_millis() / 10)
+}
+
+/// Formats the load average in fixed point with 2 decimal places.
+fn load(load: usize) -> String {
+    let frac = ((load & ((1 << FSHIFT) - 1)) * 100) >> FSHIFT;
+    format!("{}.{frac:02}", load >> FSHIFT)
+}
+
+impl SysFile {
+    fn new(kind: Kind) -> Arc<Self> {
+        Arc::new(SysFile {
+            kind,
+            pos: AtomicUsize::new(0),
+        })
+    }
+
+    fn render(&self) -> String {
+        let mut buf = String::new();
+        match self.kind {
+            Kind::CpuInfo => {
+                for (processor, hart) in hart_ids().enumerate() {
+                    writeln!(buf, "processor\t: {processor}").unwrap();
+                    writeln!(buf, "hart\t\t: {hart}").unwrap();
+                    writeln!(buf, "isa\t\t: {}", rxx::isa()).unwrap();
+                    writeln!(buf, "mmu\t\t: sv39").unwrap();
+                    writeln!(buf).unwrap();
+                }
+            }
+            Kind::Filesystems => {
+                for (ty, block) in FS_TYPES {
+                    let nodev = if block { "" } else { "nodev" };
+                    writeln!(buf, "{nodev}\t{ty}").unwrap();
+                }
+            }
+            Kind::LoadAvg => {
+                let [one, five, fifteen] = cpu::loadavg().map(load);
+                writeln!(
+                    buf,
+                    "{one} {five} {fifteen} {}/{} {}",
+                    executor().load(),
+                    pid::count(),
+                    pid::last()
+                )
+                .unwrap();
+            }
+            Kind::RandomizeVaSpace => {
+                writeln!(buf, "{}", crate::mem::randomize_va_space()).unwrap();
+            }
+            Kind::Stat => {
+                let times = executor().times().map(|(busy, idle)| {
+                    let busy = ticks(config::to_duration(busy));
+                    (busy, ticks(config::to_duration(idle)))
+                });
+                let times = times.collect::<Vec<_>>();
+                let (busy, idle) = (times.iter()).fold((0, 0), |(b, i), (tb, ti)| (b + tb, i + ti));
+
+                // The runners do not tell the time in user mode from the one in
+                // the kernel, so it is all accounted as user time.
+                writeln!(buf, "cpu  {busy} 0 0 {idle} 0 0 0 0 0 0").unwrap();
+                for (index, (busy, idle)) in times.iter().enumerate() {
+                    writeln!(buf, "cpu{index} {busy} 0 0 {idle} 0 0 0 0 0 0").unwrap();
+                }
+
+                // The timer interrupts are counted as the ones of IRQ 0.
+                let counts = dev::INTR.counts();
+                let timer = crate::trap::TIMER_COUNT.load(Relaxed);
+                let total = timer + counts.values().sum::<usize>();
+                write!(buf, "intr {total} {timer}").unwrap();
+                let max = counts.keys().max().copied().unwrap_or_default();
+                for pin in 1..=max {
+                    let count = counts.get(&pin).copied().unwrap_or_default();
+                    write!(buf, " {count}").unwrap();
+                }
+                writeln!(buf).unwrap();
+
+                let btime = dev::realtime().saturating_sub(dev::uptime());
+                writeln!(buf, "btime {}", btime.as_secs()).unwrap();
+                writeln!(buf, "processes {}", pid::last()).unwrap();
+                writeln!(buf, "procs_running {}", executor().load()).unwrap();
+                writeln!(buf, "procs_blocked 0").unwrap();
+            }
+            Kind::Swaps => {
+                writeln!(buf, "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority").unwrap();
+                for swap in kmem::swaps() {
+                    let ty = if swap.partition { "partition" } else { "file" };
+                    let [size, used] =
+                        [swap.pages, swap.used].map(|pages| pages * PAGE_SIZE / 1024);
+                    writeln!(
+                        buf,
+                        "{:<40}{ty:<16}{size:<16}{used:<16}{}",
+                        swap.name, swap.priority
+                    )
+                    .unwrap();
+                }
+            }
+            Kind::Uptime => {
+                let idle = executor().times().map(|(_, idle)| idle).sum();
+                let idle = config::to_duration(idle);
+                writeln!(buf, "{} {}", secs(dev::uptime()), secs(idle)).unwrap();
+            }
+            Kind::Version => {
+                let [sysname, nodename, release, version, machine, _] = UTS_NAME;
+                writeln!(
+                    buf,
+                    "{sysname} version {release} ({nodename}) #{version} {machine}"
+                )
+                .unwrap();
+            }
+        }
+        buf
+    }
+}
+
+#[async_trait]
+impl Io for SysFile {
+    async fn seek(&self, whence: SeekFrom) -> Result<usize, Error> {
+        let pos = match whence {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(_) => return Err(ESPIPE),
+            SeekFrom::Current(pos) if pos >= 0 => self.pos.load(SeqCst).checked_add(pos as usize),
+            SeekFrom::Current(pos) => self.pos.load(SeqCst).checked_sub(pos.unsigned_abs()),
+        }
+        .ok_or(EINVAL)?;
+        self.pos.store(pos, SeqCst);
+        Ok(pos)
+    }
+
+    async fn read_at(&self, offset: usize, buffer: &mut [IoSliceMut]) -> Result<usize, Error> {
+        let buf = self.render();
+        let Some(buf) = buf.as_bytes().get(offset..) else {
+            return Ok(0)
+        };
+        Ok(copy_to_ioslice(buf, buffer))
+    }
+
+    async fn write_at(&self, _: usize, buffer: &mut [IoSlice]) -> Result<usize, Error> {
+        let Kind::RandomizeVaSpace = self.kind else {
+            return Err(EPERM)
+        };
+        let info = task::current().and_then(|current| current.info());
+        if !info.map_or(true, |info| info.cred.is_root()) {
+            return Err(EACCES);
+        }
+        let data = buffer.iter().flat_map(|buf| buf.iter().copied());
+        let data = data.collect::<Vec<_>>();
+        let text = core::str::from_utf8(&data).map_err(|_| EINVAL)?;
+        let level = text.trim().parse().map_err(|_| EINVAL)?;
+        crate::mem::set_randomize_va_space(level)?;
+        Ok(data.len())
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Entry for SysFile {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        umifs::misc::open_file(
+            self,
+            path,
+            options,
+            perm,
+            Permissions::all_same(true, true, false),
+        )
+        .await
+    }
+
+    async fn metadata(&self) -> Metadata {
+        let perm = match self.kind {
+            Kind::RandomizeVaSpace => Permissions::from_bits_truncate(0o644),
+            _ => Permissions::all_same(true, false, false),
+        };
+        Metadata {
+            ty: FileType::FILE,
+            perm,
+            ..dir_metadata(0)
+        }
+    }
+}
+impl IoPoll for SysFile {}
+
 #[derive(Default)]
 pub struct MemInfo(Mutex<String>, AtomicUsize);
 
@@ -89,11 +433,12 @@ pub struct MemInfo(Mutex<String>, AtomicUsize);
 impl Io for MemInfo {
     async fn seek(&self, whence: SeekFrom) -> Result<usize, Error> {
         let pos = match whence {
-            SeekFrom::Start(pos) => pos,
+            SeekFrom::Start(pos) => Some(pos),
             SeekFrom::End(_) => return Err(ESPIPE),
-            SeekFrom::Current(pos) if pos >= 0 => self.1.load(SeqCst) + pos as usize,
-            SeekFrom::Current(pos) => self.1.load(SeqCst) - (-pos as usize),
-        };
+            SeekFrom::Current(pos) if pos >= 0 => self.1.load(SeqCst).checked_add(pos as usize),
+            SeekFrom::Current(pos) => self.1.load(SeqCst).checked_sub(pos.unsigned_abs()),
+        }
+        .ok_or(EINVAL)?;
         self.1.store(pos, SeqCst);
         Ok(pos)
     }
@@ -109,6 +454,17 @@ impl Io for MemInfo {
         writeln!(buf, "MemTotal:     {:>10} kB", total / 1024).unwrap();
         writeln!(buf, "MemAvailable: {:>10} kB", (total - used) / 1024).unwrap();
 
+        let swaps = kmem::swaps();
+        let swap_total = swaps.iter().map(|swap| swap.pages).sum::<usize>() * PAGE_SIZE;
+        let swap_used = swaps.iter().map(|swap| swap.used).sum::<usize>() * PAGE_SIZE;
+        writeln!(buf, "SwapTotal:    {:>10} kB", swap_total / 1024).unwrap();
+        writeln!(
+            buf,
+            "SwapFree:     {:>10} kB",
+            (swap_total - swap_used) / 1024
+        )
+        .unwrap();
+
         let Some(buf) = buf.as_bytes().get(offset..) else {
             return Ok(0)
         };
@@ -147,7 +503,10 @@ impl Entry for MemInfo {
             ty: FileType::FILE,
             len: 0,
             offset: rand_riscv::seed64(),
+            link_count: 1,
             perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
             block_size: 1024,
             block_count: 0,
             times: Default::default(),
@@ -163,11 +522,12 @@ pub struct Mounts(Mutex<String>, AtomicUsize);
 impl Io for Mounts {
     async fn seek(&self, whence: SeekFrom) -> Result<usize, Error> {
         let pos = match whence {
-            SeekFrom::Start(pos) => pos,
+            SeekFrom::Start(pos) => Some(pos),
             SeekFrom::End(_) => return Err(ESPIPE),
-            SeekFrom::Current(pos) if pos >= 0 => self.1.load(SeqCst) + pos as usize,
-            SeekFrom::Current(pos) => self.1.load(SeqCst) - (-pos as usize),
-        };
+            SeekFrom::Current(pos) if pos >= 0 => self.1.load(SeqCst).checked_add(pos as usize),
+            SeekFrom::Current(pos) => self.1.load(SeqCst).checked_sub(pos.unsigned_abs()),
+        }
+        .ok_or(EINVAL)?;
         self.1.store(pos, SeqCst);
         Ok(pos)
     }
@@ -180,11 +540,25 @@ impl Io for Mounts {
 
         for (dst, handle) in fs.iter() {
             let stat = handle.fs.stat().await;
-            write!(buf, "{} /{dst} {} rw,relatime", handle.dev, stat.ty).unwrap();
+            let flags = handle.flags;
+            let rw = if flags.contains(MountFlags::RDONLY) {
+                "ro"
+            } else {
+                "rw"
+            };
+            write!(buf, "{} /{dst} {} {rw}", handle.dev, stat.ty).unwrap();
+            for (flag, name) in [
+                (MountFlags::NOSUID, "nosuid"),
+                (MountFlags::NODEV, "nodev"),
+                (MountFlags::NOEXEC, "noexec"),
+            ] {
+                if flags.contains(flag) {
+                    write!(buf, ",{name}").unwrap();
+                }
+            }
+            write!(buf, ",relatime").unwrap();
             if stat.block_count != 0 {
                 write!(buf, ",size={}k", stat.block_count * stat.block_size / 1024).unwrap();
-            } else {
-                write!(buf, ",nosuid,nodev,noexec").unwrap();
             }
             writeln!(buf, " 0 0").unwrap();
         }
@@ -227,7 +601,10 @@ impl Entry for Mounts {
             ty: FileType::FILE,
             len: 0,
             offset: rand_riscv::seed64(),
+            link_count: 1,
             perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
             block_size: 1024,
             block_count: 0,
             times: Default::default(),
@@ -243,17 +620,18 @@ pub struct Interrupts(Mutex<String>, AtomicUsize);
 impl Io for Interrupts {
     async fn seek(&self, whence: SeekFrom) -> Result<usize, Error> {
         let pos = match whence {
-            SeekFrom::Start(pos) => pos,
+            SeekFrom::Start(pos) => Some(pos),
             SeekFrom::End(_) => return Err(ESPIPE),
-            SeekFrom::Current(pos) if pos >= 0 => self.1.load(SeqCst) + pos as usize,
-            SeekFrom::Current(pos) => self.1.load(SeqCst) - (-pos as usize),
-        };
+            SeekFrom::Current(pos) if pos >= 0 => self.1.load(SeqCst).checked_add(pos as usize),
+            SeekFrom::Current(pos) => self.1.load(SeqCst).checked_sub(pos.unsigned_abs()),
+        }
+        .ok_or(EINVAL)?;
         self.1.store(pos, SeqCst);
         Ok(pos)
     }
 
     async fn read_at(&self, offset: usize, buffer: &mut [IoSliceMut]) -> Result<usize, Error> {
-        let counts = crate::dev::INTR.counts();
+        let counts = dev::INTR.counts();
 
         let mut buf = self.0.lock().await;
         buf.clear();
@@ -301,7 +679,10 @@ impl Entry for Interrupts {
             ty: FileType::FILE,
             len: 0,
             offset: rand_riscv::seed64(),
+            link_count: 1,
             perm: Permissions::all_same(true, false, false),
+            uid: 0,
+            gid: 0,
             block_size: 1024,
             block_count: 0,
             times: Default::default(),
@@ -0,0 +1,541 @@
+//! The directories of the tasks: `/proc/<pid>` and `/proc/<pid>/task/<tid>`.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+};
+
+use async_trait::async_trait;
+use ksc::Error::{self, EACCES, EEXIST, EINVAL, ENOENT, ENOTDIR, EPERM, ESPIPE};
+use rv39_paging::Attr;
+use umifs::{
+    path::{Path, PathBuf},
+    traits::{Directory, Entry},
+    types::{DirEntry, FileType, Metadata, OpenOptions, Permissions},
+};
+use umio::*;
+
+use super::{copy_to_ioslice, ticks};
+use crate::task::{self, pid, Task, TaskInfo};
+
+/// Splits the first component from `path`.
+fn split(path: &Path) -> (&str, &Path) {
+    let mut comps = path.components();
+    let first = comps.next().map_or("", |c| c.as_str());
+    (first, comps.as_path())
+}
+
+/// Parses a PID or TID, rejecting the ones with leading zeros or signs.
+pub fn parse_id(name: &str) -> Option<usize> {
+    let valid = name.bytes().all(|b| b.is_ascii_digit()) && !name.starts_with('0');
+    valid.then(|| name.parse().ok()).flatten()
+}
+
+fn metadata(task: &Task, ty: FileType, perm: u32, offset: usize) -> Metadata {
+    let (uid, gid) = task
+        .info()
+        .map_or((0, 0), |i| (i.cred.uid.effective, i.cred.gid.effective));
+    Metadata {
+        ty,
+        len: 0,
+        offset,
+        link_count: 1,
+        perm: Permissions::from_bits_truncate(perm),
+        uid,
+        gid,
+        block_size: 1024,
+        block_count: 0,
+        times: Default::default(),
+    }
+}
+
+/// The entries of a task directory, with the one of `task` at the end only
+/// present for processes.
+static ENTRIES: [(&str, FileType); 11] = [
+    ("cmdline", FileType::FILE),
+    ("cwd", FileType::LNK),
+    ("environ", FileType::FILE),
+    ("exe", FileType::LNK),
+    ("fd", FileType::DIR),
+    ("maps", FileType::FILE),
+    ("oom_score", FileType::FILE),
+    ("oom_score_adj", FileType::FILE),
+    ("stat", FileType::FILE),
+    ("status", FileType::FILE),
+    ("task", FileType::DIR),
+];
+
+/// `/proc/<pid>` of a process, or `/proc/<pid>/task/<tid>` of a thread.
+pub struct TaskDir {
+    task: Arc<Task>,
+    thread: bool,
+}
+
+impl TaskDir {
+    pub fn new(task: Arc<Task>, thread: bool) -> Arc<Self> {
+        Arc::new(TaskDir { task, thread })
+    }
+
+    fn entries(&self) -> &'static [(&'static str, FileType)] {
+        match self.thread {
+            true => &ENTRIES[..ENTRIES.len() - 1],
+            false => &ENTRIES,
+        }
+    }
+}
+
+impl ToIo for TaskDir {}
+
+#[async_trait]
+impl Entry for TaskDir {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        let (name, rest) = split(path);
+        let entry: Arc<dyn Entry> = match name {
+            "" | "." => return Ok((self, false)),
+            "cmdline" => TaskFile::new(self.task.clone(), Kind::Cmdline),
+            "environ" => TaskFile::new(self.task.clone(), Kind::Environ),
+            "maps" => TaskFile::new(self.task.clone(), Kind::Maps),
+            "oom_score" => TaskFile::new(self.task.clone(), Kind::OomScore),
+            "oom_score_adj" => TaskFile::new(self.task.clone(), Kind::OomScoreAdj),
+            "stat" => TaskFile::new(self.task.clone(), Kind::Stat(self.thread)),
+            "status" => TaskFile::new(self.task.clone(), Kind::Status(self.thread)),
+            "exe" => {
+                let exe = self.task.executable();
+                let exe = match exe.starts_with('/') {
+                    true => exe,
+                    false => format!("/{exe}"),
+                };
+                TaskLink::new(self.task.clone(), exe.into())
+            }
+            "cwd" => {
+                let info = self.task.info().ok_or(ENOENT)?;
+                let cwd = info.files.cwd();
+                let cwd = match cwd.as_str().starts_with('/') {
+                    true => cwd,
+                    false => Path::new("/").join(cwd),
+                };
+                TaskLink::new(self.task.clone(), cwd)
+            }
+            "fd" => Arc::new(FdDir(self.task.clone())),
+            "task" if !self.thread => Arc::new(ThreadList(self.task.clone())),
+            _ => return Err(ENOENT),
+        };
+        entry.open(rest, options, perm).await
+    }
+
+    async fn metadata(&self) -> Metadata {
+        metadata(&self.task, FileType::DIR, 0o555, self.task.tid())
+    }
+
+    fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
+        Some(self)
+    }
+}
+
+impl IoPoll for TaskDir {}
+
+#[async_trait]
+impl Directory for TaskDir {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        // The offsets of the entries are their indices plus one.
+        let index = last.map_or(0, |last| last.metadata.offset);
+        Ok(self.entries().get(index).map(|&(name, ty)| DirEntry {
+            name: name.to_string(),
+            metadata: Metadata {
+                offset: index + 1,
+                ..metadata(&self.task, ty, 0o444, 0)
+            },
+        }))
+    }
+}
+
+/// `/proc/<pid>/task`, listing the threads of a process.
+struct ThreadList(Arc<Task>);
+
+impl ToIo for ThreadList {}
+
+#[async_trait]
+impl Entry for ThreadList {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        let (name, rest) = split(path);
+        if name.is_empty() || name == "." {
+            return Ok((self, false));
+        }
+        let task = parse_id(name).and_then(pid::find).ok_or(ENOENT)?;
+        if task.tgid() != self.0.tgid() {
+            return Err(ENOENT);
+        }
+        TaskDir::new(task, true).open(rest, options, perm).await
+    }
+
+    async fn metadata(&self) -> Metadata {
+        metadata(&self.0, FileType::DIR, 0o555, 0)
+    }
+
+    fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
+        Some(self)
+    }
+}
+
+impl IoPoll for ThreadList {}
+
+#[async_trait]
+impl Directory for ThreadList {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        // The offsets of the entries are their TIDs plus one.
+        let start = last.map_or(0, |last| last.metadata.offset);
+        let next = pid::next_thread(self.0.tgid(), start);
+        Ok(next.map(|task| DirEntry {
+            name: format!("{}", task.tid()),
+            metadata: Metadata {
+                offset: task.tid() + 1,
+                ..metadata(&task, FileType::DIR, 0o555, 0)
+            },
+        }))
+    }
+}
+
+/// `/proc/<pid>/fd`, where each entry opens the file of the descriptor.
+struct FdDir(Arc<Task>);
+
+impl ToIo for FdDir {}
+
+#[async_trait]
+impl Entry for FdDir {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        let (name, rest) = split(path);
+        if name.is_empty() || name == "." {
+            return Ok((self, false));
+        }
+        let info = self.0.info().ok_or(ENOENT)?;
+        let fd = name.parse().map_err(|_| ENOENT)?;
+        let entry = info.files.get(fd).await.map_err(|_| ENOENT)?;
+        if options.contains(OpenOptions::CREAT | OpenOptions::EXCL) {
+            return Err(EEXIST);
+        }
+        entry.open(rest, options, perm).await
+    }
+
+    async fn metadata(&self) -> Metadata {
+        metadata(&self.0, FileType::DIR, 0o500, 0)
+    }
+
+    fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
+        Some(self)
+    }
+}
+
+impl IoPoll for FdDir {}
+
+#[async_trait]
+impl Directory for FdDir {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        let Some(info) = self.0.info() else {
+            return Ok(None)
+        };
+        // The offsets of the entries are the descriptors plus one.
+        let start = last.map_or(0, |last| last.metadata.offset);
+        let fds = info.files.fds().await;
+        let next = fds.into_iter().find(|&fd| fd as usize >= start);
+        Ok(next.map(|fd| DirEntry {
+            name: format!("{fd}"),
+            metadata: Metadata {
+                offset: fd as usize + 1,
+                ..metadata(&self.0, FileType::LNK, 0o700, 0)
+            },
+        }))
+    }
+}
+
+/// A symbolic link to a path of a task.
+pub struct TaskLink {
+    task: Arc<Task>,
+    target: PathBuf,
+}
+
+impl TaskLink {
+    pub fn new(task: Arc<Task>, target: PathBuf) -> Arc<Self> {
+        Arc::new(TaskLink { task, target })
+    }
+}
+
+impl ToIo for TaskLink {}
+
+#[async_trait]
+impl Entry for TaskLink {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        _: OpenOptions,
+        _: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if path == "" || path == "." {
+            Ok((self, false))
+        } else {
+            Err(ENOTDIR)
+        }
+    }
+
+    async fn metadata(&self) -> Metadata {
+        Metadata {
+            len: self.target.as_str().len(),
+            ..metadata(&self.task, FileType::LNK, 0o777, 0)
+        }
+    }
+
+    async fn read_link(&self) -> Result<PathBuf, Error> {
+        Ok(self.target.clone())
+    }
+}
+
+impl IoPoll for TaskLink {}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Cmdline,
+    Environ,
+    Maps,
+    OomScore,
+    /// The only writable file, shared by all the threads of a process.
+    OomScoreAdj,
+    /// `stat` of a thread if set, or of a whole process otherwise.
+    Stat(bool),
+    Status(bool),
+}
+
+/// A file of a task, whose content is generated on every read.
+struct TaskFile {
+    task: Arc<Task>,
+    kind: Kind,
+    pos: AtomicUsize,
+}
+
+impl TaskFile {
+    fn new(task: Arc<Task>, kind: Kind) -> Arc<Self> {
+        Arc::new(TaskFile {
+            task,
+            kind,
+            pos: AtomicUsize::new(0),
+        })
+    }
+
+    async fn render(&self) -> String {
+        let task = &self.task;
+        let info = task.info();
+        let mut buf = String::new();
+        match self.kind {
+            Kind::Cmdline | Kind::Environ => {
+                let Some(info) = info else { return buf };
+                let strings = match self.kind {
+                    Kind::Cmdline => &info.args,
+                    _ => &info.envs,
+                };
+                strings.iter().for_each(|s| write!(buf, "{s}\0").unwrap());
+            }
+            Kind::Maps => {
+                let Some(info) = info else { return buf };
+                for mapping in info.virt.mappings().await {
+                    let attr = mapping.attr;
+                    let flag = |a: Attr, c: char| if attr.contains(a) { c } else { '-' };
+                    writeln!(
+                        buf,
+                        "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+                        mapping.range.start.val(),
+                        mapping.range.end.val(),
+                        flag(Attr::READABLE, 'r'),
+                        flag(Attr::WRITABLE, 'w'),
+                        flag(Attr::EXECUTABLE, 'x'),
+                        if mapping.private { 'p' } else { 's' },
+                        mapping.offset,
+                    )
+                    .unwrap();
+                }
+            }
+            Kind::OomScore => writeln!(buf, "{}", task.oom_score()).unwrap(),
+            Kind::OomScoreAdj => writeln!(buf, "{}", task.oom_score_adj()).unwrap(),
+            Kind::Stat(thread) => {
+                let (state, vsize) = match &info {
+                    Some(info) => ('R', vm_size(info).await),
+                    None => ('Z', 0),
+                };
+                let [user, system, children_user, children_system] =
+                    task.cpu_times(!thread).map(ticks);
+                write!(
+                    buf,
+                    "{} ({}) {state} {} {} {} 0 -1 0 0 0 0 0 {user} {system} \
+                    {children_user} {children_system} 20 0 {} 0 0 {vsize} 0",
+                    task.tid(),
+                    name(task),
+                    task.ppid(),
+                    task.pgid(),
+                    task.sid(),
+                    pid::thread_count(task.tgid()),
+                )
+                .unwrap();
+                // The rest of the 52 fields are not tracked.
+                (24..52).for_each(|_| buf.push_str(" 0"));
+                buf.push('\n');
+            }
+            Kind::Status(thread) => {
+                let tid = match thread {
+                    true => task.tid(),
+                    false => task.tgid(),
+                };
+                let state = match info {
+                    Some(_) => "R (running)",
+                    None => "Z (zombie)",
+                };
+                writeln!(buf, "Name:\t{}", name(task)).unwrap();
+                writeln!(buf, "State:\t{state}").unwrap();
+                writeln!(buf, "Tgid:\t{}", task.tgid()).unwrap();
+                writeln!(buf, "Pid:\t{tid}").unwrap();
+                writeln!(buf, "PPid:\t{}", task.ppid()).unwrap();
+                if let Some(info) = &info {
+                    let (uid, gid) = (info.cred.uid, info.cred.gid);
+                    // The file system IDs are always the effective ones.
+                    writeln!(
+                        buf,
+                        "Uid:\t{}\t{}\t{}\t{}",
+                        uid.real, uid.effective, uid.saved, uid.effective
+                    )
+                    .unwrap();
+                    writeln!(
+                        buf,
+                        "Gid:\t{}\t{}\t{}\t{}",
+                        gid.real, gid.effective, gid.saved, gid.effective
+                    )
+                    .unwrap();
+                    write!(buf, "Groups:").unwrap();
+                    info.cred
+                        .groups
+                        .iter()
+                        .for_each(|g| write!(buf, " {g}").unwrap());
+                    writeln!(buf).unwrap();
+                    writeln!(buf, "VmSize:\t{:>8} kB", vm_size(info).await / 1024).unwrap();
+                }
+                writeln!(buf, "Threads:\t{}", pid::thread_count(task.tgid())).unwrap();
+            }
+        }
+        buf
+    }
+}
+
+/// Returns the name of the task, which is the file name of its executable.
+fn name(task: &Task) -> String {
+    let exe = task.executable();
+    let name = exe.rsplit('/').next().unwrap_or(&exe);
+    // The names are truncated to 15 bytes.
+    name.chars()
+        .scan(0, |len, c| {
+            *len += c.len_utf8();
+            (*len <= 15).then_some(c)
+        })
+        .collect()
+}
+
+async fn vm_size(info: &TaskInfo) -> usize {
+    let mappings = info.virt.mappings().await;
+    mappings
+        .iter()
+        .map(|m| m.range.end.val() - m.range.start.val())
+        .sum()
+}
+
+#[async_trait]
+impl Io for TaskFile {
+    async fn seek(&self, whence: SeekFrom) -> Result<usize, Error> {
+        let pos = match whence {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(_) => return Err(ESPIPE),
+            SeekFrom::Current(pos) if pos >= 0 => self.pos.load(SeqCst).checked_add(pos as usize),
+            SeekFrom::Current(pos) => self.pos.load(SeqCst).checked_sub(pos.unsigned_abs()),
+        }
+        .ok_or(EINVAL)?;
+        self.pos.store(pos, SeqCst);
+        Ok(pos)
+    }
+
+    async fn read_at(&self, offset: usize, buffer: &mut [IoSliceMut]) -> Result<usize, Error> {
+        let buf = self.render().await;
+        let Some(buf) = buf.as_bytes().get(offset..) else {
+            return Ok(0)
+        };
+        Ok(copy_to_ioslice(buf, buffer))
+    }
+
+    async fn write_at(&self, _: usize, buffer: &mut [IoSlice]) -> Result<usize, Error> {
+        let Kind::OomScoreAdj = self.kind else {
+            return Err(EPERM)
+        };
+        let data = buffer.iter().flat_map(|buf| buf.iter().copied());
+        let data = data.collect::<Vec<_>>();
+        let text = core::str::from_utf8(&data).map_err(|_| EINVAL)?;
+        let score_adj = text.trim().parse().map_err(|_| EINVAL)?;
+
+        // Only root can make a process less likely to be killed.
+        if score_adj < self.task.oom_score_adj() {
+            let info = task::current().and_then(|current| current.info());
+            if !info.map_or(true, |info| info.cred.is_root()) {
+                return Err(EACCES);
+            }
+        }
+        self.task.set_oom_score_adj(score_adj)?;
+        Ok(data.len())
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Entry for TaskFile {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        umifs::misc::open_file(
+            self,
+            path,
+            options,
+            perm,
+            Permissions::all_same(true, true, false),
+        )
+        .await
+    }
+
+    async fn metadata(&self) -> Metadata {
+        let perm = match self.kind {
+            Kind::Environ => 0o400,
+            Kind::OomScoreAdj => 0o644,
+            _ => 0o444,
+        };
+        metadata(&self.task, FileType::FILE, perm, 0)
+    }
+}
+
+impl IoPoll for TaskFile {}
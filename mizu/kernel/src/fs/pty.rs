@@ -0,0 +1,429 @@
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    sync::{Arc, Weak},
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+
+use arsc_rs::Arsc;
+use async_trait::async_trait;
+use ksc::{
+    Boxed,
+    Error::{self, EEXIST, EIO, ENOENT, ENOTDIR, ESPIPE},
+};
+use ksync::event::Event;
+use rv39_paging::PAGE_SIZE;
+use spin::Mutex;
+use umifs::{
+    path::Path,
+    traits::{Directory, Entry, FileSystem, Io, ToIo},
+    types::{DirEntry, FileType, FsStat, Metadata, OpenOptions, Permissions},
+};
+use umio::{IntoAnyExt, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher, Watchers};
+
+use super::tty::Tty;
+
+/// The output of the slave side, which is read from the master side.
+#[derive(Default)]
+struct Output {
+    data: Mutex<VecDeque<u8>>,
+    ready: Event,
+    watchers: Watchers,
+}
+
+impl Output {
+    fn wake(&self) {
+        self.ready.notify(usize::MAX);
+        self.watchers
+            .notify(umio::Event::READABLE | umio::Event::HANG_UP);
+    }
+}
+
+struct Pty {
+    index: usize,
+    tty: Arc<Tty>,
+    output: Arc<Output>,
+    locked: AtomicBool,
+    /// The number of opened slave sides.
+    slaves: AtomicUsize,
+    /// Whether all the slave sides have been closed after opened.
+    slaves_closed: AtomicBool,
+}
+
+/// All the pseudo-terminals with their master sides opened, indexed by their
+/// numbers in `/dev/pts`.
+static PTYS: Mutex<BTreeMap<usize, Weak<Pty>>> = Mutex::new(BTreeMap::new());
+
+fn get(index: usize) -> Option<Arc<Pty>> {
+    ksync::critical(|| PTYS.lock().get(&index).and_then(Weak::upgrade))
+}
+
+/// Returns the terminal of an opened file, if it is either side of a
+/// pseudo-terminal.
+pub(super) fn tty_of(entry: &Arc<dyn Entry>) -> Option<Arc<Tty>> {
+    if let Some(master) = entry.clone().downcast::<PtyMaster>() {
+        return Some(master.0.tty.clone());
+    }
+    let slave = entry.clone().downcast::<PtySlave>()?;
+    Some(slave.0.tty.clone())
+}
+
+fn char_metadata(perm: u32) -> Metadata {
+    Metadata {
+        ty: FileType::CHR,
+        len: 0,
+        offset: 0,
+        link_count: 1,
+        perm: Permissions::from_bits_truncate(perm),
+        uid: 0,
+        gid: 0,
+        block_size: 0,
+        block_count: 0,
+        times: Default::default(),
+    }
+}
+
+/// The master side of a pseudo-terminal, created by opening `/dev/ptmx`.
+pub struct PtyMaster(Arc<Pty>);
+
+impl PtyMaster {
+    /// Allocates a new pseudo-terminal with the lowest free number.
+    pub fn new() -> Arc<Self> {
+        let output = Arc::new(Output::default());
+        let sink = output.clone();
+        let tty = Tty::new(move |buf| {
+            ksync::critical(|| sink.data.lock().extend(buf));
+            sink.wake();
+        });
+        ksync::critical(|| {
+            let mut ptys = PTYS.lock();
+            let index = (0..).find(|index| !ptys.contains_key(index)).unwrap();
+            let pty = Arc::new(Pty {
+                index,
+                tty,
+                output,
+                // Slave sides cannot be opened until `unlockpt`.
+                locked: AtomicBool::new(true),
+                slaves: Default::default(),
+                slaves_closed: Default::default(),
+            });
+            ptys.insert(index, Arc::downgrade(&pty));
+            Arc::new(PtyMaster(pty))
+        })
+    }
+
+    pub fn index(&self) -> usize {
+        self.0.index
+    }
+
+    pub fn set_locked(&self, locked: bool) {
+        self.0.locked.store(locked, SeqCst)
+    }
+
+    async fn read(&self, buffer: &mut [IoSliceMut<'_>]) -> Result<usize, Error> {
+        let output = &self.0.output;
+        let mut listener = None;
+        loop {
+            let len = ksync::critical(|| {
+                let mut data = output.data.lock();
+                let dst = buffer.iter_mut().flat_map(|buf| buf.iter_mut());
+                dst.zip(data.drain(..)).fold(0, |acc, (dst, b)| {
+                    *dst = b;
+                    acc + 1
+                })
+            });
+            if len > 0 || buffer.iter().all(|buf| buf.is_empty()) {
+                break Ok(len);
+            }
+            if self.0.slaves_closed.load(SeqCst) {
+                break Err(EIO);
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(output.ready.listen()),
+            }
+        }
+    }
+
+    async fn event(&self, expected: umio::Event) -> Option<umio::Event> {
+        if expected.contains(umio::Event::WRITABLE) {
+            return Some(umio::Event::WRITABLE);
+        }
+        if !expected.contains(umio::Event::READABLE) {
+            return None;
+        }
+        let output = &self.0.output;
+        let mut listener = None;
+        loop {
+            if !ksync::critical(|| output.data.lock().is_empty()) {
+                break Some(umio::Event::READABLE);
+            }
+            if self.0.slaves_closed.load(SeqCst) {
+                break Some(umio::Event::HANG_UP);
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(output.ready.listen()),
+            }
+        }
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        ksync::critical(|| PTYS.lock().remove(&self.0.index));
+        self.0.tty.hang_up();
+    }
+}
+
+#[async_trait]
+impl Io for PtyMaster {
+    fn read<'a: 'r, 'b: 'r, 'r>(
+        &'a self,
+        buffer: &'b mut [IoSliceMut],
+    ) -> Boxed<'r, Result<usize, Error>> {
+        Box::pin(self.read(buffer))
+    }
+
+    async fn write(&self, buffer: &mut [IoSlice]) -> Result<usize, Error> {
+        Ok(buffer.iter().fold(0, |acc, buf| {
+            self.0.tty.receive(buf);
+            acc + buf.len()
+        }))
+    }
+
+    async fn seek(&self, _: SeekFrom) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn read_at(&self, _: usize, _: &mut [IoSliceMut]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn write_at(&self, _: usize, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Entry for PtyMaster {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        _perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if !path.as_str().is_empty() || options.contains(OpenOptions::DIRECTORY) {
+            return Err(ENOTDIR);
+        }
+        Ok((self, false))
+    }
+
+    async fn metadata(&self) -> Metadata {
+        char_metadata(0o666)
+    }
+}
+
+impl IoPoll for PtyMaster {
+    fn event<'a: 'r, 'r>(&'a self, expected: umio::Event) -> Boxed<'r, Option<umio::Event>> {
+        Box::pin(self.event(expected))
+    }
+
+    fn poll(&self) -> umio::Event {
+        let mut events = umio::Event::WRITABLE;
+        if !ksync::critical(|| self.0.output.data.lock().is_empty()) {
+            events |= umio::Event::READABLE;
+        }
+        if self.0.slaves_closed.load(SeqCst) {
+            events |= umio::Event::HANG_UP;
+        }
+        events
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.0.output.watchers.push(watcher, exclusive);
+        true
+    }
+}
+
+/// The slave side of a pseudo-terminal, opened from `/dev/pts`.
+struct PtySlave(Arc<Pty>);
+
+impl PtySlave {
+    fn new(pty: Arc<Pty>) -> Arc<Self> {
+        pty.slaves.fetch_add(1, SeqCst);
+        pty.slaves_closed.store(false, SeqCst);
+        Arc::new(PtySlave(pty))
+    }
+
+    async fn event(&self, expected: umio::Event) -> Option<umio::Event> {
+        if expected.contains(umio::Event::WRITABLE) {
+            return Some(umio::Event::WRITABLE);
+        }
+        if !expected.contains(umio::Event::READABLE) {
+            return None;
+        }
+        Some(self.0.tty.event().await)
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        if self.0.slaves.fetch_sub(1, SeqCst) == 1 {
+            self.0.slaves_closed.store(true, SeqCst);
+            self.0.output.wake();
+        }
+    }
+}
+
+#[async_trait]
+impl Io for PtySlave {
+    async fn read(&self, buffer: &mut [IoSliceMut]) -> Result<usize, Error> {
+        Ok(self.0.tty.read(buffer).await)
+    }
+
+    async fn write(&self, buffer: &mut [IoSlice]) -> Result<usize, Error> {
+        if self.0.tty.is_hung_up() {
+            return Err(EIO);
+        }
+        Ok(self.0.tty.write(buffer))
+    }
+
+    async fn seek(&self, _: SeekFrom) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn read_at(&self, _: usize, _: &mut [IoSliceMut]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn write_at(&self, _: usize, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Entry for PtySlave {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        _perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if !path.as_str().is_empty() || options.contains(OpenOptions::DIRECTORY) {
+            return Err(ENOTDIR);
+        }
+        Ok((PtySlave::new(self.0.clone()), false))
+    }
+
+    async fn metadata(&self) -> Metadata {
+        char_metadata(0o620)
+    }
+}
+
+impl IoPoll for PtySlave {
+    fn event<'a: 'r, 'r>(&'a self, expected: umio::Event) -> Boxed<'r, Option<umio::Event>> {
+        Box::pin(self.event(expected))
+    }
+
+    fn poll(&self) -> umio::Event {
+        self.0.tty.poll_event() | umio::Event::WRITABLE
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.0.tty.watch(watcher, exclusive);
+        true
+    }
+}
+
+pub struct DevPts;
+
+#[async_trait]
+impl FileSystem for DevPts {
+    async fn root_dir(self: Arsc<Self>) -> Result<Arc<dyn Entry>, Error> {
+        Ok(Arc::new(PtsRoot))
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn stat(&self) -> FsStat {
+        FsStat {
+            ty: "devpts",
+            block_size: PAGE_SIZE,
+            block_count: 0,
+            block_free: 0,
+            file_count: ksync::critical(|| PTYS.lock().len()),
+        }
+    }
+}
+
+struct PtsRoot;
+
+impl ToIo for PtsRoot {}
+
+#[async_trait]
+impl Entry for PtsRoot {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        options: OpenOptions,
+        _perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if path.as_str().is_empty() {
+            return Ok((self, false));
+        }
+        let index = path.as_str().parse().map_err(|_| ENOENT)?;
+        let pty = get(index).ok_or(ENOENT)?;
+        if options.contains(OpenOptions::CREAT | OpenOptions::EXCL) {
+            return Err(EEXIST);
+        }
+        if options.contains(OpenOptions::DIRECTORY) {
+            return Err(ENOTDIR);
+        }
+        if pty.locked.load(SeqCst) {
+            return Err(EIO);
+        }
+        Ok((PtySlave::new(pty), false))
+    }
+
+    async fn metadata(&self) -> Metadata {
+        Metadata {
+            ty: FileType::DIR,
+            perm: Permissions::from_bits_truncate(0o755),
+            ..char_metadata(0)
+        }
+    }
+
+    fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
+        Some(self)
+    }
+}
+
+impl IoPoll for PtsRoot {}
+
+#[async_trait]
+impl Directory for PtsRoot {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        // The offsets of the entries are their numbers plus one.
+        let start = last.map_or(0, |last| last.metadata.offset);
+        let next = ksync::critical(|| PTYS.lock().range(start..).next().map(|(&i, _)| i));
+        Ok(next.map(|index| DirEntry {
+            name: format!("{index}"),
+            metadata: Metadata {
+                offset: index + 1,
+                ..char_metadata(0o620)
+            },
+        }))
+    }
+}
@@ -1,18 +1,44 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
 use core::future::ready;
 
 use async_trait::async_trait;
-use futures_util::{stream, FutureExt, StreamExt};
+use futures_util::{FutureExt, StreamExt};
 use ksc::{
     Boxed,
     Error::{self, EBADF, ENOSYS, ENOTDIR},
 };
+use spin::Once;
 use umifs::{
     path::Path,
     traits::{Entry, Io},
     types::{FileType, Metadata, OpenOptions, Permissions},
 };
-use umio::{Event, IoPoll, IoSlice, IoSliceMut, SeekFrom};
+use umio::{Event, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher};
+
+use super::tty::Tty;
+use crate::{executor, task::Access};
+
+static CONSOLE: Once<Arc<Tty>> = Once::new();
+
+/// Returns the terminal on the serial console, which takes over all the input
+/// from the device once it is created.
+pub fn console() -> &'static Arc<Tty> {
+    CONSOLE.call_once(|| {
+        let tty = Tty::new(|buf| ksync::critical(|| crate::dev::Stdout::new().write_bytes(buf)));
+        let input = tty.clone();
+        let task = async move {
+            let mut stdin = crate::dev::Stdin::new();
+            while let Some(b) = stdin.next().await {
+                input.receive(&[b]);
+            }
+        };
+        executor().spawn(task).detach();
+        tty
+    })
+}
 
 pub struct Serial {
     read: bool,
@@ -40,28 +66,14 @@ impl Io for Serial {
         if !self.read {
             return Err(EBADF);
         }
-        Ok(stream::iter(buffer.iter_mut())
-            .fold(0, |acc, buf| async move {
-                stream::iter(buf.iter_mut())
-                    .zip(crate::dev::Stdin::new())
-                    .for_each(|(dst, b)| async move { *dst = b })
-                    .await;
-                acc + buf.len()
-            })
-            .await)
+        Ok(console().read(buffer).await)
     }
 
     async fn write(&self, buffer: &mut [IoSlice]) -> Result<usize, Error> {
         if !self.write {
             return Err(EBADF);
         }
-        Ok(ksync::critical(|| {
-            let mut stdout = crate::dev::Stdout::new();
-            buffer.iter().fold(0, |acc, buf| {
-                stdout.write_bytes(buf);
-                acc + buf.len()
-            })
-        }))
+        Ok(console().write(buffer))
     }
 
     async fn seek(&self, _: SeekFrom) -> Result<usize, Error> {
@@ -92,12 +104,11 @@ impl Entry for Serial {
         if !path.as_str().is_empty() || options.contains(OpenOptions::DIRECTORY) {
             return Err(ENOTDIR);
         }
-        let (read, write) = match options.intersection(OpenOptions::ACCMODE) {
-            OpenOptions::RDWR => (true, true),
-            OpenOptions::WRONLY => (false, true),
-            OpenOptions::RDONLY => (true, false),
-            _ => unreachable!(),
-        };
+        let access = Access::from_options(options);
+        let (read, write) = (
+            access.contains(Access::READ),
+            access.contains(Access::WRITE),
+        );
         Ok((Arc::new(Serial { read, write }), false))
     }
 
@@ -106,9 +117,12 @@ impl Entry for Serial {
             ty: FileType::FILE | FileType::REG,
             len: 0,
             offset: 0,
+            link_count: 1,
             block_size: 1,
             block_count: isize::MAX as usize,
             perm: Permissions::all_same(self.read, self.write, false),
+            uid: 0,
+            gid: 0,
             times: Default::default(),
         }
     }
@@ -119,6 +133,15 @@ impl IoPoll for Serial {
         if expected != Event::READABLE {
             return Box::pin(ready(None));
         }
-        Box::pin(crate::dev::Stdin::event().map(Some))
+        Box::pin(console().event().map(Some))
+    }
+
+    fn poll(&self) -> Event {
+        console().poll_event() | Event::WRITABLE
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        console().watch(watcher, exclusive);
+        true
     }
 }
@@ -1,9 +1,12 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
 use core::{iter, ops::Deref, pin::pin, time::Duration};
 
 use arsc_rs::Arsc;
 use async_trait::async_trait;
-use devices::net::{tcp, udp, Config, Socket, Stack};
+use devices::net::{tcp, udp, Config, ConfigV4, ConfigV6, Socket, Stack, StaticConfigV4};
 use futures_util::future::{select, Either};
 use ksc::{
     Error,
@@ -16,14 +19,28 @@ use umifs::{
     traits::Entry,
     types::{FileType, Metadata, OpenOptions, Permissions},
 };
-use umio::{advance_slices, Event, Io, IoPoll, IoSlice, IoSliceMut, SeekFrom};
+use umio::{advance_slices, Event, Io, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher};
 
-use crate::trap::poll_with;
+use crate::{
+    cmdline::{cmdline, Ip},
+    trap::poll_with,
+};
 
 static STACK: Once<Arsc<Stack>> = Once::INIT;
 
 fn config() -> Config {
-    Default::default()
+    match cmdline().ip {
+        Ip::Dhcp => Config::dhcpv4(Default::default()),
+        Ip::Static { address, gateway } => Config::ipv4_static(StaticConfigV4 {
+            address,
+            gateway,
+            dns_servers: Default::default(),
+        }),
+        Ip::Off => Config {
+            ipv4: ConfigV4::None,
+            ipv6: ConfigV6::None,
+        },
+    }
 }
 
 pub(super) fn init_stack() {
@@ -178,7 +195,10 @@ impl Entry for SocketFile {
             ty: FileType::SOCK,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
@@ -220,6 +240,15 @@ impl IoPoll for SocketFile {
             }
         }
     }
+
+    fn poll(&self) -> Event {
+        self.socket.events()
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.socket.watch(watcher, exclusive);
+        true
+    }
 }
 
 #[async_trait]
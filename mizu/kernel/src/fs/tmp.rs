@@ -1,26 +1,51 @@
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{
+    AtomicU64, AtomicUsize,
+    Ordering::{Relaxed, SeqCst},
+};
 
 use arsc_rs::Arsc;
 use async_trait::async_trait;
-use hashbrown::HashMap;
 use kmem::Phys;
-use ksc::Error::{self, EEXIST, ENOENT, ENOSYS, ENOTDIR, EPERM};
-use ktime::Instant;
-use rand_riscv::RandomState;
+use ksc::Error::{self, EEXIST, EINVAL, EISDIR, ENOENT, ENOTDIR, ENOTEMPTY, EPERM, EXDEV};
 use rv39_paging::PAGE_SIZE;
 use spin::Mutex;
 use umifs::{
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     traits::{Directory, DirectoryMut, Entry, FileSystem, Io, ToIo},
     types::{DirEntry, FileType, FsStat, Metadata, OpenOptions, Permissions, SetMetadata, Times},
 };
-use umio::IoPoll;
+use umio::{IntoAnyExt, IoPoll, SeekFrom};
+
+use crate::dev::timestamp;
+
+/// The inode number allocator shared by all tmpfs instances.
+static INO: AtomicU64 = AtomicU64::new(1);
+
+/// Serializes all the renaming operations across directories, so that the
+/// ancestry check of directory moves cannot race with each other.
+static RENAME: Mutex<()> = Mutex::new(());
+
+/// The cookies of `.` and `..` are fixed, and the ones of real entries start
+/// right after them.
+const COOKIE_CUR: u64 = 1;
+const COOKIE_PARENT: u64 = 2;
+const COOKIE_START: u64 = 3;
 
-pub struct TmpFs(Arc<TmpRoot>);
+pub struct TmpFs(Arc<TmpDir>);
 
 impl TmpFs {
     pub fn new() -> Self {
-        TmpFs(Arc::new(TmpRoot(Default::default())))
+        TmpFs(TmpDir::new(
+            Weak::new(),
+            Permissions::all_same(true, true, true),
+        ))
     }
 }
 
@@ -40,71 +65,350 @@ impl FileSystem for TmpFs {
             block_size: PAGE_SIZE,
             block_count: 0,
             block_free: 0,
-            file_count: ksync::critical(|| self.0 .0.lock().len()),
+            file_count: self.0.count(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Attrs {
+    perm: Permissions,
+    uid: u32,
+    gid: u32,
+    times: Times,
+}
+
+impl Attrs {
+    fn new(perm: Permissions) -> Self {
+        let now = timestamp();
+        Attrs {
+            perm,
+            uid: 0,
+            gid: 0,
+            times: Times {
+                last_created: Some(now),
+                last_modified: Some(now),
+                last_access: Some(now),
+            },
+        }
+    }
+
+    fn set(&mut self, metadata: &SetMetadata) {
+        if let Some(perm) = metadata.perm {
+            self.perm = perm;
+        }
+        if let Some(uid) = metadata.uid {
+            self.uid = uid;
+        }
+        if let Some(gid) = metadata.gid {
+            self.gid = gid;
+        }
+        let times = &mut self.times;
+        if metadata.times.last_created.is_some() {
+            times.last_created = metadata.times.last_created;
+        }
+        if metadata.times.last_modified.is_some() {
+            times.last_modified = metadata.times.last_modified;
+        }
+        if metadata.times.last_access.is_some() {
+            times.last_access = metadata.times.last_access;
+        }
+    }
+
+    fn touch(&mut self) {
+        self.times.last_modified = Some(timestamp());
+    }
+}
+
+#[derive(Clone)]
+enum TmpNode {
+    Dir(Arc<TmpDir>),
+    File(Arc<TmpFile>),
+    Link(Arc<TmpLink>),
+}
+
+impl TmpNode {
+    fn is_dir(&self) -> bool {
+        matches!(self, TmpNode::Dir(_))
+    }
+
+    fn ptr_eq(&self, other: &TmpNode) -> bool {
+        match (self, other) {
+            (TmpNode::Dir(a), TmpNode::Dir(b)) => Arc::ptr_eq(a, b),
+            (TmpNode::File(a), TmpNode::File(b)) => Arc::ptr_eq(a, b),
+            (TmpNode::Link(a), TmpNode::Link(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// The hard link counter of non-directory nodes.
+    fn link_count(&self) -> Option<&AtomicUsize> {
+        match self {
+            TmpNode::Dir(_) => None,
+            TmpNode::File(file) => Some(&file.link_count),
+            TmpNode::Link(link) => Some(&link.link_count),
+        }
+    }
+
+    /// Called when the node is detached from one of its parent directories.
+    fn unlinked(&self) {
+        if let Some(link_count) = self.link_count() {
+            link_count.fetch_sub(1, SeqCst);
         }
     }
+
+    async fn metadata(&self) -> Metadata {
+        match self {
+            TmpNode::Dir(dir) => dir.metadata().await,
+            TmpNode::File(file) => file.metadata().await,
+            TmpNode::Link(link) => link.metadata().await,
+        }
+    }
+}
+
+struct DirInner {
+    parent: Weak<TmpDir>,
+    entries: BTreeMap<String, (u64, TmpNode)>,
+    cookies: BTreeMap<u64, String>,
+    next_cookie: u64,
+}
+
+impl DirInner {
+    fn get(&self, name: &str) -> Option<TmpNode> {
+        self.entries.get(name).map(|(_, node)| node.clone())
+    }
+
+    fn insert(&mut self, name: &str, node: TmpNode) -> Option<TmpNode> {
+        let cookie = self.next_cookie;
+        self.next_cookie += 1;
+        self.cookies.insert(cookie, name.to_string());
+        let old = self.entries.insert(name.to_string(), (cookie, node));
+        old.map(|(cookie, node)| {
+            self.cookies.remove(&cookie);
+            node
+        })
+    }
+
+    fn remove(&mut self, name: &str) -> Option<TmpNode> {
+        let (cookie, node) = self.entries.remove(name)?;
+        self.cookies.remove(&cookie);
+        Some(node)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
-struct TmpRoot(Mutex<HashMap<PathBuf, Arc<TmpFile>, RandomState>>);
+pub struct TmpDir {
+    this: Weak<TmpDir>,
+    ino: u64,
+    attrs: Mutex<Attrs>,
+    inner: Mutex<DirInner>,
+}
+
+impl TmpDir {
+    fn new(parent: Weak<TmpDir>, perm: Permissions) -> Arc<Self> {
+        Arc::new_cyclic(|this| TmpDir {
+            this: this.clone(),
+            ino: INO.fetch_add(1, Relaxed),
+            attrs: Mutex::new(Attrs::new(perm)),
+            inner: Mutex::new(DirInner {
+                parent,
+                entries: BTreeMap::new(),
+                cookies: BTreeMap::new(),
+                next_cookie: COOKIE_START,
+            }),
+        })
+    }
+
+    fn arc(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    fn parent(self: &Arc<Self>) -> Arc<Self> {
+        let parent = ksync::critical(|| self.inner.lock().parent.upgrade());
+        parent.unwrap_or_else(|| self.clone())
+    }
+
+    fn count(&self) -> usize {
+        let (files, dirs) = ksync::critical(|| {
+            let inner = self.inner.lock();
+            let dirs = (inner.entries.values())
+                .filter_map(|(_, node)| match node {
+                    TmpNode::Dir(dir) => Some(dir.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (inner.entries.len() - dirs.len(), dirs)
+        });
+        dirs.iter().fold(files + 1, |acc, dir| acc + dir.count())
+    }
+
+    /// Walks through all the components of `path` except the last one, and
+    /// returns the directory containing the last component, along with the
+    /// component itself.
+    fn walk<'a>(self: &Arc<Self>, path: &'a Path) -> Result<(Arc<Self>, Component<'a>), Error> {
+        let mut dir = self.clone();
+        let mut comps = path.components().peekable();
+        while let Some(comp) = comps.next() {
+            if comps.peek().is_none() {
+                return Ok((dir, comp));
+            }
+            dir = match comp {
+                Component::CurDir => dir,
+                Component::ParentDir => dir.parent(),
+                Component::Normal(name) => match ksync::critical(|| dir.inner.lock().get(name)) {
+                    Some(TmpNode::Dir(next)) => next,
+                    Some(_) => return Err(ENOTDIR),
+                    None => return Err(ENOENT),
+                },
+            }
+        }
+        Ok((dir, Component::CurDir))
+    }
+
+    /// Like `walk`, but requires the last component to be a normal name.
+    fn walk_name<'a>(self: &Arc<Self>, path: &'a Path) -> Result<(Arc<Self>, &'a str), Error> {
+        match self.walk(path)? {
+            (dir, Component::Normal(name)) => Ok((dir, name)),
+            _ => Err(EINVAL),
+        }
+    }
+
+    /// Checks if `self` is `dir` or one of its ancestors.
+    ///
+    /// Must be called with [`RENAME`] held.
+    fn is_ancestor_of(self: &Arc<Self>, dir: &Arc<Self>) -> bool {
+        let mut dir = dir.clone();
+        loop {
+            if Arc::ptr_eq(self, &dir) {
+                break true;
+            }
+            match ksync::critical(|| dir.inner.lock().parent.upgrade()) {
+                Some(parent) => dir = parent,
+                None => break false,
+            }
+        }
+    }
+
+    fn create(
+        self: &Arc<Self>,
+        name: &str,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(TmpNode, bool), Error> {
+        ksync::critical(|| {
+            let mut inner = self.inner.lock();
+            if let Some(node) = inner.get(name) {
+                if options.contains(OpenOptions::EXCL) {
+                    return Err(EEXIST);
+                }
+                return Ok((node, false));
+            }
+            let node = if options.contains(OpenOptions::DIRECTORY) {
+                TmpNode::Dir(TmpDir::new(Arc::downgrade(self), perm))
+            } else {
+                TmpNode::File(Arc::new(TmpFile {
+                    ino: INO.fetch_add(1, Relaxed),
+                    phys: Arc::new(Phys::new(false)),
+                    link_count: AtomicUsize::new(1),
+                    attrs: Mutex::new(Attrs::new(perm)),
+                }))
+            };
+            inner.insert(name, node.clone());
+            drop(inner);
+            self.attrs.lock().touch();
+            Ok((node, true))
+        })
+    }
+}
 
-impl ToIo for TmpRoot {}
+impl ToIo for TmpDir {}
 
 #[async_trait]
-impl Entry for TmpRoot {
+impl Entry for TmpDir {
     async fn open(
         self: Arc<Self>,
         path: &Path,
         options: OpenOptions,
         perm: Permissions,
     ) -> Result<(Arc<dyn Entry>, bool), Error> {
-        if path == "" {
-            if options.contains(OpenOptions::CREAT) {
-                return Err(EEXIST);
+        let (dir, comp) = self.walk(path)?;
+        let (node, created) = match comp {
+            Component::CurDir | Component::ParentDir => {
+                if options.contains(OpenOptions::CREAT | OpenOptions::EXCL) {
+                    return Err(EEXIST);
+                }
+                let dir = if comp == Component::ParentDir {
+                    dir.parent()
+                } else {
+                    dir
+                };
+                (TmpNode::Dir(dir), false)
             }
-            return Ok((self, false));
-        }
-
-        if options.contains(OpenOptions::DIRECTORY) {
-            return Err(ENOTDIR);
-        }
-        if options.contains(OpenOptions::CREAT) {
-            let file = Arc::new(TmpFile {
-                phys: Arc::new(Phys::new(false)),
-                perm,
-                times: Mutex::new({
-                    let now = Instant::now();
-                    Times {
-                        last_created: Some(now),
-                        last_modified: Some(now),
-                        last_access: Some(now),
+            Component::Normal(name) if options.contains(OpenOptions::CREAT) => {
+                dir.create(name, options, perm)?
+            }
+            Component::Normal(name) => {
+                let node = ksync::critical(|| dir.inner.lock().get(name));
+                (node.ok_or(ENOENT)?, false)
+            }
+        };
+        match node {
+            TmpNode::Dir(dir) => {
+                if !options.contains(OpenOptions::DIRECTORY) && options.contains(OpenOptions::CREAT)
+                {
+                    return Err(EISDIR);
+                }
+                Ok((dir, created))
+            }
+            TmpNode::File(file) => {
+                if options.contains(OpenOptions::DIRECTORY) {
+                    return Err(ENOTDIR);
+                }
+                if !created {
+                    let self_perm = ksync::critical(|| file.attrs.lock().perm);
+                    if !self_perm.contains(perm) {
+                        return Err(EPERM);
                     }
-                }),
-            });
-            ksync::critical(|| {
-                let mut list = self.0.lock();
-                if list.try_insert(path.to_path_buf(), file.clone()).is_err() {
-                    return Err(EEXIST);
                 }
-                Ok((file as _, true))
-            })
-        } else {
-            let file = ksync::critical(|| self.0.lock().get(path).cloned());
-            Ok((file.ok_or(ENOENT)?, false))
+                if options.contains(OpenOptions::TRUNC) {
+                    file.phys.resize(0);
+                    file.phys.seek(SeekFrom::Start(0)).await?;
+                }
+                Ok((file, created))
+            }
+            // Symbolic links are resolved by the VFS, so hand them out as is.
+            TmpNode::Link(link) => Ok((link, created)),
         }
     }
 
     async fn metadata(&self) -> Metadata {
+        let subdirs = ksync::critical(|| {
+            let inner = self.inner.lock();
+            inner.entries.values().filter(|(_, n)| n.is_dir()).count()
+        });
+        let attrs = ksync::critical(|| *self.attrs.lock());
         Metadata {
             ty: FileType::DIR,
             len: 0,
-            offset: rand_riscv::seed64(),
-            perm: Permissions::all_same(true, true, true),
+            offset: self.ino,
+            link_count: 2 + subdirs,
+            perm: attrs.perm,
+            uid: attrs.uid,
+            gid: attrs.gid,
             block_size: PAGE_SIZE,
             block_count: 0,
-            times: Default::default(),
+            times: attrs.times,
         }
     }
 
+    async fn set_metadata(&self, metadata: SetMetadata) -> Result<(), Error> {
+        ksync::critical(|| self.attrs.lock().set(&metadata));
+        Ok(())
+    }
+
     fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
         Some(self)
     }
@@ -113,48 +417,206 @@ impl Entry for TmpRoot {
         Some(self)
     }
 }
-impl IoPoll for TmpRoot {}
+impl IoPoll for TmpDir {}
 
 #[async_trait]
-impl Directory for TmpRoot {
-    async fn next_dirent(&self, _: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
-        todo!()
+impl Directory for TmpDir {
+    async fn next_dirent(&self, last: Option<&DirEntry>) -> Result<Option<DirEntry>, Error> {
+        let last = last.map_or(0, |last| last.metadata.offset);
+        let (cookie, name, node) = match last {
+            0 => (COOKIE_CUR, ".".into(), None),
+            COOKIE_CUR => (COOKIE_PARENT, "..".into(), None),
+            _ => {
+                let next = ksync::critical(|| {
+                    let inner = self.inner.lock();
+                    let (&cookie, name) = inner.cookies.range((last + 1)..).next()?;
+                    let (_, node) = inner.entries.get(name)?;
+                    Some((cookie, name.clone(), Some(node.clone())))
+                });
+                match next {
+                    Some(next) => next,
+                    None => return Ok(None),
+                }
+            }
+        };
+        let metadata = match node {
+            Some(node) => node.metadata().await,
+            None => self.metadata().await,
+        };
+        Ok(Some(DirEntry {
+            name,
+            metadata: Metadata {
+                offset: cookie,
+                ..metadata
+            },
+        }))
     }
 }
 
 #[async_trait]
-impl DirectoryMut for TmpRoot {
+impl DirectoryMut for TmpDir {
     async fn rename(
         self: Arc<Self>,
-        _: &Path,
-        _: Arc<dyn DirectoryMut>,
-        _: &Path,
+        src_path: &Path,
+        dst_parent: Arc<dyn DirectoryMut>,
+        dst_path: &Path,
     ) -> Result<(), Error> {
-        Err(ENOSYS)
+        let dst_parent = dst_parent.downcast::<Self>().ok_or(EXDEV)?;
+
+        let (src_dir, src_name) = self.walk_name(src_path)?;
+        let (dst_dir, dst_name) = dst_parent.walk_name(dst_path)?;
+
+        ksync::critical(|| {
+            let _guard = RENAME.lock();
+
+            let src = src_dir.inner.lock().get(src_name).ok_or(ENOENT)?;
+            if let TmpNode::Dir(ref dir) = src {
+                // A directory cannot be moved into its own subtree.
+                if dir.is_ancestor_of(&dst_dir) {
+                    return Err(EINVAL);
+                }
+            }
+
+            // Directories are always locked from ancestors to descendants, so
+            // lock unrelated directories in the order of their addresses to
+            // avoid deadlocks.
+            let src_first = src_dir.is_ancestor_of(&dst_dir)
+                || (!dst_dir.is_ancestor_of(&src_dir)
+                    && Arc::as_ptr(&src_dir) < Arc::as_ptr(&dst_dir));
+            let (mut src_inner, mut dst_inner) = if Arc::ptr_eq(&src_dir, &dst_dir) {
+                (src_dir.inner.lock(), None)
+            } else if src_first {
+                let src_inner = src_dir.inner.lock();
+                (src_inner, Some(dst_dir.inner.lock()))
+            } else {
+                let dst_inner = dst_dir.inner.lock();
+                (src_dir.inner.lock(), Some(dst_inner))
+            };
+
+            let dst = match dst_inner {
+                Some(ref dst_inner) => dst_inner.get(dst_name),
+                None => src_inner.get(dst_name),
+            };
+            if let Some(ref dst) = dst {
+                if dst.ptr_eq(&src) {
+                    return Ok(());
+                }
+                match (&src, dst) {
+                    (TmpNode::Dir(_), TmpNode::Dir(dst)) => {
+                        if !dst.inner.lock().is_empty() {
+                            return Err(ENOTEMPTY);
+                        }
+                    }
+                    (TmpNode::Dir(_), _) => return Err(ENOTDIR),
+                    (_, TmpNode::Dir(_)) => return Err(EISDIR),
+                    _ => {}
+                }
+            }
+
+            src_inner.remove(src_name);
+            let old = match dst_inner {
+                Some(ref mut dst_inner) => dst_inner.insert(dst_name, src.clone()),
+                None => src_inner.insert(dst_name, src.clone()),
+            };
+            if let Some(old) = old {
+                old.unlinked();
+            }
+            // The moved directory is a child of both locked ones, so locking
+            // it last keeps the order from ancestors to descendants.
+            if let TmpNode::Dir(ref dir) = src {
+                dir.inner.lock().parent = Arc::downgrade(&dst_dir);
+            }
+            drop((src_inner, dst_inner));
+
+            src_dir.attrs.lock().touch();
+            dst_dir.attrs.lock().touch();
+            Ok(())
+        })
     }
 
     async fn link(
         self: Arc<Self>,
-        _: &Path,
-        _: Arc<dyn DirectoryMut>,
-        _: &Path,
+        src_path: &Path,
+        dst_parent: Arc<dyn DirectoryMut>,
+        dst_path: &Path,
     ) -> Result<(), Error> {
-        Err(ENOSYS)
+        let dst_parent = dst_parent.downcast::<Self>().ok_or(EXDEV)?;
+
+        let (src_dir, src_name) = self.walk_name(src_path)?;
+        let (dst_dir, dst_name) = dst_parent.walk_name(dst_path)?;
+
+        let src = ksync::critical(|| src_dir.inner.lock().get(src_name));
+        let src = src.ok_or(ENOENT)?;
+        let link_count = src.link_count().ok_or(EPERM)?;
+
+        ksync::critical(|| {
+            let mut inner = dst_dir.inner.lock();
+            if inner.get(dst_name).is_some() {
+                return Err(EEXIST);
+            }
+            link_count.fetch_add(1, SeqCst);
+            inner.insert(dst_name, src.clone());
+            drop(inner);
+            dst_dir.attrs.lock().touch();
+            Ok(())
+        })
     }
 
-    async fn unlink(&self, _: &Path, _: Option<bool>) -> Result<(), Error> {
-        Ok(())
+    async fn unlink(&self, path: &Path, expect_dir: Option<bool>) -> Result<(), Error> {
+        let (dir, name) = self.arc().walk_name(path)?;
+
+        ksync::critical(|| {
+            let mut inner = dir.inner.lock();
+            let node = inner.get(name).ok_or(ENOENT)?;
+            match (&node, expect_dir) {
+                (TmpNode::Dir(_), Some(false)) => return Err(EISDIR),
+                (TmpNode::Dir(dir), _) => {
+                    if !dir.inner.lock().is_empty() {
+                        return Err(ENOTEMPTY);
+                    }
+                }
+                (_, Some(true)) => return Err(ENOTDIR),
+                _ => {}
+            }
+            inner.remove(name);
+            node.unlinked();
+            drop(inner);
+            dir.attrs.lock().touch();
+            Ok(())
+        })
+    }
+
+    async fn symlink(&self, path: &Path, target: &Path) -> Result<(), Error> {
+        let (dir, name) = self.arc().walk_name(path)?;
+        let link = Arc::new(TmpLink {
+            ino: INO.fetch_add(1, Relaxed),
+            target: target.to_path_buf(),
+            link_count: AtomicUsize::new(1),
+            attrs: Mutex::new(Attrs::new(Permissions::all_same(true, true, true))),
+        });
+
+        ksync::critical(|| {
+            let mut inner = dir.inner.lock();
+            if inner.get(name).is_some() {
+                return Err(EEXIST);
+            }
+            inner.insert(name, TmpNode::Link(link));
+            drop(inner);
+            dir.attrs.lock().touch();
+            Ok(())
+        })
     }
 }
 
 struct TmpFile {
+    ino: u64,
     phys: Arc<Phys>,
-    perm: Permissions,
-    times: Mutex<Times>,
+    link_count: AtomicUsize,
+    attrs: Mutex<Attrs>,
 }
 
 impl ToIo for TmpFile {
-    fn to_io(self: Arc<Self>) -> Option<Arc<dyn umifs::traits::Io>> {
+    fn to_io(self: Arc<Self>) -> Option<Arc<dyn Io>> {
         Some(self.phys.clone())
     }
 }
@@ -167,46 +629,86 @@ impl Entry for TmpFile {
         options: OpenOptions,
         perm: Permissions,
     ) -> Result<(Arc<dyn Entry>, bool), Error> {
-        if !path.as_str().is_empty() || options.contains(OpenOptions::DIRECTORY) {
-            return Err(ENOTDIR);
+        let self_perm = ksync::critical(|| self.attrs.lock().perm);
+        umifs::misc::open_file(self, path, options, perm, self_perm).await
+    }
+
+    async fn metadata(&self) -> Metadata {
+        let attrs = ksync::critical(|| *self.attrs.lock());
+        let len = self.phys.stream_len().await.unwrap();
+        Metadata {
+            ty: FileType::FILE,
+            len,
+            offset: self.ino,
+            link_count: self.link_count.load(SeqCst),
+            perm: attrs.perm,
+            uid: attrs.uid,
+            gid: attrs.gid,
+            block_size: PAGE_SIZE,
+            block_count: (len + PAGE_SIZE - 1) / PAGE_SIZE,
+            times: attrs.times,
         }
-        if options.contains(OpenOptions::CREAT) {
-            return Err(EEXIST);
+    }
+
+    async fn set_metadata(&self, metadata: SetMetadata) -> Result<(), Error> {
+        if let Some(len) = metadata.len {
+            self.phys.resize(len);
         }
-        if !self.perm.contains(perm) {
-            return Err(EPERM);
+        ksync::critical(|| self.attrs.lock().set(&metadata));
+        Ok(())
+    }
+}
+
+impl IoPoll for TmpFile {}
+
+struct TmpLink {
+    ino: u64,
+    target: PathBuf,
+    link_count: AtomicUsize,
+    attrs: Mutex<Attrs>,
+}
+
+impl ToIo for TmpLink {}
+
+#[async_trait]
+impl Entry for TmpLink {
+    async fn open(
+        self: Arc<Self>,
+        path: &Path,
+        _: OpenOptions,
+        _: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        if path == "" || path == "." {
+            Ok((self, false))
+        } else {
+            Err(ENOTDIR)
         }
-        Ok((self, false))
     }
 
     async fn metadata(&self) -> Metadata {
-        let times = ksync::critical(|| *self.times.lock());
+        let attrs = ksync::critical(|| *self.attrs.lock());
         Metadata {
-            ty: FileType::FILE,
-            len: self.phys.stream_len().await.unwrap(),
-            offset: u64::MAX,
-            perm: self.perm,
+            ty: FileType::LNK,
+            len: self.target.as_str().len(),
+            offset: self.ino,
+            link_count: self.link_count.load(SeqCst),
+            perm: attrs.perm,
+            uid: attrs.uid,
+            gid: attrs.gid,
             block_size: PAGE_SIZE,
             block_count: 0,
-            times,
+            times: attrs.times,
         }
     }
 
     async fn set_metadata(&self, metadata: SetMetadata) -> Result<(), Error> {
-        ksync::critical(|| {
-            let mut times = self.times.lock();
-            if metadata.times.last_created.is_some() {
-                times.last_created = metadata.times.last_created;
-            }
-            if metadata.times.last_modified.is_some() {
-                times.last_modified = metadata.times.last_modified;
-            }
-            if metadata.times.last_access.is_some() {
-                times.last_access = metadata.times.last_access;
-            }
-        });
+        ksync::critical(|| self.attrs.lock().set(&metadata));
         Ok(())
     }
+
+    async fn read_link(&self) -> Result<PathBuf, Error> {
+        Ok(self.target.clone())
+    }
 }
 
-impl IoPoll for TmpFile {}
+impl IoPoll for TmpLink {}
@@ -0,0 +1,529 @@
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+    time::Duration,
+};
+
+use ksync::event::Event;
+use ktime::TimeOutExt;
+use spin::Mutex;
+use sygnal::Sig;
+use umifs::traits::Entry;
+use umio::{IntoAnyExt, IoSlice, IoSliceMut, Watcher, Watchers};
+
+use super::{
+    pty,
+    serial::{console, Serial},
+};
+
+const NCCS: usize = 19;
+
+// Indices of `Termios::cc`.
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VTIME: usize = 5;
+const VMIN: usize = 6;
+const VSUSP: usize = 10;
+const VEOL: usize = 11;
+const VWERASE: usize = 14;
+const VEOL2: usize = 16;
+
+// Bits of `Termios::iflag`.
+const ISTRIP: u32 = 0o40;
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+const IXON: u32 = 0o2000;
+const IUTF8: u32 = 0o40000;
+
+// Bits of `Termios::oflag`.
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+
+// Bits of `Termios::cflag`.
+const B38400: u32 = 0o17;
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+
+// Bits of `Termios::lflag`.
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHOK: u32 = 0o40;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const ECHOCTL: u32 = 0o1000;
+const ECHOKE: u32 = 0o4000;
+const IEXTEN: u32 = 0o100000;
+
+/// The terminal attributes, in the layout of the kernel's `struct termios`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        Termios {
+            iflag: ICRNL | IXON | IUTF8,
+            // Output processing stays off, so that the console receives
+            // exactly what user programs write, as it always has.
+            oflag: 0,
+            cflag: B38400 | CS8 | CREAD,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            line: 0,
+            cc: *b"\x03\x1c\x7f\x15\x04\0\x01\0\x11\x13\x1a\0\x12\x0f\x17\x16\0",
+        }
+    }
+}
+
+/// The window size, in the layout of `struct winsize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+impl Default for WinSize {
+    fn default() -> Self {
+        WinSize {
+            row: 24,
+            col: 80,
+            xpixel: 0,
+            ypixel: 0,
+        }
+    }
+}
+
+/// The line discipline.
+#[derive(Default)]
+struct Ldisc {
+    termios: Termios,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+    /// The input ready for reading.
+    input: VecDeque<u8>,
+    /// The lengths of the complete lines at the front of `input`, in
+    /// canonical mode.
+    lines: VecDeque<usize>,
+}
+
+impl Ldisc {
+    fn lflag(&self, flag: u32) -> bool {
+        self.termios.lflag & flag != 0
+    }
+
+    /// Whether `b` is the control character `index`, which is disabled by 0.
+    fn is_cc(&self, b: u8, index: usize) -> bool {
+        b != 0 && self.termios.cc[index] == b
+    }
+
+    fn is_ctl(b: u8) -> bool {
+        (b < b' ' && b != b'\n' && b != b'\t') || b == 0x7f
+    }
+
+    fn post(&self, b: u8, out: &mut Vec<u8>) {
+        let oflag = self.termios.oflag;
+        if b == b'\n' && oflag & OPOST != 0 && oflag & ONLCR != 0 {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+
+    fn echo(&self, b: u8, out: &mut Vec<u8>) {
+        if self.lflag(ECHO) {
+            if self.lflag(ECHOCTL) && Self::is_ctl(b) {
+                out.extend([b'^', b ^ 0x40]);
+            } else {
+                self.post(b, out);
+            }
+        } else if b == b'\n' && self.lflag(ECHONL) && self.lflag(ICANON) {
+            self.post(b, out);
+        }
+    }
+
+    /// Removes the last character of the line and erases its echo.
+    fn erase(&mut self, out: &mut Vec<u8>) -> Option<u8> {
+        let b = self.line.pop()?;
+        if self.lflag(ECHO) && self.lflag(ECHOE) {
+            let width = match self.lflag(ECHOCTL) && Self::is_ctl(b) {
+                true => 2,
+                false => 1,
+            };
+            (0..width).for_each(|_| out.extend(*b"\x08 \x08"));
+        }
+        Some(b)
+    }
+
+    fn commit(&mut self) {
+        self.lines.push_back(self.line.len());
+        self.input.extend(self.line.drain(..));
+    }
+
+    fn flush(&mut self) {
+        self.line.clear();
+        self.input.clear();
+        self.lines.clear();
+    }
+
+    fn set_termios(&mut self, termios: Termios) {
+        let was_canonical = self.lflag(ICANON);
+        self.termios = termios;
+        match (was_canonical, self.lflag(ICANON)) {
+            // Pending input becomes plain bytes.
+            (true, false) => {
+                self.input.extend(self.line.drain(..));
+                self.lines.clear();
+            }
+            // Pending input becomes a line, which can be read right away.
+            (false, true) if !self.input.is_empty() => self.lines.push_back(self.input.len()),
+            _ => {}
+        }
+    }
+
+    /// Processes an input byte, putting the echo to `out` and returning the
+    /// signal to generate, if any.
+    fn receive(&mut self, mut b: u8, out: &mut Vec<u8>) -> Option<Sig> {
+        let iflag = self.termios.iflag;
+        if iflag & ISTRIP != 0 {
+            b &= 0x7f;
+        }
+        match b {
+            b'\r' if iflag & IGNCR != 0 => return None,
+            b'\r' if iflag & ICRNL != 0 => b = b'\n',
+            b'\n' if iflag & INLCR != 0 => b = b'\r',
+            _ => {}
+        }
+
+        if self.lflag(ISIG) {
+            let sig = if self.is_cc(b, VINTR) {
+                Some(Sig::SIGINT)
+            } else if self.is_cc(b, VQUIT) {
+                Some(Sig::SIGQUIT)
+            } else if self.is_cc(b, VSUSP) {
+                Some(Sig::SIGTSTP)
+            } else {
+                None
+            };
+            if sig.is_some() {
+                if !self.lflag(NOFLSH) {
+                    self.flush();
+                }
+                self.echo(b, out);
+                return sig;
+            }
+        }
+
+        if !self.lflag(ICANON) {
+            self.echo(b, out);
+            self.input.push_back(b);
+            return None;
+        }
+
+        if self.is_cc(b, VERASE) {
+            self.erase(out);
+        } else if self.is_cc(b, VWERASE) && self.lflag(IEXTEN) {
+            while self.line.last() == Some(&b' ') {
+                self.erase(out);
+            }
+            while self.line.last().is_some_and(|&b| b != b' ') {
+                self.erase(out);
+            }
+        } else if self.is_cc(b, VKILL) {
+            while self.erase(out).is_some() {}
+        } else if self.is_cc(b, VEOF) {
+            self.commit();
+        } else {
+            self.echo(b, out);
+            self.line.push(b);
+            if b == b'\n' || self.is_cc(b, VEOL) || self.is_cc(b, VEOL2) {
+                self.commit();
+            }
+        }
+        None
+    }
+
+    /// Reads the input if enough of it is ready.
+    fn read(&mut self, buffer: &mut [IoSliceMut]) -> Option<usize> {
+        let capacity = buffer.iter().map(|buf| buf.len()).sum::<usize>();
+        let len = if self.lflag(ICANON) {
+            let line = self.lines.front_mut()?;
+            let len = (*line).min(capacity);
+            *line -= len;
+            // An empty line comes from `VEOF`, and reads as the end of file.
+            if *line == 0 && capacity > 0 {
+                self.lines.pop_front();
+            }
+            len
+        } else {
+            let min = usize::from(self.termios.cc[VMIN]).clamp(1, capacity.max(1));
+            if self.input.len() < min {
+                return None;
+            }
+            self.input.len().min(capacity)
+        };
+        let dst = buffer.iter_mut().flat_map(|buf| buf.iter_mut());
+        dst.zip(self.input.drain(..len))
+            .for_each(|(dst, b)| *dst = b);
+        Some(len)
+    }
+
+    fn readable(&self) -> bool {
+        match self.lflag(ICANON) {
+            true => !self.lines.is_empty(),
+            false => !self.input.is_empty(),
+        }
+    }
+}
+
+/// A terminal, which runs its input through the line discipline and sends
+/// its output to `output`.
+pub struct Tty {
+    ldisc: Mutex<Ldisc>,
+    winsize: Mutex<WinSize>,
+    input_ready: Event,
+    watchers: Watchers,
+    output: Box<dyn Fn(&[u8]) + Send + Sync>,
+    hung_up: AtomicBool,
+
+    /// The session which the terminal controls, or 0 for none.
+    session: AtomicUsize,
+    /// The foreground process group, or 0 for none.
+    foreground: AtomicUsize,
+}
+
+impl Tty {
+    pub fn new(output: impl Fn(&[u8]) + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Tty {
+            ldisc: Default::default(),
+            winsize: Default::default(),
+            input_ready: Default::default(),
+            watchers: Watchers::new(),
+            output: Box::new(output),
+            hung_up: Default::default(),
+            session: Default::default(),
+            foreground: Default::default(),
+        })
+    }
+
+    /// Feeds the input from the device into the terminal.
+    pub fn receive(&self, input: &[u8]) {
+        let mut echo = Vec::new();
+        let (sigs, readable) = ksync::critical(|| {
+            let mut ldisc = self.ldisc.lock();
+            let iter = input.iter().map(|&b| ldisc.receive(b, &mut echo));
+            let sigs = iter.flatten().collect::<Vec<_>>();
+            (sigs, ldisc.readable())
+        });
+        if !echo.is_empty() {
+            (self.output)(&echo);
+        }
+        let foreground = self.foreground();
+        if foreground != 0 {
+            sigs.into_iter()
+                .for_each(|sig| crate::task::kill_group(foreground, sig));
+        }
+        if readable {
+            self.wake();
+        }
+    }
+
+    fn wake(&self) {
+        self.input_ready.notify(usize::MAX);
+        self.watchers
+            .notify(umio::Event::READABLE | umio::Event::HANG_UP);
+    }
+
+    async fn wait_read(&self, buffer: &mut [IoSliceMut<'_>]) -> usize {
+        let mut listener = None;
+        loop {
+            if let Some(len) = ksync::critical(|| self.ldisc.lock().read(buffer)) {
+                break len;
+            }
+            if self.is_hung_up() {
+                break 0;
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(self.input_ready.listen()),
+            }
+        }
+    }
+
+    pub async fn read(&self, buffer: &mut [IoSliceMut<'_>]) -> usize {
+        let termios = self.termios();
+        let (vmin, vtime) = (termios.cc[VMIN], termios.cc[VTIME]);
+        if termios.lflag & ICANON != 0 || vmin > 0 {
+            return self.wait_read(buffer).await;
+        }
+        // With `VMIN == 0`, reads return after `VTIME` deciseconds at most.
+        match vtime {
+            0 => ksync::critical(|| self.ldisc.lock().read(buffer)).unwrap_or(0),
+            _ => {
+                let timeout = Duration::from_millis(u64::from(vtime) * 100);
+                self.wait_read(buffer).on_timeout(timeout, || 0).await
+            }
+        }
+    }
+
+    pub fn write(&self, buffer: &mut [IoSlice]) -> usize {
+        let oflag = self.termios().oflag;
+        if oflag & OPOST == 0 || oflag & ONLCR == 0 {
+            return buffer.iter().fold(0, |acc, buf| {
+                (self.output)(buf);
+                acc + buf.len()
+            });
+        }
+        let mut out = Vec::new();
+        let len = buffer.iter().fold(0, |acc, buf| {
+            buf.iter().for_each(|&b| {
+                if b == b'\n' {
+                    out.push(b'\r');
+                }
+                out.push(b)
+            });
+            acc + buf.len()
+        });
+        (self.output)(&out);
+        len
+    }
+
+    /// Returns the current input events of the terminal without waiting.
+    pub fn poll_event(&self) -> umio::Event {
+        if ksync::critical(|| self.ldisc.lock().readable()) {
+            umio::Event::READABLE
+        } else if self.is_hung_up() {
+            umio::Event::HANG_UP
+        } else {
+            umio::Event::empty()
+        }
+    }
+
+    pub async fn event(&self) -> umio::Event {
+        let mut listener = None;
+        loop {
+            let events = self.poll_event();
+            if !events.is_empty() {
+                break events;
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(self.input_ready.listen()),
+            }
+        }
+    }
+
+    /// Registers `watcher` to be notified whenever the result of
+    /// [`Tty::poll_event`] may change.
+    pub fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) {
+        self.watchers.push(watcher, exclusive)
+    }
+
+    pub fn is_hung_up(&self) -> bool {
+        self.hung_up.load(SeqCst)
+    }
+
+    /// Disconnects the terminal from its device, after which reads return
+    /// the end of file once the pending input is consumed.
+    pub fn hang_up(&self) {
+        self.hung_up.store(true, SeqCst);
+        self.wake();
+        let foreground = self.foreground();
+        if foreground != 0 {
+            crate::task::kill_group(foreground, Sig::SIGHUP);
+        }
+    }
+
+    /// Returns the number of bytes ready for reading.
+    pub fn pending(&self) -> usize {
+        ksync::critical(|| {
+            let ldisc = self.ldisc.lock();
+            match ldisc.lflag(ICANON) {
+                true => ldisc.lines.iter().sum(),
+                false => ldisc.input.len(),
+            }
+        })
+    }
+
+    pub fn flush_input(&self) {
+        ksync::critical(|| self.ldisc.lock().flush())
+    }
+
+    pub fn termios(&self) -> Termios {
+        ksync::critical(|| self.ldisc.lock().termios)
+    }
+
+    pub fn set_termios(&self, termios: Termios, flush: bool) {
+        ksync::critical(|| {
+            let mut ldisc = self.ldisc.lock();
+            if flush {
+                ldisc.flush();
+            }
+            ldisc.set_termios(termios)
+        });
+        // Leaving canonical mode may make the pending input readable.
+        self.wake();
+    }
+
+    pub fn winsize(&self) -> WinSize {
+        ksync::critical(|| *self.winsize.lock())
+    }
+
+    pub fn set_winsize(&self, winsize: WinSize) {
+        let old = ksync::critical(|| core::mem::replace(&mut *self.winsize.lock(), winsize));
+        let foreground = self.foreground();
+        if old != winsize && foreground != 0 {
+            crate::task::kill_group(foreground, Sig::SIGWINCH);
+        }
+    }
+
+    pub fn session(&self) -> usize {
+        self.session.load(SeqCst)
+    }
+
+    pub fn foreground(&self) -> usize {
+        self.foreground.load(SeqCst)
+    }
+
+    pub fn set_foreground(&self, pgid: usize) {
+        self.foreground.store(pgid, SeqCst)
+    }
+
+    /// Makes the terminal control the session `sid`, with `pgid` in the
+    /// foreground.
+    pub fn attach(&self, sid: usize, pgid: usize) {
+        self.session.store(sid, SeqCst);
+        self.foreground.store(pgid, SeqCst);
+    }
+
+    pub fn detach(&self) {
+        self.attach(0, 0)
+    }
+}
+
+/// Returns the terminal of an opened file, if it is one.
+pub fn tty_of(entry: &Arc<dyn Entry>) -> Option<Arc<Tty>> {
+    let entry = super::inner(entry);
+    match entry.clone().downcast::<Serial>() {
+        Some(_) => Some(console().clone()),
+        None => pty::tty_of(&entry),
+    }
+}
@@ -0,0 +1,666 @@
+//! Unix domain sockets, with the addresses either in the abstract namespace or
+//! bound to files.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    mem,
+    pin::pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+};
+
+use async_trait::async_trait;
+use futures_util::future::select;
+use ksc::Error::{
+    self, EADDRINUSE, EAGAIN, ECONNREFUSED, EINVAL, EISCONN, EMSGSIZE, ENOSYS, ENOTCONN,
+    EOPNOTSUPP, EPIPE, EPROTOTYPE, ESPIPE,
+};
+use ksync::event::Event;
+use spin::Mutex;
+use umifs::{
+    path::{Path, PathBuf},
+    traits::Entry,
+    types::{FileType, Metadata, OpenOptions, Permissions},
+};
+use umio::{Io, IoPoll, IoSlice, IoSliceMut, SeekFrom, Watcher, Watchers};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+use crate::task::fd::FdInfo;
+
+/// The maximum number of the bytes queued in a socket.
+pub const BUFFER_CAP: usize = 212992;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Stream,
+    Dgram,
+    SeqPacket,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnixAddr {
+    Unnamed,
+    /// A name in the abstract namespace, without the leading NUL.
+    Abstract(Vec<u8>),
+    /// The normalized path of the file the socket is bound to, relative to the
+    /// root.
+    Path(PathBuf),
+}
+
+/// The credentials of a process, as `struct ucred`.
+#[derive(Debug, Clone, Copy, Default, FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct Ucred {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// A datagram, or a segment of a stream.
+struct Message {
+    data: Vec<u8>,
+    /// The offset of the unread data.
+    start: usize,
+    /// The file descriptors passed along with the first byte of the data.
+    fds: Vec<FdInfo>,
+    from: UnixAddr,
+}
+
+enum State {
+    Idle,
+    Listening {
+        backlog: usize,
+        pending: VecDeque<Arc<UnixSocket>>,
+    },
+    /// Connected to a peer, or only with a default destination for datagrams.
+    Connected {
+        peer: Weak<UnixSocket>,
+        cred: Ucred,
+    },
+}
+
+struct Inner {
+    addr: UnixAddr,
+    state: State,
+    queue: VecDeque<Message>,
+    /// The number of the bytes in the queue.
+    queued: usize,
+}
+
+pub struct UnixSocket {
+    ty: Type,
+    /// The credentials of the owner, as seen by the peers.
+    cred: Ucred,
+    inner: Mutex<Inner>,
+    shut_read: AtomicBool,
+    shut_write: AtomicBool,
+    /// Notified on every change of the state, including the arrival and the
+    /// consumption of the messages.
+    event: Event,
+    watchers: Watchers,
+}
+
+/// The sockets with addresses, which are only upgraded outside the lock so
+/// that none of them is dropped with the lock held.
+static NAMES: Mutex<BTreeMap<UnixAddr, Weak<UnixSocket>>> = Mutex::new(BTreeMap::new());
+
+fn lookup(addr: &UnixAddr) -> Result<Arc<UnixSocket>, Error> {
+    let socket = ksync::critical(|| NAMES.lock().get(addr).cloned());
+    socket.and_then(|s| s.upgrade()).ok_or(ECONNREFUSED)
+}
+
+/// The received data of a socket.
+#[derive(Debug, Default)]
+pub struct Received {
+    pub len: usize,
+    /// The rest of the datagram is discarded.
+    pub truncated: bool,
+    pub fds: Vec<FdInfo>,
+    pub from: Option<UnixAddr>,
+}
+
+impl UnixSocket {
+    pub fn new(ty: Type, cred: Ucred) -> Arc<Self> {
+        Arc::new(UnixSocket {
+            ty,
+            cred,
+            inner: Mutex::new(Inner {
+                addr: UnixAddr::Unnamed,
+                state: State::Idle,
+                queue: VecDeque::new(),
+                queued: 0,
+            }),
+            shut_read: AtomicBool::new(false),
+            shut_write: AtomicBool::new(false),
+            event: Event::new(),
+            watchers: Watchers::new(),
+        })
+    }
+
+    /// Creates a pair of connected sockets.
+    pub fn pair(ty: Type, cred: Ucred) -> (Arc<Self>, Arc<Self>) {
+        let (a, b) = (Self::new(ty, cred), Self::new(ty, cred));
+        let connect = |this: &UnixSocket, peer: &Arc<UnixSocket>| {
+            let peer = Arc::downgrade(peer);
+            ksync::critical(|| this.inner.lock().state = State::Connected { peer, cred })
+        };
+        connect(&a, &b);
+        connect(&b, &a);
+        (a, b)
+    }
+
+    pub fn ty(&self) -> Type {
+        self.ty
+    }
+
+    pub fn local_addr(&self) -> UnixAddr {
+        ksync::critical(|| self.inner.lock().addr.clone())
+    }
+
+    fn peer(&self) -> Option<Weak<UnixSocket>> {
+        ksync::critical(|| match &self.inner.lock().state {
+            State::Connected { peer, .. } => Some(peer.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn peer_addr(&self) -> Result<UnixAddr, Error> {
+        let peer = self.peer().ok_or(ENOTCONN)?;
+        Ok(peer.upgrade().map_or(UnixAddr::Unnamed, |p| p.local_addr()))
+    }
+
+    /// Returns the credentials of the peer when connected.
+    pub fn peer_cred(&self) -> Result<Ucred, Error> {
+        ksync::critical(|| match self.inner.lock().state {
+            State::Connected { cred, .. } => Ok(cred),
+            _ => Err(ENOTCONN),
+        })
+    }
+
+    /// Binds the socket to `addr`, or to a unique abstract name if unnamed.
+    ///
+    /// Files of the bound paths should be created exclusively beforehand, so
+    /// the stale sockets of the unlinked ones are replaced.
+    pub fn bind(self: &Arc<Self>, addr: UnixAddr) -> Result<(), Error> {
+        static AUTOBIND: AtomicUsize = AtomicUsize::new(0);
+
+        ksync::critical(|| {
+            let mut inner = self.inner.lock();
+            if inner.addr != UnixAddr::Unnamed {
+                return Err(EINVAL);
+            }
+            let mut names = NAMES.lock();
+            let addr = match addr {
+                UnixAddr::Unnamed => loop {
+                    let id = AUTOBIND.fetch_add(1, SeqCst) & 0xfffff;
+                    let addr = UnixAddr::Abstract(format!("{id:05x}").into_bytes());
+                    if names.get(&addr).map_or(true, |s| s.strong_count() == 0) {
+                        break addr;
+                    }
+                },
+                UnixAddr::Abstract(_) => {
+                    if names.get(&addr).map_or(false, |s| s.strong_count() > 0) {
+                        return Err(EADDRINUSE);
+                    }
+                    addr
+                }
+                UnixAddr::Path(_) => addr,
+            };
+            names.insert(addr.clone(), Arc::downgrade(self));
+            inner.addr = addr;
+            Ok(())
+        })
+    }
+
+    pub fn listen(self: &Arc<Self>, backlog: usize) -> Result<(), Error> {
+        if self.ty == Type::Dgram {
+            return Err(EOPNOTSUPP);
+        }
+        if self.local_addr() == UnixAddr::Unnamed {
+            self.bind(UnixAddr::Unnamed)?;
+        }
+        ksync::critical(|| {
+            let mut inner = self.inner.lock();
+            match &mut inner.state {
+                State::Idle => {
+                    inner.state = State::Listening {
+                        backlog: backlog.max(1),
+                        pending: VecDeque::new(),
+                    }
+                }
+                State::Listening { backlog: old, .. } => *old = backlog.max(1),
+                State::Connected { .. } => return Err(EINVAL),
+            }
+            Ok(())
+        })
+    }
+
+    pub async fn connect(self: &Arc<Self>, addr: &UnixAddr, nonblock: bool) -> Result<(), Error> {
+        let target = lookup(addr)?;
+        if target.ty != self.ty {
+            return Err(EPROTOTYPE);
+        }
+        if self.ty == Type::Dgram {
+            let peer = Arc::downgrade(&target);
+            let cred = target.cred;
+            ksync::critical(|| self.inner.lock().state = State::Connected { peer, cred });
+            return Ok(());
+        }
+        if ksync::critical(|| !matches!(self.inner.lock().state, State::Idle)) {
+            return Err(EISCONN);
+        }
+
+        let server = Self::new(self.ty, target.cred);
+        let mut listener = None;
+        loop {
+            let accepted = ksync::critical(|| {
+                let mut inner = target.inner.lock();
+                let addr = inner.addr.clone();
+                let State::Listening { backlog, pending } = &mut inner.state else {
+                    return Err(ECONNREFUSED);
+                };
+                if pending.len() >= *backlog {
+                    return Ok(false);
+                }
+                let mut si = server.inner.lock();
+                si.addr = addr;
+                si.state = State::Connected {
+                    peer: Arc::downgrade(self),
+                    cred: self.cred,
+                };
+                drop(si);
+                pending.push_back(server.clone());
+                Ok(true)
+            })?;
+            if accepted {
+                break;
+            }
+            if nonblock {
+                return Err(EAGAIN);
+            }
+            match listener.take() {
+                Some(l) => l.await,
+                None => listener = Some(target.event.listen()),
+            }
+        }
+        let peer = Arc::downgrade(&server);
+        let cred = target.cred;
+        ksync::critical(|| self.inner.lock().state = State::Connected { peer, cred });
+        target.wake();
+        Ok(())
+    }
+
+    pub async fn accept(&self, nonblock: bool) -> Result<Arc<Self>, Error> {
+        let mut listener = None;
+        loop {
+            let socket = ksync::critical(|| match &mut self.inner.lock().state {
+                State::Listening { pending, .. } => Ok(pending.pop_front()),
+                _ => Err(EINVAL),
+            })?;
+            if let Some(socket) = socket {
+                // Let the blocked connectors know about the free slot.
+                self.wake();
+                break Ok(socket);
+            }
+            if nonblock {
+                break Err(EAGAIN);
+            }
+            match listener.take() {
+                Some(l) => l.await,
+                None => listener = Some(self.event.listen()),
+            }
+        }
+    }
+
+    /// Sends the data to the connected peer, or to `to` for datagrams, with
+    /// the file descriptors passed along.
+    pub async fn send(
+        &self,
+        buffer: &[IoSlice<'_>],
+        mut fds: Vec<FdInfo>,
+        to: Option<&UnixAddr>,
+        nonblock: bool,
+    ) -> Result<usize, Error> {
+        if self.shut_write.load(SeqCst) {
+            return Err(EPIPE);
+        }
+        let peer = match (self.ty, to) {
+            (Type::Dgram, Some(to)) => lookup(to)?,
+            (_, Some(_)) if self.peer().is_some() => return Err(EISCONN),
+            (_, Some(_)) => return Err(EOPNOTSUPP),
+            (ty, None) => match self.peer().map(|peer| peer.upgrade()) {
+                Some(Some(peer)) => peer,
+                Some(None) if ty == Type::Dgram => return Err(ECONNREFUSED),
+                Some(None) => return Err(EPIPE),
+                None => return Err(ENOTCONN),
+            },
+        };
+        if peer.ty != self.ty {
+            return Err(EPROTOTYPE);
+        }
+        let from = self.local_addr();
+
+        let data = buffer.concat();
+        if self.ty != Type::Stream && data.len() > BUFFER_CAP {
+            return Err(EMSGSIZE);
+        }
+
+        let mut sent = 0;
+        let mut listener = None;
+        loop {
+            let res = ksync::critical(|| {
+                if peer.shut_read.load(SeqCst) {
+                    return Err(EPIPE);
+                }
+                let mut inner = peer.inner.lock();
+                let room = BUFFER_CAP.saturating_sub(inner.queued);
+                let len = match self.ty {
+                    Type::Stream => room.min(data.len() - sent),
+                    _ if room >= data.len() || inner.queue.is_empty() => data.len(),
+                    _ => 0,
+                };
+                if len == 0 && !(data.is_empty() && self.ty != Type::Stream) {
+                    return Ok(0);
+                }
+                inner.queued += len;
+                inner.queue.push_back(Message {
+                    data: data[sent..][..len].to_vec(),
+                    start: 0,
+                    fds: mem::take(&mut fds),
+                    from: from.clone(),
+                });
+                Ok(len)
+            });
+            match res {
+                Ok(0) if !data.is_empty() => {}
+                Ok(len) => {
+                    peer.wake();
+                    sent += len;
+                    if sent == data.len() {
+                        break Ok(sent);
+                    }
+                    continue;
+                }
+                Err(_) if sent != 0 => break Ok(sent),
+                Err(err) => break Err(err),
+            }
+            if nonblock {
+                break if sent != 0 { Ok(sent) } else { Err(EAGAIN) };
+            }
+            match listener.take() {
+                Some(l) => l.await,
+                None => listener = Some(peer.event.listen()),
+            }
+        }
+    }
+
+    /// Returns whether the peer is gone or has shut down writing, after which
+    /// no more data arrives.
+    fn is_eof(&self, inner: &Inner) -> bool {
+        if self.shut_read.load(SeqCst) {
+            return true;
+        }
+        match &inner.state {
+            State::Connected { peer, .. } if self.ty != Type::Dgram => match peer.upgrade() {
+                Some(peer) => peer.shut_write.load(SeqCst),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+
+    /// Receives a datagram, or as much data as possible from the stream
+    /// without crossing a segment with file descriptors.
+    pub async fn receive(
+        &self,
+        mut buffer: &mut [IoSliceMut<'_>],
+        nonblock: bool,
+    ) -> Result<Received, Error> {
+        if self.ty != Type::Dgram && self.peer().is_none() {
+            return Err(ENOTCONN);
+        }
+        let mut listener = None;
+        loop {
+            let received = ksync::critical(|| {
+                let mut inner = self.inner.lock();
+                let inner = &mut *inner;
+                let mut ret = Received::default();
+                while let Some(message) = inner.queue.front_mut() {
+                    if ret.len != 0 && !message.fds.is_empty() {
+                        break;
+                    }
+                    let mut data = &message.data[message.start..];
+                    let len = copy(&mut data, &mut buffer);
+                    ret.len += len;
+                    ret.fds.append(&mut message.fds);
+                    ret.from = Some(message.from.clone());
+
+                    if self.ty == Type::Stream {
+                        message.start += len;
+                        inner.queued -= len;
+                        if !data.is_empty() {
+                            break;
+                        }
+                        inner.queue.pop_front();
+                    } else {
+                        ret.truncated = !data.is_empty();
+                        inner.queued -= message.data.len();
+                        inner.queue.pop_front();
+                        break;
+                    }
+                }
+                match ret.from {
+                    Some(_) => Some(ret),
+                    None if self.is_eof(inner) => Some(ret),
+                    None => None,
+                }
+            });
+            if let Some(received) = received {
+                // Let the blocked senders know about the free space.
+                self.wake();
+                if let Some(peer) = self.peer().and_then(|p| p.upgrade()) {
+                    peer.watchers.notify(umio::Event::WRITABLE);
+                }
+                break Ok(received);
+            }
+            if nonblock {
+                break Err(EAGAIN);
+            }
+            match listener.take() {
+                Some(l) => l.await,
+                None => listener = Some(self.event.listen()),
+            }
+        }
+    }
+
+    pub fn shutdown(&self, read: bool, write: bool) {
+        if read {
+            self.shut_read.store(true, SeqCst);
+        }
+        if write {
+            self.shut_write.store(true, SeqCst);
+        }
+        self.wake();
+        if let Some(peer) = self.peer().and_then(|p| p.upgrade()) {
+            peer.wake();
+        }
+    }
+
+    fn wake(&self) {
+        self.event.notify(usize::MAX);
+        self.watchers.notify(umio::Event::all());
+    }
+
+    /// Returns the current events of the socket, and the peer to wait for if
+    /// writing is expected.
+    fn readiness(&self) -> (umio::Event, Option<Arc<UnixSocket>>) {
+        let mut events = umio::Event::empty();
+        let peer = ksync::critical(|| {
+            let inner = self.inner.lock();
+            match &inner.state {
+                State::Listening { pending, .. } if !pending.is_empty() => {
+                    events |= umio::Event::READABLE
+                }
+                State::Listening { .. } => {}
+                _ if !inner.queue.is_empty() => events |= umio::Event::READABLE,
+                _ if self.is_eof(&inner) => events |= umio::Event::READABLE | umio::Event::HANG_UP,
+                _ => {}
+            }
+            match &inner.state {
+                State::Connected { peer, .. } => Some(peer.clone()),
+                _ => None,
+            }
+        });
+        let peer = match peer.map(|p| p.upgrade()) {
+            Some(Some(peer)) => {
+                let writable = peer.shut_read.load(SeqCst)
+                    || ksync::critical(|| peer.inner.lock().queued < BUFFER_CAP);
+                if writable {
+                    events |= umio::Event::WRITABLE
+                }
+                Some(peer)
+            }
+            Some(None) => {
+                events |= umio::Event::WRITABLE | umio::Event::HANG_UP;
+                None
+            }
+            None => {
+                if self.ty == Type::Dgram {
+                    events |= umio::Event::WRITABLE
+                }
+                None
+            }
+        };
+        (events, peer)
+    }
+
+    async fn event(&self, expected: umio::Event) -> Option<umio::Event> {
+        loop {
+            let mine = self.event.listen();
+            let (events, peer) = self.readiness();
+            let theirs = peer.as_ref().map(|peer| peer.event.listen());
+            let ret = events & (expected | umio::Event::HANG_UP | umio::Event::ERROR);
+            if !ret.is_empty() {
+                break Some(ret);
+            }
+            match theirs {
+                Some(theirs) => drop(select(pin!(mine), pin!(theirs)).await),
+                None => mine.await,
+            }
+        }
+    }
+}
+
+/// Copies the data to the buffers, advancing both of them.
+fn copy(data: &mut &[u8], buffer: &mut &mut [IoSliceMut]) -> usize {
+    let mut copied = 0;
+    while let Some(first) = buffer.first_mut() {
+        if data.is_empty() {
+            break;
+        }
+        let len = first.len().min(data.len());
+        first[..len].copy_from_slice(&data[..len]);
+        *data = &data[len..];
+        copied += len;
+        umio::advance_slices(buffer, len);
+    }
+    copied
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+        if inner.addr != UnixAddr::Unnamed {
+            let me = self as *const UnixSocket;
+            let old = ksync::critical(|| {
+                let mut names = NAMES.lock();
+                match names.get(&inner.addr) {
+                    Some(s) if s.as_ptr() == me => names.remove(&inner.addr),
+                    _ => None,
+                }
+            });
+            drop(old);
+        }
+        if let State::Connected { peer, .. } = &inner.state {
+            if let Some(peer) = peer.upgrade() {
+                peer.wake();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Entry for UnixSocket {
+    async fn open(
+        self: Arc<Self>,
+        _: &Path,
+        _: OpenOptions,
+        _: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        Err(ENOSYS)
+    }
+
+    async fn metadata(&self) -> Metadata {
+        Metadata {
+            ty: FileType::SOCK,
+            len: 0,
+            offset: 0,
+            link_count: 1,
+            perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
+            block_size: 0,
+            block_count: 0,
+            times: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl IoPoll for UnixSocket {
+    async fn event(&self, expected: umio::Event) -> Option<umio::Event> {
+        self.event(expected).await
+    }
+
+    fn poll(&self) -> umio::Event {
+        self.readiness().0
+    }
+
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        self.watchers.push(watcher, exclusive);
+        true
+    }
+}
+
+#[async_trait]
+impl Io for UnixSocket {
+    async fn read(&self, buffer: &mut [IoSliceMut]) -> Result<usize, Error> {
+        // The passed file descriptors are discarded without `recvmsg`.
+        self.receive(buffer, false).await.map(|r| r.len)
+    }
+
+    async fn write(&self, buffer: &mut [IoSlice]) -> Result<usize, Error> {
+        self.send(buffer, Vec::new(), None, false).await
+    }
+
+    async fn seek(&self, _: SeekFrom) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn read_at(&self, _: usize, _: &mut [IoSliceMut]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn write_at(&self, _: usize, _: &mut [IoSlice]) -> Result<usize, Error> {
+        Err(ESPIPE)
+    }
+
+    async fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -14,6 +14,7 @@
 #![feature(result_option_inspect)]
 #![feature(thread_local)]
 
+mod cmdline;
 mod cpu;
 mod dev;
 pub mod fs;
@@ -27,6 +28,11 @@ mod test;
 
 extern crate alloc;
 
+use alloc::{string::String, vec};
+use core::iter;
+
+use ksc::Error::ENOENT;
+
 pub use self::rxx::executor;
 
 async fn main(payload: usize) {
@@ -37,11 +43,49 @@ async fn main(payload: usize) {
         let device_tree = config::device_tree(payload);
         crate::dev::init(device_tree).expect("failed to initialize devices")
     }
+    executor().spawn(cpu::sample_load()).detach();
+    executor().spawn(mem::reclaim()).detach();
     // Init FS.
     fs::fs_init().await;
 
-    mem::test_phys().await;
-    fs::test_file().await;
+    if !run_init().await {
+        mem::test_phys().await;
+        fs::test_file().await;
 
-    self::test::test_all().await;
+        self::test::test_all().await;
+    }
+
+    // The system is powered off after returning.
+    fs::flush_all().await;
+}
+
+/// The programs tried as the init process if `init=` is not specified.
+const DEFAULT_INIT: [&str; 2] = ["/init", "/sbin/init"];
+
+/// Runs the init process with the rest of the command line until it exits,
+/// returning `false` if no init program is found.
+async fn run_init() -> bool {
+    let cmdline = cmdline::cmdline();
+    let paths = match &cmdline.init {
+        Some(init) => vec![init.as_str()],
+        None => DEFAULT_INIT.to_vec(),
+    };
+    for path in paths {
+        let mut cmd = task::Command::new(path);
+        match cmd.open_executable().await {
+            Ok(_) => {}
+            Err(ENOENT) if cmdline.init.is_none() => continue,
+            Err(err) => panic!("failed to open init {path:?}: {err:?}"),
+        }
+        let args = iter::once(path).chain(cmdline.init_args.iter().map(String::as_str));
+        let envs = ["HOME=/", "TERM=linux"].into_iter();
+        let envs = envs.chain(cmdline.init_envs.iter().map(String::as_str));
+
+        let task = cmd.init().args(args).envs(envs).spawn().await;
+        let task = task.unwrap_or_else(|err| panic!("failed to run init {path:?}: {err:?}"));
+        let exit = task.wait().await;
+        log::warn!("init exited with {exit:?}");
+        return true;
+    }
+    false
 }
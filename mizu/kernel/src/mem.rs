@@ -4,12 +4,18 @@ mod syscall;
 mod user;
 
 use alloc::sync::Arc;
-use core::ops::Range;
+use core::{
+    alloc::Layout,
+    num::NonZeroUsize,
+    ops::Range,
+    sync::atomic::{AtomicU8, Ordering::Relaxed},
+    time::Duration,
+};
 
 use arsc_rs::Arsc;
 use kmem::{Phys, Virt};
-use ksc::Error;
-use rv39_paging::{CANONICAL_PREFIX, PAGE_SIZE};
+use ksc::Error::{self, EINVAL};
+use rv39_paging::{CANONICAL_PREFIX, PAGE_MASK, PAGE_SHIFT, PAGE_SIZE};
 use umifs::traits::{IntoAnyExt, Io, IoExt};
 
 pub use self::{
@@ -18,12 +24,42 @@ pub use self::{
     syscall::*,
     user::{In, InOut, Out, UserBuffer, UserPtr, UA_FAULT},
 };
-use crate::rxx::KERNEL_PAGES;
+use crate::{rxx::KERNEL_PAGES, task};
 
 pub const USER_RANGE: Range<usize> = 0x1000..((!CANONICAL_PREFIX) + 1);
 
+/// The interval to wait for before retrying when no frame can be reclaimed.
+const RECLAIM_RETRY: Duration = Duration::from_millis(100);
+
+/// The number of the frames preferably added to the kernel heap at once.
+const HEAP_GROW_COUNT: usize = 16;
+
+/// The level of the address space layout randomization, as in
+/// `/proc/sys/kernel/randomize_va_space`:
+///
+/// - 0: Disabled.
+/// - 1: Randomizes the mappings, the stacks and the bases of the ELF images.
+/// - 2: Also randomizes the start of the heap.
+static RANDOMIZE_VA_SPACE: AtomicU8 = AtomicU8::new(2);
+
+pub fn randomize_va_space() -> u8 {
+    RANDOMIZE_VA_SPACE.load(Relaxed)
+}
+
+/// Sets the level of the randomization, which applies to the processes
+/// executed from now on.
+pub fn set_randomize_va_space(level: u8) -> Result<(), Error> {
+    if level > 2 {
+        return Err(EINVAL);
+    }
+    RANDOMIZE_VA_SPACE.store(level, Relaxed);
+    Ok(())
+}
+
 pub fn new_virt() -> Arsc<Virt> {
-    Virt::new(USER_RANGE.start.into()..USER_RANGE.end.into(), KERNEL_PAGES)
+    let virt = Virt::new(USER_RANGE.start.into()..USER_RANGE.end.into(), KERNEL_PAGES);
+    virt.set_randomized(randomize_va_space() > 0);
+    virt
 }
 
 pub fn new_phys(from: Arc<dyn Io>, cow: bool) -> Phys {
@@ -39,6 +75,59 @@ pub async fn deep_fork(virt: &Arsc<Virt>) -> Result<Arsc<Virt>, Error> {
     virt.deep_fork(KERNEL_PAGES).await
 }
 
+/// Adds some frames to the kernel heap when it runs out, which must not
+/// allocate from the heap itself.
+pub fn grow_heap(layout: Layout) -> bool {
+    // The blocks of the buddy allocator are aligned to their sizes, so twice
+    // the size is needed for one of them to fit anyway.
+    let size = layout.size().max(layout.align()).next_power_of_two() * 2;
+    let count = (size + PAGE_MASK) >> PAGE_SHIFT;
+    for count in [count.max(HEAP_GROW_COUNT), count] {
+        let count = NonZeroUsize::new(count).unwrap();
+        if let Some(laddr) = kmem::frames().allocate(count) {
+            unsafe { kalloc::add(laddr.val(), count.get() << PAGE_SHIFT) };
+            return true;
+        }
+    }
+    false
+}
+
+/// Reclaims the frames whenever the free ones run below the low watermark,
+/// until they are back to the high one.
+pub async fn reclaim() {
+    loop {
+        kmem::reclaim::pressure().await;
+        loop {
+            let (_, high) = kmem::reclaim::watermarks();
+            let free = kmem::reclaim::free_count();
+            if free >= high {
+                break;
+            }
+            let target = high - free;
+            let freed = kmem::reclaim::reclaim(target).await;
+            if freed < target {
+                // The pages mapped by the processes can only be reclaimed
+                // after being unmapped.
+                age(target - freed).await;
+            }
+            if freed == 0 {
+                ktime::sleep(RECLAIM_RETRY).await;
+            }
+        }
+    }
+}
+
+/// Unmaps at most `count` pages from every process.
+async fn age(count: usize) {
+    let mut start = 0;
+    while let Some(process) = task::pid::next_process(start) {
+        start = process.tid() + 1;
+        if let Some(info) = process.info() {
+            info.virt.age(count).await;
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub async fn test_phys() {
     let p = Phys::new(false);
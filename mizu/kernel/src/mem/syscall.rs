@@ -1,32 +1,65 @@
-use alloc::boxed::Box;
-use core::{mem, time::Duration};
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use core::{mem, ops::Range, time::Duration};
 
 use co_trap::UserCx;
-use kmem::Phys;
+use kmem::{Phys, Virt};
 use ksc::{
     async_handler,
-    Error::{self, EAGAIN, EINVAL, EISDIR, ENOENT, ENOSYS, EPERM, ETIMEDOUT},
+    Error::{
+        self, EAGAIN, EEXIST, EINVAL, EISDIR, ENOENT, ENOMEM, ENOSPC, ENOSYS, EPERM, ETIMEDOUT,
+    },
 };
 use ktime::TimeOutExt;
-use rv39_paging::{Attr, LAddr, PAGE_MASK, PAGE_SHIFT};
+use rand_riscv::rand_core::RngCore;
+use rv39_paging::{Attr, LAddr, Level, PAGE_MASK, PAGE_SHIFT, PAGE_SIZE};
+use umifs::{
+    path::PathBuf,
+    traits::IntoAnyExt,
+    types::{FileType, OpenOptions, Permissions},
+};
+use umio::IoExt;
 
 use crate::{
+    fs::MountFlags,
     mem::{futex::RobustListHead, user::FutexKey, In, InOut, Out, UserPtr},
     syscall::{ffi::Ts, ScRet},
-    task::TaskState,
+    task::{fd::MAX_PATH_LEN, TaskState},
 };
 
+/// The maximum size of the heap.
+const BRK_MAX_SIZE: usize = 1 << 30;
+/// The maximum size of the pages locked by an unprivileged task, as its
+/// `RLIMIT_MEMLOCK`.
+const MEMLOCK_MAX: usize = 8 << 20;
+/// The range within which the start of the heap is randomized.
+const BRK_RANDOM_RANGE: usize = 32 << 20;
+
+/// Returns the start of the heap of the image ending at `image_end`, which is
+/// randomized if enabled in [`randomize_va_space`](super::randomize_va_space).
+///
+/// The whole window the heap may grow in is reserved with inaccessible pages,
+/// so that no other mapping is placed in its way. The heap is placed elsewhere
+/// if the window right after the image is taken.
+pub async fn brk_start(virt: &Virt, image_end: LAddr) -> Result<usize, Error> {
+    let mut start = (image_end.val() + PAGE_MASK) & !PAGE_MASK;
+    if super::randomize_va_space() >= 2 {
+        let offset = rand_riscv::rng().next_u64() as usize % (BRK_RANDOM_RANGE >> PAGE_SHIFT);
+        start += offset << PAGE_SHIFT;
+    }
+    let count = BRK_MAX_SIZE >> PAGE_SHIFT;
+    let attr = Attr::builder().user_access(true).build();
+    let reserved = virt.map(Some(start.into()), Phys::new(true), 0, count, attr);
+    match reserved.await {
+        Ok(_) => Ok(start),
+        Err(_) => Ok(virt.map(None, Phys::new(true), 0, count, attr).await?.val()),
+    }
+}
+
 #[async_handler]
 pub async fn brk(ts: &mut TaskState, cx: UserCx<'_, fn(usize) -> Result<usize, Error>>) -> ScRet {
-    const BRK_START: usize = 0x12345000;
-    const BRK_END: usize = 0x56789000;
-
     let addr = cx.args();
     let fut = async {
-        if ts.brk == 0 {
-            ts.brk = BRK_START;
-        }
-        if !(BRK_START..BRK_END).contains(&addr) {
+        if !(ts.brk_start..(ts.brk_start + BRK_MAX_SIZE)).contains(&addr) {
             return Ok(ts.brk);
         }
         if addr > ts.brk {
@@ -34,6 +67,8 @@ pub async fn brk(ts: &mut TaskState, cx: UserCx<'_, fn(usize) -> Result<usize, E
             let new_page = (addr + PAGE_MASK) & !PAGE_MASK;
             let count = (new_page - old_page) >> PAGE_SHIFT;
             if count > 0 {
+                // Replace the reserved pages.
+                ts.virt.unmap(old_page.into()..new_page.into()).await?;
                 let phys = Phys::new(true);
                 let attr = Attr::builder()
                     .user_access(true)
@@ -153,29 +188,72 @@ bitflags::bitflags! {
         const FIXED     = 0x10;  /* Interpret addr exactly */
         const ANONYMOUS = 0x20;  /* don't use a file */
 
+        const GROWSDOWN = 0x0100;  /* stack-like segment */
+        const NORESERVE = 0x4000;  /* don't check for reservations */
         const POPULATE  = 0x8000;  /* populate (prefault) pagetables */
+        const STACK     = 0x20000; /* give out an address that is best suited for process/thread stacks */
+        const HUGETLB   = 0x40000; /* create a huge page mapping */
+        const FIXED_NOREPLACE = 0x100000; /* MAP_FIXED which doesn't unmap underlying mapping */
     }
 }
 
+/// The bits of the `mmap` flags storing the log2 of the huge page size.
+const MAP_HUGE_SHIFT: i32 = 26;
+const MAP_HUGE_MASK: i32 = 0x3f;
+
+/// Returns the level of the huge pages requested by the `mmap` flags.
+fn huge_level(flags: i32) -> Result<Level, Error> {
+    let size_shift = (flags >> MAP_HUGE_SHIFT) & MAP_HUGE_MASK;
+    if size_shift == 0 {
+        return Ok(Level::new(1));
+    }
+    let iter = (1..=Level::max().val()).map(Level::new);
+    let mut iter = iter.filter(|level| level.page_shift() == size_shift as u32);
+    iter.next().ok_or(EINVAL)
+}
+
 #[async_handler]
 pub async fn mmap(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(usize, usize, i32, i32, i32, usize) -> Result<usize, Error>>,
 ) -> ScRet {
-    let (addr, len, prot, flags, fd, offset) = cx.args();
+    let (addr, len, prot, raw_flags, fd, offset) = cx.args();
     let fut = async move {
         let prot = Prot::from_bits(prot).ok_or(ENOSYS)?;
-        let flags = Flags::from_bits_truncate(flags);
+        let flags = Flags::from_bits_truncate(raw_flags);
 
+        // Huge pages are only supported for anonymous mappings.
+        let huge = if flags.contains(Flags::HUGETLB) {
+            if !flags.contains(Flags::ANONYMOUS) {
+                return Err(EINVAL);
+            }
+            Some(huge_level(raw_flags)?)
+        } else {
+            None
+        };
+        let len = match huge {
+            Some(level) => len.checked_add(level.page_mask()).ok_or(EINVAL)? & !level.page_mask(),
+            None => len,
+        };
+
+        // `MAP_NORESERVE` and `MAP_STACK` need no handling, since frames are
+        // never reserved in advance and stacks are placed like other mappings.
         let cow = flags.contains(Flags::PRIVATE);
-        let phys = if flags.contains(Flags::ANONYMOUS) {
-            Phys::new(cow)
+        let (phys, noexec) = if flags.contains(Flags::ANONYMOUS) {
+            (Phys::new(cow), false)
         } else {
             let entry = ts.files.get(fd).await?;
-            crate::mem::new_phys(entry.to_io().ok_or(EISDIR)?, cow)
+            let noexec = crate::fs::flags_of(&entry).contains(MountFlags::NOEXEC);
+            if prot.contains(Prot::EXEC) && noexec {
+                return Err(EPERM);
+            }
+            let phys = crate::mem::new_phys(entry.to_io().ok_or(EISDIR)?, cow);
+            (phys, noexec)
         };
 
-        let addr = (flags.contains(Flags::FIXED) || addr != 0).then(|| LAddr::from(addr));
+        let noreplace = flags.contains(Flags::FIXED_NOREPLACE);
+        let fixed = flags.contains(Flags::FIXED) || noreplace;
+        let addr = (fixed || addr != 0).then(|| LAddr::from(addr));
 
         log::trace!("user mmap at {addr:?}, len = {len}, prot = {prot:?}, flags = {flags:?}");
         log::trace!("user mmap: fd = {fd}, offset = {offset}");
@@ -193,14 +271,30 @@ pub async fn mmap(
             .executable(prot.contains(Prot::EXEC))
             .build();
 
-        if flags.contains(Flags::FIXED) {
+        if fixed && !noreplace {
             let addr = addr.unwrap().val() & !PAGE_MASK;
             let len = (len + PAGE_MASK) & !PAGE_MASK;
             ts.virt.unmap(addr.into()..(addr + len).into()).await?;
         }
 
         let count = (len + PAGE_MASK) >> PAGE_SHIFT;
-        let addr = ts.virt.map(addr, phys, offset, count, attr).await?;
+        let addr = match huge {
+            Some(level) => {
+                let map = ts.virt.map_huge(addr, phys, offset, count, attr, level);
+                map.await
+            }
+            None => ts.virt.map(addr, phys, offset, count, attr).await,
+        };
+        let addr = match addr {
+            Err(ENOSPC) if noreplace => return Err(EEXIST),
+            addr => addr?,
+        };
+        if flags.contains(Flags::GROWSDOWN) {
+            ts.virt.set_grows_down(addr).await?;
+        }
+        if noexec {
+            ts.virt.set_noexec(addr).await?;
+        }
 
         if flags.contains(Flags::POPULATE) {
             ts.virt.commit(addr, Default::default()).await?;
@@ -309,6 +403,128 @@ pub async fn munmap(
     ScRet::Continue(None)
 }
 
+#[async_handler]
+pub async fn mremap(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, usize, usize, i32, usize) -> Result<usize, Error>>,
+) -> ScRet {
+    const MREMAP_MAYMOVE: i32 = 1;
+    const MREMAP_FIXED: i32 = 2;
+
+    let (addr, old_len, new_len, flags, new_addr) = cx.args();
+    let fut = async move {
+        if flags & !(MREMAP_MAYMOVE | MREMAP_FIXED) != 0 {
+            return Err(EINVAL);
+        }
+        let may_move = flags & MREMAP_MAYMOVE != 0;
+        let fixed = if flags & MREMAP_FIXED != 0 {
+            if !may_move {
+                return Err(EINVAL);
+            }
+            Some(LAddr::from(new_addr))
+        } else {
+            None
+        };
+
+        let old_len = old_len.checked_add(PAGE_MASK).ok_or(EINVAL)? & !PAGE_MASK;
+        let new_count = new_len.checked_add(PAGE_MASK).ok_or(EINVAL)? >> PAGE_SHIFT;
+        let range = addr.into()..addr.checked_add(old_len).ok_or(EINVAL)?.into();
+
+        let remap = ts.virt.remap(range, new_count, may_move, fixed);
+        Ok(remap.await?.val())
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn mincore(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, usize, UserPtr<u8, Out>) -> Result<(), Error>>,
+) -> ScRet {
+    let (addr, len, mut vec) = cx.args();
+    let fut = async move {
+        let range = addr..addr.checked_add(len).ok_or(ENOMEM)?;
+        let residency = ts.virt.residency(range.start.into()..range.end.into());
+        let residency = residency.await?.into_iter().map(u8::from);
+        vec.write_slice(&ts.virt, &residency.collect::<Vec<_>>(), false)
+            .await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+/// Returns the pages containing the bytes from `addr`.
+fn page_range(addr: usize, len: usize) -> Result<Range<LAddr>, Error> {
+    let end = addr.checked_add(len).ok_or(ENOMEM)?;
+    let end = end.checked_add(PAGE_MASK).ok_or(ENOMEM)? & !PAGE_MASK;
+    Ok((addr & !PAGE_MASK).into()..end.into())
+}
+
+/// Returns the maximum size of the pages the task may lock.
+pub fn memlock_limit(ts: &TaskState) -> usize {
+    match ts.cred().is_root() {
+        true => usize::MAX,
+        false => MEMLOCK_MAX,
+    }
+}
+
+#[async_handler]
+pub async fn mlock(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, usize) -> Result<(), Error>>,
+) -> ScRet {
+    let (addr, len) = cx.args();
+    let fut = async move {
+        let limit = memlock_limit(ts);
+        ts.virt.lock(page_range(addr, len)?, true, limit).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn munlock(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, usize) -> Result<(), Error>>,
+) -> ScRet {
+    let (addr, len) = cx.args();
+    let fut = async move {
+        ts.virt
+            .lock(page_range(addr, len)?, false, usize::MAX)
+            .await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn mlockall(ts: &mut TaskState, cx: UserCx<'_, fn(i32) -> Result<(), Error>>) -> ScRet {
+    const MCL_CURRENT: i32 = 1;
+    const MCL_FUTURE: i32 = 2;
+    const MCL_ONFAULT: i32 = 4;
+
+    let flags = cx.args();
+    let fut = async move {
+        if flags & !(MCL_CURRENT | MCL_FUTURE | MCL_ONFAULT) != 0
+            || flags & (MCL_CURRENT | MCL_FUTURE) == 0
+        {
+            return Err(EINVAL);
+        }
+        let (current, future) = (flags & MCL_CURRENT != 0, flags & MCL_FUTURE != 0);
+        ts.virt.lock_all(current, future, memlock_limit(ts)).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn munlockall(ts: &mut TaskState, cx: UserCx<'_, fn() -> Result<(), Error>>) -> ScRet {
+    ts.virt.unlock_all().await;
+    cx.ret(Ok(()));
+    ScRet::Continue(None)
+}
+
 #[async_handler]
 pub async fn membarrier(
     _: &mut TaskState,
@@ -402,3 +618,88 @@ pub async fn shmdt(ts: &mut TaskState, cx: UserCx<'_, fn(usize) -> Result<(), Er
     });
     ScRet::Continue(None)
 }
+
+/// Reads the path of a swap area, returning its absolute form.
+async fn swap_path(ts: &TaskState, path: UserPtr<u8, In>) -> Result<PathBuf, Error> {
+    let mut buf = [0; MAX_PATH_LEN];
+    let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
+    Ok(if root {
+        path.to_path_buf()
+    } else {
+        ts.files.cwd().join(path)
+    })
+}
+
+#[async_handler]
+pub async fn swapon(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(UserPtr<u8, In>, i32) -> Result<(), Error>>,
+) -> ScRet {
+    const SWAP_FLAG_PREFER: i32 = 0x8000;
+    const SWAP_FLAG_PRIO_MASK: i32 = 0x7fff;
+
+    const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+    // The offset of `last_page` in the header, after the boot block and the
+    // version.
+    const LAST_PAGE_OFFSET: usize = 1024 + 4;
+
+    let (path, flags) = cx.args();
+    let fut = async {
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
+        let path = swap_path(ts, path).await?;
+        log::trace!("user swapon path = {path:?}, flags = {flags:#x}");
+
+        let options = OpenOptions::RDWR;
+        let perm = Permissions::all_same(true, true, false);
+        let (entry, _) = crate::fs::open(&path, options, perm).await?;
+        let metadata = entry.metadata().await;
+        let (partition, size) = if metadata.ty == FileType::BLK {
+            (true, metadata.block_size * metadata.block_count)
+        } else if metadata.ty == FileType::FILE {
+            (false, metadata.len)
+        } else {
+            return Err(EINVAL);
+        };
+        // The pages are written out directly to the backend, bypassing the
+        // cache of the file.
+        let io = entry.to_io().ok_or(EINVAL)?;
+        let io = io.downcast::<Phys>().and_then(|phys| phys.backend());
+        let io = io.ok_or(EINVAL)?;
+
+        let mut header = vec![0; PAGE_SIZE];
+        io.read_exact_at(0, &mut header).await?;
+        if !header.ends_with(SWAP_MAGIC) {
+            return Err(EINVAL);
+        }
+        let last_page = header[LAST_PAGE_OFFSET..][..4].try_into().unwrap();
+        let last_page = u32::from_le_bytes(last_page) as usize;
+        let pages = (last_page + 1).min(size >> PAGE_SHIFT);
+
+        let priority =
+            (flags & SWAP_FLAG_PREFER != 0).then_some((flags & SWAP_FLAG_PRIO_MASK) as isize);
+        kmem::swap_on(format!("/{path}"), partition, io, pages, priority)
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn swapoff(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(UserPtr<u8, In>) -> Result<(), Error>>,
+) -> ScRet {
+    let path = cx.args();
+    let fut = async {
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
+        let path = swap_path(ts, path).await?;
+        log::trace!("user swapoff path = {path:?}");
+
+        kmem::swap_off(&format!("/{path}")).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
@@ -1,5 +1,6 @@
+use alloc::string::String;
 #[cfg(not(feature = "test"))]
-use core::arch::asm;
+use core::{arch::asm, ops::Range};
 
 use arsc_rs::Arsc;
 use art::Executor;
@@ -42,29 +43,86 @@ pub const KERNEL_PAGES: Table = const {
 
 static EXECUTOR: Once<Arsc<Executor>> = Once::new();
 
+/// The IDs of the available harts read from the device tree, as a bit mask.
+static HARTS: Once<usize> = Once::new();
+
+/// The ISA string of the harts read from the device tree.
+static ISA: Once<String> = Once::new();
+
 #[track_caller]
 pub fn executor() -> &'static Arsc<Executor> {
     EXECUTOR.get().unwrap()
 }
 
+/// The action taken after the executor stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reset {
+    PowerOff,
+    Restart,
+    /// Stops all the harts without powering off.
+    Halt,
+}
+
+static RESET: Once<Reset> = Once::new();
+
+/// Stops the executor, after which the system is reset as `reset`.
+///
+/// The caller should have flushed the file systems before.
+pub fn shutdown(reset: Reset) {
+    RESET.call_once(|| reset);
+    executor().shutdown()
+}
+
+/// Returns the IDs of the harts running the executor, as a bit mask.
+pub fn harts() -> usize {
+    HARTS.get().copied().unwrap_or_default() | (1 << hart_id::bsp_id())
+}
+
+/// Returns the ISA string of the harts, like `rv64imafdc`.
+pub fn isa() -> &'static str {
+    ISA.get().map_or("rv64imafdc", String::as_str)
+}
+
+#[cfg(not(feature = "test"))]
+fn reset(reset: Reset) -> ! {
+    use sbi_rt::{ColdReboot, NoReason, Shutdown};
+
+    log::info!("System reset: {reset:?}");
+    let ret = match reset {
+        Reset::PowerOff => sbi_rt::system_reset(Shutdown, NoReason),
+        Reset::Restart => sbi_rt::system_reset(ColdReboot, NoReason),
+        Reset::Halt => loop {
+            unsafe { asm!("wfi") }
+        },
+    };
+    // The firmware does not support the System Reset extension, and the legacy
+    // extension can only power off.
+    log::warn!(
+        "SBI system reset failed with {:?}, using the legacy shutdown",
+        ret.err()
+    );
+    sbi_rt::legacy::shutdown()
+}
+
 #[cfg(not(feature = "test"))]
 fn run_art(payload: usize) {
     use alloc::boxed::Box;
 
     type Payload = *mut Box<dyn FnOnce() + Send>;
     if hart_id::is_bsp() {
-        log::debug!("Starting ART");
-        let mut runners = Executor::start(config::MAX_HARTS, move |e| async move {
+        let harts = harts();
+        log::debug!("Starting ART with {} harts", harts.count_ones());
+        let num = harts.count_ones() as usize;
+        let mut runners = Executor::start(num, ktime::Instant::now_raw, move |e| async move {
             EXECUTOR.call_once(|| e);
             crate::main(payload).await;
             EXECUTOR.get().unwrap().shutdown()
         });
 
         let me = runners.next().unwrap();
-        for (id, runner) in config::HART_RANGE
-            .filter(|&id| id != hart_id::bsp_id())
-            .zip(runners)
-        {
+        let others =
+            (0..config::MAX_HARTS).filter(|&id| harts & (1 << id) != 0 && id != hart_id::bsp_id());
+        for (id, runner) in others.zip(runners) {
             log::debug!("Starting #{id}");
 
             let payload: Payload = Box::into_raw(Box::new(Box::new(runner)));
@@ -73,6 +131,8 @@ fn run_art(payload: usize) {
 
             if let Some(err) = ret.err() {
                 log::error!("failed to start hart {id} due to error {err:?}");
+                // SAFETY: The hart didn't take the payload.
+                drop(unsafe { Box::from_raw(payload) });
             }
         }
         me();
@@ -85,14 +145,73 @@ fn run_art(payload: usize) {
     }
 }
 
+/// Reads the command line, the RAM size and the available harts from the device
+/// tree, returning the free memory after `start`.
+///
+/// # Safety
+///
+/// `payload` must be the argument passed to the boot hart.
+#[cfg(not(feature = "test"))]
+unsafe fn probe(payload: usize, start: usize) -> Range<usize> {
+    let fdt_base = config::device_tree(payload);
+    let fdt = unsafe { fdt::Fdt::from_ptr(fdt_base.cast()) }.expect("invalid device tree");
+
+    let bootargs = (fdt.find_node("/chosen"))
+        .and_then(|chosen| chosen.property("bootargs"))
+        .and_then(|bootargs| bootargs.as_str());
+    crate::cmdline::init(bootargs);
+
+    let ram_size = fdt
+        .memory()
+        .regions()
+        .find(|region| region.starting_address as usize == config::RAM_START)
+        .and_then(|region| region.size)
+        .expect("no RAM found in the device tree");
+    if ram_size > config::MAX_RAM_SIZE {
+        log::warn!(
+            "RAM size {ram_size:#x} exceeds the limit, using {:#x}",
+            config::MAX_RAM_SIZE
+        );
+    }
+    let mut free = start..config::VIRT_START + ram_size.min(config::MAX_RAM_SIZE);
+
+    // The firmware may place the device tree at the end of the RAM.
+    let fdt_start = fdt_base as usize & !rv39_paging::PAGE_MASK;
+    if free.contains(&fdt_start) {
+        free.end = fdt_start;
+    }
+
+    let harts = fdt
+        .cpus()
+        .filter(|cpu| {
+            let status = cpu.property("status").and_then(|s| s.as_str());
+            status.map_or(true, |s| s == "okay")
+        })
+        .map(|cpu| cpu.ids().first())
+        .filter(|&id| id < config::MAX_HARTS)
+        .fold(0, |acc, id| acc | (1 << id));
+    HARTS.call_once(|| harts);
+
+    let isa = fdt
+        .cpus()
+        .find_map(|cpu| cpu.property("riscv,isa")?.as_str());
+    if let Some(isa) = isa {
+        ISA.call_once(|| isa.into());
+    }
+
+    log::info!(
+        "RAM size = {ram_size:#x}, {} harts available",
+        harts.count_ones()
+    );
+    free
+}
+
 #[cfg(not(feature = "test"))]
 #[no_mangle]
 unsafe extern "C" fn __rt_init(hartid: usize, payload: usize) {
     use core::mem;
 
-    use config::VIRT_END;
     use riscv::register::{sie, sstatus};
-    use sbi_rt::{NoReason, Shutdown};
     use spin::Lazy;
 
     extern "C" {
@@ -139,17 +258,25 @@ unsafe extern "C" fn __rt_init(hartid: usize, payload: usize) {
 
         hart_id::init_bsp_id(hartid);
 
+        unsafe { crate::dev::init_logger() };
+
+        let free = unsafe { probe(payload, &_end as *const u8 as usize) };
+
+        // Give 1/8 of the free memory to the kernel heap.
+        let heap_len = ((free.end - free.start) / 8) & !rv39_paging::PAGE_MASK;
+        unsafe { kalloc::add(free.start, heap_len) };
+
         // Init the frame allocator.
         unsafe {
-            let range = (&_end as *const u8).into()..VIRT_END.into();
+            let range = (free.start + heap_len).into()..free.end.into();
             kmem::init_frames(range)
         }
+        kalloc::set_grow_handler(crate::mem::grow_heap);
+        kmem::reclaim::set_oom_handler(crate::task::out_of_memory);
 
         // Init lazies.
         Lazy::force(&crate::syscall::SYSCALL);
         Lazy::force(&kmem::ZERO);
-
-        unsafe { crate::dev::init_logger() };
     }
     hart_id::init_hart_id(hartid);
 
@@ -169,7 +296,7 @@ unsafe extern "C" fn __rt_init(hartid: usize, payload: usize) {
     unsafe { ksync::disable() };
 
     if hart_id::is_bsp() {
-        sbi_rt::system_reset(Shutdown, NoReason);
+        reset(RESET.get().copied().unwrap_or(Reset::PowerOff));
     }
     loop {
         core::hint::spin_loop()
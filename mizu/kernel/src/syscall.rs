@@ -1,16 +1,15 @@
 pub mod ffi;
 
 use alloc::boxed::Box;
-use core::{mem, ops::ControlFlow, time::Duration};
+use core::{future, mem, ops::ControlFlow, time::Duration};
 
 use co_trap::{TrapFrame, UserCx};
 use kmem::Virt;
 use ksc::{
     async_handler, AHandlers,
-    Error::{self, EINVAL},
+    Error::{self, EINVAL, EOPNOTSUPP, EPERM},
     Scn::{self, *},
 };
-use ktime::Instant;
 use rand_riscv::rand_core::RngCore;
 use spin::Lazy;
 use sygnal::SigInfo;
@@ -18,7 +17,8 @@ use sygnal::SigInfo;
 use self::ffi::{Ts, Tv};
 use crate::{
     mem::{In, Out, UserPtr},
-    task::{self, fd, signal, TaskState},
+    rxx::Reset,
+    task::{self, fd, signal, Clock, TaskState},
 };
 
 pub type ScParams<'a> = (&'a mut TaskState, &'a mut TrapFrame);
@@ -36,11 +36,19 @@ pub static SYSCALL: Lazy<AHandlers<Scn, ScParams, ScRet>> = Lazy::new(|| {
         .map(MADVISE, crate::mem::madvise)
         .map(MPROTECT, crate::mem::mprotect)
         .map(MUNMAP, crate::mem::munmap)
+        .map(MREMAP, crate::mem::mremap)
+        .map(MINCORE, crate::mem::mincore)
+        .map(MLOCK, crate::mem::mlock)
+        .map(MUNLOCK, crate::mem::munlock)
+        .map(MLOCKALL, crate::mem::mlockall)
+        .map(MUNLOCKALL, crate::mem::munlockall)
         .map(MEMBARRIER, crate::mem::membarrier)
         .map(SHMGET, crate::mem::shmget)
         .map(SHMCTL, dummy_zero)
         .map(SHMAT, crate::mem::shmat)
         .map(SHMDT, crate::mem::shmdt)
+        .map(SWAPON, crate::mem::swapon)
+        .map(SWAPOFF, crate::mem::swapoff)
         // Tasks
         .map(SCHED_YIELD, task::uyield)
         .map(SCHED_SETSCHEDULER, dummy_zero)
@@ -51,6 +59,24 @@ pub static SYSCALL: Lazy<AHandlers<Scn, ScParams, ScRet>> = Lazy::new(|| {
         .map(GETTID, task::tid)
         .map(GETPID, task::pid)
         .map(GETPPID, task::ppid)
+        .map(GETUID, task::getuid)
+        .map(GETEUID, task::geteuid)
+        .map(GETGID, task::getgid)
+        .map(GETEGID, task::getegid)
+        .map(SETUID, task::setuid)
+        .map(SETGID, task::setgid)
+        .map(SETREUID, task::setreuid)
+        .map(SETREGID, task::setregid)
+        .map(SETRESUID, task::setresuid)
+        .map(SETRESGID, task::setresgid)
+        .map(GETRESUID, task::getresuid)
+        .map(GETRESGID, task::getresgid)
+        .map(GETGROUPS, task::getgroups)
+        .map(SETGROUPS, task::setgroups)
+        .map(SETPGID, task::setpgid)
+        .map(GETPGID, task::getpgid)
+        .map(SETSID, task::setsid)
+        .map(GETSID, task::getsid)
         .map(TIMES, task::times)
         .map(SETITIMER, task::setitimer)
         .map(PRLIMIT64, task::prlimit)
@@ -83,6 +109,9 @@ pub static SYSCALL: Lazy<AHandlers<Scn, ScParams, ScRet>> = Lazy::new(|| {
         .map(LSEEK, fd::lseek)
         .map(PPOLL, fd::ppoll)
         .map(PSELECT6, fd::pselect)
+        .map(EPOLL_CREATE1, fd::epoll_create1)
+        .map(EPOLL_CTL, fd::epoll_ctl)
+        .map(EPOLL_PWAIT, fd::epoll_pwait)
         .map(SENDFILE, fd::sendfile)
         .map(COPY_FILE_RANGE, fd::copy_file_range)
         .map(SYNC, fd::sync)
@@ -100,10 +129,14 @@ pub static SYSCALL: Lazy<AHandlers<Scn, ScParams, ScRet>> = Lazy::new(|| {
         .map(NEWFSTATAT, fd::fstatat)
         .map(FCHMOD, fd::fchmod)
         .map(FCHMODAT, fd::fchmodat)
+        .map(FCHOWN, fd::fchown)
+        .map(FCHOWNAT, fd::fchownat)
         .map(UTIMENSAT, fd::utimensat)
         .map(GETDENTS64, fd::getdents64)
         .map(RENAMEAT2, fd::renameat)
         .map(UNLINKAT, fd::unlinkat)
+        .map(SYMLINKAT, fd::symlinkat)
+        .map(LINKAT, fd::linkat)
         .map(CLOSE, fd::close)
         .map(PIPE2, fd::pipe)
         .map(MOUNT, fd::mount)
@@ -121,6 +154,8 @@ pub static SYSCALL: Lazy<AHandlers<Scn, ScParams, ScRet>> = Lazy::new(|| {
         .map(GETPEERNAME, fd::getpeername)
         .map(SENDTO, fd::sendto)
         .map(RECVFROM, fd::recvfrom)
+        .map(SENDMSG, fd::sendmsg)
+        .map(RECVMSG, fd::recvmsg)
         .map(CONNECT, fd::connect)
         .map(BIND, fd::bind)
         .map(LISTEN, fd::listen)
@@ -128,21 +163,18 @@ pub static SYSCALL: Lazy<AHandlers<Scn, ScParams, ScRet>> = Lazy::new(|| {
         .map(SHUTDOWN, fd::shutdown)
         // Time
         .map(GETTIMEOFDAY, gettimeofday)
+        .map(SETTIMEOFDAY, settimeofday)
         .map(CLOCK_GETTIME, clock_gettime)
+        .map(CLOCK_SETTIME, clock_settime)
         .map(CLOCK_GETRES, clock_getres)
         .map(CLOCK_NANOSLEEP, clock_nanosleep)
         .map(NANOSLEEP, sleep)
         // Miscellaneous
         .map(UNAME, uname)
         .map(GETRANDOM, getrandom)
-        .map(SETSID, dummy_zero)
-        .map(GETEUID, dummy_zero)
-        .map(GETEGID, dummy_zero)
-        .map(GETPGID, dummy_zero)
-        .map(GETUID, dummy_zero)
-        .map(GETGID, dummy_zero)
         .map(SYSLOG, dummy_zero)
         .map(UMASK, dummy_umask)
+        .map(REBOOT, reboot)
 });
 
 #[async_handler]
@@ -164,24 +196,73 @@ async fn gettimeofday(
 ) -> ScRet {
     let (mut out, _) = cx.args();
 
-    let t = Instant::now().into();
+    let t = crate::dev::realtime().into();
     let ret = out.write(&ts.virt, t).await;
     cx.ret(ret);
 
     ScRet::Continue(None)
 }
 
+#[async_handler]
+async fn settimeofday(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(UserPtr<Tv, In>, usize) -> Result<(), Error>>,
+) -> ScRet {
+    let (input, _) = cx.args();
+    let fut = async {
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
+        // The time zone is obsolete, and a null time leaves the clock unchanged.
+        if input.is_null() {
+            return Ok(());
+        }
+        let t = input.read(&ts.virt).await?;
+        if t.sec >= isize::MAX as _ || t.usec >= 1_000_000 {
+            return Err(EINVAL);
+        }
+        crate::dev::set_realtime(t.into());
+        Ok(())
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
 #[async_handler]
 async fn clock_gettime(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(usize, UserPtr<Ts, Out>) -> Result<(), Error>>,
 ) -> ScRet {
-    let (_, mut out) = cx.args();
-
-    let t = Instant::now().into();
-    let ret = out.write(&ts.virt, t).await;
-    cx.ret(ret);
+    let (id, mut out) = cx.args();
+    let fut = async {
+        let t = Clock::from_id(id)?.now(ts);
+        out.write(&ts.virt, t.into()).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
 
+#[async_handler]
+async fn clock_settime(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, UserPtr<Ts, In>) -> Result<(), Error>>,
+) -> ScRet {
+    let (id, input) = cx.args();
+    let fut = async {
+        if Clock::from_id(id)? != Clock::Realtime {
+            return Err(EINVAL);
+        }
+        let t = input.read(&ts.virt).await?;
+        if t.sec >= isize::MAX as _ || t.nsec >= 1_000_000_000 {
+            return Err(EINVAL);
+        }
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
+        crate::dev::set_realtime(t.into());
+        Ok(())
+    };
+    cx.ret(fut.await);
     ScRet::Continue(None)
 }
 
@@ -190,8 +271,15 @@ async fn clock_getres(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(usize, UserPtr<Ts, Out>) -> Result<(), Error>>,
 ) -> ScRet {
-    let (_, mut out) = cx.args();
-    cx.ret(out.write(&ts.virt, Duration::from_nanos(1).into()).await);
+    let (id, mut out) = cx.args();
+    let fut = async {
+        Clock::from_id(id)?;
+        if !out.is_null() {
+            out.write(&ts.virt, Duration::from_nanos(1).into()).await?;
+        }
+        Ok(())
+    };
+    cx.ret(fut.await);
     ScRet::Continue(None)
 }
 
@@ -200,28 +288,32 @@ async fn clock_nanosleep(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(usize, usize, UserPtr<Ts, In>, UserPtr<Ts, Out>) -> Result<(), Error>>,
 ) -> ScRet {
-    let (_, flags, input, mut output) = cx.args();
+    const TIMER_ABSTIME: usize = 1;
+
+    let (id, flags, input, mut output) = cx.args();
     let fut = async {
+        let clock = match Clock::from_id(id)? {
+            Clock::ThreadCputime => return Err(EINVAL),
+            Clock::ProcessCputime => return Err(EOPNOTSUPP),
+            clock => clock,
+        };
         let t = input.read(&ts.virt).await?;
         if t.sec >= isize::MAX as _ || t.nsec >= 1_000_000_000 {
             return Err(EINVAL);
         }
 
-        if flags == 0 {
-            let dur: Duration = t.into();
-            if dur.is_zero() {
-                crate::task::yield_now().await
-            } else {
-                ktime::sleep(dur).await;
-            }
+        let dur: Duration = t.into();
+        let dur = match flags & TIMER_ABSTIME {
+            0 => dur,
+            _ => dur.saturating_sub(clock.now(ts)),
+        };
+        if dur.is_zero() {
+            crate::task::yield_now().await
         } else {
-            let inst: Instant = t.into();
-            if Instant::now() < inst {
-                ktime::sleep_until(inst).await;
-            }
+            ktime::sleep(dur).await;
         }
 
-        if !output.is_null() {
+        if flags & TIMER_ABSTIME == 0 && !output.is_null() {
             output.write(&ts.virt, Default::default()).await?;
         }
         Ok(())
@@ -264,14 +356,17 @@ async fn sleep(
     ScRet::Continue(None)
 }
 
+/// The system name, the node name, the release, the version, the machine and
+/// the domain name returned by `uname(2)`.
+pub const UTS_NAME: [&str; 6] = ["mizu", "umi", "5.0.0", "23.05", "riscv", ""];
+
 #[async_handler]
 async fn uname(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(UserPtr<u8, Out>) -> Result<(), Error>>,
 ) -> ScRet {
     async fn inner(virt: &Virt, mut out: UserPtr<u8, Out>) -> Result<(), Error> {
-        let names: [&str; 6] = ["mizu", "umi", "5.0.0", "23.05", "riscv", ""];
-        for name in names {
+        for name in UTS_NAME {
             out.write_slice(virt, name.as_bytes(), true).await?;
             out.advance(65);
         }
@@ -303,3 +398,42 @@ async fn getrandom(
     cx.ret(fut.await);
     ScRet::Continue(None)
 }
+
+const REBOOT_MAGIC1: u32 = 0xfee1dead;
+const REBOOT_MAGIC2: [u32; 4] = [0x28121969, 0x05121996, 0x16041998, 0x20112000];
+
+const REBOOT_CMD_RESTART: u32 = 0x01234567;
+const REBOOT_CMD_HALT: u32 = 0xcdef0123;
+const REBOOT_CMD_POWER_OFF: u32 = 0x4321fedc;
+const REBOOT_CMD_CAD_ON: u32 = 0x89abcdef;
+const REBOOT_CMD_CAD_OFF: u32 = 0;
+
+#[async_handler]
+async fn reboot(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(u32, u32, u32, usize) -> Result<(), Error>>,
+) -> ScRet {
+    let (magic1, magic2, cmd, _) = cx.args();
+    let reset = match (magic1, cmd) {
+        _ if !ts.cred().is_root() => Err(EPERM),
+        (REBOOT_MAGIC1, _) if !REBOOT_MAGIC2.contains(&magic2) => Err(EINVAL),
+        (REBOOT_MAGIC1, REBOOT_CMD_POWER_OFF) => Ok(Some(Reset::PowerOff)),
+        (REBOOT_MAGIC1, REBOOT_CMD_RESTART) => Ok(Some(Reset::Restart)),
+        (REBOOT_MAGIC1, REBOOT_CMD_HALT) => Ok(Some(Reset::Halt)),
+        // Ctrl-Alt-Del is not delivered to the kernel anyway.
+        (REBOOT_MAGIC1, REBOOT_CMD_CAD_ON | REBOOT_CMD_CAD_OFF) => Ok(None),
+        _ => Err(EINVAL),
+    };
+    match reset {
+        Ok(Some(reset)) => {
+            log::warn!("reboot: {reset:?} requested from user space");
+            crate::fs::flush_all().await;
+            crate::rxx::shutdown(reset);
+            // The calling task never returns to user mode.
+            future::pending().await
+        }
+        Ok(None) => cx.ret(Ok(())),
+        Err(err) => cx.ret(Err(err)),
+    }
+    ScRet::Continue(None)
+}
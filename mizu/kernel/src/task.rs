@@ -1,7 +1,11 @@
 mod cmd;
+mod cred;
 mod elf;
 pub mod fd;
 mod future;
+mod job;
+mod oom;
+pub mod pid;
 pub mod signal;
 mod syscall;
 mod time;
@@ -11,6 +15,7 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::{fmt, mem, ptr, time::Duration};
 
 use arsc_rs::Arsc;
 use crossbeam_queue::SegQueue;
@@ -22,11 +27,23 @@ use ksync::{
     AtomicArsc,
 };
 use rv39_paging::{Attr, PAGE_SIZE};
+use spin::Once;
 use sygnal::{ActionSet, Sig, SigInfo, SigSet, Signals};
+use umifs::traits::Entry;
 
-pub use self::{cmd::Command, future::yield_now, syscall::*};
+pub use self::{
+    cmd::Command,
+    cred::{Access, Credentials},
+    future::{current, yield_now},
+    job::kill_group,
+    oom::out_of_memory,
+    syscall::*,
+    time::Clock,
+};
 use self::{
     fd::Files,
+    job::Job,
+    oom::Oom,
     signal::SigStack,
     time::{Counter, Times},
 };
@@ -50,23 +67,162 @@ pub enum TaskEvent {
 struct Child {
     task: Arc<Task>,
     event: Receiver<SegQueue<TaskEvent>>,
+    /// The latest stop or continuation not yet waited for, kept for the next
+    /// caller who asks for it.
+    pending: Arc<spin::Mutex<Option<TaskEvent>>>,
 }
 
 #[derive(Debug)]
 pub struct Task {
-    parent: Weak<Task>,
+    /// The parent task, which is replaced with the init process when the
+    /// original one exits.
+    parent: spin::Mutex<Weak<Task>>,
     children: spin::Mutex<Vec<Child>>,
     tid: usize,
+    /// The ID of the process, which is the TID of its first thread.
+    tgid: usize,
     executable: spin::Mutex<String>,
+    /// The resources exposed through `/proc`, which are released as soon as
+    /// the task exits.
+    info: spin::Mutex<Option<TaskInfo>>,
 
     times: Arc<Times>,
+    job: Arc<Job>,
+    oom: Arc<Oom>,
 
     sig: Signals,
     shared_sig: AtomicArsc<Signals>,
     event: Broadcast<SegQueue<TaskEvent>>,
 }
 
+/// The resources of a task exposed to other ones, refreshed whenever they are
+/// replaced.
+#[derive(Clone)]
+pub struct TaskInfo {
+    pub virt: Arsc<Virt>,
+    pub files: Files,
+    pub cred: Credentials,
+    pub args: Arc<[String]>,
+    pub envs: Arc<[String]>,
+    /// The executable and its interpreter, which keep their mounts busy.
+    pub images: Arc<[Arc<dyn Entry>]>,
+}
+
+impl fmt::Debug for TaskInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskInfo")
+            .field("cred", &self.cred)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The PID of the init process.
+const INIT_PID: usize = 1;
+
+/// The init process, which adopts all the orphaned processes.
+static INIT: Once<Weak<Task>> = Once::new();
+
+fn init_task() -> Option<Arc<Task>> {
+    INIT.get().and_then(Weak::upgrade)
+}
+
 impl Task {
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    pub fn tgid(&self) -> usize {
+        self.tgid
+    }
+
+    fn parent(&self) -> Option<Arc<Task>> {
+        ksync::critical(|| self.parent.lock().upgrade())
+    }
+
+    pub fn ppid(&self) -> usize {
+        match self.parent() {
+            Some(parent) => parent.tgid,
+            // The init process has no parent.
+            None if self.tgid == INIT_PID => 0,
+            // Tasks spawned by the kernel are considered children of init.
+            None => INIT_PID,
+        }
+    }
+
+    pub fn pgid(&self) -> usize {
+        self.job.pgid()
+    }
+
+    pub fn sid(&self) -> usize {
+        self.job.sid()
+    }
+
+    pub fn oom_score(&self) -> usize {
+        oom::score(self)
+    }
+
+    pub fn oom_score_adj(&self) -> i32 {
+        self.oom.score_adj()
+    }
+
+    pub fn set_oom_score_adj(&self, score_adj: i32) -> Result<(), Error> {
+        self.oom.set_score_adj(score_adj)
+    }
+
+    pub fn executable(&self) -> String {
+        ksync::critical(|| self.executable.lock().clone())
+    }
+
+    /// Returns the user and system time of the thread, or of the whole
+    /// process if `process` is set, followed by the ones of the reaped
+    /// children.
+    pub fn cpu_times(&self, process: bool) -> [Duration; 4] {
+        let [user, system] = match process {
+            true => self.times.get_process(),
+            false => self.times.get_thread(),
+        };
+        let [children_user, children_system] = self.times.get_children();
+        [user, system, children_user, children_system]
+    }
+
+    /// Returns the resources of the task, or `None` if it has exited.
+    pub fn info(&self) -> Option<TaskInfo> {
+        ksync::critical(|| self.info.lock().clone())
+    }
+
+    /// Hands over all the children to `new_parent`, or to the init process if
+    /// not specified. Children left with no one to adopt them are reaped.
+    fn reparent_children(&self, new_parent: Option<Arc<Task>>) {
+        let children = ksync::critical(|| mem::take(&mut *self.children.lock()));
+        if children.is_empty() {
+            return;
+        }
+        let new_parent = match new_parent.or_else(init_task) {
+            Some(parent) if !ptr::eq(&*parent, self) => parent,
+            _ => return,
+        };
+        log::trace!(
+            "task::reparent: {} child(ren) of {} => {}",
+            children.len(),
+            self.tid,
+            new_parent.tid
+        );
+        let weak = Arc::downgrade(&new_parent);
+        ksync::critical(|| {
+            for child in &children {
+                *child.task.parent.lock() = weak.clone();
+            }
+            new_parent.children.lock().extend(children);
+        });
+        // Wake up the new parent for the zombies among the adopted children.
+        new_parent.sig.push(SigInfo {
+            sig: Sig::SIGCHLD,
+            code: sygnal::SigCode::KERNEL as _,
+            fields: sygnal::SigFields::None,
+        });
+    }
+
     fn event(&self) -> Receiver<SegQueue<TaskEvent>> {
         let (tx, rx) = unbounded();
         self.event.subscribe(tx);
@@ -88,6 +244,12 @@ impl Task {
     }
 }
 
+impl Drop for Task {
+    fn drop(&mut self) {
+        pid::remove(self.tid);
+    }
+}
+
 pub struct TaskState {
     pub(crate) task: Arc<Task>,
     tgroup: Arsc<(usize, spin::RwLock<Vec<Arc<Task>>>)>,
@@ -95,6 +257,8 @@ pub struct TaskState {
 
     sig_mask: SigSet,
     sig_stack: Option<SigStack>,
+    /// The start and the current end of the heap.
+    pub(crate) brk_start: usize,
     pub(crate) brk: usize,
 
     pub(crate) virt: Arsc<Virt>,
@@ -102,6 +266,8 @@ pub struct TaskState {
     pub(crate) shm: Arsc<Shm>,
     sig_actions: Arsc<ActionSet>,
     pub(crate) files: Files,
+    /// The credentials shared by the whole thread group.
+    cred: Arsc<spin::RwLock<Credentials>>,
     tid_clear: Option<UserPtr<usize, Out>>,
     exit_signal: Option<Sig>,
 }
@@ -123,52 +289,113 @@ impl From<isize> for PidSelection {
     }
 }
 
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct WaitOptions: i32 {
+        const NOHANG    = 1;
+        const UNTRACED  = 2;
+        const CONTINUED = 8;
+    }
+}
+
 impl TaskState {
-    async fn wait(&self, pid: PidSelection) -> Result<(TaskEvent, usize), Error> {
-        let (res, tid) = match pid {
-            PidSelection::Task(None) => {
-                let children = ksync::critical(|| self.task.children.lock().clone());
-                log::trace!("task::wait found {} child(ren)", children.len());
-
-                for c in children.iter() {
-                    self.task.times.append_child(&c.task.times)
-                }
-                match &children[..] {
-                    [] => return Err(ECHILD),
-                    [a] => (a.event.recv().await, a.task.tid),
-                    [a, b] => match select(a.event.recv(), b.event.recv()).await {
-                        Either::Left((te, _)) => (te, a.task.tid),
-                        Either::Right((te, _)) => (te, b.task.tid),
-                    },
-                    _ => {
-                        let events = children.iter().map(|c| &c.event);
-                        let select_all = select_all(events.map(|event| event.recv())).await;
-                        (select_all.0, children[select_all.1].task.tid)
-                    }
+    /// Returns a snapshot of the credentials of the thread group.
+    pub(crate) fn cred(&self) -> Credentials {
+        ksync::critical(|| self.cred.read().clone())
+    }
+
+    /// Changes the credentials of the thread group, and publishes them to all
+    /// of its threads.
+    fn update_cred<T>(&self, f: impl FnOnce(&mut Credentials) -> T) -> T {
+        ksync::critical(|| {
+            let mut cred = self.cred.write();
+            let ret = f(&mut cred);
+            for task in self.tgroup.1.read().iter() {
+                if let Some(info) = &mut *task.info.lock() {
+                    info.cred = cred.clone();
                 }
             }
-            PidSelection::Task(Some(tid)) if tid == self.task.tid => return Err(EPERM),
-            PidSelection::Task(Some(tid)) => {
-                let child = ksync::critical(|| {
-                    let children = self.task.children.lock();
-                    for c in children.iter() {
-                        self.task.times.append_child(&c.task.times)
-                    }
-                    children.iter().find(|c| c.task.tid == tid).cloned()
-                });
-                (child.ok_or(ECHILD)?.event.recv().await, tid)
+            ret
+        })
+    }
+
+    async fn wait(
+        &self,
+        pid: PidSelection,
+        options: WaitOptions,
+    ) -> Result<(TaskEvent, usize), Error> {
+        if let PidSelection::Task(Some(tid)) = pid {
+            if tid == self.task.tid {
+                return Err(EPERM);
             }
-            x => todo!("{x:?}"),
+        }
+        let pgid = self.task.job.pgid();
+        let selected = |c: &&Child| match pid {
+            PidSelection::Task(None) => true,
+            PidSelection::Task(Some(tid)) => c.task.tid == tid,
+            PidSelection::Group(None) => c.task.job.pgid() == pgid,
+            PidSelection::Group(Some(pgid)) => c.task.job.pgid() == pgid,
         };
-        log::trace!("task::wait tid = {tid}, event = {res:?}");
-        let event = match res {
-            Ok(w) => w,
-            Err(e) => e.data().ok_or(ECHILD)?,
+        let children = ksync::critical(|| {
+            let children = self.task.children.lock();
+            for c in children.iter() {
+                self.task.times.append_child(&c.task.times)
+            }
+            children
+                .iter()
+                .filter(selected)
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        log::trace!("task::wait found {} child(ren)", children.len());
+
+        let reported = |event: &TaskEvent| match event {
+            TaskEvent::Exited(..) => true,
+            TaskEvent::Suspended(_) => options.contains(WaitOptions::UNTRACED),
+            TaskEvent::Continued => options.contains(WaitOptions::CONTINUED),
         };
-        if matches!(event, TaskEvent::Exited(..)) {
-            ksync::critical(|| self.task.children.lock().retain(|c| c.task.tid != tid));
+        for c in children.iter() {
+            let pending = ksync::critical(|| {
+                let mut pending = c.pending.lock();
+                match *pending {
+                    Some(event) if reported(&event) => pending.take(),
+                    _ => None,
+                }
+            });
+            if let Some(event) = pending {
+                return Ok((event, c.task.tid));
+            }
+        }
+
+        loop {
+            let (res, child) = match &children[..] {
+                [] => return Err(ECHILD),
+                [a] => (a.event.recv().await, a),
+                [a, b] => match select(a.event.recv(), b.event.recv()).await {
+                    Either::Left((te, _)) => (te, a),
+                    Either::Right((te, _)) => (te, b),
+                },
+                _ => {
+                    let events = children.iter().map(|c| &c.event);
+                    let select_all = select_all(events.map(|event| event.recv())).await;
+                    (select_all.0, &children[select_all.1])
+                }
+            };
+            let tid = child.task.tid;
+            log::trace!("task::wait tid = {tid}, event = {res:?}");
+            let event = match res {
+                Ok(w) => w,
+                Err(e) => e.data().ok_or(ECHILD)?,
+            };
+            if let TaskEvent::Exited(..) = event {
+                ksync::critical(|| self.task.children.lock().retain(|c| c.task.tid != tid));
+            }
+            if reported(&event) {
+                break Ok((event, tid));
+            }
+            // Leave it to the callers who ask for it.
+            ksync::critical(|| *child.pending.lock() = Some(event));
         }
-        Ok((event, tid))
     }
 
     async fn cleanup(mut self, code: i32, sig: Option<Sig>) {
@@ -177,21 +404,27 @@ impl TaskState {
             self.futex.notify(tid_clear.to_futex_key(), 1);
         }
 
-        let last_thread = ksync::critical(|| {
+        let next_thread = ksync::critical(|| {
             let mut tgroup = self.tgroup.1.write();
             let index = tgroup.iter().position(|t| Arc::ptr_eq(t, &self.task));
             tgroup.swap_remove(index.unwrap());
-            tgroup.is_empty()
+            tgroup.first().cloned()
         });
+        let last_thread = next_thread.is_none();
+        // The children of a thread are adopted by another thread in the group,
+        // or by the init process if it is the last one.
+        self.task.reparent_children(next_thread);
         if last_thread {
+            job::leave(self.tgroup.0, &self.task.job);
+
             let exit_signal = self.exit_signal.take();
-            if let (Some(sig), Some(parent)) = (exit_signal, self.task.parent.upgrade()) {
+            if let (Some(sig), Some(parent)) = (exit_signal, self.task.parent()) {
                 parent.sig.push(SigInfo {
                     sig,
                     code: sygnal::SigCode::USER as _,
                     fields: sygnal::SigFields::SigChld {
                         pid: self.task.tid,
-                        uid: 0,
+                        uid: self.cred().uid.real as usize,
                         status: code,
                     },
                 })
@@ -204,6 +437,8 @@ impl TaskState {
         }
 
         let _ = self.files.flush_all().await;
+        let info = ksync::critical(|| self.task.info.lock().take());
+        drop(info);
 
         self.task.event.send(&TaskEvent::Exited(code, sig)).await;
         log::trace!("Sent exited event {code} {sig:?}");
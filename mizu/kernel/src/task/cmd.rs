@@ -4,16 +4,12 @@ use alloc::{
     vec,
     vec::Vec,
 };
-use core::{
-    ffi::CStr,
-    mem,
-    sync::atomic::{AtomicUsize, Ordering::SeqCst},
-};
+use core::{ffi::CStr, mem, sync::atomic::Ordering::SeqCst};
 
 use arsc_rs::Arsc;
 use co_trap::TrapFrame;
 use kmem::{Frame, Phys, Virt};
-use ksc::Error::{self, EISDIR, ENOSYS};
+use ksc::Error::{self, EACCES, EISDIR, ENOSYS};
 use ksync::channel::Broadcast;
 use rand_riscv::rand_core::RngCore;
 use riscv::register::sstatus;
@@ -21,28 +17,36 @@ use rv39_paging::{Attr, LAddr, PAGE_MASK, PAGE_SHIFT, PAGE_SIZE};
 use sygnal::{Action, ActionSet, Sig, SigSet};
 use umifs::{
     path::Path,
+    traits::Entry,
     types::{OpenOptions, Permissions},
 };
 
 use crate::{
     executor,
+    fs::MountFlags,
     mem::Futexes,
     task::{
         elf, fd,
         fd::Files,
         future::{user_loop, TaskFut},
-        Task, TaskState, DEFAULT_STACK_ATTR, DEFAULT_STACK_SIZE,
+        job::{self, Job},
+        oom::Oom,
+        pid, Access, Credentials, Task, TaskInfo, TaskState, DEFAULT_STACK_ATTR,
+        DEFAULT_STACK_SIZE, INIT, INIT_PID,
     },
 };
 
 #[derive(Default)]
 pub struct Command {
     image: Option<Arc<Phys>>,
+    exe: Option<Arc<dyn Entry>>,
     executable: String,
     virt: Option<Arsc<Virt>>,
     parent: Weak<Task>,
     args: Vec<String>,
     envs: Vec<String>,
+    cred: Credentials,
+    init: bool,
 }
 
 impl Command {
@@ -58,27 +62,31 @@ impl Command {
         self
     }
 
+    /// Makes the new task the init process, which has the PID 1 and adopts all
+    /// the orphaned processes.
+    pub fn init(&mut self) -> &mut Self {
+        self.init = true;
+        self
+    }
+
+    /// Sets the credentials of the new task, which should be set before
+    /// opening the image for the checks and set-ID bits to apply.
+    pub fn cred(&mut self, cred: Credentials) -> &mut Self {
+        self.cred = cred;
+        self
+    }
+
     pub async fn open(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, Error> {
-        let (entry, _) = crate::fs::open(
-            path.as_ref(),
-            OpenOptions::RDONLY,
-            Permissions::SELF_R | Permissions::SELF_X,
-        )
-        .await?;
-        let io = entry.to_io().ok_or(EISDIR)?;
-        self.image = Some(Arc::new(crate::mem::new_phys(io, true)));
+        let (image, exe) = open_image(path.as_ref(), &mut self.cred).await?;
+        self.image = Some(Arc::new(image));
+        self.exe = Some(exe);
         Ok(self)
     }
 
     pub async fn open_executable(&mut self) -> Result<&mut Self, Error> {
-        let (entry, _) = crate::fs::open(
-            self.executable.as_ref(),
-            OpenOptions::RDONLY,
-            Permissions::SELF_R | Permissions::SELF_X,
-        )
-        .await?;
-        let io = entry.to_io().ok_or(EISDIR)?;
-        self.image = Some(Arc::new(crate::mem::new_phys(io, true)));
+        let (image, exe) = open_image(self.executable.as_ref(), &mut self.cred).await?;
+        self.image = Some(Arc::new(image));
+        self.exe = Some(exe);
         Ok(self)
     }
 
@@ -115,25 +123,31 @@ impl Command {
     async fn build(&mut self) -> Result<InitTask, Error> {
         let Command {
             image,
+            exe,
             executable,
             virt,
             parent,
             args,
             envs,
+            cred,
+            init: _,
         } = mem::take(self);
         InitTask::from_elf(
             executable,
             parent,
             &image.expect("Require an image"),
+            exe,
             virt.unwrap_or_else(crate::mem::new_virt),
             args,
             envs,
+            cred,
         )
         .await
     }
 
     pub async fn spawn(&mut self) -> Result<Arc<Task>, Error> {
-        self.build().await?.spawn()
+        let init = self.init;
+        self.build().await?.spawn(init)
     }
 
     pub async fn exec(&mut self, ts: &mut TaskState, tf: &mut TrapFrame) -> Result<(), Error> {
@@ -142,12 +156,36 @@ impl Command {
     }
 }
 
+async fn open_image(path: &Path, cred: &mut Credentials) -> Result<(Phys, Arc<dyn Entry>), Error> {
+    let (entry, _) = crate::fs::open(
+        path,
+        OpenOptions::empty(),
+        Permissions::SELF_R | Permissions::SELF_X,
+    )
+    .await?;
+    let flags = crate::fs::flags_of(&entry);
+    if flags.contains(MountFlags::NOEXEC) {
+        return Err(EACCES);
+    }
+    let metadata = entry.metadata().await;
+    cred.check(&metadata, Access::EXEC)?;
+    cred.exec(&metadata, flags.contains(MountFlags::NOSUID));
+    let io = entry.clone().to_io().ok_or(EISDIR)?;
+    Ok((crate::mem::new_phys(io, true), entry))
+}
+
 struct InitTask {
     executable: String,
     parent: Weak<Task>,
     virt: Arsc<Virt>,
     tf: TrapFrame,
+    brk_start: usize,
     files: Files,
+    cred: Credentials,
+    /// The arguments and environment variables, as shown in `/proc`.
+    args: Arc<[String]>,
+    envs: Arc<[String]>,
+    images: Arc<[Arc<dyn Entry>]>,
 }
 
 impl InitTask {
@@ -158,10 +196,13 @@ impl InitTask {
         envs: &[String],
         auxv: &[(u8, usize)],
     ) -> Result<LAddr, Error> {
+        const AT_RANDOM: u8 = 25; // Address of 16 random bytes
+
         let argc_len = mem::size_of::<usize>();
         let argv_len = mem::size_of::<usize>() * (args.len() + 1);
         let envp_len = mem::size_of::<usize>() * (envs.len() + 1);
-        let auxv_len = mem::size_of::<usize>() * (auxv.len() * 2 + 1);
+        // Followed by `AT_RANDOM` and the terminating `AT_NULL`.
+        let auxv_len = mem::size_of::<[usize; 2]>() * (auxv.len() + 2);
         let rand_len = mem::size_of::<u64>() * 2;
         let args_len = args.iter().map(|s| s.len() + 1).sum::<usize>();
         let envs_len = envs.iter().map(|s| s.len() + 1).sum::<usize>();
@@ -171,9 +212,9 @@ impl InitTask {
             return Err(ENOSYS);
         }
         let kernel_end = LAddr::from(frame.as_ptr_range().end);
-        let ret = LAddr::from((stack - len).val() & !7);
+        let ret = LAddr::from((stack - len).val() & !15);
 
-        let argc_ptr = LAddr::from((kernel_end - len).val() & !7);
+        let argc_ptr = LAddr::from((kernel_end - len).val() & !15);
         let mut argv_ptr = argc_ptr + argc_len;
         let argv_addr = ret + argc_len;
 
@@ -211,12 +252,8 @@ impl InitTask {
             envs_addr += src.len() + 1;
         }
 
-        for (idx, val) in auxv.iter().copied() {
-            let val = if val == 0xdeadbeef {
-                rand_addr.val()
-            } else {
-                val
-            };
+        let random = [(AT_RANDOM, rand_addr.val()), (0, 0)];
+        for (idx, val) in auxv.iter().chain(&random).copied() {
             auxv_ptr.cast::<[usize; 2]>().write([idx as usize, val]);
             auxv_ptr += mem::size_of::<[usize; 2]>();
         }
@@ -292,21 +329,25 @@ impl InitTask {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn from_elf(
         executable: String,
         parent: Weak<Task>,
         phys: &Arc<Phys>,
+        exe: Option<Arc<dyn Entry>>,
         virt: Arsc<Virt>,
         args: Vec<String>,
         envs: Vec<String>,
+        cred: Credentials,
     ) -> Result<Self, Error> {
         const AT_PHDR: u8 = 3; // Program header table base address
         const AT_PHENT: u8 = 4; // Size of program header entry
         const AT_PHNUM: u8 = 5; // Number of program headers
         const AT_PAGESZ: u8 = 6;
         const AT_BASE: u8 = 7; // Load base address
-        const AT_RANDOM: u8 = 25;
 
+        let (shown_args, shown_envs) = (args.as_slice().into(), envs.as_slice().into());
+        let mut images = Vec::from_iter(exe);
         let (loaded, args) = match elf::get_interp(phys).await? {
             Some(interp) => {
                 let mut interp = CStr::from_bytes_until_nul(&interp)?.to_str()?.to_string();
@@ -314,25 +355,23 @@ impl InitTask {
 
                 let (entry, _) = crate::fs::open(
                     interp.as_ref(),
-                    OpenOptions::RDONLY,
+                    OpenOptions::empty(),
                     Permissions::SELF_R | Permissions::SELF_X,
                 )
                 .await?;
-                let phys = crate::mem::new_phys(entry.to_io().ok_or(EISDIR)?, true);
+                let phys = crate::mem::new_phys(entry.clone().to_io().ok_or(EISDIR)?, true);
+                images.push(entry);
                 let loaded = elf::load(&Arc::new(phys), None, &virt).await?;
 
                 let args = [interp, "--library-path=/".into()].into_iter().chain(args);
                 (loaded, args.collect())
             }
-            None => {
-                let loaded = elf::load(phys, None, &virt).await?;
-                if loaded.is_dyn {
-                    return Err(ENOSYS);
-                }
-                (loaded, args)
-            }
+            // Static PIEs relocate themselves, and are loaded at a randomized base
+            // as well.
+            None => (elf::load(phys, None, &virt).await?, args),
         };
         virt.commit(loaded.entry, Attr::USER_RX).await?;
+        let brk_start = crate::mem::brk_start(&virt, loaded.range.end).await?;
 
         let base = loaded.range.start;
 
@@ -343,7 +382,6 @@ impl InitTask {
             &envs,
             &[
                 (AT_PAGESZ, PAGE_SIZE),
-                (AT_RANDOM, 0xdeadbeef),
                 (AT_BASE, base.val()),
                 (AT_PHDR, base.val() + loaded.header.e_phoff as usize),
                 (AT_PHENT, loaded.header.e_phentsize as usize),
@@ -359,24 +397,50 @@ impl InitTask {
             parent,
             virt,
             tf,
+            brk_start,
             files: Files::new(fd::default_stdio().await?, "/".into()),
+            cred,
+            args: shown_args,
+            envs: shown_envs,
+            images: images.into(),
         })
     }
 
-    fn spawn(self) -> Result<Arc<Task>, ksc::Error> {
-        let tid = alloc_tid();
+    fn info(&self) -> TaskInfo {
+        TaskInfo {
+            virt: self.virt.clone(),
+            files: self.files.share(),
+            cred: self.cred.clone(),
+            args: self.args.clone(),
+            envs: self.envs.clone(),
+            images: self.images.clone(),
+        }
+    }
+
+    fn spawn(self, init: bool) -> Result<Arc<Task>, ksc::Error> {
+        let tid = if init { INIT_PID } else { pid::alloc() };
+        let info = self.info();
         let task = Arc::new(Task {
             executable: spin::Mutex::new(self.executable),
-            parent: self.parent,
+            parent: spin::Mutex::new(self.parent),
             children: spin::Mutex::new(Default::default()),
             tid,
+            tgid: tid,
+            info: spin::Mutex::new(Some(info)),
 
             times: Default::default(),
+            job: Job::new(tid, tid),
+            oom: Oom::new(0),
 
             sig: Default::default(),
             shared_sig: Default::default(),
             event: Broadcast::new(),
         });
+        pid::insert(&task);
+        job::join(&task);
+        if init {
+            INIT.call_once(|| Arc::downgrade(&task));
+        }
 
         let ts = TaskState {
             task: task.clone(),
@@ -384,33 +448,44 @@ impl InitTask {
             counters: super::time::counters(),
             sig_mask: Action::default_sig_mask(),
             sig_stack: None,
-            brk: 0,
+            brk_start: self.brk_start,
+            brk: self.brk_start,
             virt: self.virt,
             futex: Arsc::new(Futexes::new()),
             shm: Default::default(),
             files: self.files,
+            cred: Arsc::new(spin::RwLock::new(self.cred)),
             sig_actions: Arsc::new(ActionSet::new()),
             tid_clear: None,
             exit_signal: Some(Sig::SIGCHLD),
         };
 
-        let fut = TaskFut::new(ts.virt.clone(), user_loop(ts, self.tf));
+        let fut = TaskFut::new(task.clone(), ts.virt.clone(), user_loop(ts, self.tf));
         executor().spawn(fut).detach();
 
         Ok(task)
     }
 
     pub async fn reset(self, ts: &mut TaskState, tf: &mut TrapFrame) {
-        ksync::critical(|| *ts.task.executable.lock() = self.executable);
+        let info = TaskInfo {
+            files: ts.files.share(),
+            ..self.info()
+        };
+        ksync::critical(|| {
+            *ts.task.executable.lock() = self.executable;
+            *ts.task.info.lock() = Some(info);
+        });
         crate::trap::FP.with(|fp| fp.mark_reset());
         crate::task::yield_now().await;
         ts.task.shared_sig.swap(Default::default(), SeqCst);
         ts.sig_mask = SigSet::EMPTY;
         ts.sig_stack = None;
-        ts.brk = 0;
+        ts.brk_start = self.brk_start;
+        ts.brk = self.brk_start;
         ts.virt = self.virt;
         ts.futex = Arsc::new(Default::default());
         ts.files.close_on_exec().await;
+        ts.update_cred(|cred| *cred = self.cred);
         ts.sig_actions = Arsc::new(ActionSet::new());
         ts.tid_clear = None;
         ts.exit_signal = Some(Sig::SIGCHLD);
@@ -418,8 +493,3 @@ impl InitTask {
         super::yield_now().await
     }
 }
-
-pub(super) fn alloc_tid() -> usize {
-    static TID: AtomicUsize = AtomicUsize::new(2);
-    TID.fetch_add(1, SeqCst)
-}
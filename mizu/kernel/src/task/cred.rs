@@ -0,0 +1,231 @@
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+use ksc::Error::{self, EACCES, EPERM};
+use umifs::types::{FileType, Metadata, OpenOptions, Permissions};
+
+bitflags! {
+    /// The access requested on a file, in the layout of `access(2)`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Access: u32 {
+        const READ  = 4;
+        const WRITE = 2;
+        const EXEC  = 1;
+    }
+}
+
+impl Access {
+    pub fn from_options(options: OpenOptions) -> Self {
+        if options.contains(OpenOptions::PATH) {
+            return Access::empty();
+        }
+        // The access mode from the user is `O_RDONLY = 0`, `O_WRONLY = 1` and
+        // `O_RDWR = 2`, which doesn't match `OpenOptions::ACCMODE`.
+        let access = match options.bits() & 3 {
+            0 => Access::READ,
+            1 => Access::WRITE,
+            _ => Access::READ | Access::WRITE,
+        };
+        match options.contains(OpenOptions::TRUNC) {
+            true => access | Access::WRITE,
+            false => access,
+        }
+    }
+}
+
+/// A triple of real, effective and saved IDs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ids {
+    pub real: u32,
+    pub effective: u32,
+    pub saved: u32,
+}
+
+impl Ids {
+    fn contains(&self, id: u32) -> bool {
+        self.real == id || self.effective == id || self.saved == id
+    }
+
+    /// The semantics of `setuid(2)`.
+    pub fn set(&mut self, id: u32, privileged: bool) -> Result<(), Error> {
+        if privileged {
+            *self = Ids {
+                real: id,
+                effective: id,
+                saved: id,
+            };
+        } else if id == self.real || id == self.saved {
+            self.effective = id;
+        } else {
+            return Err(EPERM);
+        }
+        Ok(())
+    }
+
+    /// The semantics of `setreuid(2)`.
+    pub fn set_re(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        privileged: bool,
+    ) -> Result<(), Error> {
+        if !privileged {
+            let real_ok = real.map_or(true, |id| id == self.real || id == self.effective);
+            let effective_ok = effective.map_or(true, |id| self.contains(id));
+            if !real_ok || !effective_ok {
+                return Err(EPERM);
+            }
+        }
+        let old_real = self.real;
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if real.is_some() || effective.is_some_and(|id| id != old_real) {
+            self.saved = self.effective;
+        }
+        Ok(())
+    }
+
+    /// The semantics of `setresuid(2)`.
+    pub fn set_res(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        saved: Option<u32>,
+        privileged: bool,
+    ) -> Result<(), Error> {
+        let ids = [real, effective, saved];
+        if !privileged && ids.iter().flatten().any(|&id| !self.contains(id)) {
+            return Err(EPERM);
+        }
+        self.real = real.unwrap_or(self.real);
+        self.effective = effective.unwrap_or(self.effective);
+        self.saved = saved.unwrap_or(self.saved);
+        Ok(())
+    }
+}
+
+/// The identity a task acts with. Every ID defaults to root.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub uid: Ids,
+    pub gid: Ids,
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn is_root(&self) -> bool {
+        self.uid.effective == 0
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid.effective == gid || self.groups.contains(&gid)
+    }
+
+    /// Returns the credentials that check accesses with the real IDs instead,
+    /// as `access(2)` does.
+    pub fn real(&self) -> Self {
+        let mut ret = self.clone();
+        ret.uid.effective = self.uid.real;
+        ret.gid.effective = self.gid.real;
+        ret
+    }
+
+    /// Checks the access to an entry against its owner, group and mode.
+    pub fn check(&self, metadata: &Metadata, access: Access) -> Result<(), Error> {
+        let bits = metadata.perm.bits();
+        if self.is_root() {
+            // Root can do anything but execute files that nobody can execute.
+            let executable = metadata.ty == FileType::DIR || bits & 0o111 != 0;
+            return match !access.contains(Access::EXEC) || executable {
+                true => Ok(()),
+                false => Err(EACCES),
+            };
+        }
+        let mode = if self.uid.effective == metadata.uid {
+            bits >> 6
+        } else if self.in_group(metadata.gid) {
+            bits >> 3
+        } else {
+            bits
+        };
+        match Access::from_bits_truncate(mode).contains(access) {
+            true => Ok(()),
+            false => Err(EACCES),
+        }
+    }
+
+    /// Checks the removal of `entry` from `dir`, if `dir` is sticky.
+    pub fn check_sticky(&self, dir: &Metadata, entry: &Metadata) -> Result<(), Error> {
+        let owner = self.uid.effective;
+        match dir.perm.contains(Permissions::STICKY)
+            && !self.is_root()
+            && owner != dir.uid
+            && owner != entry.uid
+        {
+            true => Err(EPERM),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks whether the caller may send signals to a task acting with
+    /// `target`, as `kill(2)` requires.
+    pub fn check_kill(&self, target: &Credentials) -> Result<(), Error> {
+        let sender = [self.uid.real, self.uid.effective];
+        match self.is_root()
+            || sender.contains(&target.uid.real)
+            || sender.contains(&target.uid.saved)
+        {
+            true => Ok(()),
+            false => Err(EPERM),
+        }
+    }
+
+    /// Checks whether the caller owns an entry, as changing its mode requires.
+    pub fn check_owner(&self, metadata: &Metadata) -> Result<(), Error> {
+        match self.is_root() || self.uid.effective == metadata.uid {
+            true => Ok(()),
+            false => Err(EPERM),
+        }
+    }
+
+    /// Checks whether the caller may change the owner or group of an entry.
+    pub fn check_chown(
+        &self,
+        metadata: &Metadata,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<(), Error> {
+        if self.is_root() {
+            return Ok(());
+        }
+        // Only root gives entries away, while the owner can change the group
+        // to one of its own.
+        let uid_ok = uid.map_or(true, |uid| uid == metadata.uid && uid == self.uid.effective);
+        let gid_ok = gid.map_or(true, |gid| {
+            self.uid.effective == metadata.uid && self.in_group(gid)
+        });
+        match uid_ok && gid_ok {
+            true => Ok(()),
+            false => Err(EPERM),
+        }
+    }
+
+    /// Applies the set-user-ID and set-group-ID bits of an executable.
+    pub fn exec(&mut self, metadata: &Metadata, nosuid: bool) {
+        if !nosuid && metadata.perm.contains(Permissions::SET_UID) {
+            self.uid.effective = metadata.uid;
+        }
+        // Without the group execute bit, the set-group-ID bit means mandatory
+        // locking instead.
+        let setgid = Permissions::SET_GID | Permissions::GROUP_X;
+        if !nosuid && metadata.perm.contains(setgid) {
+            self.gid.effective = metadata.gid;
+        }
+        self.uid.saved = self.uid.effective;
+        self.gid.saved = self.gid.effective;
+    }
+}
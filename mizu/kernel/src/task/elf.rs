@@ -35,7 +35,6 @@ impl From<Error> for ksc::Error {
 }
 
 pub struct LoadedElf {
-    pub is_dyn: bool,
     pub range: Range<LAddr>,
     pub header: Header,
     /// Note: The size of the stack can be zero and the caller should check it
@@ -265,7 +264,6 @@ pub async fn load(phys: &Phys, force_dyn: Option<bool>, virt: &Virt) -> Result<L
         if is_dyn { "dynamic" } else { "static" }
     );
     Ok(LoadedElf {
-        is_dyn,
         range: base..(base + (max - min)),
         header,
         stack,
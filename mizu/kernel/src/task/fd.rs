@@ -9,19 +9,19 @@ use core::{
 use arsc_rs::Arsc;
 use futures_util::future::join_all;
 use hashbrown::HashMap;
-use ksc::Error::{self, EBADF, EMFILE};
+use ksc::Error::{self, EBADF, EINVAL, EMFILE, ENOTDIR};
 use ksync::RwLock;
 use rand_riscv::RandomState;
 use spin::Mutex;
 use umifs::{
     path::{Path, PathBuf},
-    traits::Entry,
+    traits::{DirectoryMut, Entry},
     types::{DirEntry, OpenOptions, Permissions},
 };
 use umio::IntoAnyExt;
 
 pub use self::syscall::*;
-use crate::fs::socket::SocketFile;
+use crate::{fs::socket::SocketFile, task::Credentials};
 
 pub const MAX_FDS: usize = 65536;
 const CWD: i32 = -100;
@@ -166,12 +166,9 @@ impl Files {
     pub async fn get_fi(&self, fd: i32) -> Result<FdInfo, Error> {
         match fd {
             CWD => {
-                let entry = crate::fs::open_dir(
-                    &self.cwd(),
-                    OpenOptions::RDONLY | OpenOptions::DIRECTORY,
-                    Permissions::SELF_R,
-                )
-                .await?;
+                let entry =
+                    crate::fs::open_dir(&self.cwd(), OpenOptions::DIRECTORY, Permissions::SELF_R)
+                        .await?;
                 Ok(FdInfo {
                     entry,
                     close_on_exec: false,
@@ -204,6 +201,43 @@ impl Files {
         self.get_fi(fd).await.map(|fi| fi.entry)
     }
 
+    /// Opens `path` relative to the directory `fd`, or from the root if `root`
+    /// is set, on behalf of `cred`.
+    pub async fn open_at(
+        &self,
+        cred: &Credentials,
+        fd: i32,
+        path: &Path,
+        root: bool,
+        options: OpenOptions,
+        perm: Permissions,
+    ) -> Result<(Arc<dyn Entry>, bool), Error> {
+        let (base, path) = match fd {
+            _ if root => (None, path.to_path_buf()),
+            CWD => (None, self.cwd().join(path)),
+            _ => (Some(self.get(fd).await?), path.to_path_buf()),
+        };
+        crate::fs::open_as(cred, base, &path, options, perm).await
+    }
+
+    /// Opens the parent directory of `path` relative to the directory `fd` on
+    /// behalf of `cred`, and returns it along with the final component of
+    /// `path`.
+    pub async fn open_parent<'a>(
+        &self,
+        cred: &Credentials,
+        fd: i32,
+        path: &'a Path,
+        root: bool,
+    ) -> Result<(Arc<dyn DirectoryMut>, &'a str), Error> {
+        let name = path.file_name().ok_or(EINVAL)?;
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let options = OpenOptions::PATH | OpenOptions::DIRECTORY;
+        let open = self.open_at(cred, fd, parent, root, options, Permissions::SELF_R);
+        let (dir, _) = open.await?;
+        Ok((dir.to_dir_mut().ok_or(ENOTDIR)?, name))
+    }
+
     pub async fn close(&self, fd: i32) -> Result<(), Error> {
         match self.fds.map.write().await.remove(&fd) {
             Some(fi) => {
@@ -233,6 +267,28 @@ impl Files {
         join_all(iter).await;
     }
 
+    /// Returns another handle to the same table and working directory.
+    pub fn share(&self) -> Self {
+        Files {
+            fds: self.fds.clone(),
+            cwd: self.cwd.clone(),
+        }
+    }
+
+    /// Returns the opened file descriptors in ascending order.
+    pub async fn fds(&self) -> Vec<i32> {
+        let mut fds = self
+            .fds
+            .map
+            .read()
+            .await
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        fds.sort_unstable();
+        fds
+    }
+
     pub async fn deep_fork(&self, share_cwd: bool, share_fd: bool) -> Self {
         Files {
             cwd: if share_cwd {
@@ -280,7 +336,7 @@ pub async fn default_stdio() -> Result<[Arc<dyn Entry>; 3], Error> {
     let stdout = stderr.clone();
     let stdin = stderr
         .clone()
-        .open("".as_ref(), OpenOptions::RDONLY, Permissions::SELF_R)
+        .open("".as_ref(), OpenOptions::empty(), Permissions::SELF_R)
         .await?
         .0;
     Ok([stdin, stdout, stderr])
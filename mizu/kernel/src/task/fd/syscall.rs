@@ -16,10 +16,10 @@ use umio::IntoAnyExt;
 pub use self::{fs::*, io::*, net::*};
 use super::Files;
 use crate::{
-    fs::CoverageFile,
-    mem::{In, Out, UserPtr},
+    fs::{CoverageFile, PtyMaster, Tty},
+    mem::{In, InOut, Out, UserPtr},
     syscall::ScRet,
-    task::{fd::FdInfo, TaskState},
+    task::{fd::FdInfo, job, Access, TaskState},
 };
 
 #[async_handler]
@@ -32,7 +32,7 @@ pub async fn readlinkat(
         let mut buf = [0; MAX_PATH_LEN];
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
 
-        let options = OpenOptions::RDONLY;
+        let options = OpenOptions::NOFOLLOW | OpenOptions::PATH;
         let perm = Default::default();
 
         log::trace!("user readlinkat fd = {fd}, path = {path:?}");
@@ -47,19 +47,15 @@ pub async fn readlinkat(
             return Ok(executable.len());
         }
 
-        let _entry = if root {
-            crate::fs::open(path, options, perm).await?.0
-        } else {
-            let base = ts.files.get(fd).await?;
-            match base.open(path, options, perm).await {
-                Ok((entry, _)) => entry,
-                Err(ENOENT) if ts.files.cwd() == "" => {
-                    crate::fs::open(path, options, perm).await?.0
-                }
-                Err(err) => return Err(err),
-            }
-        };
-        Err(EINVAL)
+        let (entry, _) = ts
+            .files
+            .open_at(&ts.cred(), fd, path, root, options, perm)
+            .await?;
+        let target = entry.read_link().await?;
+        let target = target.as_str().as_bytes();
+        let target = &target[..target.len().min(len)];
+        out.write_slice(&ts.virt, target, false).await?;
+        Ok(target.len())
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -78,15 +74,16 @@ pub async fn chdir(
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
 
         log::trace!("user chdir path = {path:?}");
-        if root {
-            crate::fs::open_dir(path, OpenOptions::RDONLY, Permissions::SELF_R).await?;
-
-            ts.files.chdir(path).await;
-        } else {
-            let path = ts.files.cwd().join(path);
-            crate::fs::open_dir(&path, OpenOptions::RDONLY, Permissions::SELF_R).await?;
-            ts.files.chdir(&path).await;
-        }
+        let path = match root {
+            true => path.to_path_buf(),
+            false => ts.files.cwd().join(path),
+        };
+        let cred = ts.cred();
+        let options = OpenOptions::PATH | OpenOptions::DIRECTORY;
+        let open = crate::fs::open_as(&cred, None, &path, options, Permissions::SELF_R);
+        let (dir, _) = open.await?;
+        cred.check(&dir.metadata().await, Access::EXEC)?;
+        ts.files.chdir(&path).await;
         Ok(())
     };
     cx.ret(fut.await);
@@ -226,31 +223,143 @@ pub async fn pipe(
     ScRet::Continue(None)
 }
 
-#[async_handler]
-pub async fn socket_pair(
-    ts: &mut TaskState,
-    cx: UserCx<'_, fn(usize, usize, usize, UserPtr<i32, Out>) -> Result<(), Error>>,
-) -> ScRet {
-    let fut = pipe_inner(&ts.files, &ts.virt, cx.args().3);
-    cx.ret(fut.await);
-    ScRet::Continue(None)
+const TCGETS: u32 = 0x5401;
+const TCSETS: u32 = 0x5402;
+const TCSETSW: u32 = 0x5403;
+const TCSETSF: u32 = 0x5404;
+const TCSBRK: u32 = 0x5409;
+const TCFLSH: u32 = 0x540b;
+const TIOCSCTTY: u32 = 0x540e;
+const TIOCGPGRP: u32 = 0x540f;
+const TIOCSPGRP: u32 = 0x5410;
+const TIOCGWINSZ: u32 = 0x5413;
+const TIOCSWINSZ: u32 = 0x5414;
+const FIONREAD: u32 = 0x541b;
+const TIOCNOTTY: u32 = 0x5422;
+const TIOCGSID: u32 = 0x5429;
+const TIOCGPTN: u32 = 0x80045430;
+const TIOCSPTLCK: u32 = 0x40045431;
+
+/// Checks that `tty` controls the session of the caller.
+///
+/// The console has no session leader to open it, so the first session that
+/// asks for its job control takes it as the controlling terminal.
+fn controlling(ts: &TaskState, tty: &Tty) -> Result<(), Error> {
+    let sid = ts.task.job.sid();
+    if tty.session() == 0 {
+        tty.attach(sid, ts.task.job.pgid());
+    }
+    match tty.session() == sid {
+        true => Ok(()),
+        false => Err(ENOTTY),
+    }
+}
+
+async fn tty_ioctl(
+    ts: &TaskState,
+    tty: &Tty,
+    request: u32,
+    arg: UserPtr<u8, InOut>,
+) -> Result<usize, Error> {
+    match request {
+        TCGETS => arg.cast().write(&ts.virt, tty.termios()).await?,
+        TCSETS | TCSETSW | TCSETSF => {
+            let termios = arg.cast().read(&ts.virt).await?;
+            tty.set_termios(termios, request == TCSETSF)
+        }
+        TCSBRK => {}
+        TCFLSH => match arg.addr().val() {
+            // TCIFLUSH or TCIOFLUSH; the output is never buffered.
+            0 | 2 => tty.flush_input(),
+            1 => {}
+            _ => return Err(EINVAL),
+        },
+        TIOCGWINSZ => arg.cast().write(&ts.virt, tty.winsize()).await?,
+        TIOCSWINSZ => tty.set_winsize(arg.cast().read(&ts.virt).await?),
+        FIONREAD => arg.cast().write(&ts.virt, tty.pending() as i32).await?,
+        TIOCGPGRP => {
+            controlling(ts, tty)?;
+            arg.cast().write(&ts.virt, tty.foreground() as i32).await?
+        }
+        TIOCGSID => {
+            controlling(ts, tty)?;
+            arg.cast().write(&ts.virt, tty.session() as i32).await?
+        }
+        TIOCSPGRP => {
+            controlling(ts, tty)?;
+            let pgid: i32 = arg.cast().read(&ts.virt).await?;
+            let pgid = usize::try_from(pgid).map_err(|_| EINVAL)?;
+            let members = job::members(pgid);
+            if members.is_empty() {
+                return Err(ESRCH);
+            }
+            if members.iter().any(|t| t.job.sid() != tty.session()) {
+                return Err(EPERM);
+            }
+            tty.set_foreground(pgid)
+        }
+        TIOCSCTTY => {
+            let sid = ts.task.job.sid();
+            if sid != ts.tgroup.0 {
+                return Err(EPERM);
+            }
+            // Only root steals the terminal from another session.
+            let steal = arg.addr().val() == 1 && ts.cred().is_root();
+            if tty.session() != 0 && tty.session() != sid && !steal {
+                return Err(EPERM);
+            }
+            tty.attach(sid, ts.task.job.pgid())
+        }
+        TIOCNOTTY => {
+            controlling(ts, tty)?;
+            if ts.task.job.sid() == ts.tgroup.0 {
+                tty.detach()
+            }
+        }
+        _ => return Err(ENOTTY),
+    }
+    Ok(0)
 }
 
 #[async_handler]
-pub async fn ioctl(ts: &mut TaskState, cx: UserCx<'_, fn(i32, i32) -> Result<(), Error>>) -> ScRet {
-    let (fd, request) = cx.args();
+pub async fn ioctl(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, u32, UserPtr<u8, InOut>) -> Result<usize, Error>>,
+) -> ScRet {
+    let (fd, request, arg) = cx.args();
     let fut = async {
         let file = ts.files.get(fd).await?;
-        if let Some(cov) = file.downcast::<CoverageFile>() {
-            const KCOV_ENABLE: i32 = 100 + ((b'c' as i32) << 8);
-            const KCOV_DISABLE: i32 = 101 + ((b'c' as i32) << 8);
+        if let Some(master) = crate::fs::inner(&file).downcast::<PtyMaster>() {
+            match request {
+                TIOCGPTN => {
+                    let index = master.index() as u32;
+                    return arg.cast().write(&ts.virt, index).await.map(|_| 0);
+                }
+                TIOCSPTLCK => {
+                    let locked: i32 = arg.cast().read(&ts.virt).await?;
+                    master.set_locked(locked != 0);
+                    return Ok(0);
+                }
+                _ => {}
+            }
+        }
+        if let Some(tty) = crate::fs::tty_of(&file) {
+            return tty_ioctl(ts, &tty, request, arg).await;
+        }
+        if let Some(cov) = crate::fs::inner(&file).downcast::<CoverageFile>() {
+            const KCOV_ENABLE: u32 = 100 + ((b'c' as u32) << 8);
+            const KCOV_DISABLE: u32 = 101 + ((b'c' as u32) << 8);
             match request {
                 KCOV_ENABLE => cov.enable(true),
                 KCOV_DISABLE => cov.enable(false),
                 _ => {}
             }
         }
-        Ok(())
+        // Terminal requests on anything else tell that it is not a terminal.
+        match (request >> 8) & 0xff == u32::from(b'T') {
+            true => Err(ENOTTY),
+            false => Ok(0),
+        }
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
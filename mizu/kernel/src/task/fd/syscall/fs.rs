@@ -1,26 +1,33 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, format, string::ToString, sync::Arc};
 use core::{
     alloc::Layout,
     mem::{self, MaybeUninit},
 };
 
-use afat32::NullTimeProvider;
 use arsc_rs::Arsc;
 use co_trap::UserCx;
+use kmem::Phys;
 use ksc::{
     async_handler,
     Error::{self, *},
 };
 use ktime::Instant;
 use rand_riscv::RandomState;
-use umifs::types::{FileType, Metadata, OpenOptions, Permissions, SetMetadata, Times};
+use umifs::{
+    path::Path,
+    traits::{DirectoryMut, Entry},
+    types::{FileType, Metadata, OpenOptions, Permissions, SetMetadata, Times},
+};
+use umio::IntoAnyExt;
 
 use crate::{
+    dev::{timestamp, RtcTimeProvider},
+    fs::{flags_of, MountFlags},
     mem::{In, Out, UserPtr},
     syscall::{ffi::Ts, ScRet},
     task::{
         fd::{FdInfo, SavedNextDirent, MAX_PATH_LEN},
-        TaskState,
+        pid, Access, Credentials, TaskState,
     },
 };
 
@@ -59,7 +66,9 @@ impl From<Metadata> for Kstat {
             dev: 1,
             inode: metadata.offset,
             mode: mode(metadata.ty, metadata.perm),
-            link_count: 1,
+            link_count: metadata.link_count as u32,
+            uid: metadata.uid,
+            gid: metadata.gid,
             size: metadata.len,
             blksize: metadata.block_size as u32,
             blocks: metadata.block_count as u64,
@@ -71,6 +80,49 @@ impl From<Metadata> for Kstat {
     }
 }
 
+/// Checks whether the caller may create entries in `dir`.
+async fn check_create(cred: &Credentials, dir: &Arc<dyn DirectoryMut>) -> Result<(), Error> {
+    match cred.is_root() {
+        true => Ok(()),
+        false => cred.check(&dir.metadata().await, Access::WRITE | Access::EXEC),
+    }
+}
+
+/// Checks whether the caller may remove `name` from `dir`, or replace it if it
+/// doesn't exist yet.
+async fn check_remove(
+    cred: &Credentials,
+    dir: &Arc<dyn DirectoryMut>,
+    name: &str,
+) -> Result<(), Error> {
+    if cred.is_root() {
+        return Ok(());
+    }
+    let metadata = dir.metadata().await;
+    cred.check(&metadata, Access::WRITE | Access::EXEC)?;
+    if !metadata.perm.contains(Permissions::STICKY) {
+        return Ok(());
+    }
+    let options = OpenOptions::PATH | OpenOptions::NOFOLLOW;
+    let perm = Default::default();
+    match dir.clone().open(name.as_ref(), options, perm).await {
+        Ok((entry, _)) => cred.check_sticky(&metadata, &entry.metadata().await),
+        Err(ENOENT) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Makes the caller the owner of a newly created entry.
+pub(super) async fn set_owner(cred: &Credentials, entry: &Arc<dyn Entry>) {
+    let metadata = SetMetadata {
+        uid: Some(cred.uid.effective),
+        gid: Some(cred.gid.effective),
+        ..Default::default()
+    };
+    // File systems without owners refuse anyone but root, which is fine.
+    let _ = entry.set_metadata(metadata).await;
+}
+
 #[async_handler]
 pub async fn openat(
     ts: &mut TaskState,
@@ -78,6 +130,7 @@ pub async fn openat(
 ) -> ScRet {
     let (fd, path, options, perm) = cx.args();
     let fut = async {
+        let cred = ts.cred();
         let mut buf = [0; MAX_PATH_LEN];
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
 
@@ -88,18 +141,13 @@ pub async fn openat(
             "user openat fd = {fd}, path = {path:?}, options = {options:?}, perm = {perm:?}"
         );
 
-        let entry = if root {
-            crate::fs::open(path, options, perm).await?.0
-        } else {
-            let base = ts.files.get(fd).await?;
-            match base.open(path, options, perm).await {
-                Ok((entry, _)) => entry,
-                Err(ENOENT) if ts.files.cwd() == "" => {
-                    crate::fs::open(path, options, perm).await?.0
-                }
-                Err(err) => return Err(err),
-            }
-        };
+        let (entry, created) = ts
+            .files
+            .open_at(&cred, fd, path, root, options, perm)
+            .await?;
+        if created {
+            set_owner(&cred, &entry).await;
+        }
 
         let fi = FdInfo {
             entry,
@@ -117,30 +165,41 @@ pub async fn openat(
 #[async_handler]
 pub async fn faccessat(
     ts: &mut TaskState,
-    cx: UserCx<'_, fn(i32, UserPtr<u8, In>, i32, u32) -> Result<(), Error>>,
+    cx: UserCx<'_, fn(i32, UserPtr<u8, In>, u32, i32) -> Result<(), Error>>,
 ) -> ScRet {
-    let (fd, path, options, perm) = cx.args();
+    const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+    const AT_EACCESS: i32 = 0x200;
+
+    let (fd, path, mode, flags) = cx.args();
     let fut = async {
+        let cred = ts.cred();
         let mut buf = [0; MAX_PATH_LEN];
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
 
-        let options = OpenOptions::from_bits_truncate(options);
-        let perm = Permissions::from_bits(perm).ok_or(EPERM)?;
+        let access = Access::from_bits(mode).ok_or(EINVAL)?;
 
         log::trace!(
-            "user accessat fd = {fd}, path = {path:?}, options = {options:?}, perm = {perm:?}"
+            "user accessat fd = {fd}, path = {path:?}, access = {access:?}, flags = {flags:#x}"
         );
 
-        if root {
-            crate::fs::open(path, options, perm).await?;
+        let options = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+            OpenOptions::PATH | OpenOptions::NOFOLLOW
         } else {
-            let base = ts.files.get(fd).await?;
-            match base.open(path, options, perm).await {
-                Err(ENOENT) if ts.files.cwd() == "" => crate::fs::open(path, options, perm).await?,
-                res => res?,
-            };
+            OpenOptions::PATH
         };
-        Ok(())
+        let perm = Default::default();
+        let (entry, _) = ts
+            .files
+            .open_at(&cred, fd, path, root, options, perm)
+            .await?;
+        if access.contains(Access::WRITE) && flags_of(&entry).contains(MountFlags::RDONLY) {
+            return Err(EROFS);
+        }
+        let metadata = entry.metadata().await;
+        match flags & AT_EACCESS {
+            0 => cred.real().check(&metadata, access),
+            _ => cred.check(&metadata, access),
+        }
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -153,22 +212,23 @@ pub async fn mkdirat(
 ) -> ScRet {
     let (fd, path, perm) = cx.args();
     let fut = async {
+        let cred = ts.cred();
         let mut buf = [0; MAX_PATH_LEN];
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
         let perm = Permissions::from_bits(perm).ok_or(EPERM)?;
 
         log::trace!("user mkdir fd = {fd}, path = {path:?}, perm = {perm:?}");
 
-        let (_, created) = if root {
-            crate::fs::open(path, OpenOptions::DIRECTORY | OpenOptions::CREAT, perm).await?
-        } else {
-            let base = ts.files.get(fd).await?;
-            base.open(path, OpenOptions::DIRECTORY | OpenOptions::CREAT, perm)
-                .await?
-        };
+        // Only the permission to create it is checked.
+        let options = OpenOptions::DIRECTORY | OpenOptions::CREAT | OpenOptions::PATH;
+        let (entry, created) = ts
+            .files
+            .open_at(&cred, fd, path, root, options, perm)
+            .await?;
         if !created {
             return Err(EEXIST);
         }
+        set_owner(&cred, &entry).await;
         Ok(())
     };
     cx.ret(fut.await);
@@ -193,37 +253,27 @@ pub async fn fstat(
 #[async_handler]
 pub async fn fstatat(
     ts: &mut TaskState,
-    cx: UserCx<'_, fn(i32, UserPtr<u8, In>, UserPtr<Kstat, Out>) -> Result<(), Error>>,
+    cx: UserCx<'_, fn(i32, UserPtr<u8, In>, UserPtr<Kstat, Out>, i32) -> Result<(), Error>>,
 ) -> ScRet {
-    let (fd, path, mut out) = cx.args();
+    const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+
+    let (fd, path, mut out, flags) = cx.args();
     let ret = async {
         let mut buf = [0; MAX_PATH_LEN];
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
 
-        log::trace!("user fstatat fd = {fd}, path = {path:?}");
+        log::trace!("user fstatat fd = {fd}, path = {path:?}, flags = {flags:#x}");
 
-        let file = if root {
-            crate::fs::open(
-                path,
-                OpenOptions::RDONLY,
-                Permissions::all_same(true, false, false),
-            )
-            .await?
-            .0
+        let options = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+            OpenOptions::NOFOLLOW | OpenOptions::PATH
         } else {
-            let base = ts.files.get(fd).await?;
-            if path == "" {
-                base
-            } else {
-                base.open(
-                    path,
-                    OpenOptions::RDONLY,
-                    Permissions::all_same(true, false, false),
-                )
-                .await?
-                .0
-            }
+            OpenOptions::PATH
         };
+        let perm = Permissions::all_same(true, false, false);
+        let (file, _) = ts
+            .files
+            .open_at(&ts.cred(), fd, path, root, options, perm)
+            .await?;
         let metadata = file.metadata().await;
         out.write(&ts.virt, metadata.into()).await
     };
@@ -256,7 +306,7 @@ pub async fn fchmodat(
         let entry = if root {
             crate::fs::open(
                 path,
-                OpenOptions::RDONLY,
+                OpenOptions::empty(),
                 Permissions::all_same(true, false, false),
             )
             .await?
@@ -268,13 +318,14 @@ pub async fn fchmodat(
             } else {
                 base.open(
                     path,
-                    OpenOptions::RDONLY,
+                    OpenOptions::empty(),
                     Permissions::all_same(true, false, false),
                 )
                 .await?
                 .0
             }
         };
+        ts.cred().check_owner(&entry.metadata().await)?;
         let metadata = SetMetadata {
             perm: Some(Permissions::from_bits_truncate(perm)),
             ..Default::default()
@@ -287,6 +338,83 @@ pub async fn fchmodat(
     ScRet::Continue(None)
 }
 
+async fn chown(cred: &Credentials, entry: Arc<dyn Entry>, uid: u32, gid: u32) -> Result<(), Error> {
+    // `-1` leaves the ID unchanged.
+    let uid = (uid != u32::MAX).then_some(uid);
+    let gid = (gid != u32::MAX).then_some(gid);
+
+    let metadata = entry.metadata().await;
+    cred.check_chown(&metadata, uid, gid)?;
+
+    // Changing the owner drops the set-ID bits of regular files.
+    let setid = Permissions::SET_UID | Permissions::SET_GID;
+    let perm = (metadata.ty == FileType::REG
+        && (uid.is_some() || gid.is_some())
+        && metadata.perm.intersects(setid))
+    .then(|| metadata.perm - setid);
+
+    let metadata = SetMetadata {
+        perm,
+        uid,
+        gid,
+        ..Default::default()
+    };
+    entry.set_metadata(metadata).await
+}
+
+#[async_handler]
+pub async fn fchown(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, u32, u32) -> Result<(), Error>>,
+) -> ScRet {
+    let (fd, uid, gid) = cx.args();
+    let fut = async {
+        let entry = ts.files.get(fd).await?;
+        chown(&ts.cred(), entry, uid, gid).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn fchownat(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, UserPtr<u8, In>, u32, u32, i32) -> Result<(), Error>>,
+) -> ScRet {
+    const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+    const AT_EMPTY_PATH: i32 = 0x1000;
+
+    let (fd, path, uid, gid, flags) = cx.args();
+    let fut = async {
+        let cred = ts.cred();
+        let mut buf = [0; MAX_PATH_LEN];
+        let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
+
+        log::trace!("user fchownat fd = {fd}, path = {path:?}, uid = {uid}, gid = {gid}");
+
+        let entry = if path == "" && !root {
+            if flags & AT_EMPTY_PATH == 0 {
+                return Err(ENOENT);
+            }
+            ts.files.get(fd).await?
+        } else {
+            let options = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+                OpenOptions::PATH | OpenOptions::NOFOLLOW
+            } else {
+                OpenOptions::PATH
+            };
+            let perm = Default::default();
+            ts.files
+                .open_at(&cred, fd, path, root, options, perm)
+                .await?
+                .0
+        };
+        chown(&cred, entry, uid, gid).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
 #[async_handler]
 pub async fn utimensat(
     ts: &mut TaskState,
@@ -323,7 +451,7 @@ pub async fn utimensat(
             }
         };
 
-        let now = Instant::now();
+        let now = timestamp();
         let (a, m) = if times.is_null() {
             (Some(now), Some(now))
         } else {
@@ -440,15 +568,19 @@ pub async fn renameat(
 ) -> ScRet {
     let (src, src_path, dst, dst_path) = cx.args();
     let ret = async {
+        let cred = ts.cred();
         let [mut src_buf, mut dst_buf] = [[0; MAX_PATH_LEN]; 2];
-        let (src_path, _) = src_path.read_path(&ts.virt, &mut src_buf).await?;
-        let (dst_path, _) = dst_path.read_path(&ts.virt, &mut dst_buf).await?;
+        let (src_path, src_root) = src_path.read_path(&ts.virt, &mut src_buf).await?;
+        let (dst_path, dst_root) = dst_path.read_path(&ts.virt, &mut dst_buf).await?;
 
         log::trace!("user renameat src = {src}/{src_path:?}, dst = {dst}/{dst_path:?}");
 
-        let src = ts.files.get(src).await?.to_dir_mut().ok_or(ENOTDIR)?;
-        let dst = ts.files.get(dst).await?.to_dir_mut().ok_or(ENOTDIR)?;
-        src.rename(src_path, dst, dst_path).await?;
+        let (src, src_name) = ts.files.open_parent(&cred, src, src_path, src_root).await?;
+        let (dst, dst_name) = ts.files.open_parent(&cred, dst, dst_path, dst_root).await?;
+        check_remove(&cred, &src, src_name).await?;
+        check_remove(&cred, &dst, dst_name).await?;
+        src.rename(src_name.as_ref(), dst, dst_name.as_ref())
+            .await?;
 
         Ok(())
     };
@@ -456,6 +588,98 @@ pub async fn renameat(
     ScRet::Continue(None)
 }
 
+#[async_handler]
+pub async fn symlinkat(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(UserPtr<u8, In>, i32, UserPtr<u8, In>) -> Result<(), Error>>,
+) -> ScRet {
+    let (target, fd, path) = cx.args();
+    let ret = async {
+        let cred = ts.cred();
+        let [mut target_buf, mut path_buf] = [[0; MAX_PATH_LEN]; 2];
+        let target = target.read_str(&ts.virt, &mut target_buf).await?;
+        let (path, root) = path.read_path(&ts.virt, &mut path_buf).await?;
+
+        log::trace!("user symlinkat target = {target:?}, fd = {fd}, path = {path:?}");
+
+        if target.is_empty() {
+            return Err(ENOENT);
+        }
+        let (dir, name) = ts.files.open_parent(&cred, fd, path, root).await?;
+        check_create(&cred, &dir).await?;
+        dir.symlink(name.as_ref(), target.as_ref()).await
+    };
+    cx.ret(ret.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn linkat(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, UserPtr<u8, In>, i32, UserPtr<u8, In>, i32) -> Result<(), Error>>,
+) -> ScRet {
+    const AT_SYMLINK_FOLLOW: i32 = 0x400;
+    const AT_EMPTY_PATH: i32 = 0x1000;
+
+    let (src, src_path, dst, dst_path, flags) = cx.args();
+    let ret = async {
+        let cred = ts.cred();
+        let [mut src_buf, mut dst_buf] = [[0; MAX_PATH_LEN]; 2];
+        let (src_path, mut src_root) = src_path.read_path(&ts.virt, &mut src_buf).await?;
+        let (dst_path, dst_root) = dst_path.read_path(&ts.virt, &mut dst_buf).await?;
+
+        log::trace!(
+            "user linkat src = {src}/{src_path:?}, dst = {dst}/{dst_path:?}, flags = {flags:#x}"
+        );
+
+        if flags & !(AT_SYMLINK_FOLLOW | AT_EMPTY_PATH) != 0 {
+            return Err(EINVAL);
+        }
+        if src_path == "" && !src_root {
+            // Linking the file descriptor itself needs privileges, and even
+            // then its entry cannot be linked without a name.
+            return Err(match flags & AT_EMPTY_PATH != 0 && cred.is_root() {
+                true => EOPNOTSUPP,
+                false => ENOENT,
+            });
+        }
+
+        // The links at the end of the source are resolved if following them.
+        let mut src_path = src_path.to_path_buf();
+        if flags & AT_SYMLINK_FOLLOW != 0 {
+            let perm = Default::default();
+            let options = OpenOptions::PATH | OpenOptions::NOFOLLOW;
+            let mut links = 0..crate::fs::MAX_SYMLINKS;
+            loop {
+                let open = ts
+                    .files
+                    .open_at(&cred, src, &src_path, src_root, options, perm);
+                let Ok(target) = open.await?.0.read_link().await else {
+                    break
+                };
+                links.next().ok_or(ELOOP)?;
+                src_path = match target.as_str().strip_prefix('/') {
+                    Some(target) => {
+                        src_root = true;
+                        target.into()
+                    }
+                    None => src_path.parent().unwrap_or(Path::new("")).join(target),
+                };
+            }
+        }
+
+        let (src, src_name) = ts
+            .files
+            .open_parent(&cred, src, &src_path, src_root)
+            .await?;
+        let (dst, dst_name) = ts.files.open_parent(&cred, dst, dst_path, dst_root).await?;
+        check_create(&cred, &dst).await?;
+        src.link(src_name.as_ref(), dst, dst_name.as_ref()).await
+    };
+    cx.ret(ret.await);
+    ScRet::Continue(None)
+}
+
 #[async_handler]
 pub async fn unlinkat(
     ts: &mut TaskState,
@@ -463,11 +687,16 @@ pub async fn unlinkat(
 ) -> ScRet {
     let (fd, path, flags) = cx.args();
     let fut = async {
+        let cred = ts.cred();
         let mut buf = [0; MAX_PATH_LEN];
         let (path, root) = path.read_path(&ts.virt, &mut buf).await?;
 
         log::trace!("user unlinkat fd = {fd}, path = {path:?}, flags = {flags}");
 
+        if !cred.is_root() {
+            let (dir, name) = ts.files.open_parent(&cred, fd, path, root).await?;
+            check_remove(&cred, &dir, name).await?;
+        }
         if root {
             crate::fs::unlink(path).await
         } else {
@@ -498,78 +727,121 @@ pub async fn mount(
         ) -> Result<(), Error>,
     >,
 ) -> ScRet {
-    let (src, dst, ty, _flags, _data) = cx.args();
+    let (src, dst, ty, flags, _data) = cx.args();
     let fut = async {
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
         let mut src_buf = [0; MAX_PATH_LEN];
         let mut dst_buf = [0; MAX_PATH_LEN];
         let mut ty_buf = [0; 64];
         let (src, root_src) = src.read_path(&ts.virt, &mut src_buf).await?;
         let (dst, root_dst) = dst.read_path(&ts.virt, &mut dst_buf).await?;
-        let ty = ty.read_str(&ts.virt, &mut ty_buf).await?;
+        let flags = MountFlags::from_bits_truncate(flags);
 
-        let (src, _) = if root_src {
-            crate::fs::open(
-                src,
-                Default::default(),
-                Permissions::all_same(true, true, true),
-            )
-            .await?
+        log::trace!("user mount src = {src:?}, dst = {dst:?}, flags = {flags:?}");
+
+        let src_path = if root_src {
+            src.to_path_buf()
         } else {
-            crate::fs::open(
-                &ts.files.cwd().join(src),
-                Default::default(),
-                Permissions::all_same(true, true, true),
-            )
-            .await?
+            ts.files.cwd().join(src)
         };
-        if root_dst {
-            crate::fs::open_dir(dst, Default::default(), Default::default()).await?;
+        let dst = if root_dst {
+            dst.to_path_buf()
         } else {
-            crate::fs::open_dir(
-                &ts.files.cwd().join(dst),
-                Default::default(),
-                Default::default(),
-            )
-            .await?;
+            ts.files.cwd().join(dst)
+        };
+        crate::fs::open_dir(&dst, Default::default(), Default::default()).await?;
+
+        if flags.contains(MountFlags::REMOUNT) {
+            return crate::fs::remount(&dst, flags);
+        }
+        if flags.contains(MountFlags::BIND) {
+            let dir =
+                crate::fs::open_dir(&src_path, Default::default(), Default::default()).await?;
+            return crate::fs::bind(dst, &src_path, dir, flags);
         }
 
+        let ty = ty.read_str(&ts.virt, &mut ty_buf).await?;
+        if ty != "vfat" {
+            let fs = crate::fs::new_virtual(ty)?;
+            let dev = if root_src {
+                format!("/{src}")
+            } else {
+                src.to_string()
+            };
+            return crate::fs::mount(dst, dev.into(), fs, None, flags).await;
+        }
+
+        let (src, _) = crate::fs::open(
+            &src_path,
+            Default::default(),
+            Permissions::all_same(true, true, true),
+        )
+        .await?;
         let metadata = src.metadata().await;
         if metadata.ty != FileType::BLK {
             return Err(ENOTBLK);
         }
-        let Some(io) = src.to_io()else {
+        let Some(io) = src.to_io() else {
             return Err(ENOTBLK)
         };
 
-        if ty == "vfat" {
-            let fatfs =
-                afat32::FatFileSystem::new(io, metadata.block_size.ilog2(), NullTimeProvider)
-                    .await?;
-            crate::fs::mount(dst.to_path_buf(), "<UNKNOWN>".into(), fatfs);
-        } else {
-            return Err(ENODEV);
-        }
-        Ok(())
+        let device = io.clone().downcast::<Phys>();
+        let fatfs =
+            afat32::FatFileSystem::new(io, metadata.block_size.ilog2(), RtcTimeProvider).await?;
+        let dev = format!("/{src_path}").into();
+        crate::fs::mount(dst, dev, fatfs, device, flags).await
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
 }
 
+/// Returns whether the current directory of any task is under `path`.
+fn is_cwd_under(path: &Path) -> bool {
+    let mut start = 0;
+    while let Some(process) = pid::next_process(start) {
+        start = process.tid() + 1;
+        let mut tid = process.tid();
+        while let Some(thread) = pid::next_thread(process.tid(), tid) {
+            tid = thread.tid() + 1;
+            if let Some(info) = thread.info() {
+                if info.files.cwd().starts_with(path) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
 #[async_handler]
 pub async fn umount(
     ts: &mut TaskState,
-    cx: UserCx<'_, fn(UserPtr<u8, In>) -> Result<(), Error>>,
+    cx: UserCx<'_, fn(UserPtr<u8, In>, i32) -> Result<(), Error>>,
 ) -> ScRet {
-    let target = cx.args();
+    const MNT_DETACH: i32 = 2;
+
+    let (target, flags) = cx.args();
     let fut = async {
-        let mut buf = [9; MAX_PATH_LEN];
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
+        let mut buf = [0; MAX_PATH_LEN];
         let (target, root) = target.read_path(&ts.virt, &mut buf).await?;
-        if root {
-            crate::fs::unmount(target);
+        let target = if root {
+            target.to_path_buf()
         } else {
-            crate::fs::unmount(&ts.files.cwd().join(target));
+            ts.files.cwd().join(target)
+        };
+
+        log::trace!("user umount target = {target:?}, flags = {flags:#x}");
+
+        let detach = flags & MNT_DETACH != 0;
+        if !detach && is_cwd_under(&target) {
+            return Err(EBUSY);
         }
-        Ok(())
+        crate::fs::unmount(&target, detach)
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -648,7 +920,7 @@ pub async fn truncate(
         let (path, _) = path.read_path(&ts.virt, &mut buf).await?;
         let (file, _) = crate::fs::open(
             path,
-            OpenOptions::RDONLY,
+            OpenOptions::empty(),
             Permissions::all_same(true, false, false),
         )
         .await?;
@@ -13,12 +13,17 @@ use ksc::{
 use ktime::TimeOutExt;
 use rv39_paging::{Attr, PAGE_SIZE};
 use sygnal::SigSet;
-use umio::{Io, SeekFrom};
+use umifs::types::{OpenOptions, Permissions};
+use umio::{IntoAnyExt, Io, SeekFrom};
 
 use crate::{
-    mem::{In, InOut, UserBuffer, UserPtr},
+    fs::epoll::{Epoll, Mode},
+    mem::{In, InOut, Out, UserBuffer, UserPtr},
     syscall::{ffi::Ts, ScRet},
-    task::{fd::Files, yield_now, TaskState},
+    task::{
+        fd::{FdInfo, Files},
+        yield_now, TaskState,
+    },
 };
 
 #[async_handler]
@@ -114,10 +119,10 @@ pub async fn pwrite(
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct IoVec {
-    buffer: UserBuffer,
-    len: usize,
+    pub(super) buffer: UserBuffer,
+    pub(super) len: usize,
 }
-const MAX_IOV_LEN: usize = 8;
+pub(super) const MAX_IOV_LEN: usize = 8;
 
 #[async_handler]
 pub async fn readv(
@@ -599,3 +604,118 @@ pub async fn pselect(
     cx.ret(fut.await);
     ScRet::Continue(None)
 }
+
+const EPOLL_CLOEXEC: i32 = OpenOptions::CLOEXEC.bits();
+
+const EPOLL_CTL_ADD: i32 = 1;
+const EPOLL_CTL_DEL: i32 = 2;
+const EPOLL_CTL_MOD: i32 = 3;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct EpollEvent {
+    events: u32,
+    _pad: u32,
+    data: u64,
+}
+
+async fn epoll_of(files: &Files, epfd: i32) -> Result<Arc<Epoll>, Error> {
+    let entry = files.get(epfd).await?;
+    entry.downcast().ok_or(EINVAL)
+}
+
+#[async_handler]
+pub async fn epoll_create1(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32) -> Result<i32, Error>>,
+) -> ScRet {
+    let flags = cx.args();
+    let fut = async {
+        if flags & !EPOLL_CLOEXEC != 0 {
+            return Err(EINVAL);
+        }
+        let fi = FdInfo {
+            entry: Epoll::new(),
+            close_on_exec: flags & EPOLL_CLOEXEC != 0,
+            nonblock: false,
+            perm: Permissions::all_same(true, true, false),
+            saved_next_dirent: Default::default(),
+        };
+        ts.files.open(fi).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn epoll_ctl(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, i32, i32, UserPtr<EpollEvent, In>) -> Result<(), Error>>,
+) -> ScRet {
+    let (epfd, op, fd, event) = cx.args();
+    let fut = async {
+        let epoll = epoll_of(&ts.files, epfd).await?;
+        let entry = ts.files.get(fd).await?;
+        if op == EPOLL_CTL_DEL {
+            return epoll.delete(fd, &entry);
+        }
+
+        let event = event.read(&ts.virt).await?;
+        let events = umio::Event::from_bits_truncate(event.events as u16);
+        let mode = Mode::from_bits_truncate(event.events);
+        log::trace!("epoll_ctl {epfd}: op = {op}, fd = {fd}, events = {events:?}, mode = {mode:?}");
+        match op {
+            EPOLL_CTL_ADD => epoll.add(fd, &entry, events, mode, event.data),
+            EPOLL_CTL_MOD => epoll.modify(fd, &entry, events, mode, event.data),
+            _ => Err(EINVAL),
+        }
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn epoll_pwait(
+    ts: &mut TaskState,
+    cx: UserCx<
+        '_,
+        fn(
+            i32,
+            UserPtr<EpollEvent, Out>,
+            i32,
+            i32,
+            UserPtr<SigSet, In>,
+            usize,
+        ) -> Result<usize, Error>,
+    >,
+) -> ScRet {
+    let (epfd, mut out, max, timeout, _sigmask, _sigmask_size) = cx.args();
+    let fut = async {
+        if max <= 0 {
+            return Err(EINVAL);
+        }
+        let epoll = epoll_of(&ts.files, epfd).await?;
+        let max = max as usize;
+
+        // The timeout is in milliseconds, and negative for waiting forever.
+        let ready = match timeout {
+            0 => epoll.harvest(max),
+            t if t < 0 => epoll.wait(max).await,
+            t => {
+                let timeout = Duration::from_millis(t as u64);
+                epoll.wait(max).on_timeout(timeout, Vec::new).await
+            }
+        };
+
+        let events = ready.iter().map(|&(events, data)| EpollEvent {
+            events: events.bits().into(),
+            _pad: 0,
+            data,
+        });
+        let events = events.collect::<Vec<_>>();
+        out.write_slice(&ts.virt, &events, false).await?;
+        Ok(events.len())
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
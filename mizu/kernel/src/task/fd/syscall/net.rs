@@ -1,21 +1,32 @@
-use alloc::{boxed::Box, sync::Arc};
-use core::{mem, time::Duration};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use core::{mem, str, time::Duration};
 
 use co_trap::UserCx;
 use devices::net::{Socket, BUFFER_CAP};
-use kmem::Virt;
+use kmem::{Virt, VirtCommitGuard};
 use ksc::{
     async_handler,
-    Error::{self, EAFNOSUPPORT, EAGAIN, EINVAL, ENOPROTOOPT, ENOTCONN, ENOTSOCK},
+    Error::{
+        self, EADDRINUSE, EAFNOSUPPORT, EAGAIN, EEXIST, EINVAL, ENOPROTOOPT, ENOTCONN, ENOTSOCK,
+        EOPNOTSUPP,
+    },
 };
 use rv39_paging::Attr;
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address, Ipv6Address};
-use umifs::types::{OpenOptions, Permissions};
+use umifs::{
+    path::Path,
+    traits::Entry,
+    types::{OpenOptions, Permissions},
+};
 use umio::IntoAnyExt;
 use zerocopy::{AsBytes, FromBytes, FromZeroes};
 
+use super::{fs::set_owner, IoVec, MAX_IOV_LEN};
 use crate::{
-    fs::socket::{self, SocketFile},
+    fs::{
+        socket::{self, SocketFile},
+        unix::{self, Received, Ucred, UnixAddr, UnixSocket},
+    },
     mem::{In, InOut, Out, UserBuffer, UserPtr},
     syscall::{ffi::Tv, ScRet},
     task::{
@@ -25,6 +36,7 @@ use crate::{
     trap::poll_with,
 };
 
+const AF_UNIX: u16 = 1; // Unix domain sockets
 const AF_INET: u16 = 2; // Internet IP Protocol
 const AF_INET6: u16 = 10; // IP version 6
 
@@ -118,62 +130,152 @@ where
     }
 }
 
-async fn write_ipaddr(
-    virt: &Virt,
-    (addr, port): (Option<IpAddress>, u16),
-    mut ptr: UserPtr<u16, Out>,
-    len: UserPtr<usize, InOut>,
-) -> Result<(), Error> {
-    if ptr.is_null() {
-        return Ok(());
-    }
-    let buf_len = len.read(virt).await?;
-    if buf_len < mem::size_of::<u16>() {
-        return Err(EINVAL);
-    }
+/// Encodes the IP endpoint as a `struct sockaddr_in` or `struct sockaddr_in6`.
+fn ip_sockaddr((addr, port): (Option<IpAddress>, u16)) -> Vec<u8> {
+    let port = port.to_be_bytes();
     match addr.unwrap_or(IpAddress::Ipv4(Default::default())) {
         IpAddress::Ipv4(addr) => {
-            ptr.write(virt, AF_INET).await?;
-            ptr.advance(mem::size_of::<u16>());
-
-            let sav4 = SockAddrIpv4 {
-                port: port.to_be_bytes(),
-                addr: addr.0,
-            };
-            let len = sav4.as_bytes().len().min(buf_len - mem::size_of::<u16>());
-            ptr.cast::<u8>()
-                .write_slice(virt, &sav4.as_bytes()[..len], false)
-                .await?;
-            Ok(())
+            let sav4 = SockAddrIpv4 { port, addr: addr.0 };
+            [AF_INET.as_bytes(), sav4.as_bytes()].concat()
         }
         IpAddress::Ipv6(addr) => {
-            ptr.write(virt, AF_INET6).await?;
-            ptr.advance(mem::size_of::<u16>());
-
             let sav6 = SockAddrIpv6 {
-                port: port.to_be_bytes(),
+                port,
                 addr: addr.0,
                 ..Default::default()
             };
-            let len = sav6.as_bytes().len().min(buf_len - mem::size_of::<u16>());
-            ptr.cast::<u8>()
-                .write_slice(virt, &sav6.as_bytes()[..len], false)
-                .await?;
-            Ok(())
+            [AF_INET6.as_bytes(), sav6.as_bytes()].concat()
         }
     }
 }
 
-async fn sock<'a>(
-    files: &Files,
-    fd: i32,
-    storage: &'a mut Option<Arc<SocketFile>>,
-) -> Result<(&'a SocketFile, bool), Error> {
+/// The maximum length of `sun_path` in `struct sockaddr_un`.
+const UNIX_PATH_MAX: usize = 108;
+
+async fn unix_addr(
+    ts: &TaskState,
+    mut addr: UserPtr<u16, In>,
+    len: usize,
+) -> Result<UnixAddr, Error> {
+    if addr.is_null() || len < mem::size_of::<u16>() {
+        return Err(EINVAL);
+    }
+    if addr.read(&ts.virt).await? != AF_UNIX {
+        return Err(EAFNOSUPPORT);
+    }
+    addr.advance(mem::size_of::<u16>());
+
+    let mut buf = [0; UNIX_PATH_MAX];
+    let name = &mut buf[..(len - mem::size_of::<u16>()).min(UNIX_PATH_MAX)];
+    addr.cast::<u8>().read_slice(&ts.virt, name).await?;
+    Ok(match name.split_first() {
+        None => UnixAddr::Unnamed,
+        Some((0, name)) => UnixAddr::Abstract(name.to_vec()),
+        Some(_) => {
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let path = str::from_utf8(&name[..len]).map_err(|_| EINVAL)?;
+            UnixAddr::Path(match path.strip_prefix('/') {
+                Some(path) => Path::new(path).normalize(),
+                None => ts.files.cwd().join_normalized(path),
+            })
+        }
+    })
+}
+
+/// Encodes the address as a `struct sockaddr_un`.
+fn unix_sockaddr(addr: &UnixAddr) -> Vec<u8> {
+    let mut ret = AF_UNIX.as_bytes().to_vec();
+    match addr {
+        UnixAddr::Unnamed => {}
+        UnixAddr::Abstract(name) => {
+            ret.push(0);
+            ret.extend_from_slice(name);
+        }
+        UnixAddr::Path(path) => {
+            ret.push(b'/');
+            ret.extend_from_slice(path.as_str().as_bytes());
+            ret.push(0);
+        }
+    }
+    ret
+}
+
+/// Writes the encoded socket address truncated to the length of the buffer,
+/// and stores its actual length back.
+async fn write_sockaddr(
+    virt: &Virt,
+    addr: &[u8],
+    ptr: UserPtr<u16, Out>,
+    len: UserPtr<usize, InOut>,
+) -> Result<(), Error> {
+    if ptr.is_null() {
+        return Ok(());
+    }
+    // `socklen_t` is 32-bit wide.
+    let mut len = len.cast::<u32>();
+    let buf_len = len.read(virt).await? as usize;
+    let addr_len = addr.len().min(buf_len);
+    ptr.cast::<u8>()
+        .write_slice(virt, &addr[..addr_len], false)
+        .await?;
+    len.write(virt, addr.len() as u32).await
+}
+
+/// Checks that the file of `addr` still exists if it is bound to a path.
+async fn check_path(addr: &UnixAddr) -> Result<(), Error> {
+    if let UnixAddr::Path(path) = addr {
+        let options = OpenOptions::PATH;
+        crate::fs::open(path, options, Default::default()).await?;
+    }
+    Ok(())
+}
+
+fn ucred(ts: &TaskState) -> Ucred {
+    let cred = ts.cred();
+    Ucred {
+        pid: ts.task.tgid() as u32,
+        uid: cred.uid.effective,
+        gid: cred.gid.effective,
+    }
+}
+
+enum Sock {
+    Inet(Arc<SocketFile>),
+    Unix(Arc<UnixSocket>),
+}
+
+async fn sock(files: &Files, fd: i32) -> Result<(Sock, bool), Error> {
     let fi = files.get_fi(fd).await?;
-    Ok((
-        &*storage.insert(fi.entry.downcast().ok_or(ENOTSOCK)?),
-        fi.nonblock,
-    ))
+    let socket = match fi.entry.clone().downcast() {
+        Some(socket) => Sock::Inet(socket),
+        None => Sock::Unix(fi.entry.downcast().ok_or(ENOTSOCK)?),
+    };
+    Ok((socket, fi.nonblock))
+}
+
+const SOCK_STREAM: i32 = 1;
+const SOCK_DGRAM: i32 = 2;
+const SOCK_SEQPACKET: i32 = 5;
+const SOCK_CLOEXEC: i32 = OpenOptions::CLOEXEC.bits();
+const SOCK_NONBLOCK: i32 = OpenOptions::NONBLOCK.bits();
+
+fn unix_type(ty: i32) -> Result<unix::Type, Error> {
+    Ok(match ty & 0xf {
+        SOCK_STREAM => unix::Type::Stream,
+        SOCK_DGRAM => unix::Type::Dgram,
+        SOCK_SEQPACKET => unix::Type::SeqPacket,
+        _ => return Err(EINVAL),
+    })
+}
+
+fn sock_fi(entry: Arc<dyn Entry>, ty: i32) -> FdInfo {
+    FdInfo {
+        entry,
+        close_on_exec: ty & SOCK_CLOEXEC != 0,
+        nonblock: ty & SOCK_NONBLOCK != 0,
+        perm: Permissions::all_same(true, true, false),
+        saved_next_dirent: Default::default(),
+    }
 }
 
 #[async_handler]
@@ -181,29 +283,37 @@ pub async fn socket(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(u16, i32, i32) -> Result<i32, Error>>,
 ) -> ScRet {
-    const SOCK_STREAM: i32 = 1;
-    const SOCK_DGRAM: i32 = 2;
-    const SOCK_CLOEXEC: i32 = OpenOptions::CLOEXEC.bits();
-    const SOCK_NONBLOCK: i32 = OpenOptions::NONBLOCK.bits();
-
     let (domain, ty, _protocol) = cx.args();
     let fut = async {
-        let socket = match domain {
+        let socket: Arc<dyn Entry> = match domain {
             AF_INET | AF_INET6 => match ty & 0xf {
                 SOCK_DGRAM => socket::udp()?,
                 SOCK_STREAM => socket::tcp()?,
                 _ => return Err(EINVAL),
             },
+            AF_UNIX => UnixSocket::new(unix_type(ty)?, ucred(ts)),
             _ => return Err(EINVAL),
         };
-        let fi = FdInfo {
-            entry: socket,
-            close_on_exec: ty & SOCK_CLOEXEC != 0,
-            nonblock: ty & SOCK_NONBLOCK != 0,
-            perm: Permissions::all_same(true, true, false),
-            saved_next_dirent: Default::default(),
-        };
-        ts.files.open(fi).await
+        ts.files.open(sock_fi(socket, ty)).await
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn socket_pair(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(u16, i32, i32, UserPtr<i32, Out>) -> Result<(), Error>>,
+) -> ScRet {
+    let (domain, ty, _protocol, mut fds) = cx.args();
+    let fut = async {
+        if domain != AF_UNIX {
+            return Err(EOPNOTSUPP);
+        }
+        let (a, b) = UnixSocket::pair(unix_type(ty)?, ucred(ts));
+        let a = ts.files.open(sock_fi(a, ty)).await?;
+        let b = ts.files.open(sock_fi(b, ty)).await?;
+        fds.write_slice(&ts.virt, &[a, b], false).await
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -216,12 +326,14 @@ pub async fn getsockname(
 ) -> ScRet {
     let (fd, ptr, len) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
-        if let Some(IpListenEndpoint { addr, port }) = socket.listen_endpoint() {
-            write_ipaddr(&ts.virt, (addr, port), ptr, len).await?;
-        }
-        Ok(())
+        let addr = match sock(&ts.files, fd).await?.0 {
+            Sock::Inet(socket) => match socket.listen_endpoint() {
+                Some(IpListenEndpoint { addr, port }) => ip_sockaddr((addr, port)),
+                None => return Ok(()),
+            },
+            Sock::Unix(socket) => unix_sockaddr(&socket.local_addr()),
+        };
+        write_sockaddr(&ts.virt, &addr, ptr, len).await
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -231,6 +343,7 @@ pub async fn getsockname(
 union SockOpt {
     value: u32,
     tv: Tv,
+    cred: Ucred,
 }
 
 unsafe impl FromZeroes for SockOpt {
@@ -260,9 +373,11 @@ unsafe impl FromBytes for SockOpt {
 
 const SOL_SOCKET: i32 = 1;
 
+const SO_TYPE: i32 = 3;
 const SO_SNDBUF: i32 = 7;
 const SO_RCVBUF: i32 = 8;
 const SO_RCVTIMEO: i32 = 20;
+const SO_PEERCRED: i32 = 17;
 const SO_SNDTIMEO: i32 = 21;
 
 const IPPROTO_TCP: i32 = 6;
@@ -278,8 +393,39 @@ pub async fn getsockopt(
 ) -> ScRet {
     let (fd, level, opt, mut ptr, mut len) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
+        let socket = match sock(&ts.files, fd).await?.0 {
+            Sock::Inet(socket) => socket,
+            Sock::Unix(socket) => {
+                let (value, value_len) = match (level, opt) {
+                    (SOL_SOCKET, SO_TYPE) => {
+                        let value = match socket.ty() {
+                            unix::Type::Stream => SOCK_STREAM,
+                            unix::Type::Dgram => SOCK_DGRAM,
+                            unix::Type::SeqPacket => SOCK_SEQPACKET,
+                        };
+                        (
+                            SockOpt {
+                                value: value as u32,
+                            },
+                            mem::size_of::<u32>(),
+                        )
+                    }
+                    (SOL_SOCKET, SO_RCVBUF | SO_SNDBUF) => {
+                        let value = unix::BUFFER_CAP.try_into()?;
+                        (SockOpt { value }, mem::size_of::<u32>())
+                    }
+                    (SOL_SOCKET, SO_PEERCRED) => {
+                        let cred = socket.peer_cred()?;
+                        (SockOpt { cred }, mem::size_of::<Ucred>())
+                    }
+                    _ => return Ok(()),
+                };
+                let written_len = len.read(&ts.virt).await?.min(value_len);
+                ptr.write_slice(&ts.virt, &value.as_bytes()[..written_len], false)
+                    .await?;
+                return len.write(&ts.virt, value_len).await;
+            }
+        };
 
         let (value, value_len) = match level {
             SOL_SOCKET => match opt {
@@ -302,7 +448,7 @@ pub async fn getsockopt(
                 _ => return Ok(()),
             },
             IPPROTO_TCP => match opt {
-                TCP_MAXSEG => match &**socket {
+                TCP_MAXSEG => match &***socket {
                     Socket::Tcp(socket) => {
                         let value = socket.max_segment_size().try_into()?;
                         (SockOpt { value }, mem::size_of::<u32>())
@@ -332,8 +478,10 @@ pub async fn setsockopt(
 ) -> ScRet {
     let (fd, level, opt, ptr, len) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
+        let Sock::Inet(socket) = sock(&ts.files, fd).await?.0 else {
+            // No option of Unix domain sockets is settable.
+            return Ok(());
+        };
 
         match level {
             SOL_SOCKET => {
@@ -364,6 +512,27 @@ pub async fn setsockopt(
     ScRet::Continue(None)
 }
 
+/// Reads the destination of a Unix domain socket, if any.
+async fn unix_dest(
+    ts: &TaskState,
+    addr: UserPtr<u16, In>,
+    len: usize,
+) -> Result<Option<UnixAddr>, Error> {
+    if addr.is_null() {
+        return Ok(None);
+    }
+    let addr = unix_addr(ts, addr, len).await?;
+    check_path(&addr).await?;
+    Ok(Some(addr))
+}
+
+async fn commit_iov(guard: &mut VirtCommitGuard<'_>, iov: &[IoVec]) -> Result<(), Error> {
+    for iov in iov {
+        iov.buffer.commit(guard, iov.len).await?;
+    }
+    Ok(())
+}
+
 #[async_handler]
 pub async fn sendto(
     ts: &mut TaskState,
@@ -374,15 +543,25 @@ pub async fn sendto(
 ) -> ScRet {
     let (fd, buf, len, _flags, addr, addr_len) = cx.args();
     let fut = async {
-        let mut guard = ts.virt.start_commit(Attr::READABLE).await;
-        buf.commit(&mut guard, len).await?;
-
-        let endpoint = ipaddr(&ts.virt, addr, addr_len).await?;
-
-        let mut storage = None;
-        let (socket, nonblock) = sock(&ts.files, fd, &mut storage).await?;
-
-        socket.send(guard.as_slice(), endpoint, nonblock).await
+        let (socket, nonblock) = sock(&ts.files, fd).await?;
+        match socket {
+            Sock::Inet(socket) => {
+                let endpoint = ipaddr(&ts.virt, addr, addr_len).await?;
+
+                let mut guard = ts.virt.start_commit(Attr::READABLE).await;
+                buf.commit(&mut guard, len).await?;
+                socket.send(guard.as_slice(), endpoint, nonblock).await
+            }
+            Sock::Unix(socket) => {
+                let to = unix_dest(ts, addr, addr_len).await?;
+
+                let mut guard = ts.virt.start_commit(Attr::READABLE).await;
+                buf.commit(&mut guard, len).await?;
+                socket
+                    .send(guard.as_slice(), Vec::new(), to.as_ref(), nonblock)
+                    .await
+            }
+        }
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -405,15 +584,28 @@ pub async fn recvfrom(
 ) -> ScRet {
     let (fd, buf, len, _flags, ptr, addr_len) = cx.args();
     let fut = async {
+        let (socket, nonblock) = sock(&ts.files, fd).await?;
+
         let mut guard = ts.virt.start_commit(Attr::WRITABLE).await;
         buf.commit(&mut guard, len).await?;
 
-        let mut storage = None;
-        let (socket, nonblock) = sock(&ts.files, fd, &mut storage).await?;
+        let (len, addr) = match socket {
+            Sock::Inet(socket) => {
+                let (len, endpoint) = socket.receive(guard.as_mut_slice(), nonblock).await?;
+                let addr =
+                    endpoint.map(|IpEndpoint { addr, port }| ip_sockaddr((Some(addr), port)));
+                (len, addr)
+            }
+            Sock::Unix(socket) => {
+                // The passed file descriptors are discarded without `recvmsg`.
+                let received = socket.receive(guard.as_mut_slice(), nonblock).await?;
+                (received.len, received.from.as_ref().map(unix_sockaddr))
+            }
+        };
+        drop(guard);
 
-        let (len, endpoint) = socket.receive(guard.as_mut_slice(), nonblock).await?;
-        if let Some(IpEndpoint { addr, port }) = endpoint {
-            write_ipaddr(&ts.virt, (Some(addr), port), ptr, addr_len).await?;
+        if let Some(addr) = addr {
+            write_sockaddr(&ts.virt, &addr, ptr, addr_len).await?;
         }
         Ok(len)
     };
@@ -421,6 +613,223 @@ pub async fn recvfrom(
     ScRet::Continue(None)
 }
 
+/// `struct msghdr`.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct MsgHdr {
+    name: UserBuffer,
+    name_len: u32,
+    iov: UserBuffer,
+    iov_len: usize,
+    control: UserBuffer,
+    control_len: usize,
+    flags: i32,
+}
+
+/// `struct cmsghdr`, followed by its data aligned to `usize`.
+#[derive(Debug, Clone, Copy, FromZeroes, FromBytes, AsBytes)]
+#[repr(C)]
+struct CmsgHdr {
+    len: usize,
+    level: i32,
+    ty: i32,
+}
+
+const CMSG_LEN: usize = mem::size_of::<CmsgHdr>();
+
+const fn cmsg_align(len: usize) -> usize {
+    (len + mem::size_of::<usize>() - 1) & !(mem::size_of::<usize>() - 1)
+}
+
+const SCM_RIGHTS: i32 = 1;
+/// The maximum number of the file descriptors passed in a message.
+const SCM_MAX_FD: usize = 253;
+const MAX_CONTROL_LEN: usize = 4096;
+
+const MSG_CTRUNC: i32 = 0x8;
+const MSG_TRUNC: i32 = 0x20;
+const MSG_DONTWAIT: i32 = 0x40;
+const MSG_CMSG_CLOEXEC: i32 = 0x40000000;
+
+async fn read_iov<'a>(
+    virt: &Virt,
+    msg: &MsgHdr,
+    iov: &'a mut [IoVec; MAX_IOV_LEN],
+) -> Result<&'a [IoVec], Error> {
+    let iov = &mut iov[..msg.iov_len.min(MAX_IOV_LEN)];
+    UserPtr::<_, In>::new(msg.iov.addr())
+        .read_slice(virt, iov)
+        .await?;
+    Ok(iov)
+}
+
+/// Collects the file descriptors to be passed in the `SCM_RIGHTS` control
+/// messages.
+async fn rights(ts: &TaskState, msg: &MsgHdr) -> Result<Vec<FdInfo>, Error> {
+    let mut fds = Vec::new();
+    if msg.control.addr().is_null() || msg.control_len == 0 {
+        return Ok(fds);
+    }
+    let mut control = vec![0; msg.control_len.min(MAX_CONTROL_LEN)];
+    UserPtr::<_, In>::new(msg.control.addr())
+        .read_slice(&ts.virt, &mut control)
+        .await?;
+
+    let mut rest = &control[..];
+    while let Some(header) = CmsgHdr::read_from_prefix(rest) {
+        let data = rest.get(CMSG_LEN..header.len).ok_or(EINVAL)?;
+        if (header.level, header.ty) == (SOL_SOCKET, SCM_RIGHTS) {
+            for fd in data.chunks_exact(mem::size_of::<i32>()) {
+                if fds.len() >= SCM_MAX_FD {
+                    return Err(EINVAL);
+                }
+                let fd = i32::from_ne_bytes(fd.try_into().unwrap());
+                fds.push(ts.files.get_fi(fd).await?);
+            }
+        }
+        rest = rest.get(cmsg_align(header.len)..).unwrap_or_default();
+    }
+    Ok(fds)
+}
+
+/// Installs the passed file descriptors, and encodes them as a `SCM_RIGHTS`
+/// control message that fits in the buffer of `msg`.
+async fn install_rights(
+    ts: &TaskState,
+    fds: Vec<FdInfo>,
+    msg: &mut MsgHdr,
+    close_on_exec: bool,
+) -> Vec<u8> {
+    let room = msg.control_len.saturating_sub(CMSG_LEN) / mem::size_of::<i32>();
+    let mut data = Vec::new();
+    for (index, mut fi) in fds.into_iter().enumerate() {
+        if index >= room {
+            msg.flags |= MSG_CTRUNC;
+            break;
+        }
+        fi.close_on_exec = close_on_exec;
+        match ts.files.open(fi).await {
+            Ok(fd) => data.extend_from_slice(fd.as_bytes()),
+            Err(_) => {
+                msg.flags |= MSG_CTRUNC;
+                break;
+            }
+        }
+    }
+    if data.is_empty() {
+        return data;
+    }
+    let header = CmsgHdr {
+        len: CMSG_LEN + data.len(),
+        level: SOL_SOCKET,
+        ty: SCM_RIGHTS,
+    };
+    [header.as_bytes(), &data].concat()
+}
+
+#[async_handler]
+pub async fn sendmsg(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, UserPtr<MsgHdr, In>, i32) -> Result<usize, Error>>,
+) -> ScRet {
+    let (fd, msg, flags) = cx.args();
+    let fut = async {
+        let msg = msg.read(&ts.virt).await?;
+        let (socket, nonblock) = sock(&ts.files, fd).await?;
+        let nonblock = nonblock || flags & MSG_DONTWAIT != 0;
+
+        let mut iov = [Default::default(); MAX_IOV_LEN];
+        let iov = read_iov(&ts.virt, &msg, &mut iov).await?;
+        let name = UserPtr::new(msg.name.addr());
+        let name_len = msg.name_len as usize;
+
+        match socket {
+            Sock::Inet(socket) => {
+                let endpoint = ipaddr(&ts.virt, name, name_len).await?;
+
+                let mut guard = ts.virt.start_commit(Attr::READABLE).await;
+                commit_iov(&mut guard, iov).await?;
+                socket.send(guard.as_slice(), endpoint, nonblock).await
+            }
+            Sock::Unix(socket) => {
+                let to = unix_dest(ts, name, name_len).await?;
+                let fds = rights(ts, &msg).await?;
+
+                let mut guard = ts.virt.start_commit(Attr::READABLE).await;
+                commit_iov(&mut guard, iov).await?;
+                socket
+                    .send(guard.as_slice(), fds, to.as_ref(), nonblock)
+                    .await
+            }
+        }
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
+#[async_handler]
+pub async fn recvmsg(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(i32, UserPtr<MsgHdr, InOut>, i32) -> Result<usize, Error>>,
+) -> ScRet {
+    let (fd, mut ptr, flags) = cx.args();
+    let fut = async {
+        let mut msg = ptr.read(&ts.virt).await?;
+        let (socket, nonblock) = sock(&ts.files, fd).await?;
+        let nonblock = nonblock || flags & MSG_DONTWAIT != 0;
+
+        let mut iov = [Default::default(); MAX_IOV_LEN];
+        let iov = read_iov(&ts.virt, &msg, &mut iov).await?;
+
+        let mut guard = ts.virt.start_commit(Attr::WRITABLE).await;
+        commit_iov(&mut guard, iov).await?;
+        let (name, received) = match socket {
+            Sock::Inet(socket) => {
+                let (len, endpoint) = socket.receive(guard.as_mut_slice(), nonblock).await?;
+                let name =
+                    endpoint.map(|IpEndpoint { addr, port }| ip_sockaddr((Some(addr), port)));
+                (
+                    name,
+                    Received {
+                        len,
+                        ..Default::default()
+                    },
+                )
+            }
+            Sock::Unix(socket) => {
+                let received = socket.receive(guard.as_mut_slice(), nonblock).await?;
+                (received.from.as_ref().map(unix_sockaddr), received)
+            }
+        };
+        drop(guard);
+
+        msg.flags = if received.truncated { MSG_TRUNC } else { 0 };
+
+        let name = name.unwrap_or_default();
+        if !msg.name.addr().is_null() {
+            let len = name.len().min(msg.name_len as usize);
+            UserPtr::<_, Out>::new(msg.name.addr())
+                .write_slice(&ts.virt, &name[..len], false)
+                .await?;
+        }
+        msg.name_len = name.len() as u32;
+
+        let close_on_exec = flags & MSG_CMSG_CLOEXEC != 0;
+        let control = install_rights(ts, received.fds, &mut msg, close_on_exec).await;
+        if !control.is_empty() {
+            UserPtr::<_, Out>::new(msg.control.addr())
+                .write_slice(&ts.virt, &control, false)
+                .await?;
+        }
+        msg.control_len = control.len();
+
+        ptr.write(&ts.virt, msg).await?;
+        Ok(received.len)
+    };
+    cx.ret(fut.await);
+    ScRet::Continue(None)
+}
+
 #[async_handler]
 pub async fn getpeername(
     ts: &mut TaskState,
@@ -428,11 +837,14 @@ pub async fn getpeername(
 ) -> ScRet {
     let (fd, ptr, len) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
-        let IpEndpoint { addr, port } = socket.remote_endpoint().ok_or(ENOTCONN)?;
-        write_ipaddr(&ts.virt, (Some(addr), port), ptr, len).await?;
-        Ok(())
+        let addr = match sock(&ts.files, fd).await?.0 {
+            Sock::Inet(socket) => {
+                let IpEndpoint { addr, port } = socket.remote_endpoint().ok_or(ENOTCONN)?;
+                ip_sockaddr((Some(addr), port))
+            }
+            Sock::Unix(socket) => unix_sockaddr(&socket.peer_addr()?),
+        };
+        write_sockaddr(&ts.virt, &addr, ptr, len).await
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -445,17 +857,25 @@ pub async fn connect(
 ) -> ScRet {
     let (fd, addr, len) = cx.args();
     let fut = async {
-        let endpoint = ipaddr(&ts.virt, addr, len).await?.ok_or(EINVAL)?;
-        let mut storage = None;
-        let (socket, nonblock) = sock(&ts.files, fd, &mut storage).await?;
-        let timeout = if nonblock {
-            Some(Duration::ZERO)
-        } else {
-            ksync::critical(|| *socket.recv_timeout.lock())
-        };
-        match poll_with(socket.connect(endpoint), timeout).await {
-            Err(EAGAIN) => Ok(()),
-            res => res,
+        let (socket, nonblock) = sock(&ts.files, fd).await?;
+        match socket {
+            Sock::Inet(socket) => {
+                let endpoint = ipaddr(&ts.virt, addr, len).await?.ok_or(EINVAL)?;
+                let timeout = if nonblock {
+                    Some(Duration::ZERO)
+                } else {
+                    ksync::critical(|| *socket.recv_timeout.lock())
+                };
+                match poll_with(socket.connect(endpoint), timeout).await {
+                    Err(EAGAIN) => Ok(()),
+                    res => res,
+                }
+            }
+            Sock::Unix(socket) => {
+                let addr = unix_addr(ts, addr, len).await?;
+                check_path(&addr).await?;
+                socket.connect(&addr, nonblock).await
+            }
         }
     };
     cx.ret(fut.await);
@@ -469,10 +889,27 @@ pub async fn bind(
 ) -> ScRet {
     let (fd, addr, len) = cx.args();
     let fut = async {
-        let endpoint = ipaddr(&ts.virt, addr, len).await?.ok_or(EINVAL)?;
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
-        socket.bind(endpoint)
+        let socket = match sock(&ts.files, fd).await?.0 {
+            Sock::Inet(socket) => {
+                let endpoint = ipaddr(&ts.virt, addr, len).await?.ok_or(EINVAL)?;
+                return socket.bind(endpoint);
+            }
+            Sock::Unix(socket) => socket,
+        };
+        let addr = unix_addr(ts, addr, len).await?;
+        if socket.local_addr() != UnixAddr::Unnamed {
+            return Err(EINVAL);
+        }
+        if let UnixAddr::Path(path) = &addr {
+            let options = OpenOptions::CREAT | OpenOptions::EXCL | OpenOptions::WRONLY;
+            let perm = Permissions::all_same(true, true, false);
+            let (entry, _) = match crate::fs::open(path, options, perm).await {
+                Err(EEXIST) => return Err(EADDRINUSE),
+                res => res?,
+            };
+            set_owner(&ts.cred(), &entry).await;
+        }
+        socket.bind(addr)
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -485,10 +922,13 @@ pub async fn listen(
 ) -> ScRet {
     let (fd, backlog) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
-        crate::executor().spawn(socket.listen(backlog)?).detach();
-        Ok(())
+        match sock(&ts.files, fd).await?.0 {
+            Sock::Inet(socket) => {
+                crate::executor().spawn(socket.listen(backlog)?).detach();
+                Ok(())
+            }
+            Sock::Unix(socket) => socket.listen(backlog),
+        }
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -501,25 +941,30 @@ pub async fn accept(
 ) -> ScRet {
     let (fd, ptr, len) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, nonblock) = sock(&ts.files, fd, &mut storage).await?;
-        let timeout = if nonblock {
-            Some(Duration::ZERO)
-        } else {
-            ksync::critical(|| *socket.recv_timeout.lock())
+        let (socket, nonblock) = sock(&ts.files, fd).await?;
+        let (entry, addr) = match socket {
+            Sock::Inet(socket) => {
+                let timeout = if nonblock {
+                    Some(Duration::ZERO)
+                } else {
+                    ksync::critical(|| *socket.recv_timeout.lock())
+                };
+                let new = poll_with(socket.accept(), timeout).await?;
+                let addr = new
+                    .remote_endpoint()
+                    .map(|IpEndpoint { addr, port }| ip_sockaddr((Some(addr), port)));
+                (socket::tcp_accept(new), addr)
+            }
+            Sock::Unix(socket) => {
+                let new = socket.accept(nonblock).await?;
+                let addr = unix_sockaddr(&new.peer_addr()?);
+                (new as Arc<dyn Entry>, Some(addr))
+            }
         };
-        let new = poll_with(socket.accept(), timeout).await?;
-        if let Some(IpEndpoint { addr, port }) = new.remote_endpoint() {
-            write_ipaddr(&ts.virt, (Some(addr), port), ptr, len).await?;
+        if let Some(addr) = addr {
+            write_sockaddr(&ts.virt, &addr, ptr, len).await?;
         }
-        let fi = FdInfo {
-            entry: socket::tcp_accept(new),
-            close_on_exec: false,
-            nonblock: false,
-            perm: Permissions::all_same(true, true, false),
-            saved_next_dirent: Default::default(),
-        };
-        ts.files.open(fi).await
+        ts.files.open(sock_fi(entry, 0)).await
     };
     cx.ret(fut.await);
     ScRet::Continue(None)
@@ -530,15 +975,25 @@ pub async fn shutdown(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(i32, i32) -> Result<(), Error>>,
 ) -> ScRet {
+    const SHUT_RD: i32 = 0;
     const SHUT_WR: i32 = 1;
+    const SHUT_RDWR: i32 = 2;
     let (fd, cmd) = cx.args();
     let fut = async {
-        let mut storage = None;
-        let (socket, _) = sock(&ts.files, fd, &mut storage).await?;
-        if cmd & SHUT_WR != 0 {
-            if let Socket::Tcp(socket) = &**socket {
-                socket.close().await;
+        match sock(&ts.files, fd).await?.0 {
+            Sock::Inet(socket) => {
+                if cmd & SHUT_WR != 0 {
+                    if let Socket::Tcp(socket) = &***socket {
+                        socket.close().await;
+                    }
+                }
             }
+            Sock::Unix(socket) => match cmd {
+                SHUT_RD => socket.shutdown(true, false),
+                SHUT_WR => socket.shutdown(false, true),
+                SHUT_RDWR => socket.shutdown(true, true),
+                _ => return Err(EINVAL),
+            },
         }
         Ok(())
     };
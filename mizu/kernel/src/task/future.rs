@@ -1,3 +1,4 @@
+use alloc::sync::Arc;
 use core::{
     future::Future,
     mem,
@@ -8,7 +9,7 @@ use core::{
 };
 
 use arsc_rs::Arsc;
-use co_trap::{FastResult, TrapFrame};
+use co_trap::{FastResult, Fp, TrapFrame};
 use futures_util::{
     future::{select, Either},
     FutureExt,
@@ -22,18 +23,22 @@ use riscv::register::{
     time,
 };
 use rv39_paging::Attr;
+use scoped_tls::scoped_thread_local;
 use sygnal::{Sig, SigCode, SigInfo, SigSet};
 
-use super::TaskState;
-use crate::{
-    fs::Coverage,
-    syscall::ScRet,
-    task::signal::SIGRETURN_GUARD,
-    trap::{Fp, FP},
-};
+use super::{Task, TaskState};
+use crate::{fs::Coverage, syscall::ScRet, task::signal::SIGRETURN_GUARD, trap::FP};
+
+scoped_thread_local!(static CURRENT: Arc<Task>);
+
+/// Returns the task running on the current hart, if any.
+pub fn current() -> Option<Arc<Task>> {
+    CURRENT.is_set().then(|| CURRENT.with(Arc::clone))
+}
 
 #[pin_project]
 pub struct TaskFut<F> {
+    task: Arc<Task>,
     virt: Arsc<Virt>,
     fp: Fp,
     coverage: Coverage,
@@ -42,8 +47,9 @@ pub struct TaskFut<F> {
 }
 
 impl<F> TaskFut<F> {
-    pub fn new(virt: Arsc<Virt>, fut: F) -> Self {
+    pub fn new(task: Arc<Task>, virt: Arsc<Virt>, fut: F) -> Self {
         TaskFut {
+            task,
             virt,
             fp: FP.try_with(Fp::copy).unwrap_or_default(),
             coverage: Coverage::new(),
@@ -59,7 +65,9 @@ impl<F: Future> Future for TaskFut<F> {
         unsafe { self.virt.clone().load() };
         let this = self.project();
         let ret = FP.set(this.fp, || {
-            crate::fs::COVERAGE.set(this.coverage, || this.fut.poll(cx))
+            let fut = this.fut;
+            let poll = || crate::fs::COVERAGE.set(this.coverage, || fut.poll(cx));
+            CURRENT.set(this.task, poll)
         });
         if ret.is_pending() {
             this.fp.yield_now();
@@ -184,6 +192,9 @@ async fn handle_scause(scause: Scause, ts: &mut TaskState, tf: &mut TrapFrame) -
                 }
             }
             Exception::IllegalInstruction => {
+                if FP.with(|fp| fp.restore(&mut tf.sstatus)) {
+                    return Continue(None);
+                }
                 // TODO: avoid raw instruction.
                 #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
                 if excep == Exception::InstructionPageFault {
@@ -0,0 +1,131 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use spin::Mutex;
+use sygnal::{Sig, SigCode, SigFields, SigInfo};
+
+use super::Task;
+
+/// The process group and session of a process, shared by all of its threads.
+#[derive(Debug)]
+pub struct Job {
+    pgid: AtomicUsize,
+    sid: AtomicUsize,
+}
+
+impl Job {
+    pub fn new(pgid: usize, sid: usize) -> Arc<Self> {
+        Arc::new(Job {
+            pgid: pgid.into(),
+            sid: sid.into(),
+        })
+    }
+
+    pub fn fork(&self) -> Arc<Self> {
+        Job::new(self.pgid(), self.sid())
+    }
+
+    pub fn pgid(&self) -> usize {
+        self.pgid.load(SeqCst)
+    }
+
+    pub fn sid(&self) -> usize {
+        self.sid.load(SeqCst)
+    }
+}
+
+#[derive(Debug)]
+struct Group {
+    sid: usize,
+    members: BTreeMap<usize, Weak<Task>>,
+}
+
+/// All the process groups, indexed by their IDs.
+static GROUPS: Mutex<BTreeMap<usize, Group>> = Mutex::new(BTreeMap::new());
+
+fn insert(groups: &mut BTreeMap<usize, Group>, pid: usize, task: &Arc<Task>) {
+    let group = groups.entry(task.job.pgid()).or_insert_with(|| Group {
+        sid: task.job.sid(),
+        members: BTreeMap::new(),
+    });
+    group.members.insert(pid, Arc::downgrade(task));
+}
+
+fn remove(groups: &mut BTreeMap<usize, Group>, pid: usize, pgid: usize) {
+    if let Some(group) = groups.get_mut(&pgid) {
+        group.members.remove(&pid);
+        if group.members.is_empty() {
+            groups.remove(&pgid);
+        }
+    }
+}
+
+/// Adds the process `task` to its process group.
+pub fn join(task: &Arc<Task>) {
+    ksync::critical(|| insert(&mut GROUPS.lock(), task.tid, task))
+}
+
+/// Removes the process `pid` from its process group.
+pub fn leave(pid: usize, job: &Job) {
+    ksync::critical(|| remove(&mut GROUPS.lock(), pid, job.pgid()))
+}
+
+/// Moves the process `task` into the group `pgid`, which must be either
+/// itself or an existing group in the same session.
+pub fn set_pgid(task: &Arc<Task>, pgid: usize) -> bool {
+    ksync::critical(|| {
+        let mut groups = GROUPS.lock();
+        let sid = task.job.sid();
+        if pgid != task.tid && groups.get(&pgid).map_or(true, |g| g.sid != sid) {
+            return false;
+        }
+        remove(&mut groups, task.tid, task.job.pgid());
+        task.job.pgid.store(pgid, SeqCst);
+        insert(&mut groups, task.tid, task);
+        true
+    })
+}
+
+/// Makes the process `task` the leader of a new session and a new group.
+pub fn set_sid(task: &Arc<Task>) {
+    ksync::critical(|| {
+        let mut groups = GROUPS.lock();
+        remove(&mut groups, task.tid, task.job.pgid());
+        task.job.pgid.store(task.tid, SeqCst);
+        task.job.sid.store(task.tid, SeqCst);
+        insert(&mut groups, task.tid, task);
+    })
+}
+
+/// Returns whether the group `pgid` exists.
+pub fn exists(pgid: usize) -> bool {
+    ksync::critical(|| GROUPS.lock().contains_key(&pgid))
+}
+
+/// Returns the live processes in the group `pgid`.
+pub fn members(pgid: usize) -> Vec<Arc<Task>> {
+    ksync::critical(|| {
+        let groups = GROUPS.lock();
+        let iter = groups
+            .get(&pgid)
+            .into_iter()
+            .flat_map(|g| g.members.values());
+        iter.filter_map(Weak::upgrade).collect()
+    })
+}
+
+/// Sends `sig` from the kernel to every process in the group `pgid`.
+pub fn kill_group(pgid: usize, sig: Sig) {
+    let si = SigInfo {
+        sig,
+        code: SigCode::KERNEL as _,
+        fields: SigFields::None,
+    };
+    members(pgid)
+        .iter()
+        .for_each(|task| task.shared_sig.load(SeqCst).push(si))
+}
@@ -0,0 +1,198 @@
+//! The out-of-memory killer, which kills the process with the highest badness
+//! when no frame can be allocated even after the reclamation.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, AtomicI32, Ordering::SeqCst},
+    time::Duration,
+};
+
+use ksc::{
+    Boxed,
+    Error::{self, EINVAL},
+};
+use ksync::event::Event;
+use rv39_paging::PAGE_SIZE;
+use sygnal::{Sig, SigCode, SigFields, SigInfo};
+
+use super::{pid, Task, INIT_PID};
+
+pub const OOM_SCORE_ADJ_MIN: i32 = -1000;
+pub const OOM_SCORE_ADJ_MAX: i32 = 1000;
+
+/// The interval to check whether a victim has exited.
+const EXIT_POLL: Duration = Duration::from_millis(10);
+/// The time to wait for a victim to exit before retrying the allocation.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The state of a process for the OOM killer, shared by all of its threads.
+#[derive(Debug)]
+pub struct Oom {
+    score_adj: AtomicI32,
+    /// Set once the process is chosen as a victim.
+    killed: AtomicBool,
+}
+
+impl Oom {
+    pub fn new(score_adj: i32) -> Arc<Self> {
+        Arc::new(Oom {
+            score_adj: score_adj.into(),
+            killed: false.into(),
+        })
+    }
+
+    pub fn fork(&self) -> Arc<Self> {
+        Oom::new(self.score_adj())
+    }
+
+    pub fn score_adj(&self) -> i32 {
+        self.score_adj.load(SeqCst)
+    }
+
+    pub fn set_score_adj(&self, score_adj: i32) -> Result<(), Error> {
+        if !(OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(&score_adj) {
+            return Err(EINVAL);
+        }
+        self.score_adj.store(score_adj, SeqCst);
+        Ok(())
+    }
+
+    fn is_killed(&self) -> bool {
+        self.killed.load(SeqCst)
+    }
+}
+
+/// Returns the badness of the process in pages, which is its resident set size
+/// adjusted by `oom_score_adj` in thousandths of all the frames, or `None` if
+/// it is never killed.
+fn badness(process: &Task) -> Option<usize> {
+    let info = process.info()?;
+    let score_adj = process.oom.score_adj();
+    if process.tgid == INIT_PID || score_adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+    let total = kmem::frames().total_count() as isize;
+    let points = info.virt.resident() as isize + score_adj as isize * total / 1000;
+    // Processes still alive are always worth something.
+    Some(points.max(1) as usize)
+}
+
+/// Returns the badness of the process normalized to `0..=2000`, as shown in
+/// `/proc/<pid>/oom_score`.
+pub fn score(process: &Task) -> usize {
+    let total = kmem::frames().total_count().max(1);
+    badness(process).map_or(0, |points| (points * 1000 / total).min(2000))
+}
+
+/// Set whenever a victim is being chosen or waited for.
+static BUSY: AtomicBool = AtomicBool::new(false);
+/// Notified whenever a victim is chosen or the killer is done.
+static EVENT: Event = Event::new();
+
+/// The handler of [`kmem::reclaim`] called when the memory runs out.
+pub fn out_of_memory() -> Boxed<'static, bool> {
+    Box::pin(async {
+        let current = super::current();
+        let killed = || current.as_ref().map_or(false, |task| task.oom.is_killed());
+
+        let mut listener = None;
+        loop {
+            // The victims cannot exit until their allocations fail.
+            if killed() {
+                return false;
+            }
+            if !BUSY.swap(true, SeqCst) {
+                break;
+            }
+            match listener.take() {
+                Some(listener) => listener.await,
+                None => listener = Some(EVENT.listen()),
+            }
+        }
+        let ret = kill(current.as_deref()).await;
+        BUSY.store(false, SeqCst);
+        EVENT.notify(usize::MAX);
+        ret
+    })
+}
+
+/// Returns the victim still exiting if any, or the process with the highest
+/// badness otherwise.
+fn select() -> Option<(Arc<Task>, usize)> {
+    let mut victim: Option<(Arc<Task>, usize)> = None;
+    let mut start = 0;
+    while let Some(process) = pid::next_process(start) {
+        start = process.tid + 1;
+        let Some(points) = badness(&process) else {
+            continue;
+        };
+        if process.oom.is_killed() {
+            return Some((process, points));
+        }
+        if victim.as_ref().map_or(true, |(_, max)| points > *max) {
+            victim = Some((process, points));
+        }
+    }
+    victim
+}
+
+async fn kill(current: Option<&Task>) -> bool {
+    // Some frames may have been freed while waiting for the last victim.
+    if kmem::reclaim::free_count() > 0 {
+        return true;
+    }
+    let Some((victim, points)) = select() else {
+        log::error!("Out of memory: no process to kill");
+        return false;
+    };
+    if !victim.oom.killed.swap(true, SeqCst) {
+        log::warn!(
+            "Out of memory: killed process {} ({}), badness = {points}, rss = {} kB",
+            victim.tgid,
+            victim.executable(),
+            victim.info().map_or(0, |info| info.virt.resident()) * PAGE_SIZE / 1024,
+        );
+        let si = SigInfo {
+            sig: Sig::SIGKILL,
+            code: SigCode::KERNEL as _,
+            fields: SigFields::None,
+        };
+        let mut start = 0;
+        while let Some(thread) = pid::next_thread(victim.tgid, start) {
+            start = thread.tid + 1;
+            thread.sig.push(si);
+        }
+        // Wake up the threads of the victim waiting for the killer.
+        EVENT.notify(usize::MAX);
+    }
+    // The process cannot exit while the current thread is waiting for it.
+    if current.map_or(false, |task| task.tgid == victim.tgid) {
+        return false;
+    }
+    wait_exit(&victim).await;
+    true
+}
+
+/// Waits until all the threads of the process have exited, after which its
+/// address space is cleared.
+async fn wait_exit(process: &Task) {
+    let exited = || {
+        let mut start = 0;
+        while let Some(thread) = pid::next_thread(process.tgid, start) {
+            if thread.info().is_some() {
+                return false;
+            }
+            start = thread.tid + 1;
+        }
+        true
+    };
+    let mut waited = Duration::ZERO;
+    while !exited() {
+        if waited >= EXIT_TIMEOUT {
+            log::warn!("Out of memory: process {} is not exiting", process.tgid);
+            break;
+        }
+        ktime::sleep(EXIT_POLL).await;
+        waited += EXIT_POLL;
+    }
+}
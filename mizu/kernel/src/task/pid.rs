@@ -0,0 +1,78 @@
+//! The table of all the tasks in the system, indexed by their thread IDs.
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use spin::Mutex;
+
+use super::Task;
+
+/// All the tasks not yet reaped, including the zombies, with the IDs of their
+/// processes.
+///
+/// The tasks are only upgraded if they are to be returned, so that none of
+/// them is dropped with the lock held.
+static TASKS: Mutex<BTreeMap<usize, (usize, Weak<Task>)>> = Mutex::new(BTreeMap::new());
+
+/// The next ID to be allocated, with the one of the init process reserved.
+static NEXT: AtomicUsize = AtomicUsize::new(2);
+
+pub(super) fn alloc() -> usize {
+    NEXT.fetch_add(1, SeqCst)
+}
+
+/// Returns the most recently allocated ID.
+pub fn last() -> usize {
+    NEXT.load(SeqCst) - 1
+}
+
+/// Returns the number of all the tasks.
+pub fn count() -> usize {
+    ksync::critical(|| TASKS.lock().len())
+}
+
+pub(super) fn insert(task: &Arc<Task>) {
+    let value = (task.tgid, Arc::downgrade(task));
+    ksync::critical(|| TASKS.lock().insert(task.tid, value));
+}
+
+pub(super) fn remove(tid: usize) {
+    ksync::critical(|| TASKS.lock().remove(&tid));
+}
+
+/// Finds the task `tid`, which is either a thread or a process.
+pub fn find(tid: usize) -> Option<Arc<Task>> {
+    ksync::critical(|| TASKS.lock().get(&tid).and_then(|(_, t)| t.upgrade()))
+}
+
+/// Returns the first live task with its thread ID no less than `start` that
+/// satisfies `predicate` with its thread ID and process ID.
+fn next(start: usize, predicate: impl Fn(usize, usize) -> bool) -> Option<Arc<Task>> {
+    ksync::critical(|| {
+        let tasks = TASKS.lock();
+        let mut iter = tasks.range(start..);
+        iter.find_map(|(&tid, (tgid, t))| predicate(tid, *tgid).then(|| t.upgrade())?)
+    })
+}
+
+/// Returns the first process with its ID no less than `start`.
+pub fn next_process(start: usize) -> Option<Arc<Task>> {
+    next(start, |tid, tgid| tid == tgid)
+}
+
+/// Returns the first thread of the process `pid` with its ID no less than
+/// `start`.
+pub fn next_thread(pid: usize, start: usize) -> Option<Arc<Task>> {
+    next(start, |_, tgid| tgid == pid)
+}
+
+/// Returns the number of the threads in the process `pid`.
+pub fn thread_count(pid: usize) -> usize {
+    ksync::critical(|| {
+        let tasks = TASKS.lock();
+        tasks.values().filter(|(tgid, _)| *tgid == pid).count()
+    })
+}
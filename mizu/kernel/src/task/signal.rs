@@ -15,6 +15,7 @@ use super::{TaskEvent, TaskState};
 use crate::{
     mem::{In, Out, UserPtr},
     syscall::ScRet,
+    trap::FP,
 };
 
 impl TaskState {
@@ -110,7 +111,8 @@ impl TaskState {
             _rsvd: 0,
             mc: Mcontext {
                 pc: tf.sepc,
-                ..Default::default()
+                x: Default::default(),
+                fp: FP.with(|fp| fp.regs()).into(),
             },
         };
         tf.gpr.copy_to_x(&mut uc.mc.x);
@@ -146,6 +148,7 @@ impl TaskState {
         ts.sig_stack = (uc.stack.len != 0).then_some(uc.stack);
         tf.sepc = uc.mc.pc;
         tf.gpr.copy_from_x(&uc.mc.x);
+        FP.with(|fp| fp.set_regs(&uc.mc.fp.into()));
         ScRet::Continue(None)
     }
 }
@@ -187,9 +190,42 @@ impl From<PaddedSigSet> for SigSet {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 struct Mcontext {
     pc: usize,
     x: [usize; 31],
+    fp: FpState,
+}
+
+/// The floating-point state in the signal context, laid out as the D extension
+/// variant of `union __riscv_fp_state`, whose largest variant is 528 bytes.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, align(16))]
+struct FpState {
+    f: [u64; 32],
+    fcsr: u32,
+    _rsvd: [u32; 67],
+}
+const_assert!(mem::size_of::<FpState>() == 528);
+
+impl From<[u64; 33]> for FpState {
+    fn from(regs: [u64; 33]) -> Self {
+        let mut f = [0; 32];
+        f.copy_from_slice(&regs[..32]);
+        FpState {
+            f,
+            fcsr: regs[32] as u32,
+            _rsvd: [0; 67],
+        }
+    }
+}
+
+impl From<FpState> for [u64; 33] {
+    fn from(state: FpState) -> Self {
+        let mut regs = [0; 33];
+        regs[..32].copy_from_slice(&state.f);
+        regs[32] = state.fcsr.into();
+        regs
+    }
 }
@@ -1,4 +1,4 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec, vec::Vec};
 use core::{mem, num::NonZeroI32, pin::pin, sync::atomic::Ordering::SeqCst};
 
 use co_trap::UserCx;
@@ -15,9 +15,16 @@ use super::UsigInfo;
 use crate::{
     mem::{In, Out, UserPtr},
     syscall::{ffi::Tv, ScRet},
-    task::{PidSelection, TaskState},
+    task::{job, pid, PidSelection, Task, TaskState},
 };
 
+/// Returns whether the current task may send signals to `task`.
+fn can_kill(ts: &TaskState, task: &Task) -> bool {
+    // Exited tasks cannot be affected by the signals anyway.
+    task.info()
+        .map_or(true, |info| ts.cred().check_kill(&info.cred).is_ok())
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct SigAction {
@@ -295,29 +302,57 @@ pub async fn kill(
     let (pid, sig) = cx.args();
     let fut = async move {
         let pid = PidSelection::from(pid);
-        let sig = NonZeroI32::new(sig)
-            .and_then(|s| Sig::new(s.get()))
-            .ok_or(EINVAL)?;
-
-        let si = SigInfo {
-            sig,
-            code: SigCode::USER as _,
-            fields: SigFields::SigKill {
-                pid: ts.task.tid,
-                uid: 0,
-            },
+        // Signal 0 only checks the existence of the targets.
+        let sig = match NonZeroI32::new(sig) {
+            Some(sig) => Some(Sig::new(sig.get()).ok_or(EINVAL)?),
+            None => None,
         };
-        match pid {
-            PidSelection::Task(Some(tid)) if tid == ts.task.tid => ts.task.sig.push(si),
-            PidSelection::Task(Some(tid)) => {
-                let child = ksync::critical(|| {
-                    let children = ts.task.children.lock();
-                    let mut iter = children.iter();
-                    iter.find(|c| c.task.tid == tid).map(|c| c.task.clone())
-                });
-                child.ok_or(ESRCH)?.sig.push(si);
+
+        let targets = match pid {
+            PidSelection::Task(Some(tid)) if tid == ts.task.tid => vec![ts.task.clone()],
+            PidSelection::Task(Some(pid)) => {
+                let task = pid::find(pid).filter(|t| t.tgid == pid);
+                task.into_iter().collect()
+            }
+            PidSelection::Task(None) => {
+                let mut all = Vec::new();
+                let mut start = 0;
+                while let Some(process) = pid::next_process(start) {
+                    start = process.tid + 1;
+                    if process.tid != 1 && process.tid != ts.tgroup.0 {
+                        all.push(process);
+                    }
+                }
+                all
             }
-            x => todo!("kill {x:?}"),
+            PidSelection::Group(None) => job::members(ts.task.job.pgid()),
+            PidSelection::Group(Some(pgid)) => job::members(pgid),
+        };
+        if targets.is_empty() {
+            return Err(ESRCH);
+        }
+        // The targets not permitted are skipped, unless none is permitted.
+        let targets = targets
+            .into_iter()
+            .filter(|task| can_kill(ts, task))
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return Err(EPERM);
+        }
+
+        if let Some(sig) = sig {
+            let si = SigInfo {
+                sig,
+                code: SigCode::USER as _,
+                fields: SigFields::SigKill {
+                    pid: ts.tgroup.0,
+                    uid: ts.cred().uid.real as usize,
+                },
+            };
+            // Signals to processes are shared by all of their threads.
+            targets
+                .iter()
+                .for_each(|task| task.shared_sig.load(SeqCst).push(si));
         }
         Ok(())
     };
@@ -341,12 +376,15 @@ pub async fn tkill(
             code: SigCode::USER as _,
             fields: SigFields::SigKill {
                 pid: ts.task.tid,
-                uid: 0,
+                uid: ts.cred().uid.real as usize,
             },
         };
 
-        let task = ksync::critical(|| ts.tgroup.1.read().iter().find(|t| t.tid == tid).cloned());
-        task.ok_or(ESRCH)?.sig.push(si);
+        let task = pid::find(tid).ok_or(ESRCH)?;
+        if !can_kill(ts, &task) {
+            return Err(EPERM);
+        }
+        task.sig.push(si);
         Ok(())
     };
     cx.ret(fut.await);
@@ -364,21 +402,21 @@ pub async fn tgkill(
             .and_then(|s| Sig::new(s.get()))
             .ok_or(EINVAL)?;
 
-        if ts.tgroup.0 != tgid {
-            return Err(EPERM);
-        }
-
         let si = SigInfo {
             sig,
             code: SigCode::USER as _,
             fields: SigFields::SigKill {
                 pid: ts.task.tid,
-                uid: 0,
+                uid: ts.cred().uid.real as usize,
             },
         };
 
-        let task = ksync::critical(|| ts.tgroup.1.read().iter().find(|t| t.tid == tid).cloned());
-        task.ok_or(ESRCH)?.sig.push(si);
+        let task = pid::find(tid).filter(|t| t.tgid == tgid);
+        let task = task.ok_or(ESRCH)?;
+        if !can_kill(ts, &task) {
+            return Err(EPERM);
+        }
+        task.sig.push(si);
         Ok(())
     };
     cx.ret(fut.await);
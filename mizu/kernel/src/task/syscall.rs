@@ -10,9 +10,10 @@ use core::{
 use arsc_rs::Arsc;
 use cmd::Command;
 use co_trap::{TrapFrame, UserCx};
+use kmem::Virt;
 use ksc::{
     async_handler,
-    Error::{self, EAGAIN, EINVAL, EPERM},
+    Error::{self, EAGAIN, EINVAL, EPERM, ESRCH},
     RawReg,
 };
 use ksync::{channel::Broadcast, AtomicArsc};
@@ -22,17 +23,19 @@ use sygnal::{Action, Sig, SigCode, SigFields, SigInfo};
 
 use crate::{
     executor,
-    mem::{deep_fork, In, Out, UserPtr, USER_RANGE},
+    mem::{deep_fork, memlock_limit, In, Out, UserPtr, USER_RANGE},
     syscall::{
         ffi::{Itv, Tv},
         ScRet,
     },
     task::{
         cmd,
+        cred::Ids,
         fd::MAX_PATH_LEN,
         future::{user_loop, TaskFut},
+        job, pid,
         time::Times,
-        yield_now, Child, Task, TaskEvent, TaskState,
+        yield_now, Child, Task, TaskEvent, TaskInfo, TaskState, WaitOptions,
     },
     trap::poll_with,
 };
@@ -75,8 +78,293 @@ pub async fn pid(ts: &mut TaskState, cx: UserCx<'_, fn() -> usize>) -> ScRet {
 
 #[async_handler]
 pub async fn ppid(ts: &mut TaskState, cx: UserCx<'_, fn() -> usize>) -> ScRet {
-    let task = &ts.task;
-    cx.ret(task.parent.upgrade().map_or(1, |parent| parent.tid));
+    cx.ret(ts.task.ppid());
+    Continue(None)
+}
+
+/// Returns the process of the calling thread, which is its group leader.
+fn current_process(ts: &TaskState) -> Result<Arc<Task>, Error> {
+    match ts.task.tid == ts.tgroup.0 {
+        true => Ok(ts.task.clone()),
+        false => pid::find(ts.tgroup.0).ok_or(ESRCH),
+    }
+}
+
+#[async_handler]
+pub async fn getpgid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize) -> Result<usize, Error>>,
+) -> ScRet {
+    let pid = cx.args();
+    let ret = match pid {
+        0 => Ok(ts.task.job.pgid()),
+        pid => pid::find(pid)
+            .filter(|t| t.tgid == pid)
+            .map(|t| t.job.pgid())
+            .ok_or(ESRCH),
+    };
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn getsid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize) -> Result<usize, Error>>,
+) -> ScRet {
+    let pid = cx.args();
+    let ret = match pid {
+        0 => Ok(ts.task.job.sid()),
+        pid => pid::find(pid)
+            .filter(|t| t.tgid == pid)
+            .map(|t| t.job.sid())
+            .ok_or(ESRCH),
+    };
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setpgid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, isize) -> Result<(), Error>>,
+) -> ScRet {
+    let (pid, pgid) = cx.args();
+    let fut = async {
+        let pid = if pid == 0 { ts.tgroup.0 } else { pid };
+        let pgid = match pgid {
+            0 => pid,
+            x if x < 0 => return Err(EINVAL),
+            x => x as usize,
+        };
+        let task = if pid == ts.tgroup.0 {
+            current_process(ts)?
+        } else {
+            let child = ksync::critical(|| {
+                let children = ts.task.children.lock();
+                children.iter().find(|c| c.task.tid == pid).cloned()
+            });
+            child.ok_or(ESRCH)?.task
+        };
+        let sid = task.job.sid();
+        // Session leaders stay in their groups, and groups never span sessions.
+        if sid == pid || sid != ts.task.job.sid() || !job::set_pgid(&task, pgid) {
+            return Err(EPERM);
+        }
+        Ok(())
+    };
+    cx.ret(fut.await);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setsid(ts: &mut TaskState, cx: UserCx<'_, fn() -> Result<usize, Error>>) -> ScRet {
+    let fut = async {
+        let pid = ts.tgroup.0;
+        if job::exists(pid) {
+            return Err(EPERM);
+        }
+        job::set_sid(&current_process(ts)?);
+        Ok(pid)
+    };
+    cx.ret(fut.await);
+    Continue(None)
+}
+
+/// Converts an ID from the user, where `-1` leaves the ID unchanged.
+fn user_id(id: u32) -> Option<u32> {
+    (id != u32::MAX).then_some(id)
+}
+
+async fn write_ids(virt: &Virt, ids: Ids, ptrs: [UserPtr<u32, Out>; 3]) -> Result<(), Error> {
+    let [mut real, mut effective, mut saved] = ptrs;
+    real.write(virt, ids.real).await?;
+    effective.write(virt, ids.effective).await?;
+    saved.write(virt, ids.saved).await
+}
+
+#[async_handler]
+pub async fn getuid(ts: &mut TaskState, cx: UserCx<'_, fn() -> usize>) -> ScRet {
+    cx.ret(ts.cred().uid.real as usize);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn geteuid(ts: &mut TaskState, cx: UserCx<'_, fn() -> usize>) -> ScRet {
+    cx.ret(ts.cred().uid.effective as usize);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn getgid(ts: &mut TaskState, cx: UserCx<'_, fn() -> usize>) -> ScRet {
+    cx.ret(ts.cred().gid.real as usize);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn getegid(ts: &mut TaskState, cx: UserCx<'_, fn() -> usize>) -> ScRet {
+    cx.ret(ts.cred().gid.effective as usize);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setuid(ts: &mut TaskState, cx: UserCx<'_, fn(u32) -> Result<(), Error>>) -> ScRet {
+    let uid = cx.args();
+    let ret = ts.update_cred(|cred| {
+        let privileged = cred.is_root();
+        cred.uid.set(uid, privileged)
+    });
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setgid(ts: &mut TaskState, cx: UserCx<'_, fn(u32) -> Result<(), Error>>) -> ScRet {
+    let gid = cx.args();
+    let ret = ts.update_cred(|cred| {
+        let privileged = cred.is_root();
+        cred.gid.set(gid, privileged)
+    });
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setreuid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(u32, u32) -> Result<(), Error>>,
+) -> ScRet {
+    let (real, effective) = cx.args();
+    let ret = ts.update_cred(|cred| {
+        let privileged = cred.is_root();
+        (cred.uid).set_re(user_id(real), user_id(effective), privileged)
+    });
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setregid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(u32, u32) -> Result<(), Error>>,
+) -> ScRet {
+    let (real, effective) = cx.args();
+    let ret = ts.update_cred(|cred| {
+        let privileged = cred.is_root();
+        (cred.gid).set_re(user_id(real), user_id(effective), privileged)
+    });
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setresuid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(u32, u32, u32) -> Result<(), Error>>,
+) -> ScRet {
+    let (real, effective, saved) = cx.args();
+    let ret = ts.update_cred(|cred| {
+        let privileged = cred.is_root();
+        (cred.uid).set_res(
+            user_id(real),
+            user_id(effective),
+            user_id(saved),
+            privileged,
+        )
+    });
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setresgid(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(u32, u32, u32) -> Result<(), Error>>,
+) -> ScRet {
+    let (real, effective, saved) = cx.args();
+    let ret = ts.update_cred(|cred| {
+        let privileged = cred.is_root();
+        (cred.gid).set_res(
+            user_id(real),
+            user_id(effective),
+            user_id(saved),
+            privileged,
+        )
+    });
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn getresuid(
+    ts: &mut TaskState,
+    cx: UserCx<
+        '_,
+        fn(UserPtr<u32, Out>, UserPtr<u32, Out>, UserPtr<u32, Out>) -> Result<(), Error>,
+    >,
+) -> ScRet {
+    let (real, effective, saved) = cx.args();
+    let ret = write_ids(&ts.virt, ts.cred().uid, [real, effective, saved]).await;
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn getresgid(
+    ts: &mut TaskState,
+    cx: UserCx<
+        '_,
+        fn(UserPtr<u32, Out>, UserPtr<u32, Out>, UserPtr<u32, Out>) -> Result<(), Error>,
+    >,
+) -> ScRet {
+    let (real, effective, saved) = cx.args();
+    let ret = write_ids(&ts.virt, ts.cred().gid, [real, effective, saved]).await;
+    cx.ret(ret);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn getgroups(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, UserPtr<u32, Out>) -> Result<usize, Error>>,
+) -> ScRet {
+    let (size, mut list) = cx.args();
+    let fut = async {
+        let groups = ts.cred().groups;
+        if size == 0 {
+            return Ok(groups.len());
+        }
+        if size < groups.len() {
+            return Err(EINVAL);
+        }
+        list.write_slice(&ts.virt, &groups, false).await?;
+        Ok(groups.len())
+    };
+    cx.ret(fut.await);
+    Continue(None)
+}
+
+#[async_handler]
+pub async fn setgroups(
+    ts: &mut TaskState,
+    cx: UserCx<'_, fn(usize, UserPtr<u32, In>) -> Result<(), Error>>,
+) -> ScRet {
+    const NGROUPS_MAX: usize = 65536;
+
+    let (size, list) = cx.args();
+    let fut = async {
+        if !ts.cred().is_root() {
+            return Err(EPERM);
+        }
+        if size > NGROUPS_MAX {
+            return Err(EINVAL);
+        }
+        let mut groups = vec![0; size];
+        list.read_slice(&ts.virt, &mut groups).await?;
+        ts.update_cred(|cred| cred.groups = groups);
+        Ok(())
+    };
+    cx.ret(fut.await);
     Continue(None)
 }
 
@@ -129,6 +417,7 @@ const RLIMIT_DATA: u32 = 2; // max data size
 const RLIMIT_STACK: u32 = 3; // max stack size
 const RLIMIT_NPROC: u32 = 6; // max number of processes
 const RLIMIT_NOFILE: u32 = 7; // max number of open files
+const RLIMIT_MEMLOCK: u32 = 8; // max locked-in-memory address space
 const RLIMIT_AS: u32 = 9; // address space limit
 
 #[derive(Debug, Clone, Copy)]
@@ -156,6 +445,10 @@ pub async fn prlimit(
                 (s, usize::MAX)
             }
             RLIMIT_DATA | RLIMIT_STACK => (8 * 1024 * 1024, usize::MAX),
+            RLIMIT_MEMLOCK => {
+                let limit = memlock_limit(ts);
+                (limit, limit)
+            }
             RLIMIT_NOFILE => {
                 let limit = if new.is_null() {
                     ts.files.get_limit()
@@ -315,22 +608,38 @@ async fn clone_task(
 
     log::trace!("clone_task: flags = {flags:?}");
 
-    let new_tid = cmd::alloc_tid();
+    let new_tid = pid::alloc();
     log::trace!("new tid = {new_tid}");
     let task = Arc::new(Task {
         executable: spin::Mutex::new(ksync::critical(|| ts.task.executable.lock().clone())),
-        parent: if flags.intersects(Flags::PARENT | Flags::THREAD) {
-            ts.task.parent.clone()
+        parent: spin::Mutex::new(if flags.intersects(Flags::PARENT | Flags::THREAD) {
+            ksync::critical(|| ts.task.parent.lock().clone())
         } else {
             Arc::downgrade(&ts.task)
-        },
+        }),
         children: spin::Mutex::new(Vec::new()),
         tid: new_tid,
+        tgid: if flags.contains(Flags::THREAD) {
+            ts.tgroup.0
+        } else {
+            new_tid
+        },
+        info: spin::Mutex::new(None),
         times: if flags.intersects(Flags::THREAD) {
             Times::new_thread(&ts.task.times)
         } else {
             Default::default()
         },
+        job: if flags.contains(Flags::THREAD) {
+            ts.task.job.clone()
+        } else {
+            ts.task.job.fork()
+        },
+        oom: if flags.contains(Flags::THREAD) {
+            ts.task.oom.clone()
+        } else {
+            ts.task.oom.fork()
+        },
         sig: Default::default(),
         shared_sig: AtomicArsc::new(if flags.contains(Flags::THREAD) {
             ts.task.shared_sig.load(SeqCst)
@@ -339,6 +648,10 @@ async fn clone_task(
         }),
         event: Broadcast::new(),
     });
+    pid::insert(&task);
+    if !flags.contains(Flags::THREAD) {
+        job::join(&task);
+    }
     if flags.contains(Flags::PARENT_SETTID) {
         ptid.write(&ts.virt, new_tid).await?;
     }
@@ -381,6 +694,7 @@ async fn clone_task(
         counters: super::time::counters(),
         sig_mask: Action::default_sig_mask(),
         sig_stack: None,
+        brk_start: ts.brk_start,
         brk: ts.brk,
         virt,
         futex: if flags.contains(Flags::THREAD) {
@@ -397,6 +711,11 @@ async fn clone_task(
             .files
             .deep_fork(flags.contains(Flags::FS), flags.contains(Flags::FILES))
             .await,
+        cred: if flags.contains(Flags::THREAD) {
+            ts.cred.clone()
+        } else {
+            Arsc::new(spin::RwLock::new(ts.cred()))
+        },
         sig_actions: if flags.contains(Flags::SIGHAND) {
             ts.sig_actions.clone()
         } else {
@@ -405,18 +724,28 @@ async fn clone_task(
         tid_clear: flags.contains(Flags::CHILD_CLEARTID).then_some(ctid),
         exit_signal,
     };
+    if let Some(info) = ts.task.info() {
+        let info = TaskInfo {
+            virt: new_ts.virt.clone(),
+            files: new_ts.files.share(),
+            cred: new_ts.cred(),
+            ..info
+        };
+        ksync::critical(|| *task.info.lock() = Some(info));
+    }
 
     if !flags.contains(Flags::THREAD) {
         log::trace!(
             "clone_task: push into parent: {:?}",
-            new_ts.task.parent.upgrade().map(|s| s.tid)
+            new_ts.task.parent().map(|s| s.tid)
         );
 
-        if let Some(parent) = new_ts.task.parent.upgrade() {
+        if let Some(parent) = new_ts.task.parent() {
             ksync::critical(|| {
                 parent.children.lock().push(Child {
                     task: new_ts.task.clone(),
                     event: new_ts.task.event(),
+                    pending: Default::default(),
                 })
             });
 
@@ -425,7 +754,7 @@ async fn clone_task(
     }
 
     yield_now().await;
-    let fut = TaskFut::new(new_ts.virt.clone(), user_loop(new_ts, new_tf));
+    let fut = TaskFut::new(task, new_ts.virt.clone(), user_loop(new_ts, new_tf));
     executor().spawn(fut).detach();
 
     Ok(new_tid)
@@ -459,12 +788,13 @@ pub async fn waitpid(
     ts: &mut TaskState,
     cx: UserCx<'_, fn(isize, UserPtr<i32, Out>, i32) -> Result<usize, Error>>,
 ) -> ScRet {
-    const WNOHANG: i32 = 1;
-
     let (pid, mut wstatus, options) = cx.args();
     let inner = async move {
-        let timeout = (options & WNOHANG != 0).then_some(Duration::ZERO);
-        let (event, tid) = match poll_with(ts.wait(pid.into()), timeout).await {
+        let options = WaitOptions::from_bits_truncate(options);
+        let timeout = options
+            .contains(WaitOptions::NOHANG)
+            .then_some(Duration::ZERO);
+        let (event, tid) = match poll_with(ts.wait(pid.into(), options), timeout).await {
             Err(EAGAIN) => return Ok(0),
             res => res?,
         };
@@ -533,7 +863,7 @@ pub async fn execve(
         log::trace!("task::execve: name = {name:?}, args = {args:?}, envs = {envs:?}");
 
         let mut cmd = Command::new(name);
-        cmd.open_executable().await?;
+        cmd.cred(ts.cred()).open_executable().await?;
 
         ts.sig_fatal(
             SigInfo {
@@ -547,8 +877,7 @@ pub async fn execve(
 
         log::trace!("task::execve: start loading ELF. No way back.");
 
-        cmd.parent(ts.task.parent.clone())
-            .virt(ts.virt.clone())
+        cmd.virt(ts.virt.clone())
             .args(args)
             .envs(envs)
             .exec(ts, tf)
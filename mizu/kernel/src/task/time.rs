@@ -7,9 +7,13 @@ use core::{
     time::Duration,
 };
 
+use ksc::Error::{self, EINVAL};
 use ktime::{Instant, InstantExt};
 use sygnal::{Sig, SigCode, SigFields, SigInfo};
 
+use super::TaskState;
+use crate::dev;
+
 const USER: usize = 0;
 const SYSTEM: usize = 1;
 
@@ -189,3 +193,51 @@ pub fn counters() -> [Counter; 3] {
         Counter::new_profile(),
     ]
 }
+
+/// The clocks that can be read through their IDs from the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// The wall clock, which is settable.
+    Realtime,
+    Monotonic,
+    /// Same as `Monotonic` since we never suspend.
+    Boottime,
+    ProcessCputime,
+    ThreadCputime,
+}
+
+impl Clock {
+    pub fn from_id(id: usize) -> Result<Self, Error> {
+        const CLOCK_REALTIME: usize = 0;
+        const CLOCK_MONOTONIC: usize = 1;
+        const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+        const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+        const CLOCK_MONOTONIC_RAW: usize = 4;
+        const CLOCK_REALTIME_COARSE: usize = 5;
+        const CLOCK_MONOTONIC_COARSE: usize = 6;
+        const CLOCK_BOOTTIME: usize = 7;
+        const CLOCK_REALTIME_ALARM: usize = 8;
+        const CLOCK_BOOTTIME_ALARM: usize = 9;
+        const CLOCK_TAI: usize = 11;
+
+        Ok(match id {
+            CLOCK_REALTIME | CLOCK_REALTIME_COARSE | CLOCK_REALTIME_ALARM | CLOCK_TAI => {
+                Clock::Realtime
+            }
+            CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE => Clock::Monotonic,
+            CLOCK_BOOTTIME | CLOCK_BOOTTIME_ALARM => Clock::Boottime,
+            CLOCK_PROCESS_CPUTIME_ID => Clock::ProcessCputime,
+            CLOCK_THREAD_CPUTIME_ID => Clock::ThreadCputime,
+            _ => return Err(EINVAL),
+        })
+    }
+
+    pub fn now(self, ts: &TaskState) -> Duration {
+        match self {
+            Clock::Realtime => dev::realtime(),
+            Clock::Monotonic | Clock::Boottime => dev::uptime(),
+            Clock::ProcessCputime => ts.task.times.get_process().into_iter().sum(),
+            Clock::ThreadCputime => ts.task.times.get_thread().into_iter().sum(),
+        }
+    }
+}
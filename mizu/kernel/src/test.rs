@@ -33,7 +33,7 @@ fn split_cmd(cmd: &str) -> impl Iterator<Item = &str> + '_ {
 
 #[allow(dead_code)]
 pub async fn libc() {
-    let oo = OpenOptions::RDONLY;
+    let oo = OpenOptions::empty();
     let perm = Default::default();
 
     let scripts = ["run-static.sh", "run-dynamic.sh"];
@@ -125,7 +125,7 @@ async fn print_file(path: impl AsRef<Path>) {
 
 #[allow(dead_code)]
 pub async fn busybox_cmd() {
-    let oo = OpenOptions::RDONLY;
+    let oo = OpenOptions::empty();
     let perm = Default::default();
 
     let (txt, _) = crate::fs::open("busybox_cmd.txt".as_ref(), oo, perm)
@@ -188,7 +188,7 @@ pub async fn lua() {
 
 #[allow(dead_code)]
 pub async fn lmbench_cmd() {
-    let oo = OpenOptions::RDONLY;
+    let oo = OpenOptions::empty();
     let perm = Default::default();
 
     let (txt, _) = crate::fs::open("lmbench_testcode.sh".as_ref(), oo, perm)
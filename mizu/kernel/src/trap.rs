@@ -1,9 +1,6 @@
-use core::{
-    sync::atomic::{AtomicU8, AtomicUsize, Ordering::Relaxed},
-    time::Duration,
-};
+use core::{sync::atomic::AtomicUsize, time::Duration};
 
-use co_trap::{fast_func, FastResult, TrapFrame, Tx};
+use co_trap::{fast_func, FastResult, Fp, TrapFrame, Tx};
 use futures_util::Future;
 use ksc::Error::{self, EAGAIN, ETIMEDOUT};
 use ktime::TimeOutExt;
@@ -103,73 +100,11 @@ pub unsafe fn init() {
 
 fast_func!();
 
-const CLEAN: u8 = 0;
-const DIRTY: u8 = 1;
-const YIELD: u8 = 2;
-const RESET: u8 = 3;
-
-#[repr(C)]
-pub struct Fp {
-    /// 0 ~ 31 => f0 ~ f31, 32 => fcsr
-    regs: [u64; 33],
-
-    state: AtomicU8,
-}
-
-impl Default for Fp {
-    fn default() -> Self {
-        Fp {
-            regs: [0; 33],
-            state: AtomicU8::new(YIELD),
-        }
-    }
-}
-
-impl Fp {
-    pub fn copy(other: &Self) -> Self {
-        Fp {
-            regs: other.regs,
-            state: AtomicU8::new(YIELD),
-        }
-    }
-
-    fn enter_user(&self) {
-        extern "C" {
-            fn _load_fp(regs: *const [u64; 33]);
-        }
-        if self.state.swap(CLEAN, Relaxed) == YIELD {
-            unsafe { _load_fp(&self.regs) }
-        }
-    }
-
-    fn leave_user(&self, sstatus: &mut usize) {
-        let fs = (*sstatus & 0x6000) >> 13;
-        if fs == sstatus::FS::Dirty as _ {
-            self.state.store(DIRTY, Relaxed);
-        }
-    }
-
-    pub fn mark_reset(&self) {
-        self.state.store(RESET, Relaxed);
-    }
-
-    pub fn yield_now(&mut self) {
-        extern "C" {
-            fn _save_fp(regs: *mut [u64; 33]);
-        }
-        match self.state.swap(YIELD, Relaxed) {
-            DIRTY => unsafe { _save_fp(&mut self.regs) },
-            RESET => self.regs.fill(0),
-            _ => {}
-        }
-    }
-}
-
 scoped_tls::scoped_thread_local!(pub static FP: Fp);
 
 pub fn yield_to_user(tf: &mut TrapFrame) -> (Scause, FastResult) {
     FP.with(|fp| {
-        fp.enter_user();
+        fp.enter_user(&mut tf.sstatus);
         let ret = co_trap::yield_to_user(&mut *tf);
         fp.leave_user(&mut tf.sstatus);
         ret
@@ -3,7 +3,9 @@ use core::{cmp, iter, num, pin::pin, slice, str};
 
 use async_trait::async_trait;
 use futures_util::{stream, Stream, StreamExt};
-use ksc_core::Error::{self, EEXIST, EINVAL, EIO, EISDIR, ENOENT, ENOSYS, ENOTDIR, ENOTEMPTY};
+use ksc_core::Error::{
+    self, EEXIST, EINVAL, EIO, EISDIR, ENOENT, ENOSYS, ENOTDIR, ENOTEMPTY, EPERM,
+};
 use umifs::{
     path::Path,
     traits::{Directory, DirectoryMut, Entry, Io, IoExt},
@@ -16,6 +18,7 @@ use crate::{
         DirEntryData, DirFileEntryData, DirLfnEntryData, FileAttributes, ShortName, DIR_ENTRY_SIZE,
         LFN_ENTRY_LAST_FLAG, LFN_PART_LEN, SFN_PADDING, SFN_SIZE,
     },
+    file::default_perm,
     DirEntry, FatFile, TimeProvider,
 };
 
@@ -48,11 +51,12 @@ impl<T: TimeProvider> FatDir<T> {
         last_pos: Option<u64>,
         skip_volume: bool,
     ) -> Result<Option<DirEntry<T>>, Error> {
-        let abs_start_pos = self.file.abs_start_pos().await.unwrap();
-
         let mut lfn_builder = LongNameBuilder::new();
         let mut offset = match last_pos {
-            Some(last) => (last - abs_start_pos) as usize + DIR_ENTRY_SIZE as usize,
+            Some(last) => {
+                let last = self.file.file_offset(last).await.ok_or(EINVAL)?;
+                last + DIR_ENTRY_SIZE as usize
+            }
             None => 0,
         };
         let mut begin_offset = offset;
@@ -73,7 +77,7 @@ impl<T: TimeProvider> FatDir<T> {
             }
             match raw_entry {
                 DirEntryData::File(data) => {
-                    let abs_pos = abs_start_pos + offset as u64;
+                    let abs_pos = self.file.device_offset(offset).await.ok_or(EIO)?;
                     lfn_builder.validate_chksum(data.name());
                     let short_name = ShortName::new(data.name());
                     break Ok(Some(DirEntry {
@@ -492,10 +496,13 @@ impl<T: TimeProvider> Directory for FatDir<T> {
                 },
                 len: d.len() as usize,
                 offset: d.entry_pos,
-                perm: Permissions::all(),
+                link_count: 1,
+                perm: default_perm(),
+                uid: 0,
+                gid: 0,
                 block_size: fm.block_size,
                 block_count: fm.block_count,
-                times: Default::default(),
+                times: d.data.times(),
             },
         }))
     }
@@ -527,6 +534,12 @@ impl<T: TimeProvider> DirectoryMut for FatDir<T> {
     async fn unlink(&self, path: &Path, expect_dir: Option<bool>) -> Result<(), Error> {
         self.remove(path, expect_dir).await
     }
+
+    /// FAT has no symbolic links, so none are created here, and no entry is
+    /// ever read as one.
+    async fn symlink(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(EPERM)
+    }
 }
 
 impl<T: TimeProvider> FatDir<T> {
@@ -636,11 +649,12 @@ impl<T: TimeProvider> FatDir<T> {
             .await?;
         // return new logical entry descriptor
         let short_name = ShortName::new(raw_entry.name());
+        let abs_pos = self.file.device_offset(entry_pos as usize).await;
         Ok(DirEntry {
             data: raw_entry,
             short_name,
             lfn_utf16,
-            entry_pos,
+            entry_pos: abs_pos.ok_or(EIO)?,
             offset_range: start_pos..(entry_pos + u64::from(DIR_ENTRY_SIZE)),
 
             fs: self.file.fs.clone(),
@@ -6,7 +6,10 @@ use bitflags::bitflags;
 use concat_arrays::concat_arrays;
 use ksc_core::Error;
 use nom::IResult;
-use umifs::traits::{Io, IoExt};
+use umifs::{
+    traits::{Io, IoExt},
+    types::Times,
+};
 
 use crate::{
     dir::{FatDir, LfnBuffer},
@@ -240,6 +243,16 @@ impl DirFileEntryData {
         DateTime::decode(self.modify_date, self.modify_time, 0)
     }
 
+    /// Maps the dates into file metadata. The creation time has a resolution
+    /// of 10 ms, the modification time 2 s, and the access time only a day.
+    pub(crate) fn times(&self) -> Times {
+        Times {
+            last_access: self.accessed().to_instant(),
+            last_modified: self.modified().to_instant(),
+            last_created: self.created().to_instant(),
+        }
+    }
+
     pub(crate) fn set_created(&mut self, date_time: DateTime) {
         self.create_date = date_time.date.encode();
         let encoded_time = date_time.time.encode();
@@ -497,26 +510,23 @@ impl DirEntryEditor {
         }
     }
 
-    #[allow(dead_code)]
+    /// Dates lose precision when stored, so they are compared afterwards.
     pub(crate) fn set_created(&mut self, date_time: DateTime) {
-        if date_time != self.data.created() {
-            self.data.set_created(date_time);
-            self.dirty = true;
-        }
+        let old = self.data.created();
+        self.data.set_created(date_time);
+        self.dirty |= self.data.created() != old;
     }
 
     pub(crate) fn set_accessed(&mut self, date: Date) {
-        if date != self.data.accessed() {
-            self.data.set_accessed(date);
-            self.dirty = true;
-        }
+        let old = self.data.accessed();
+        self.data.set_accessed(date);
+        self.dirty |= self.data.accessed() != old;
     }
 
     pub(crate) fn set_modified(&mut self, date_time: DateTime) {
-        if date_time != self.data.modified() {
-            self.data.set_modified(date_time);
-            self.dirty = true;
-        }
+        let old = self.data.modified();
+        self.data.set_modified(date_time);
+        self.dirty |= self.data.modified() != old;
     }
 
     pub(crate) async fn flush(&mut self, device: &dyn Io) -> Result<(), Error> {
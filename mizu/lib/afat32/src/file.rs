@@ -12,7 +12,7 @@ use arsc_rs::Arsc;
 use async_trait::async_trait;
 use ksc_core::{
     handler::Boxed,
-    Error::{self, EINVAL, EISDIR, ENOSYS, ENOTDIR},
+    Error::{self, EINVAL, EIO, EISDIR, ENOSPC, ENOSYS, ENOTDIR, EPERM},
 };
 use ksync::{Mutex, RwLock};
 use umifs::{
@@ -22,13 +22,25 @@ use umifs::{
 };
 use umio::{advance_slices, IoPoll, IoSlice, IoSliceMut, SeekFrom};
 
-use crate::{dirent::DirEntryEditor, fs::FatFileSystem, TimeProvider};
+use crate::{
+    dirent::{DirEntryEditor, DIR_ENTRY_SIZE},
+    fs::FatFileSystem,
+    DateTime, TimeProvider,
+};
+
+/// FAT has no notion of owners or modes, so every entry is owned by root and
+/// looks like one on a vfat mounted under the default umask.
+pub(crate) fn default_perm() -> Permissions {
+    Permissions::all_same(true, false, true) | Permissions::SELF_W
+}
 
 #[derive(Debug)]
 pub struct FatFile<T: TimeProvider> {
     pub(crate) fs: Arsc<FatFileSystem<T>>,
     clusters: RwLock<Vec<(u32, u32)>>,
     cluster_shift: u32,
+    /// The device offset of the fixed root directory region on FAT12/16.
+    root_region: Option<usize>,
 
     perm: AtomicU32,
     entry: Option<Mutex<DirEntryEditor>>,
@@ -60,16 +72,63 @@ impl<T: TimeProvider> FatFile<T> {
             fs,
             clusters: RwLock::new(clusters),
             cluster_shift,
-            perm: AtomicU32::new(Permissions::me(true, true, true).bits()),
+            root_region: None,
+            perm: AtomicU32::new(default_perm().bits()),
             entry: entry.map(Mutex::new),
             len: AtomicUsize::new(len),
             cur_offset: AtomicUsize::new(0),
         })
     }
 
+    /// Creates the root directory of FAT12/16, which lives in a fixed region
+    /// before the data clusters and cannot grow.
+    pub(crate) fn new_root_region(fs: Arsc<FatFileSystem<T>>) -> Self {
+        let cluster_shift = fs.bpb.cluster_size().ilog2();
+        let start = fs.bpb.bytes_from_sectors(fs.bpb.root_dir_sector()) as usize;
+        let len = usize::from(fs.bpb.root_entries) * DIR_ENTRY_SIZE as usize;
+
+        FatFile {
+            fs,
+            clusters: RwLock::new(Vec::new()),
+            cluster_shift,
+            root_region: Some(start),
+            perm: AtomicU32::new(default_perm().bits()),
+            entry: None,
+            len: AtomicUsize::new(len),
+            cur_offset: AtomicUsize::new(0),
+        }
+    }
+
     pub(crate) async fn abs_start_pos(&self) -> Option<u64> {
-        let cluster = self.first_cluster().await;
-        cluster.map(|cluster| u64::from(cluster) << self.cluster_shift)
+        self.device_offset(0).await
+    }
+
+    /// Maps an offset in the file to its absolute position on the device.
+    pub(crate) async fn device_offset(&self, offset: usize) -> Option<u64> {
+        if let Some(start) = self.root_region {
+            return (offset < self.len.load(SeqCst)).then_some((start + offset) as u64);
+        }
+        let (cluster_index, offset_in_cluster) = self.decomp(offset);
+        let clusters = self.clusters.read().await;
+        let &(cluster, _) = clusters.get(cluster_index)?;
+        Some(self.fs.offset_from_cluster(cluster) + offset_in_cluster as u64)
+    }
+
+    /// Maps an absolute position on the device back to an offset in the file.
+    pub(crate) async fn file_offset(&self, pos: u64) -> Option<usize> {
+        if let Some(start) = self.root_region {
+            let offset = usize::try_from(pos).ok()?.checked_sub(start)?;
+            return (offset < self.len.load(SeqCst)).then_some(offset);
+        }
+        let clusters = self.clusters.read().await;
+        clusters
+            .iter()
+            .enumerate()
+            .find_map(|(index, &(cluster, _))| {
+                let offset = pos.checked_sub(self.fs.offset_from_cluster(cluster))?;
+                (offset >> self.cluster_shift == 0)
+                    .then_some((index << self.cluster_shift) + offset as usize)
+            })
     }
 
     pub(crate) async fn first_cluster(&self) -> Option<u32> {
@@ -169,6 +228,52 @@ impl<T: TimeProvider> FatFile<T> {
         }
     }
 
+    async fn read_region(
+        &self,
+        mut pos: usize,
+        mut rest: usize,
+        mut buffer: &mut [IoSliceMut<'_>],
+    ) -> Result<usize, Error> {
+        let mut read_len = 0;
+        let device = self.fs.fat.device();
+        while rest > 0 && !buffer.is_empty() {
+            let len = rest.min(buffer[0].len());
+            let len = match device.read_at(pos, &mut [&mut buffer[0][..len]]).await? {
+                0 if len > 0 => return Err(EIO),
+                len => len,
+            };
+
+            pos += len;
+            read_len += len;
+            rest -= len;
+            advance_slices(&mut buffer, len)
+        }
+        Ok(read_len)
+    }
+
+    async fn write_region(
+        &self,
+        mut pos: usize,
+        mut rest: usize,
+        mut buffer: &mut [IoSlice<'_>],
+    ) -> Result<usize, Error> {
+        let mut written_len = 0;
+        let device = self.fs.fat.device();
+        while rest > 0 && !buffer.is_empty() {
+            let len = rest.min(buffer[0].len());
+            let len = match device.write_at(pos, &mut [&buffer[0][..len]]).await? {
+                0 if len > 0 => return Err(EIO),
+                len => len,
+            };
+
+            pos += len;
+            written_len += len;
+            rest -= len;
+            advance_slices(&mut buffer, len)
+        }
+        Ok(written_len)
+    }
+
     async fn flush(&self) -> Result<(), Error> {
         if let Some(ref entry) = self.entry {
             entry.lock().await.flush(&**self.fs.fat.device()).await?;
@@ -215,6 +320,11 @@ impl<T: TimeProvider> Io for FatFile<T> {
         // let ioslice_len = umio::ioslice_len(&buffer);
         // log::trace!("FatFile::read_at {offset:#x}, buffer len = {ioslice_len}");
 
+        if let Some(start) = self.root_region {
+            let rest = self.len.load(SeqCst).saturating_sub(offset);
+            return self.read_region(start + offset, rest, buffer).await;
+        }
+
         let cluster_shift = self.cluster_shift;
         let (cluster_index, offset_in_cluster) = self.decomp(offset);
 
@@ -274,6 +384,14 @@ impl<T: TimeProvider> Io for FatFile<T> {
         let ioslice_len = umio::ioslice_len(&buffer);
         // log::trace!("FatFile::write_at {offset:#x}, buffer len = {ioslice_len}");
 
+        if let Some(start) = self.root_region {
+            let rest = self.len.load(SeqCst).saturating_sub(offset);
+            if rest == 0 && ioslice_len > 0 {
+                return Err(ENOSPC);
+            }
+            return self.write_region(start + offset, rest, buffer).await;
+        }
+
         let cluster_shift = self.cluster_shift;
         let (cluster_index, offset_in_cluster) = self.decomp(offset);
 
@@ -381,20 +499,42 @@ impl<T: TimeProvider> Entry for FatFile<T> {
             ty: FileType::FILE,
             len: self.len.load(SeqCst),
             offset: self.abs_start_pos().await.unwrap_or(u64::MAX),
+            link_count: 1,
             perm: Permissions::from_bits_truncate(self.perm.load(SeqCst)),
+            uid: 0,
+            gid: 0,
             block_size: 1 << self.cluster_shift,
             block_count: self.clusters.read().await.len(),
-            times: Default::default(),
+            times: match self.entry {
+                Some(ref entry) => entry.lock().await.inner().times(),
+                None => Default::default(),
+            },
         }
     }
 
     async fn set_metadata(&self, metadata: SetMetadata) -> Result<(), Error> {
+        if metadata.uid.is_some_and(|uid| uid != 0) || metadata.gid.is_some_and(|gid| gid != 0) {
+            return Err(EPERM);
+        }
         if let Some(new_len) = metadata.len {
             self.truncate(new_len.try_into()?).await?;
         }
         if let Some(perm) = metadata.perm {
             self.perm.store(perm.bits(), SeqCst);
         }
+        if let Some(ref entry) = self.entry {
+            let times = metadata.times;
+            let mut e = entry.lock().await;
+            if let Some(created) = times.last_created {
+                e.set_created(DateTime::from_instant(created));
+            }
+            if let Some(modified) = times.last_modified {
+                e.set_modified(DateTime::from_instant(modified));
+            }
+            if let Some(accessed) = times.last_access {
+                e.set_accessed(DateTime::from_instant(accessed).date);
+            }
+        }
         Ok(())
     }
 }
@@ -6,7 +6,7 @@ use core::{
 
 use arsc_rs::Arsc;
 use async_trait::async_trait;
-use ksc_core::Error::{self, ENOSYS};
+use ksc_core::Error;
 use spin::RwLock;
 use umifs::{
     traits::{Entry, FileSystem, Io, IoExt},
@@ -15,7 +15,7 @@ use umifs::{
 
 use crate::{
     raw::{BiosParameterBlock, BootSector, FsInfoSector},
-    table::{Fat, RESERVED_FAT_ENTRIES},
+    table::{Fat, FatType, RESERVED_FAT_ENTRIES},
     FatDir, FatFile, TimeProvider,
 };
 
@@ -71,18 +71,20 @@ impl<T: TimeProvider> FatFileSystem<T> {
 
         log::trace!("BPB: {bpb:#?}");
 
-        if !bpb.is_fat32() {
-            log::error!("Unsupported FAT file system; only FAT32 is supported");
-            return Err(ENOSYS);
-        }
-
-        let fis = bpb.bytes_from_sectors(bpb.fs_info_sector());
-        device.read_exact_at(fis as usize, &mut b0).await.unwrap();
-        let (_, mut fis) = FsInfoSector::parse(&b0)?;
+        let fis = if bpb.is_fat32() {
+            let fis = bpb.bytes_from_sectors(bpb.fs_info_sector());
+            device.read_exact_at(fis as usize, &mut b0).await.unwrap();
+            let (_, mut fis) = FsInfoSector::parse(&b0)?;
 
-        log::trace!("FIS: {fis:#?}");
+            log::trace!("FIS: {fis:#?}");
 
-        fis.fix(bpb.total_clusters());
+            fis.fix(bpb.total_clusters());
+            fis
+        } else {
+            // FAT12/16 has no FS information sector, so the free clusters are
+            // counted on demand.
+            FsInfoSector::default()
+        };
 
         Ok(Arsc::new(FatFileSystem {
             fat: Fat::new(device, &bpb),
@@ -150,6 +152,9 @@ impl<T: TimeProvider> FatFileSystem<T> {
     }
 
     async fn flush_fs_info(&self) -> Result<(), Error> {
+        if !self.bpb.is_fat32() {
+            return Ok(());
+        }
         let bytes = ksync::critical(|| {
             let mut fs_info = self.fs_info.write();
             let dirty = mem::replace(&mut fs_info.dirty, false);
@@ -181,7 +186,7 @@ impl<T: TimeProvider> FatFileSystem<T> {
         // Note: only one field is written to avoid rewriting entire boot-sector which
         // could be dangerous Compute reserver_1 field offset and write new
         // flags
-        let offset = 0x041;
+        let offset = self.bpb.status_flags_offset();
         self.fat.device().write_all_at(offset, &[encoded]).await?;
         FsStatusFlags::store(&self.current_status_flags, flags);
         Ok(())
@@ -203,11 +208,18 @@ impl<T: TimeProvider> FatFileSystem<T> {
     }
 
     pub async fn root_dir(self: Arsc<Self>) -> Result<FatDir<T>, Error> {
+        if !self.bpb.is_fat32() {
+            return Ok(FatDir::new(FatFile::new_root_region(self)));
+        }
         FatFile::new(self.clone(), Some(self.bpb.root_dir_first_cluster), None)
             .await
             .map(FatDir::new)
     }
 
+    pub fn fat_type(&self) -> FatType {
+        self.fat.ty()
+    }
+
     pub async fn stats(&self) -> FatStats {
         let free_clusters_option = ksync::critical(|| self.fs_info.read().free_cluster_count);
         let free_clusters = if let Some(n) = free_clusters_option {
@@ -240,7 +252,7 @@ impl<T: TimeProvider> FileSystem for FatFileSystem<T> {
     async fn stat(&self) -> FsStat {
         let s = (*self).stats().await;
         FsStat {
-            ty: "fat32",
+            ty: self.fat_type().name(),
             block_size: s.cluster_size() as usize,
             block_count: s.total_clusters() as usize,
             block_free: s.free_clusters() as usize,
@@ -314,3 +326,230 @@ impl FsStatusFlags {
         slot.store(flags.encode(), SeqCst)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+    use core::sync::atomic::AtomicUsize;
+    use std::process::{Command, Stdio};
+
+    use futures_lite::future::block_on;
+    use futures_util::TryStreamExt;
+    use ksc_core::Error::{ENOSPC, EPERM};
+    use umifs::{path::Path, traits::Entry, types::SetMetadata};
+    use umio::{IoSlice, IoSliceMut, SeekFrom};
+
+    use super::*;
+    use crate::{table::FatEntry, NullTimeProvider};
+
+    const SECTOR: usize = 512;
+
+    struct Image(spin::Mutex<Vec<u8>>);
+
+    #[async_trait]
+    impl Io for Image {
+        async fn seek(&self, _: SeekFrom) -> Result<usize, Error> {
+            Ok(0)
+        }
+
+        async fn read_at(&self, offset: usize, buffer: &mut [IoSliceMut]) -> Result<usize, Error> {
+            let image = self.0.lock();
+            let mut offset = offset.min(image.len());
+            let mut len = 0;
+            for buf in buffer {
+                let n = buf.len().min(image.len() - offset);
+                buf[..n].copy_from_slice(&image[offset..][..n]);
+                offset += n;
+                len += n;
+            }
+            Ok(len)
+        }
+
+        async fn write_at(&self, offset: usize, buffer: &mut [IoSlice]) -> Result<usize, Error> {
+            let mut image = self.0.lock();
+            let mut offset = offset.min(image.len());
+            let mut len = 0;
+            for buf in buffer {
+                let n = buf.len().min(image.len() - offset);
+                image[offset..][..n].copy_from_slice(&buf[..n]);
+                offset += n;
+                len += n;
+            }
+            Ok(len)
+        }
+
+        async fn flush(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    /// Formats a fresh image of `kib` KiB with `mkfs.fat` from dosfstools.
+    fn mkfs_fat(ty: FatType, kib: usize, sectors_per_cluster: u8) -> Arc<dyn Io> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let bits = match ty {
+            FatType::Fat12 => "12",
+            FatType::Fat16 => "16",
+            FatType::Fat32 => "32",
+        };
+        let id = NEXT.fetch_add(1, SeqCst);
+        let name = std::format!("afat32-{}-{id}.img", std::process::id());
+        let path = std::env::temp_dir().join(name);
+
+        let status = Command::new("mkfs.fat")
+            .args(["-C", "--invariant", "-F", bits])
+            .args(["-s", &sectors_per_cluster.to_string()])
+            .arg(&path)
+            .arg(kib.to_string())
+            .stdout(Stdio::null())
+            .status()
+            .expect("failed to run mkfs.fat (is dosfstools installed?)");
+        assert!(status.success(), "mkfs.fat failed: {status}");
+
+        let image = std::fs::read(&path).expect("failed to read the image");
+        std::fs::remove_file(&path).expect("failed to remove the image");
+        Arc::new(Image(spin::Mutex::new(image)))
+    }
+
+    async fn mount(image: &Arc<dyn Io>) -> Arsc<FatFileSystem<NullTimeProvider>> {
+        FatFileSystem::new(image.clone(), SECTOR.ilog2(), NullTimeProvider)
+            .await
+            .unwrap()
+    }
+
+    async fn round_trip(image: Arc<dyn Io>, ty: FatType) {
+        let data = (0..20000u32).map(|i| i as u8).collect::<Vec<_>>();
+
+        let fs = mount(&image).await;
+        assert_eq!(fs.fat_type(), ty);
+        let free = fs.stats().await.free_clusters();
+
+        let root = fs.clone().root_dir().await.unwrap();
+        let (file, created) = root
+            .create_file(Path::new("Hello World.txt"))
+            .await
+            .unwrap();
+        assert!(created);
+        file.write_all_at(0, &data).await.unwrap();
+        Io::flush(&file).await.unwrap();
+
+        let (dir, created) = root.create_dir(Path::new("sub")).await.unwrap();
+        assert!(created);
+        let (nested, _) = dir.create_file(Path::new("nested")).await.unwrap();
+        nested.write_all_at(0, b"nested").await.unwrap();
+        Io::flush(&nested).await.unwrap();
+        fs.flush().await.unwrap();
+
+        let fs = mount(&image).await;
+        let root = fs.clone().root_dir().await.unwrap();
+        let names = root.iter(true).map_ok(|e| e.file_name());
+        let names = names.try_collect::<Vec<String>>().await.unwrap();
+        assert_eq!(names, ["Hello World.txt", "sub"]);
+
+        let file = root.open_file(Path::new("Hello World.txt")).await.unwrap();
+        let mut buf = vec![0; data.len()];
+        file.read_exact_at(0, &mut buf).await.unwrap();
+        assert_eq!(buf, data);
+
+        let nested = root.open_file(Path::new("sub/nested")).await.unwrap();
+        let mut buf = [0; 6];
+        nested.read_exact_at(0, &mut buf).await.unwrap();
+        assert_eq!(&buf, b"nested");
+
+        let stats = fs.stats().await;
+        let cluster_size = stats.cluster_size() as usize;
+        let used = (data.len() + cluster_size - 1) / cluster_size + 2;
+        assert_eq!(stats.free_clusters() as usize, free as usize - used);
+    }
+
+    #[test]
+    fn fat_type_from_clusters() {
+        assert_eq!(FatType::from_clusters(4084), FatType::Fat12);
+        assert_eq!(FatType::from_clusters(4085), FatType::Fat16);
+        assert_eq!(FatType::from_clusters(65524), FatType::Fat16);
+        assert_eq!(FatType::from_clusters(65525), FatType::Fat32);
+    }
+
+    #[test]
+    fn fat12_round_trip() {
+        block_on(round_trip(
+            mkfs_fat(FatType::Fat12, 4096, 4),
+            FatType::Fat12,
+        ))
+    }
+
+    #[test]
+    fn fat16_round_trip() {
+        block_on(round_trip(
+            mkfs_fat(FatType::Fat16, 16384, 4),
+            FatType::Fat16,
+        ))
+    }
+
+    #[test]
+    fn fat32_round_trip() {
+        block_on(round_trip(
+            mkfs_fat(FatType::Fat32, 40960, 1),
+            FatType::Fat32,
+        ))
+    }
+
+    #[test]
+    fn fat12_packed_entries() {
+        block_on(async {
+            let image = mkfs_fat(FatType::Fat12, 4096, 4);
+            let fs = mount(&image).await;
+            fs.fat.set(2, FatEntry::Next(3)).await.unwrap();
+            fs.fat.set(3, FatEntry::End).await.unwrap();
+            fs.fat.set(4, FatEntry::Bad).await.unwrap();
+
+            assert_eq!(fs.fat.get(2).await, Ok(FatEntry::Next(3)));
+            assert_eq!(fs.fat.get(3).await, Ok(FatEntry::End));
+            assert_eq!(fs.fat.get(4).await, Ok(FatEntry::Bad));
+            assert_eq!(fs.fat.get(5).await, Ok(FatEntry::Free));
+
+            for mirror in 0..2 {
+                let mut bytes = [0; 8];
+                let offset = SECTOR + mirror * fs.fat.size();
+                image.read_exact_at(offset, &mut bytes).await.unwrap();
+                assert_eq!(bytes, [0xF8, 0xFF, 0xFF, 0x03, 0xF0, 0xFF, 0xF7, 0x0F]);
+            }
+        })
+    }
+
+    #[test]
+    fn fat16_root_dir_is_fixed() {
+        block_on(async {
+            let image = mkfs_fat(FatType::Fat16, 16384, 4);
+            let fs = mount(&image).await;
+            let root = fs.clone().root_dir().await.unwrap();
+            // Every file takes a long name entry and a short name entry.
+            for i in 0..256 {
+                let name = alloc::format!("file{i}");
+                root.create_file(Path::new(&name)).await.unwrap();
+            }
+            let err = root.create_file(Path::new("full")).await.err();
+            assert_eq!(err, Some(ENOSPC));
+        })
+    }
+
+    #[test]
+    fn files_are_owned_by_root() {
+        block_on(async {
+            let image = mkfs_fat(FatType::Fat16, 16384, 4);
+            let fs = mount(&image).await;
+            let root = fs.clone().root_dir().await.unwrap();
+            let (file, _) = root.create_file(Path::new("owned")).await.unwrap();
+
+            let metadata = file.metadata().await;
+            assert_eq!((metadata.uid, metadata.gid), (0, 0));
+
+            let chown = |uid| SetMetadata {
+                uid: Some(uid),
+                ..Default::default()
+            };
+            assert_eq!(file.set_metadata(chown(0)).await, Ok(()));
+            assert_eq!(file.set_metadata(chown(1000)).await, Err(EPERM));
+        })
+    }
+}
@@ -1,7 +1,11 @@
 use concat_arrays::concat_arrays;
 use nom::{bytes, number, IResult};
 
-use crate::{dirent::DIR_ENTRY_SIZE, fs::FsStatusFlags, table::RESERVED_FAT_ENTRIES};
+use crate::{
+    dirent::DIR_ENTRY_SIZE,
+    fs::FsStatusFlags,
+    table::{FatType, RESERVED_FAT_ENTRIES},
+};
 
 fn take_byte_array<const N: usize>(mut input: &[u8]) -> IResult<&[u8], [u8; N]> {
     let data;
@@ -42,7 +46,6 @@ pub struct BiosParameterBlock {
 
 impl BiosParameterBlock {
     const RESERVED_0: usize = 12;
-    const RESERVED_1: usize = 1;
 
     pub fn parse(mut input: &[u8]) -> IResult<&[u8], Self> {
         let mut bpb = Self::default();
@@ -70,7 +73,7 @@ impl BiosParameterBlock {
         }
 
         (input, bpb.drive_num) = number::streaming::le_u8(input)?;
-        (input, _) = bytes::streaming::take(Self::RESERVED_1)(input)?;
+        (input, bpb.reserved_1) = number::streaming::le_u8(input)?;
         (input, bpb.ext_sig) = number::streaming::le_u8(input)?;
         (input, bpb.volume_id) = number::streaming::le_u32(input)?;
 
@@ -85,10 +88,21 @@ impl BiosParameterBlock {
 
         Ok((input, bpb))
     }
+
     pub(crate) fn status_flags(&self) -> FsStatusFlags {
         FsStatusFlags::decode(self.reserved_1)
     }
 
+    /// The offset of `reserved_1` in the boot sector, where the status flags
+    /// are stored.
+    pub(crate) fn status_flags_offset(&self) -> usize {
+        if self.is_fat32() {
+            0x041
+        } else {
+            0x025
+        }
+    }
+
     pub(crate) fn mirroring_enabled(&self) -> bool {
         self.extended_flags & 0x80 == 0
     }
@@ -110,6 +124,16 @@ impl BiosParameterBlock {
         self.sectors_per_fat_16 == 0
     }
 
+    /// Determines the FAT type from the count of clusters, except that volumes
+    /// with the FAT32 layout of the BPB are always FAT32.
+    pub fn fat_type(&self) -> FatType {
+        if self.is_fat32() {
+            FatType::Fat32
+        } else {
+            FatType::from_clusters(self.total_clusters())
+        }
+    }
+
     pub fn sectors_per_fat(&self) -> u32 {
         if self.is_fat32() {
             self.sectors_per_fat_32
@@ -135,10 +159,13 @@ impl BiosParameterBlock {
         u32::from(self.fats) * self.sectors_per_fat()
     }
 
+    /// The first sector of the fixed root directory region on FAT12/16.
+    pub fn root_dir_sector(&self) -> u32 {
+        u32::from(self.reserved_sectors) + self.sectors_per_all_fats()
+    }
+
     pub fn first_data_sector(&self) -> u32 {
-        let root_dir_sectors = self.root_dir_sectors();
-        let fat_sectors = self.sectors_per_all_fats();
-        self.reserved_sectors as u32 + fat_sectors + root_dir_sectors
+        self.root_dir_sector() + self.root_dir_sectors()
     }
 
     pub fn total_clusters(&self) -> u32 {
@@ -80,9 +80,95 @@ impl FatEntry {
     }
 }
 
+/// The variant of a FAT volume, which decides the width of its table entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Determines the FAT type from the count of data clusters, as the
+    /// specification requires.
+    pub const fn from_clusters(total_clusters: u32) -> Self {
+        if total_clusters < 4085 {
+            FatType::Fat12
+        } else if total_clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    /// The byte offset of the entry of `cluster` in the table.
+    const fn entry_offset(self, cluster: u32) -> usize {
+        let cluster = cluster as usize;
+        match self {
+            FatType::Fat12 => cluster + cluster / 2,
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        }
+    }
+
+    /// The count of bytes to access for one entry. FAT12 entries span 2 bytes
+    /// shared with their neighbors.
+    const fn entry_len(self) -> usize {
+        match self {
+            FatType::Fat12 | FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Extracts the entry of `cluster` from the bytes read at its offset,
+    /// widening special values to their FAT32 counterparts.
+    fn decode(self, bytes: u32, cluster: u32) -> u32 {
+        match self {
+            FatType::Fat12 => {
+                let raw = if cluster & 1 == 0 { bytes } else { bytes >> 4 } & 0xFFF;
+                if raw >= 0xFF7 {
+                    raw | 0x0FFF_F000
+                } else {
+                    raw
+                }
+            }
+            FatType::Fat16 => {
+                let raw = bytes & 0xFFFF;
+                if raw >= 0xFFF7 {
+                    raw | 0x0FFF_0000
+                } else {
+                    raw
+                }
+            }
+            FatType::Fat32 => bytes,
+        }
+    }
+
+    /// Puts the entry of `cluster` into the bytes read at its offset, keeping
+    /// the bits that don't belong to it.
+    fn encode(self, bytes: u32, raw: u32, cluster: u32) -> u32 {
+        match self {
+            FatType::Fat12 if cluster & 1 == 0 => (bytes & 0xF000) | (raw & 0xFFF),
+            FatType::Fat12 => (bytes & 0x000F) | ((raw & 0xFFF) << 4),
+            FatType::Fat16 => raw & 0xFFFF,
+            FatType::Fat32 => (bytes & 0xF000_0000) | raw,
+        }
+    }
+}
+
 pub struct Fat {
     device: Arc<dyn Io>,
+    ty: FatType,
     start_offset: usize,
+    size: usize,
     cluster_count: u32,
     mirrors: u8,
     set_lock: Mutex<()>,
@@ -92,7 +178,9 @@ pub struct Fat {
 impl fmt::Debug for Fat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Fat")
+            .field("ty", &self.ty)
             .field("start_offset", &self.start_offset)
+            .field("size", &self.size)
             .field("cluster_count", &self.cluster_count)
             .field("mirrors", &self.mirrors)
             .finish()
@@ -100,8 +188,6 @@ impl fmt::Debug for Fat {
 }
 
 impl Fat {
-    const ENTRY_SIZE: usize = mem::size_of::<u32>();
-
     pub fn new(device: Arc<dyn Io>, bpb: &BiosParameterBlock) -> Self {
         let sectors_per_fat = bpb.sectors_per_fat();
         let mirroring_enabled = bpb.mirroring_enabled();
@@ -114,7 +200,9 @@ impl Fat {
         };
         Fat {
             device,
+            ty: bpb.fat_type(),
             start_offset: bpb.bytes_from_sectors(fat_first_sector) as usize,
+            size: bpb.bytes_from_sectors(sectors_per_fat) as usize,
             cluster_count: bpb.total_clusters(),
             mirrors,
             set_lock: Default::default(),
@@ -126,8 +214,13 @@ impl Fat {
         &self.device
     }
 
+    pub const fn ty(&self) -> FatType {
+        self.ty
+    }
+
+    /// The size of one copy of the table in bytes.
     pub const fn size(&self) -> usize {
-        self.cluster_count as usize * Self::ENTRY_SIZE
+        self.size
     }
 
     pub const fn cluster_count(&self) -> u32 {
@@ -139,21 +232,25 @@ impl Fat {
     }
 
     fn offset(&self, mirror: u8, cluster: u32) -> usize {
-        self.start_offset + self.size() * mirror as usize + cluster as usize * Self::ENTRY_SIZE
+        self.start_offset + self.size * mirror as usize + self.ty.entry_offset(cluster)
     }
 
-    async fn get_raw(&self, cluster: u32) -> Result<u32, Error> {
+    async fn read_entry_bytes(&self, cluster: u32) -> Result<u32, Error> {
         let mut buf = [0; 4];
-        if cluster >= self.allocable_range().end {
-            return Err(EINVAL);
-        }
         self.device
-            .read_exact_at(self.offset(0, cluster), &mut buf)
+            .read_exact_at(self.offset(0, cluster), &mut buf[..self.ty.entry_len()])
             .await?;
-
         Ok(u32::from_le_bytes(buf))
     }
 
+    async fn get_raw(&self, cluster: u32) -> Result<u32, Error> {
+        if cluster >= self.allocable_range().end {
+            return Err(EINVAL);
+        }
+        let bytes = self.read_entry_bytes(cluster).await?;
+        Ok(self.ty.decode(bytes, cluster))
+    }
+
     /// # Safety
     ///
     /// The buf must be written zeros.
@@ -170,6 +267,14 @@ impl Fat {
             return Ok(0);
         }
         let read_len = (end - start) as usize;
+
+        if self.ty != FatType::Fat32 {
+            // SAFETY: The buf is written zeros.
+            let buf = unsafe { MaybeUninit::slice_assume_init_mut(&mut buf[0..read_len]) };
+            self.get_range_packed(start, buf).await?;
+            return Ok(read_len);
+        }
+
         let bytes = MaybeUninit::slice_as_bytes_mut(&mut buf[0..read_len]);
 
         self.device
@@ -181,6 +286,25 @@ impl Fat {
         Ok(read_len)
     }
 
+    /// Reads and decodes the FAT12/16 entries that are narrower than `u32`.
+    async fn get_range_packed(&self, start: u32, buf: &mut [u32]) -> Result<(), Error> {
+        let end = start + buf.len() as u32;
+        let base = self.ty.entry_offset(start);
+        let len = self.ty.entry_offset(end - 1) + self.ty.entry_len() - base;
+
+        let mut bytes: smallvec::SmallVec<[u8; BATCH_LEN * 2]> = smallvec::smallvec![0; len];
+        self.device
+            .read_exact_at(self.offset(0, start), &mut bytes)
+            .await?;
+
+        for (raw, cluster) in buf.iter_mut().zip(start..) {
+            let offset = self.ty.entry_offset(cluster) - base;
+            let entry = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+            *raw = self.ty.decode(entry.into(), cluster);
+        }
+        Ok(())
+    }
+
     pub async fn get_range<'a>(
         &self,
         start: u32,
@@ -204,6 +328,15 @@ impl Fat {
 
         let _set = self.set_lock.lock().await;
 
+        if self.ty != FatType::Fat32 {
+            // Neighboring FAT12 entries share bytes, so they are written one by one.
+            let end = (start + u32::try_from(buf.len())?).min(self.allocable_range().end);
+            for (cluster, entry) in (start..end).zip(entry) {
+                self.set_locked(cluster, entry).await?;
+            }
+            return Ok(());
+        }
+
         let len = unsafe { self.get_range_raw(start, mem::transmute(&mut *buf)) }.await?;
 
         for ((raw, cluster), entry) in buf[..len].iter_mut().zip(start..).zip(entry) {
@@ -234,11 +367,18 @@ impl Fat {
 
     pub async fn set(&self, cluster: u32, entry: FatEntry) -> Result<(), Error> {
         let _set = self.set_lock.lock().await;
+        self.set_locked(cluster, entry).await
+    }
 
-        let old = self.get_raw(cluster).await? & 0xf000_0000;
-        let raw = entry.into_raw(cluster, old);
+    async fn set_locked(&self, cluster: u32, entry: FatEntry) -> Result<(), Error> {
+        if cluster >= self.allocable_range().end {
+            return Err(EINVAL);
+        }
+        let old = self.read_entry_bytes(cluster).await?;
+        let raw = entry.into_raw(cluster, 0);
+        let bytes = self.ty.encode(old, raw, cluster).to_le_bytes();
 
-        let buffer = &raw.to_le_bytes();
+        let buffer = &bytes[..self.ty.entry_len()];
         try_join_all((0..self.mirrors).map(|mirror| async move {
             let offset = self.offset(mirror, cluster);
             self.device.write_all_at(offset, buffer).await
@@ -1,4 +1,6 @@
-use core::fmt::Debug;
+use core::{fmt::Debug, time::Duration};
+
+use ktime_core::{Instant, InstantExt};
 
 const MIN_YEAR: u16 = 1980;
 const MAX_YEAR: u16 = 2107;
@@ -7,6 +9,33 @@ const MAX_MONTH: u16 = 12;
 const MIN_DAY: u16 = 1;
 const MAX_DAY: u16 = 31;
 
+const SECS_PER_DAY: u64 = 86400;
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+const fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The date of days since 1970-01-01 in the proleptic Gregorian calendar.
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
 /// A DOS compatible date.
 ///
 /// Used by `DirEntry` time-related methods.
@@ -54,6 +83,25 @@ impl Date {
     pub(crate) fn encode(self) -> u16 {
         ((self.year - MIN_YEAR) << 9) | (self.month << 5) | self.day
     }
+
+    /// Checks if the date is a valid one instead of an unset field.
+    fn is_valid(&self) -> bool {
+        (MIN_MONTH..=MAX_MONTH).contains(&self.month) && (MIN_DAY..=MAX_DAY).contains(&self.day)
+    }
+
+    /// Returns the time since the UNIX epoch at the start of the date, or
+    /// `None` if the date is invalid.
+    #[must_use]
+    pub fn to_unix(self) -> Option<Duration> {
+        self.is_valid().then(|| {
+            let days = days_from_civil(self.year.into(), self.month.into(), self.day.into());
+            Duration::from_secs(days * SECS_PER_DAY)
+        })
+    }
+
+    pub(crate) fn to_instant(self) -> Option<Instant> {
+        self.to_unix().map(to_instant)
+    }
 }
 
 /// A DOS compatible time.
@@ -144,6 +192,63 @@ impl DateTime {
             Time::decode(dos_time, dos_time_hi_res),
         )
     }
+
+    /// Creates a date and time from the time since the UNIX epoch, clamped to
+    /// the range that DOS can represent.
+    #[must_use]
+    pub fn from_unix(since_epoch: Duration) -> Self {
+        let min = days_from_civil(MIN_YEAR as u64, 1, 1) * SECS_PER_DAY;
+        let max = days_from_civil(MAX_YEAR as u64 + 1, 1, 1) * SECS_PER_DAY - 1;
+
+        let (secs, millis) = match since_epoch.as_secs() {
+            secs if secs < min => (min, 0),
+            secs if secs > max => (max, 999),
+            secs => (secs, since_epoch.subsec_millis() as u16),
+        };
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let secs = secs % SECS_PER_DAY;
+
+        // safe casts: values in range of the DOS date and time
+        #[allow(clippy::cast_possible_truncation)]
+        Self::new(
+            Date::new(year as u16, month as u16, day as u16),
+            Time::new(
+                (secs / 3600) as u16,
+                (secs / 60 % 60) as u16,
+                (secs % 60) as u16,
+                millis,
+            ),
+        )
+    }
+
+    pub(crate) fn from_instant(instant: Instant) -> Self {
+        let (secs, micros) = instant.to_su();
+        Self::from_unix(Duration::new(secs, micros as u32 * 1000))
+    }
+
+    /// Returns the time since the UNIX epoch, or `None` if the date is
+    /// invalid.
+    #[must_use]
+    pub fn to_unix(self) -> Option<Duration> {
+        let date = self.date.to_unix()?;
+        let Time {
+            hour,
+            min,
+            sec,
+            millis,
+        } = self.time;
+        let secs = u64::from(hour) * 3600 + u64::from(min) * 60 + u64::from(sec);
+        Some(date + Duration::from_secs(secs) + Duration::from_millis(millis.into()))
+    }
+
+    pub(crate) fn to_instant(self) -> Option<Instant> {
+        self.to_unix().map(to_instant)
+    }
+}
+
+/// Timestamps in file metadata are counted from the UNIX epoch.
+fn to_instant(since_epoch: Duration) -> Instant {
+    Instant::from_su(since_epoch.as_secs(), since_epoch.subsec_micros().into())
 }
 
 /// A current time and date provider.
@@ -176,7 +281,9 @@ pub type DefaultTimeProvider = NullTimeProvider;
 
 #[cfg(test)]
 mod tests {
-    use super::{Date, Time};
+    use core::time::Duration;
+
+    use super::{Date, DateTime, Time};
 
     #[test]
     fn date_new_no_panic_1980() {
@@ -223,4 +330,46 @@ mod tests {
         assert_eq!(t2, Time::decode(x2, y2));
         assert_eq!(t3, Time::decode(x3, y3));
     }
+
+    #[test]
+    fn date_time_from_unix() {
+        let dt = DateTime::from_unix(Duration::from_millis(1_684_151_445_250));
+        assert_eq!(dt.date, Date::new(2023, 5, 15));
+        assert_eq!(dt.time, Time::new(11, 50, 45, 250));
+
+        let leap = DateTime::from_unix(Duration::from_secs(951_782_400));
+        assert_eq!(leap.date, Date::new(2000, 2, 29));
+    }
+
+    #[test]
+    fn date_time_from_unix_clamped() {
+        let dt = DateTime::from_unix(Duration::ZERO);
+        assert_eq!(dt.date, Date::new(1980, 1, 1));
+        assert_eq!(dt.time, Time::new(0, 0, 0, 0));
+
+        let dt = DateTime::from_unix(Duration::from_secs(u64::MAX));
+        assert_eq!(dt.date, Date::new(2107, 12, 31));
+        assert_eq!(dt.time, Time::new(23, 59, 59, 999));
+    }
+
+    #[test]
+    fn date_time_unix_round_trip() {
+        let since_epoch = Duration::from_millis(4_102_444_799_990);
+        let dt = DateTime::from_unix(since_epoch);
+        assert_eq!(dt.to_unix(), Some(since_epoch));
+
+        // The modification time only keeps even seconds.
+        let (time, _) = dt.time.encode();
+        let modified = DateTime::decode(dt.date.encode(), time, 0);
+        assert_eq!(modified.to_unix(), Some(Duration::from_secs(4_102_444_798)));
+    }
+
+    #[test]
+    fn date_to_unix_unset() {
+        assert_eq!(Date::decode(0).to_unix(), None);
+        assert_eq!(
+            Date::new(1980, 1, 1).to_unix(),
+            Some(Duration::from_secs(315_532_800))
+        );
+    }
 }
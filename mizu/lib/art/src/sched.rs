@@ -7,8 +7,8 @@ use core::{
     cell::RefCell,
     future::Future,
     sync::atomic::{
-        AtomicBool,
-        Ordering::{Acquire, Release},
+        AtomicBool, AtomicU64,
+        Ordering::{Acquire, Relaxed, Release},
     },
 };
 
@@ -28,14 +28,25 @@ struct Worker {
     preempt_slot: Option<Runnable>,
 }
 
+/// The statistics of a runner.
+#[derive(Default)]
+struct Stat {
+    busy: AtomicU64,
+    idle: AtomicU64,
+    running: AtomicBool,
+}
+
 pub(crate) struct Context {
     worker: RefCell<Worker>,
+    index: usize,
     executor: Arsc<Executor>,
 }
 
 pub struct Executor {
     injector: SegQueue<Runnable>,
     stealers: Box<[Stealer<Runnable, WORKER_CAP>]>,
+    stats: Box<[Stat]>,
+    clock: fn() -> u64,
     shutdown: AtomicBool,
 }
 
@@ -44,9 +55,15 @@ scoped_thread_local!(pub(crate) static CX: Context);
 impl Executor {
     /// Create a new executor with `num` runners and a `init` future.
     ///
+    /// The time spent by the runners is measured with `clock`.
+    ///
     /// The caller should iterate over the returned startup functions and run
     /// them concurrently.
-    pub fn start<G, F>(num: usize, init: G) -> impl Iterator<Item = impl FnOnce() + Send>
+    pub fn start<G, F>(
+        num: usize,
+        clock: fn() -> u64,
+        init: G,
+    ) -> impl Iterator<Item = impl FnOnce() + Send>
     where
         G: FnOnce(Arsc<Executor>) -> F,
         F: Future<Output = ()> + Send + 'static,
@@ -62,6 +79,8 @@ impl Executor {
         let executor = Arsc::new(Executor {
             injector: SegQueue::new(),
             stealers,
+            stats: (0..num).map(|_| Stat::default()).collect(),
+            clock,
             shutdown: AtomicBool::new(false),
         });
 
@@ -71,9 +90,9 @@ impl Executor {
         init.schedule();
         handle.detach();
 
-        workers.into_iter().map(move |worker| {
+        workers.into_iter().enumerate().map(move |(index, worker)| {
             let e = executor.clone();
-            || Self::startup(worker, e)
+            move || Self::startup(worker, index, e)
         })
     }
 
@@ -81,6 +100,22 @@ impl Executor {
         self.injector.len() + self.stealers.iter().fold(0, |acc, s| acc + s.len())
     }
 
+    /// Returns the number of tasks running or ready to run.
+    pub fn load(&self) -> usize {
+        let running = self
+            .stats
+            .iter()
+            .filter(|s| s.running.load(Relaxed))
+            .count();
+        self.count() + running
+    }
+
+    /// Returns the time spent running tasks and idling of each runner, in the
+    /// units of the clock.
+    pub fn times(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        (self.stats.iter()).map(|s| (s.busy.load(Relaxed), s.idle.load(Relaxed)))
+    }
+
     pub fn spawn<F, T>(&self, fut: F) -> Task<T>
     where
         F: Future<Output = T> + Send + 'static,
@@ -95,12 +130,13 @@ impl Executor {
         self.shutdown.store(true, Release)
     }
 
-    fn startup(rq: Local<Runnable, WORKER_CAP>, executor: Arsc<Executor>) {
+    fn startup(rq: Local<Runnable, WORKER_CAP>, index: usize, executor: Arsc<Executor>) {
         let cx = Context {
             worker: RefCell::new(Worker {
                 rq,
                 preempt_slot: None,
             }),
+            index,
             executor,
         };
         CX.set(&cx, || cx.run())
@@ -144,9 +180,27 @@ impl Context {
         task.or_else(|| self.executor.injector.pop())
     }
 
+    /// Runs `task`, accounting the time since `last` as idle and the time
+    /// spent by the task as busy.
+    fn run_task(&self, task: Runnable, last: &mut u64) {
+        let stat = &self.executor.stats[self.index];
+        let clock = self.executor.clock;
+
+        let start = clock();
+        stat.idle.fetch_add(start.saturating_sub(*last), Relaxed);
+        stat.running.store(true, Relaxed);
+
+        task.run();
+
+        stat.running.store(false, Relaxed);
+        *last = clock();
+        stat.busy.fetch_add(last.saturating_sub(start), Relaxed);
+    }
+
     fn run(&self) {
         let mut tick = 0u32;
         let mut rng = rand_riscv::rng();
+        let mut last = (self.executor.clock)();
         loop {
             tick = tick.wrapping_add(1);
 
@@ -156,13 +210,13 @@ impl Context {
 
             let next = self.next_task(tick, &mut self.worker.borrow_mut());
             if let Some(task) = next {
-                task.run();
+                self.run_task(task, &mut last);
                 continue;
             }
 
             let stealed = self.steal_task(&mut rng, &mut self.worker.borrow_mut());
             if let Some(task) = stealed {
-                task.run();
+                self.run_task(task, &mut last);
                 continue;
             }
 
@@ -0,0 +1,130 @@
+use core::sync::atomic::{AtomicU8, Ordering::Relaxed};
+
+use riscv::register::sstatus::FS;
+
+/// The registers are saved in memory, and the hardware may hold another
+/// task's.
+const SAVED: u8 = 0;
+/// The registers are loaded into the hardware and not modified since.
+const LOADED: u8 = 1;
+/// The registers are loaded into the hardware and modified since, so they
+/// must be saved before another task uses them.
+const DIRTY: u8 = 2;
+/// The registers are to be cleared, as for a new program.
+const RESET: u8 = 3;
+
+const FS_SHIFT: usize = 13;
+const FS_MASK: usize = 0b11 << FS_SHIFT;
+
+fn get_fs(sstatus: usize) -> usize {
+    (sstatus & FS_MASK) >> FS_SHIFT
+}
+
+fn set_fs(sstatus: &mut usize, fs: FS) {
+    *sstatus = (*sstatus & !FS_MASK) | ((fs as usize) << FS_SHIFT);
+}
+
+extern "C" {
+    fn _save_fp(regs: *mut [u64; 33]);
+    fn _load_fp(regs: *const [u64; 33]);
+}
+
+/// The floating-point context of a user task.
+///
+/// The context is switched lazily with `sstatus.FS` of the task:
+///
+/// - When the task enters user mode without its registers in the hardware, `FS`
+///   is `Off`, and its first floating-point instruction traps into
+///   [`Fp::restore`], which loads them and sets `FS` to `Clean`.
+/// - When the task leaves user mode with `FS` being `Dirty`, the registers are
+///   saved in [`Fp::yield_now`] before the hart runs something else.
+#[repr(C)]
+pub struct Fp {
+    /// 0 ~ 31 => f0 ~ f31, 32 => fcsr
+    regs: [u64; 33],
+
+    state: AtomicU8,
+}
+
+impl Default for Fp {
+    fn default() -> Self {
+        Fp {
+            regs: [0; 33],
+            state: AtomicU8::new(RESET),
+        }
+    }
+}
+
+impl Fp {
+    /// Copies the current context, as for a new thread or process.
+    pub fn copy(other: &Self) -> Self {
+        Fp {
+            regs: other.regs(),
+            state: AtomicU8::new(SAVED),
+        }
+    }
+
+    /// Returns the current values of the registers.
+    pub fn regs(&self) -> [u64; 33] {
+        match self.state.load(Relaxed) {
+            SAVED => self.regs,
+            RESET => [0; 33],
+            _ => {
+                let mut regs = [0; 33];
+                unsafe { _save_fp(&mut regs) };
+                regs
+            }
+        }
+    }
+
+    /// Replaces the values of the registers, as when returning from a signal
+    /// handler.
+    pub fn set_regs(&self, regs: &[u64; 33]) {
+        unsafe { _load_fp(regs) };
+        self.state.store(DIRTY, Relaxed);
+    }
+
+    pub fn enter_user(&self, sstatus: &mut usize) {
+        match self.state.load(Relaxed) {
+            SAVED | RESET => set_fs(sstatus, FS::Off),
+            _ if get_fs(*sstatus) == FS::Off as usize => set_fs(sstatus, FS::Clean),
+            _ => {}
+        }
+    }
+
+    pub fn leave_user(&self, sstatus: &mut usize) {
+        if get_fs(*sstatus) == FS::Dirty as usize {
+            self.state.store(DIRTY, Relaxed);
+            set_fs(sstatus, FS::Clean);
+        }
+    }
+
+    /// Handles an illegal instruction from user mode, returning whether it is
+    /// caused by the registers being not loaded.
+    pub fn restore(&self, sstatus: &mut usize) -> bool {
+        if get_fs(*sstatus) != FS::Off as usize {
+            return false;
+        }
+        match self.state.swap(LOADED, Relaxed) {
+            SAVED => unsafe { _load_fp(&self.regs) },
+            RESET => unsafe { _load_fp(&[0; 33]) },
+            state => self.state.store(state, Relaxed),
+        }
+        set_fs(sstatus, FS::Clean);
+        true
+    }
+
+    pub fn mark_reset(&self) {
+        self.state.store(RESET, Relaxed);
+    }
+
+    /// Saves the registers if modified, before the hart switches to another
+    /// task.
+    pub fn yield_now(&mut self) {
+        match self.state.swap(SAVED, Relaxed) {
+            DIRTY => unsafe { _save_fp(&mut self.regs) },
+            RESET => self.regs.fill(0),
+            _ => {}
+        }
+    }
+}
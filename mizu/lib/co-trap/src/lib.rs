@@ -2,6 +2,7 @@
 #![feature(const_trait_impl)]
 #![feature(macro_metavar_expr)]
 
+mod fp;
 mod tf;
 
 use core::{
@@ -16,7 +17,7 @@ use riscv::register::{
     stvec::{self, Stvec, TrapMode},
 };
 
-pub use self::tf::*;
+pub use self::{fp::Fp, tf::*};
 
 #[cfg(target_arch = "riscv64")]
 core::arch::global_asm!(include_str!("imp.S"));
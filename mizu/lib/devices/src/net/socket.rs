@@ -2,9 +2,12 @@ pub mod dns;
 pub mod tcp;
 pub mod udp;
 
+use alloc::sync::Weak;
+
 use futures_util::Future;
 use ksc::Error::{self, EOPNOTSUPP};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
+use umio::{Event, Watcher};
 
 pub const BUFFER_CAP: usize = 212992;
 const META_CAP: usize = 8;
@@ -58,6 +61,34 @@ impl Socket {
         }
     }
 
+    /// Returns the current events of the socket without waiting.
+    pub fn events(&self) -> Event {
+        let (recv, send) = match self {
+            Socket::Tcp(socket) => socket.readiness(),
+            Socket::Udp(socket) => socket.readiness(),
+        };
+        let mut events = Event::empty();
+        if recv {
+            events |= Event::READABLE;
+        }
+        if send {
+            events |= Event::WRITABLE;
+        }
+        if self.is_closed() {
+            events |= Event::HANG_UP;
+        }
+        events
+    }
+
+    /// Registers `watcher` to be notified whenever the result of
+    /// [`Socket::events`] may change.
+    pub fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) {
+        match self {
+            Socket::Tcp(socket) => socket.watch(watcher, exclusive),
+            Socket::Udp(socket) => socket.watch(watcher, exclusive),
+        }
+    }
+
     pub async fn connect(&self, endpoint: IpEndpoint) -> Result<(), Error> {
         match self {
             Socket::Tcp(socket) => socket.connect(endpoint).await,
@@ -1,4 +1,4 @@
-use alloc::vec;
+use alloc::{sync::Weak, vec};
 use core::{
     future::poll_fn,
     mem,
@@ -33,6 +33,7 @@ use smoltcp::{
     },
 };
 use spin::{Mutex, RwLock};
+use umio::Watcher;
 
 use super::BUFFER_CAP;
 use crate::net::Stack;
@@ -171,6 +172,7 @@ impl Inner {
                     if tx.send(data).await.is_err() {
                         break;
                     }
+                    self.stack.notify(umio::Event::READABLE);
                 }
             }
         }
@@ -359,6 +361,27 @@ impl Socket {
         poll_fn(|cx| self.poll_wait_for_send(cx)).await
     }
 
+    /// Returns whether the socket is ready for receiving and sending
+    /// respectively, under the same conditions as the waiting functions above.
+    pub fn readiness(&self) -> (bool, bool) {
+        let accept = ksync::critical(|| {
+            let accept = self.accept.lock();
+            accept.as_ref().map_or(false, |accept| !accept.is_empty())
+        });
+        self.inner.with(|s| {
+            let recv = s.can_recv()
+                || matches!(
+                    s.state(),
+                    State::FinWait1 | State::FinWait2 | State::CloseWait
+                );
+            (recv || accept, s.can_send() || accept)
+        })
+    }
+
+    pub fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) {
+        self.inner.stack.watch(watcher, exclusive)
+    }
+
     pub fn poll_flush(&self, cx: &mut Context) -> Poll<()> {
         self.inner.with_mut(|_, s| {
             let waiting_close = s.state() == State::Closed && s.remote_endpoint().is_some();
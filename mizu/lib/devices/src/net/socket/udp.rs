@@ -1,4 +1,4 @@
-use alloc::vec;
+use alloc::{sync::Weak, vec};
 use core::{
     future::poll_fn,
     sync::atomic::{AtomicBool, Ordering::SeqCst},
@@ -12,6 +12,7 @@ use smoltcp::{
     socket::udp::{self, PacketBuffer, PacketMetadata},
     wire::{IpEndpoint, IpListenEndpoint},
 };
+use umio::Watcher;
 
 use super::{BUFFER_CAP, META_CAP};
 use crate::net::Stack;
@@ -157,6 +158,16 @@ impl Socket {
         poll_fn(|cx| self.poll_wait_for_send(cx)).await
     }
 
+    /// Returns whether the socket is ready for receiving and sending
+    /// respectively.
+    pub fn readiness(&self) -> (bool, bool) {
+        self.with(|s| (s.can_recv(), s.can_send()))
+    }
+
+    pub fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) {
+        self.stack.watch(watcher, exclusive)
+    }
+
     pub fn poll_flush(&self, cx: &mut Context) -> Poll<()> {
         self.with_mut(|s| {
             if s.send_queue() == 0 {
@@ -1,4 +1,7 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use core::{
     convert::Infallible,
     fmt::{self, Display},
@@ -21,6 +24,7 @@ use smoltcp::{
     },
 };
 use spin::RwLock;
+use umio::{Watcher, Watchers};
 
 use super::{
     config::{Config, ConfigV4, ConfigV6, DhcpV4Config, StaticConfigV4, StaticConfigV6},
@@ -44,6 +48,8 @@ pub struct Stack {
     devices: Vec<Arc<RwLock<dyn Net>>>,
     socket: RwLock<SocketStack>,
     states: RwLock<Vec<State>>,
+    /// Notified whenever the readiness of any socket may have changed.
+    watchers: Watchers,
 }
 
 #[derive(Debug)]
@@ -199,6 +205,7 @@ impl Stack {
             devices,
             socket: RwLock::new(socket),
             states: RwLock::new(states),
+            watchers: Watchers::new(),
         })
     }
 
@@ -320,10 +327,24 @@ impl Stack {
     }
 
     fn poll(&self, cx: &mut Context) -> Option<Instant> {
-        ksync::critical(|| {
+        let (ddl, changed) = ksync::critical(|| {
             let mut write = self.socket.write();
             write.poll(cx, &self.devices, &mut self.states.write())
-        })
+        });
+        if changed {
+            self.notify(umio::Event::all());
+        }
+        ddl
+    }
+
+    /// Registers `watcher` to be notified whenever the readiness of any socket
+    /// in the stack may have changed.
+    pub fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) {
+        self.watchers.push(watcher, exclusive)
+    }
+
+    pub(in crate::net) fn notify(&self, events: umio::Event) {
+        self.watchers.notify(events)
     }
 }
 
@@ -420,23 +441,21 @@ impl SocketStack {
         cx: &mut Context,
         device: &[Arc<RwLock<dyn Net>>],
         state: &mut [State],
-    ) -> Option<Instant> {
+    ) -> (Option<Instant>, bool) {
         self.waker.register(cx.waker());
 
         let instant = instant_to_smoltcp(ktime::Instant::now());
 
         let mut ifaces = self.ifaces.iter_mut();
-        ifaces
-            .next()
-            .unwrap()
-            .poll(instant, &mut self.loopback, &mut self.sockets);
+        let loopback = ifaces.next().unwrap();
+        let mut changed = loopback.poll(instant, &mut self.loopback, &mut self.sockets);
 
         for ((iface, dev), state) in ifaces.zip(device).zip(state) {
             let mut dev = dev.write();
             Stack::update_interface(iface, &*dev);
 
             let mut poller = Tracer::new(dev.with_cx(Some(cx)), |i, p| writer(1, i, p));
-            iface.poll(instant, &mut poller, &mut self.sockets);
+            changed |= iface.poll(instant, &mut poller, &mut self.sockets);
             iface.poll(instant, &mut poller, &mut state.local_sockets);
 
             let old = mem::replace(&mut state.link_up, dev.is_link_up());
@@ -475,7 +494,7 @@ impl SocketStack {
                 (Some(acc), Some(next)) => Some(next.min(acc)),
             }
         });
-        ddl.map(instant_from_smoltcp)
+        (ddl.map(instant_from_smoltcp), changed)
     }
 }
 
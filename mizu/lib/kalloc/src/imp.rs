@@ -4,9 +4,9 @@ use core::{
 };
 
 use buddy_system_allocator::Heap;
-use spin::Mutex;
+use spin::{Mutex, Once};
 
-pub struct Allocator(Mutex<Heap<30>>);
+pub struct Allocator(Mutex<Heap<30>>, Once<fn(Layout) -> bool>);
 
 #[derive(Debug, Default)]
 pub struct Stat {
@@ -16,7 +16,16 @@ pub struct Stat {
 
 impl Allocator {
     pub const fn new() -> Self {
-        Allocator(Mutex::new(Heap::new()))
+        Allocator(Mutex::new(Heap::new()), Once::new())
+    }
+
+    /// Sets the handler called when an allocation fails, which may add more
+    /// memory to the heap and returns whether the allocation should be
+    /// retried.
+    ///
+    /// The handler must not allocate from the heap.
+    pub fn set_grow_handler(&self, handler: fn(Layout) -> bool) {
+        self.1.call_once(|| handler);
     }
 
     pub fn stat(&self) -> Stat {
@@ -35,11 +44,24 @@ impl Allocator {
     pub unsafe fn init(&self, start: usize, len: usize) {
         ksync_core::critical(|| self.0.lock().init(start, len));
     }
+
+    /// # Safety
+    ///
+    /// The memory region must be valid and not used by anything else.
+    pub unsafe fn add(&self, start: usize, len: usize) {
+        ksync_core::critical(|| self.0.lock().add_to_heap(start, start + len));
+    }
 }
 
 unsafe impl GlobalAlloc for Allocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let res = ksync_core::critical(|| self.0.lock().alloc(layout));
+        let res = match (res, self.1.get()) {
+            (Err(()), Some(grow)) if grow(layout) => {
+                ksync_core::critical(|| self.0.lock().alloc(layout))
+            }
+            (res, _) => res,
+        };
         // if let Ok(ptr) = res {
         //     log::trace!("+++ {ptr:?} {layout:?}");
         // }
@@ -73,4 +95,25 @@ mod tests {
             allocator.dealloc(ptr, layout);
         }
     }
+
+    #[test]
+    fn add_region() {
+        static mut SPACE: [u64; 256] = [0; 256];
+        #[repr(align(4096))]
+        struct Extra([u8; 8192]);
+        static mut EXTRA: Extra = Extra([0; 8192]);
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        unsafe {
+            let allocator = Allocator::new();
+            allocator.init(SPACE.as_ptr() as usize, SPACE.len() * 8);
+            assert!(allocator.alloc(layout).is_null());
+
+            allocator.add(EXTRA.0.as_ptr() as usize, EXTRA.0.len());
+            assert_eq!(allocator.stat().total, SPACE.len() * 8 + EXTRA.0.len());
+
+            let ptr = allocator.alloc(layout);
+            assert!(!ptr.is_null());
+            allocator.dealloc(ptr, layout);
+        }
+    }
 }
@@ -25,6 +25,25 @@ pub unsafe fn init<T: Copy>(start: &mut T, end: &mut T) {
     GLOBAL_ALLOC.init(start_ptr as usize, len as usize)
 }
 
+/// Add a memory region to the kernel heap.
+///
+/// # Safety
+///
+/// The region must be valid, properly aligned and not used by anything else.
+#[cfg(not(feature = "test"))]
+pub unsafe fn add(start: usize, len: usize) {
+    GLOBAL_ALLOC.add(start, len)
+}
+
+/// Set the handler called when the kernel heap runs out, which may add more
+/// memory with [`add`] and returns whether it did.
+///
+/// The handler must not allocate from the heap.
+#[cfg(not(feature = "test"))]
+pub fn set_grow_handler(handler: fn(core::alloc::Layout) -> bool) {
+    GLOBAL_ALLOC.set_grow_handler(handler)
+}
+
 pub fn stat() -> Stat {
     #[cfg(not(feature = "test"))]
     return GLOBAL_ALLOC.stat();
@@ -73,23 +73,39 @@ impl Arena {
 }
 
 impl Arena {
-    fn allocate_fresh(&self, count: NonZeroUsize) -> Option<LAddr> {
+    /// Returns the number of the pages to skip from `addr` to the next address
+    /// aligned to `align`.
+    fn skip(addr: usize, align: usize) -> usize {
+        ((addr.wrapping_add(align - 1) & !(align - 1)).wrapping_sub(addr)) >> PAGE_SHIFT
+    }
+
+    fn allocate_fresh(&self, count: NonZeroUsize, align: usize) -> Option<LAddr> {
         let mut top = self.top.load(Acquire);
         loop {
+            let skip = Self::skip(top, align);
+            let start = top.wrapping_add(skip * PAGE_SIZE);
             if !(self.base.val()..self.end.val())
-                .contains(&top.wrapping_add((count.get() - 1) * PAGE_SIZE))
+                .contains(&start.wrapping_add((count.get() - 1) * PAGE_SIZE))
             {
                 break None;
             }
-            let next = top.wrapping_add(count.get() * PAGE_SIZE);
+            let next = start.wrapping_add(count.get() * PAGE_SIZE);
             match self.top.compare_exchange_weak(top, next, AcqRel, Acquire) {
-                Ok(_) => break Some(LAddr::from(top)),
+                Ok(_) => {
+                    // The pages skipped for the alignment are left for others.
+                    if let Some(skip) = NonZeroUsize::new(skip) {
+                        unsafe { self.deallocate_list(top.into(), skip) }
+                    }
+                    break Some(LAddr::from(start));
+                }
                 Err(ptr) => top = ptr,
             }
         }
     }
 
-    fn allocate_list(&self, count: NonZeroUsize) -> Option<LAddr> {
+    /// Only the run at the head of the list is checked, so contiguous pages
+    /// are mostly allocated fresh.
+    fn allocate_list(&self, count: NonZeroUsize, align: usize) -> Option<LAddr> {
         let mut head = self.head.load(Acquire);
         loop {
             let (addr, _, id) = decompose(head);
@@ -98,21 +114,27 @@ impl Arena {
                 None => break None,
             };
 
-            let (next, nn, rest) = match unsafe { ptr.as_ref().count }.checked_sub(count.get()) {
+            let skip = Self::skip(addr.val(), align);
+            let rest = unsafe { ptr.as_ref().count }.checked_sub(skip + count.get());
+            let (next, start, rest) = match rest {
                 Some(rest) => unsafe {
                     let next = ptr.as_ref().next;
-                    let nn = addr.add(count.get() * PAGE_SIZE);
-                    (next, nn, rest)
+                    let start = addr.add(skip * PAGE_SIZE);
+                    (next, start, rest)
                 },
                 None => break None,
             };
             let next_head = compose(next.into(), 0, id.wrapping_add(1));
             match self.head.compare_exchange(head, next_head, AcqRel, Acquire) {
                 Ok(_) => {
+                    let nn = start.add(count.get() * PAGE_SIZE);
                     if let Some(rest) = NonZeroUsize::new(rest) {
                         unsafe { self.deallocate_list(nn.into(), rest) }
                     }
-                    break Some(addr);
+                    if let Some(skip) = NonZeroUsize::new(skip) {
+                        unsafe { self.deallocate_list(addr, skip) }
+                    }
+                    break Some(start.into());
                 }
                 Err(h) => head = h,
             }
@@ -136,10 +158,19 @@ impl Arena {
     }
 
     pub fn allocate(&self, count: NonZeroUsize) -> Option<LAddr> {
-        self.allocate_list(count)
-            .or_else(|| self.allocate_fresh(count))
+        self.allocate_aligned(count, PAGE_SIZE)
+    }
+
+    /// Allocates `count` physically contiguous pages starting at an address
+    /// aligned to `align`, which must be a power of two no less than a page.
+    ///
+    /// The pages can be deallocated separately.
+    pub fn allocate_aligned(&self, count: NonZeroUsize, align: usize) -> Option<LAddr> {
+        debug_assert!(align.is_power_of_two() && align >= PAGE_SIZE);
+        self.allocate_list(count, align)
+            .or_else(|| self.allocate_fresh(count, align))
             .inspect(|addr| {
-                log::trace!("frame allocation at {addr:?}, count = {count}");
+                log::trace!("frame allocation at {addr:?}, count = {count}, align = {align:#x}");
                 unsafe { addr.write_bytes(0, PAGE_SIZE) };
                 self.count.fetch_add(count.get(), SeqCst);
             })
@@ -204,3 +235,31 @@ pub fn init_frames_for_test() {
         unsafe { init_frames(range.start.into()..range.end.into()) }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned() {
+        #[repr(align(16384))]
+        struct Memory([u8; PAGE_SIZE * 16]);
+
+        let memory = Box::leak(Box::new(Memory([0; PAGE_SIZE * 16])));
+        let range = memory.0.as_mut_ptr_range();
+        let arena = unsafe { Arena::new(range.start.into()..range.end.into()) };
+        let align = PAGE_SIZE * 4;
+
+        let single = arena.allocate(NonZeroUsize::MIN).unwrap();
+        let aligned = arena
+            .allocate_aligned(NonZeroUsize::new(4).unwrap(), align)
+            .unwrap();
+        assert_eq!(aligned.val() & (align - 1), 0);
+        assert_eq!(aligned.val(), single.val() + align);
+
+        // The pages skipped for the alignment are allocated next.
+        let skipped = arena.allocate(NonZeroUsize::new(3).unwrap()).unwrap();
+        assert_eq!(skipped.val(), single.val() + PAGE_SIZE);
+        assert_eq!(arena.used_count(), 8);
+    }
+}
@@ -20,8 +20,8 @@ pub use rv39_paging::{
 pub use self::{
     frame::{frames, init_frames, Arena},
     lru::LruCache,
-    phys::{Frame, Phys, ZERO},
-    virt::{unset_virt, Virt, VirtCommitGuard},
+    phys::{reclaim, swap_off, swap_on, swaps, Frame, Phys, SwapInfo, ZERO},
+    virt::{unset_virt, MappingInfo, Virt, VirtCommitGuard},
 };
 
 pub fn sync_dma_for_cpu(from_device: bool, to_device: bool, range: core::ops::Range<LAddr>) {
@@ -1,3 +1,6 @@
+pub mod reclaim;
+mod swap;
+
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::{
     borrow::Borrow,
@@ -32,8 +35,16 @@ use rv39_paging::{PAddr, Table, ID_OFFSET, PAGE_SHIFT, PAGE_SIZE};
 use spin::{Lazy, Mutex};
 use umio::{advance_slices, ioslice_len, Io, IoExt, IoSlice, IoSliceMut, SeekFrom};
 
+pub(crate) use self::swap::swap_active;
+use self::swap::Slot;
+pub use self::swap::{swap_off, swap_on, swaps, SwapInfo};
+
 pub static ZERO: Lazy<Arsc<Frame>> = Lazy::new(|| Arsc::new(Frame::new().unwrap()));
 
+/// The number of the pages evicted at most when a frame fails to be allocated
+/// for committing.
+const DIRECT_RECLAIM_COUNT: usize = 32;
+
 pub struct Frame {
     base: PAddr,
     ptr: NonNull<u8>,
@@ -59,9 +70,9 @@ impl From<Table> for Frame {
 
 impl Frame {
     pub fn new() -> Result<Self, Error> {
-        let laddr = crate::frame::frames()
-            .allocate(NonZeroUsize::MIN)
-            .ok_or(ENOMEM)?;
+        let laddr = crate::frame::frames().allocate(NonZeroUsize::MIN);
+        reclaim::check_pressure();
+        let laddr = laddr.ok_or(ENOMEM)?;
         unsafe { laddr.write_bytes(0, PAGE_SIZE) };
         Ok(Frame {
             base: laddr.to_paddr(ID_OFFSET),
@@ -69,6 +80,23 @@ impl Frame {
         })
     }
 
+    /// Allocates `count` physically contiguous frames starting at an address
+    /// aligned to `align`, each of which is freed on its own.
+    pub fn new_contiguous(count: NonZeroUsize, align: usize) -> Result<Vec<Self>, Error> {
+        let laddr = crate::frame::frames().allocate_aligned(count, align);
+        reclaim::check_pressure();
+        let laddr = laddr.ok_or(ENOMEM)?;
+        unsafe { laddr.write_bytes(0, count.get() << PAGE_SHIFT) };
+        let iter = (0..count.get()).map(|n| {
+            let laddr = laddr + (n << PAGE_SHIFT);
+            Frame {
+                base: laddr.to_paddr(ID_OFFSET),
+                ptr: laddr.as_non_null().unwrap(),
+            }
+        });
+        Ok(iter.collect())
+    }
+
     pub fn base(&self) -> PAddr {
         self.base
     }
@@ -131,22 +159,35 @@ impl Borrow<PAddr> for Frame {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A page written out to a swap slot.
+#[derive(Debug)]
+struct Swapped {
+    slot: Slot,
+    len: usize,
+    /// The length of the content in the slot not truncated, since the slot
+    /// cannot be zeroed in place.
+    valid: usize,
+    unique: bool,
+}
+
+#[derive(Debug)]
 enum FrameState {
     Shared(Arsc<Frame>, usize),
     Unique(Arsc<Frame>, usize),
+    Swapped(Swapped),
 }
 
 impl FrameState {
-    fn frame(&mut self, write: Option<usize>) -> (Arsc<Frame>, usize) {
+    fn frame(&mut self, write: Option<usize>) -> Option<(Arsc<Frame>, usize)> {
         let (frame, len) = match self {
             FrameState::Shared(frame, len) => (frame, len),
             FrameState::Unique(frame, len) => (frame, len),
+            FrameState::Swapped(_) => return None,
         };
         if let Some(new_len) = write {
             *len = (*len).max(new_len);
         }
-        (frame.clone(), *len)
+        Some((frame.clone(), *len))
     }
 }
 
@@ -213,7 +254,12 @@ impl FrameInfo {
         let (frame, len) = match &mut self.state {
             Some(FrameState::Shared(frame, len)) => (frame, len),
             Some(FrameState::Unique(frame, len)) => (frame, len),
-            _ => return,
+            Some(FrameState::Swapped(swapped)) => {
+                swapped.valid = swapped.valid.min(new_len);
+                swapped.len = new_len;
+                return;
+            }
+            None => return,
         };
         if new_len < *len {
             unsafe { frame.as_ptr().as_mut()[new_len..*len].fill(0) };
@@ -252,6 +298,7 @@ impl FrameInfo {
                 }),
                 true,
             )),
+            Some(FrameState::Swapped(_)) => unreachable!("swapped pages must be swapped in first"),
             None => Err(ENOENT),
         }
     }
@@ -264,6 +311,9 @@ impl FrameInfo {
                 let (frame, mut len) = match s {
                     FrameState::Shared(frame, len) => (frame, len),
                     FrameState::Unique(frame, len) => (frame, len),
+                    FrameState::Swapped(_) => {
+                        unreachable!("swapped pages must be swapped in first")
+                    }
                 };
                 if let Some(new_len) = write {
                     len = len.max(new_len);
@@ -282,12 +332,16 @@ impl FrameInfo {
         }
     }
 
+    /// Returns `None` if the page is swapped out.
     fn get(
         mut this: FrameEntry,
         branch: bool,
         write: Option<usize>,
         cow: bool,
-    ) -> Result<Commit, Error> {
+    ) -> Result<Option<Commit>, Error> {
+        if let Some(FrameState::Swapped(_)) = this.get_mut().state {
+            return Ok(None);
+        }
         if branch {
             let (ret, remove) = this.get_mut().branch(write, cow)?;
             if remove {
@@ -295,11 +349,11 @@ impl FrameInfo {
             } else {
                 this.insert();
             }
-            Ok(ret)
+            Ok(Some(ret))
         } else {
             let (frame, len) = this.get_mut().leaf(write)?;
             this.insert();
-            Ok(Commit::Shared(frame, len))
+            Ok(Some(Commit::Shared(frame, len)))
         }
     }
 }
@@ -346,6 +400,29 @@ impl Parent {
 struct FrameList {
     parent: Option<Parent>,
     frames: HashMap<usize, FrameInfo, RandomState>,
+    /// The flusher of the object owning the list.
+    flusher: Option<Flusher>,
+    /// Whether the frames may be written back to the backend by any flusher,
+    /// in which case they are never swapped out.
+    flushable: bool,
+}
+
+impl FrameList {
+    fn new(
+        parent: Option<Parent>,
+        frames: HashMap<usize, FrameInfo, RandomState>,
+        flusher: Option<Flusher>,
+        flushable: bool,
+    ) -> Arc<Mutex<Self>> {
+        let list = Arc::new(Mutex::new(FrameList {
+            parent,
+            frames,
+            flusher,
+            flushable,
+        }));
+        reclaim::register(&list);
+        list
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -358,10 +435,9 @@ struct Flusher {
 #[derive(Debug)]
 pub struct Phys {
     branch: bool,
-    list: Mutex<FrameList>,
+    list: Arc<Mutex<FrameList>>,
     position: AtomicUsize,
     cow: bool,
-    flusher: Option<Flusher>,
 }
 
 impl Phys {
@@ -372,19 +448,21 @@ impl Phys {
     ) -> (Self, impl Future<Output = ()> + Send) {
         let (sender, receiver) = unbounded();
         let flushed = Arsc::new(Event::new());
+        let flusher = (!cow).then_some(Flusher {
+            sender,
+            flushed: flushed.clone(),
+            offset: 0,
+        });
         let phys = Phys {
             branch: false,
-            list: Mutex::new(FrameList {
-                parent: Some(Parent::Backend(backend.clone())),
-                frames: Default::default(),
-            }),
+            list: FrameList::new(
+                Some(Parent::Backend(backend.clone())),
+                Default::default(),
+                flusher,
+                !cow,
+            ),
             position: initial_pos.into(),
             cow,
-            flusher: (!cow).then_some(Flusher {
-                sender,
-                flushed: flushed.clone(),
-                offset: 0,
-            }),
         };
         (phys, flusher(receiver, flushed, backend))
     }
@@ -392,31 +470,28 @@ impl Phys {
     pub fn new(cow: bool) -> Phys {
         Phys {
             branch: false,
-            list: Mutex::new(FrameList {
-                parent: None,
-                frames: Default::default(),
-            }),
+            list: FrameList::new(None, Default::default(), None, false),
             position: Default::default(),
             cow,
-            flusher: None,
         }
     }
 
     pub fn clone_as(&self, cow: bool, index_offset: usize, fixed_count: Option<usize>) -> Self {
         self.merge_sole_parent(|_| Some(()));
 
-        let branch = ksync::critical(|| {
+        let (branch, flusher) = ksync::critical(|| {
             let mut list = self.list.lock();
 
             let branch = Arsc::new(Phys {
                 branch: true,
                 position: Default::default(),
-                list: Mutex::new(FrameList {
-                    parent: list.parent.clone(),
-                    frames: mem::take(&mut list.frames),
-                }),
+                list: FrameList::new(
+                    list.parent.clone(),
+                    mem::take(&mut list.frames),
+                    None,
+                    list.flushable,
+                ),
                 cow: self.cow || cow,
-                flusher: None,
             });
 
             list.parent = Some(Parent::Phys {
@@ -424,34 +499,63 @@ impl Phys {
                 start: 0,
                 end: None,
             });
+            let flusher = list.flusher.clone();
             drop(list);
-            branch
+            (branch, flusher)
         });
 
+        let flusher = flusher.and_then(|flusher| {
+            (!cow).then_some(Flusher {
+                offset: flusher.offset + index_offset,
+                ..flusher
+            })
+        });
+        let flushable = flusher.is_some();
         Phys {
             branch: false,
-            list: Mutex::new(FrameList {
-                parent: Some(Parent::Phys {
+            list: FrameList::new(
+                Some(Parent::Phys {
                     phys: branch,
                     start: index_offset,
                     end: fixed_count.map(|c| c + index_offset),
                 }),
-                frames: Default::default(),
-            }),
+                Default::default(),
+                flusher,
+                flushable,
+            ),
             position: Default::default(),
             cow,
-            flusher: self.flusher.clone().and_then(|flusher| {
-                (!cow).then_some(Flusher {
-                    offset: flusher.offset + index_offset,
-                    ..flusher
-                })
-            }),
         }
     }
 
     pub fn is_cow(&self) -> bool {
         self.cow
     }
+
+    /// Returns whether the object is backed by nothing but the memory, in
+    /// which case it can be committed with contiguous frames.
+    pub fn is_anonymous(&self) -> bool {
+        ksync::critical(|| self.list.lock().parent.is_none())
+    }
+
+    fn flusher(&self) -> Option<Flusher> {
+        ksync::critical(|| self.list.lock().flusher.clone())
+    }
+
+    /// Returns the backend at the root of the object, bypassing the cache, if
+    /// the indices of the object are the same as the ones of the backend.
+    pub fn backend(&self) -> Option<Arc<dyn Io>> {
+        let mut parent = ksync::critical(|| self.list.lock().parent.clone());
+        loop {
+            match parent? {
+                Parent::Phys { phys, start: 0, .. } => {
+                    parent = ksync::critical(|| phys.list.lock().parent.clone())
+                }
+                Parent::Phys { .. } => break None,
+                Parent::Backend(backend) => break Some(backend),
+            }
+        }
+    }
 }
 
 impl Clone for Phys {
@@ -480,12 +584,13 @@ impl Phys {
 
             match sole_parent {
                 None => break ksync::critical(|| f(&mut self.list.lock())),
-                Some((mut parent, start, end)) => {
+                Some((parent, start, end)) => {
                     // log::trace!("merging sole parent: start_index = {start}");
                     let ret = ksync::critical(|| {
                         let mut list = self.list.lock();
+                        let mut parent_list = parent.list.lock();
 
-                        let frames = mem::take(&mut parent.list.get_mut().frames);
+                        let frames = mem::take(&mut parent_list.frames);
                         let iter = frames.into_iter().filter_map(|(pi, fi)| {
                             let index = pi.checked_sub(start)?;
                             end.map_or(true, |end| pi < end).then_some((index, fi))
@@ -497,10 +602,16 @@ impl Phys {
                                 | Some(FrameState::Unique(frame, len)) => {
                                     Some(FrameState::Shared(frame, len))
                                 }
+                                Some(FrameState::Swapped(swapped)) => {
+                                    Some(FrameState::Swapped(Swapped {
+                                        unique: false,
+                                        ..swapped
+                                    }))
+                                }
                             };
                             let _ = list.frames.try_insert(index, fi);
                         }
-                        list.parent = parent.list.get_mut().parent.take().map(|pp| match pp {
+                        list.parent = parent_list.parent.take().map(|pp| match pp {
                             Parent::Phys {
                                 phys,
                                 start: pp_start,
@@ -512,6 +623,7 @@ impl Phys {
                             },
                             Parent::Backend(b) => Parent::Backend(b),
                         });
+                        drop(parent_list);
 
                         f(&mut list)
                     });
@@ -531,89 +643,125 @@ impl Phys {
     ) -> Boxed<Result<Commit, Error>> {
         let cow = self.cow || cow;
         Box::pin(async move {
-            // log::trace!("Phys::commit_impl: return from self, index = {index}");
-            let self_get = self.merge_sole_parent(|list| {
-                if let Some(ent) = FrameEntry::get(list, index) {
-                    return Some(FrameInfo::get(ent, self.branch, write, cow));
+            loop {
+                let res = match self.try_commit(index, write, cow).await {
+                    Ok(Some(commit)) => break Ok(commit),
+                    Ok(None) => reclaim::swap_in(&self.list, index).await,
+                    Err(err) => Err(err),
+                };
+                match res {
+                    Ok(()) => {}
+                    // Clean pages are dropped to make room for the new one, or
+                    // some task is killed for it as the last resort.
+                    Err(ENOMEM) => {
+                        if reclaim::shrink(DIRECT_RECLAIM_COUNT) == 0
+                            && !reclaim::out_of_memory().await
+                        {
+                            break Err(ENOMEM);
+                        }
+                    }
+                    Err(err) => break Err(err),
                 }
-                None
-            });
-            if let Some(commit) = self_get.transpose()? {
-                return Ok(commit);
             }
+        })
+    }
 
-            if let Some(parent) = ksync::critical(|| self.list.lock().parent.clone()) {
-                match parent {
-                    Parent::Phys {
-                        phys: parent,
-                        start,
-                        end,
-                    } => {
-                        if end.map_or(true, |end| (0..(end - start)).contains(&index)) {
-                            let parent_index = start + index;
-                            // log::trace!(
-                            //     "Phys::commit_impl: return from parent, parent index = {}",
-                            //     parent_index
-                            // );
-                            return match parent.commit_impl(parent_index, write, cow).await {
-                                Ok(s @ Commit::Shared(..)) => Ok(s),
-                                Ok(Commit::Unique(fi)) => ksync::critical(|| {
-                                    let mut list = self.list.lock();
-
-                                    let ent = FrameEntry::try_insert(&mut list, index, fi);
-                                    FrameInfo::get(ent, self.branch, write, cow)
-                                }),
-                                Err(err) => Err(err),
-                            };
-                        }
-                    }
-                    Parent::Backend(backend) => {
+    /// Gets the page at `index` of the list, with `fi` inserted if absent.
+    fn get_or_insert(
+        &self,
+        index: usize,
+        fi: FrameInfo,
+        write: Option<usize>,
+        cow: bool,
+    ) -> Result<Option<Commit>, Error> {
+        let ret = ksync::critical(|| {
+            let mut list = self.list.lock();
+            let ent = FrameEntry::try_insert(&mut list, index, fi);
+            FrameInfo::get(ent, self.branch, write, cow)
+        });
+        if let Ok(Some(_)) = ret {
+            reclaim::touch(&self.list, index);
+        }
+        ret
+    }
+
+    /// Returns `None` if the page at `index` of the list is swapped out.
+    async fn try_commit(
+        &self,
+        index: usize,
+        write: Option<usize>,
+        cow: bool,
+    ) -> Result<Option<Commit>, Error> {
+        // log::trace!("Phys::commit_impl: return from self, index = {index}");
+        let self_get = self.merge_sole_parent(|list| {
+            let ent = FrameEntry::get(list, index)?;
+            Some(FrameInfo::get(ent, self.branch, write, cow))
+        });
+        if let Some(commit) = self_get.transpose()? {
+            if commit.is_some() {
+                reclaim::touch(&self.list, index);
+            }
+            return Ok(commit);
+        }
+
+        if let Some(parent) = ksync::critical(|| self.list.lock().parent.clone()) {
+            match parent {
+                Parent::Phys {
+                    phys: parent,
+                    start,
+                    end,
+                } => {
+                    if end.map_or(true, |end| (0..(end - start)).contains(&index)) {
+                        let parent_index = start + index;
                         // log::trace!(
-                        //     "Phys::commit_impl: copy from backend, offset {:#x}",
-                        //     index << PAGE_SHIFT
+                        //     "Phys::commit_impl: return from parent, parent index = {}",
+                        //     parent_index
                         // );
-                        let mut frame = Frame::new()?;
-
-                        let len = {
-                            let mut read_len = 0;
-                            let mut offset = index << PAGE_SHIFT;
-                            let mut buffer = &mut frame[..];
-                            loop {
-                                if buffer.is_empty() {
-                                    break read_len;
-                                }
-                                let len = backend.read_at(offset, &mut [buffer]).await?;
-                                if len == 0 {
-                                    break read_len;
-                                }
-                                offset += len;
-                                read_len += len;
-                                buffer = &mut buffer[len..];
-                            }
+                        return match parent.commit_impl(parent_index, write, cow).await {
+                            Ok(s @ Commit::Shared(..)) => Ok(Some(s)),
+                            Ok(Commit::Unique(fi)) => self.get_or_insert(index, fi, write, cow),
+                            Err(err) => Err(err),
                         };
-                        let fi = FrameInfo::new(Arsc::new(frame), len);
-                        return ksync::critical(|| {
-                            let mut list = self.list.lock();
-                            let ent = FrameEntry::try_insert(&mut list, index, fi);
-                            FrameInfo::get(ent, self.branch, write, cow)
-                        });
                     }
                 }
+                Parent::Backend(backend) => {
+                    // log::trace!(
+                    //     "Phys::commit_impl: copy from backend, offset {:#x}",
+                    //     index << PAGE_SHIFT
+                    // );
+                    let mut frame = Frame::new()?;
+
+                    let len = {
+                        let mut read_len = 0;
+                        let mut offset = index << PAGE_SHIFT;
+                        let mut buffer = &mut frame[..];
+                        loop {
+                            if buffer.is_empty() {
+                                break read_len;
+                            }
+                            let len = backend.read_at(offset, &mut [buffer]).await?;
+                            if len == 0 {
+                                break read_len;
+                            }
+                            offset += len;
+                            read_len += len;
+                            buffer = &mut buffer[len..];
+                        }
+                    };
+                    let fi = FrameInfo::new(Arsc::new(frame), len);
+                    return self.get_or_insert(index, fi, write, cow);
+                }
             }
+        }
 
-            // log::trace!("Phys::commit_impl: return new frame");
+        // log::trace!("Phys::commit_impl: return new frame");
 
-            let Some(new_len) = write else {
-                return Ok(Commit::Shared(ZERO.clone(), 0));
-            };
+        let Some(new_len) = write else {
+            return Ok(Some(Commit::Shared(ZERO.clone(), 0)));
+        };
 
-            let fi = FrameInfo::new(Arsc::new(Frame::new()?), new_len);
-            ksync::critical(|| {
-                let mut list = self.list.lock();
-                let ent = FrameEntry::try_insert(&mut list, index, fi);
-                FrameInfo::get(ent, self.branch, write, cow)
-            })
-        })
+        let fi = FrameInfo::new(Arsc::new(Frame::new()?), new_len);
+        self.get_or_insert(index, fi, write, cow)
     }
 
     pub async fn commit(
@@ -636,6 +784,59 @@ impl Phys {
         }
     }
 
+    /// Commits `count` pages from `index` for writing with newly allocated
+    /// physically contiguous frames aligned to `align`, so that they can be
+    /// mapped with a large leaf entry.
+    ///
+    /// Returns `None` if the object is not anonymous, any of the pages has been
+    /// committed, or no such frames are available, in which case the pages
+    /// should be committed one by one.
+    pub fn commit_contiguous(
+        &self,
+        index: usize,
+        count: NonZeroUsize,
+        align: usize,
+    ) -> Option<Vec<Arsc<Frame>>> {
+        assert!(!self.branch);
+        let indices = index..(index + count.get());
+        let is_vacant = |list: &FrameList| {
+            list.parent.is_none() && !indices.clone().any(|i| list.frames.contains_key(&i))
+        };
+        if !ksync::critical(|| is_vacant(&self.list.lock())) {
+            return None;
+        }
+        let frames = Frame::new_contiguous(count, align).ok()?;
+        let frames = frames.into_iter().map(Arsc::new).collect::<Vec<_>>();
+
+        let inserted = ksync::critical(|| {
+            let mut list = self.list.lock();
+            // Some page may have been committed during the allocation.
+            if !is_vacant(&list) {
+                return false;
+            }
+            for (index, frame) in indices.clone().zip(&frames) {
+                let state = FrameState::Shared(frame.clone(), PAGE_SIZE);
+                let fi = FrameInfo {
+                    state: Some(state),
+                    dirty: true,
+                };
+                list.frames.insert(index, fi);
+            }
+            true
+        });
+        if !inserted {
+            return None;
+        }
+        log::trace!(
+            "Phys::commit_contiguous index = {index}, count = {count}, base = {:?}",
+            frames[0].base()
+        );
+        for index in indices {
+            reclaim::touch(&self.list, index);
+        }
+        Some(frames)
+    }
+
     pub fn resize(&self, new_len: usize) {
         if new_len == 0 {
             ksync::critical(|| {
@@ -671,7 +872,7 @@ impl Phys {
     }
 
     pub async fn flush(&self, mut index: usize, force_dirty: Option<bool>) -> Result<(), Error> {
-        let Some(mut flusher) = self.flusher.clone() else {
+        let Some(mut flusher) = self.flusher() else {
             return Ok(())
         };
 
@@ -687,7 +888,7 @@ impl Phys {
                     let dirty = mem::replace(&mut fi.dirty, false);
                     let dirty = force_dirty.unwrap_or(dirty);
                     dirty
-                        .then(|| fi.state.as_mut().map(|s| s.frame(None)))
+                        .then(|| fi.state.as_mut().and_then(|s| s.frame(None)))
                         .flatten()
                 })
             });
@@ -727,7 +928,7 @@ impl Phys {
 
     pub async fn flush_all(&self) -> Result<(), Error> {
         // log::trace!("Phys::flush_all len = {}", self.position.load(SeqCst));
-        let Some(mut flusher) = self.flusher.clone() else {
+        let Some(mut flusher) = self.flusher() else {
             return Ok(())
         };
 
@@ -745,7 +946,7 @@ impl Phys {
                     }
                     let dirty = mem::replace(&mut fi.dirty, false);
                     dirty
-                        .then(|| fi.state.as_mut().map(|s| s.frame(None)))
+                        .then(|| fi.state.as_mut().and_then(|s| s.frame(None)))
                         .flatten()
                         .map(|(frame, len)| (index + flusher.offset, frame, len))
                 });
@@ -778,12 +979,12 @@ impl Phys {
 
 impl Drop for Phys {
     fn drop(&mut self) {
-        let Some(mut flusher) = self.flusher.clone() else {
+        let Some(mut flusher) = self.flusher() else {
             return;
         };
 
         let mut storage = None;
-        let mut this = self;
+        let mut this = &*self;
         let mut start_index = 0;
         let mut end_index = None;
 
@@ -791,23 +992,26 @@ impl Drop for Phys {
             if flusher.sender.is_closed() {
                 break;
             }
-            let list = this.list.get_mut();
-            let data = list.frames.iter_mut().filter_map(|(&index, fi)| {
-                if !(start_index <= index && end_index.map_or(true, |e| index < e)) {
-                    return None;
-                }
-                let dirty = mem::replace(&mut fi.dirty, false);
-                dirty
-                    .then(|| fi.state.as_mut().map(|s| s.frame(None)))
-                    .flatten()
-                    .map(|(frame, len)| (index + flusher.offset, frame, len))
+            let (data, parent) = ksync::critical(|| {
+                let mut list = this.list.lock();
+                let data = list.frames.iter_mut().filter_map(|(&index, fi)| {
+                    if !(start_index <= index && end_index.map_or(true, |e| index < e)) {
+                        return None;
+                    }
+                    let dirty = mem::replace(&mut fi.dirty, false);
+                    dirty
+                        .then(|| fi.state.as_mut().and_then(|s| s.frame(None)))
+                        .flatten()
+                        .map(|(frame, len)| (index + flusher.offset, frame, len))
+                });
+                let data = data.collect::<Vec<_>>();
+                (data, list.parent.take())
             });
-            let data = data.collect::<Vec<_>>();
             if !data.is_empty() {
                 let _ = flusher.sender.try_send(FlushData::Multiple(data));
             }
 
-            let Some(Parent::Phys { phys, start, end }) = list.parent.take() else {
+            let Some(Parent::Phys { phys, start, end }) = parent else {
                 break
             };
             start_index += start;
@@ -820,7 +1024,7 @@ impl Drop for Phys {
             flusher.offset -= start;
             let phys = storage.insert(phys);
             match Arsc::get_mut(phys) {
-                Some(phys) => this = phys,
+                Some(phys) => this = &*phys,
                 None => break,
             }
         }
@@ -0,0 +1,359 @@
+//! The reclamation of the frames committed to [`Phys`](super::Phys) objects.
+//!
+//! The pages are evicted in the order they were last committed, which are
+//! tracked in an LRU list. Only the ones used nowhere else, including the page
+//! tables, are evicted:
+//!
+//! - Clean pages directly backed by a backend are dropped and read again from
+//!   the backend when needed.
+//! - Dirty pages written back to a backend are sent to its flusher first, and
+//!   dropped when they are found clean again.
+//! - The rest, which are only stored in the memory, are written out to a swap
+//!   area and read back when committed again.
+//!
+//! If no frame can be allocated even after the reclamation, the handler set by
+//! [`set_oom_handler`] is called as the last resort.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::mem;
+
+use arsc_rs::Arsc;
+use ksc_core::{handler::Boxed, Error};
+use ksync::event::Event;
+use spin::{Lazy, Mutex, Once};
+
+use super::{
+    swap::{self, Area},
+    FlushData, Frame, FrameList, FrameState, Parent, Swapped,
+};
+use crate::{frame::frames, lru::LruCache};
+
+type List = Mutex<FrameList>;
+
+/// All the frame lists, pruned whenever the vector is full.
+static LISTS: Mutex<Vec<Weak<List>>> = Mutex::new(Vec::new());
+
+/// The committed pages identified by their lists and indices.
+static PAGES: Lazy<Mutex<LruCache<(usize, usize), Weak<List>>>> =
+    Lazy::new(|| Mutex::new(LruCache::unbounded()));
+
+/// Notified whenever the free frames run below the low watermark.
+static PRESSURE: Event = Event::new();
+
+/// The handler called when the memory runs out, returning whether the
+/// allocation should be retried.
+pub type OomHandler = fn() -> Boxed<'static, bool>;
+
+static OOM_HANDLER: Once<OomHandler> = Once::new();
+
+pub(super) fn register(list: &Arc<List>) {
+    let list = Arc::downgrade(list);
+    ksync::critical(|| {
+        let mut lists = LISTS.lock();
+        if lists.len() == lists.capacity() {
+            lists.retain(|list| list.strong_count() > 0);
+        }
+        lists.push(list)
+    })
+}
+
+/// Marks the page at `index` of the list as the most recently used one.
+pub(super) fn touch(list: &Arc<List>, index: usize) {
+    let key = (Arc::as_ptr(list) as usize, index);
+    let list = Arc::downgrade(list);
+    let old = ksync::critical(|| PAGES.lock().put(key, list));
+    drop(old);
+}
+
+/// Returns the number of the free frames below which the frames are reclaimed
+/// in the background, and the one up to which they are reclaimed.
+pub fn watermarks() -> (usize, usize) {
+    let total = frames().total_count();
+    (total / 32, total / 16)
+}
+
+pub fn free_count() -> usize {
+    let frames = frames();
+    frames.total_count().saturating_sub(frames.used_count())
+}
+
+pub(super) fn check_pressure() {
+    if free_count() < watermarks().0 {
+        PRESSURE.notify(1);
+    }
+}
+
+/// Waits until the free frames run below the low watermark.
+pub async fn pressure() {
+    let mut listener = None;
+    loop {
+        if free_count() < watermarks().0 {
+            break;
+        }
+        match listener.take() {
+            Some(listener) => listener.await,
+            None => listener = Some(PRESSURE.listen()),
+        }
+    }
+}
+
+/// Sets the handler called when no frame can be allocated even after the
+/// reclamation, which should free some memory, usually by killing some task.
+///
+/// Only the first call takes effect.
+pub fn set_oom_handler(handler: OomHandler) {
+    OOM_HANDLER.call_once(|| handler);
+}
+
+/// Returns whether the failed allocation should be retried.
+pub(super) async fn out_of_memory() -> bool {
+    match OOM_HANDLER.get() {
+        Some(handler) => handler().await,
+        None => false,
+    }
+}
+
+enum Victim {
+    /// The page is no longer in the list, or has been evicted.
+    Gone,
+    Freed,
+    /// The page cannot be evicted for now.
+    Busy,
+    Swap {
+        frame: Arsc<Frame>,
+        len: usize,
+        dirty: bool,
+    },
+}
+
+fn select(list: &mut FrameList, index: usize, swap: bool) -> Victim {
+    let Some(fi) = list.frames.get_mut(&index) else {
+        return Victim::Gone
+    };
+    let frame = match &fi.state {
+        Some(FrameState::Shared(frame, _) | FrameState::Unique(frame, _)) => frame,
+        _ => return Victim::Gone,
+    };
+    // Frames mapped or being used elsewhere are kept.
+    if Arsc::count(frame) > 1 {
+        return Victim::Busy;
+    }
+    if !fi.dirty && matches!(list.parent, Some(Parent::Backend(_))) {
+        list.frames.remove(&index);
+        return Victim::Freed;
+    }
+    if list.flushable {
+        // Pages of other lists are written back by their own flushers.
+        if let Some(flusher) = &list.flusher {
+            if mem::replace(&mut fi.dirty, false) {
+                let (frame, len) = fi.state.as_mut().and_then(|s| s.frame(None)).unwrap();
+                let data = FlushData::Single((index + flusher.offset, frame, len));
+                let _ = flusher.sender.try_send(data);
+            }
+        }
+        return Victim::Busy;
+    }
+    if !swap {
+        return Victim::Busy;
+    }
+    let (frame, len) = fi.state.as_mut().and_then(|s| s.frame(None)).unwrap();
+    // Any write from now on marks the page dirty again.
+    let dirty = mem::replace(&mut fi.dirty, false);
+    Victim::Swap { frame, len, dirty }
+}
+
+enum Outcome {
+    Gone,
+    Freed,
+    Kept,
+    /// No swap slot is available.
+    NoSpace,
+}
+
+async fn swap_out(
+    list: &Arc<List>,
+    index: usize,
+    frame: Arsc<Frame>,
+    len: usize,
+    dirty: bool,
+) -> Outcome {
+    let slot = swap::alloc();
+    let written = match &slot {
+        Some(slot) => slot.location().write(&frame).await.is_ok(),
+        None => false,
+    };
+    let outcome = ksync::critical(|| {
+        let mut list = list.lock();
+        let Some(fi) = list.frames.get_mut(&index) else {
+            return Outcome::Gone
+        };
+        let modified = fi.dirty;
+        fi.dirty |= dirty;
+        let (cur_len, unique) = match &fi.state {
+            Some(FrameState::Shared(f, len)) if Arsc::ptr_eq(f, &frame) => (*len, false),
+            Some(FrameState::Unique(f, len)) if Arsc::ptr_eq(f, &frame) => (*len, true),
+            _ => return Outcome::Gone,
+        };
+        match slot {
+            // The page may have been modified or mapped during the writing.
+            Some(slot) if written && !modified && cur_len == len && Arsc::count(&frame) == 2 => {
+                fi.state = Some(FrameState::Swapped(Swapped {
+                    slot,
+                    len,
+                    valid: len,
+                    unique,
+                }));
+                Outcome::Freed
+            }
+            Some(_) => Outcome::Kept,
+            None => Outcome::NoSpace,
+        }
+    });
+    drop(frame);
+    outcome
+}
+
+/// Reads the page at `index` of the list back if it is swapped out.
+pub(super) async fn swap_in(list: &Arc<List>, index: usize) -> Result<(), Error> {
+    let location = ksync::critical(|| {
+        let list = list.lock();
+        match list.frames.get(&index).and_then(|fi| fi.state.as_ref()) {
+            Some(FrameState::Swapped(swapped)) => Some(swapped.slot.location().clone()),
+            _ => None,
+        }
+    });
+    let Some(location) = location else {
+        return Ok(())
+    };
+
+    let mut frame = Frame::new()?;
+    location.read(&mut frame).await?;
+
+    ksync::critical(|| {
+        let mut list = list.lock();
+        let Some(fi) = list.frames.get_mut(&index) else {
+            return
+        };
+        fi.state = match fi.state.take() {
+            Some(FrameState::Swapped(swapped)) if *swapped.slot.location() == location => {
+                // The content may have been truncated in the meantime.
+                frame[swapped.valid..].fill(0);
+                let frame = Arsc::new(frame);
+                Some(match swapped.unique {
+                    true => FrameState::Unique(frame, swapped.len),
+                    false => FrameState::Shared(frame, swapped.len),
+                })
+            }
+            state => state,
+        };
+    });
+    touch(list, index);
+    Ok(())
+}
+
+/// Swaps in all the pages in `area`, which no longer gives out slots.
+pub(super) async fn drain(area: &Arc<Area>) -> Result<(), Error> {
+    loop {
+        let freed = area.freed.listen();
+        if area.used() == 0 {
+            break Ok(());
+        }
+        let lists = ksync::critical(|| LISTS.lock().clone());
+        let mut found = false;
+        for list in lists.iter().filter_map(Weak::upgrade) {
+            let indices = ksync::critical(|| {
+                let list = list.lock();
+                let iter = list
+                    .frames
+                    .iter()
+                    .filter_map(|(&index, fi)| match &fi.state {
+                        Some(FrameState::Swapped(swapped))
+                            if swapped.slot.location().is_in(area) =>
+                        {
+                            Some(index)
+                        }
+                        _ => None,
+                    });
+                iter.collect::<Vec<_>>()
+            });
+            for index in indices {
+                found = true;
+                swap_in(&list, index).await?;
+            }
+        }
+        // Some slots are still being written out.
+        if !found {
+            freed.await;
+        }
+    }
+}
+
+fn pop_lru() -> Option<((usize, usize), Weak<List>)> {
+    ksync::critical(|| PAGES.lock().pop_lru())
+}
+
+fn len() -> usize {
+    ksync::critical(|| PAGES.lock().len())
+}
+
+/// Evicts at most `count` pages without writing them out, returning the
+/// number of the frames freed.
+///
+/// The dirty pages of files are sent to their flushers, to be evicted later.
+pub fn shrink(count: usize) -> usize {
+    let mut freed = 0;
+    for _ in 0..len() {
+        if freed >= count {
+            break;
+        }
+        let Some(((_, index), list)) = pop_lru() else {
+            break
+        };
+        let Some(list) = list.upgrade() else {
+            continue
+        };
+        match ksync::critical(|| select(&mut list.lock(), index, false)) {
+            Victim::Gone => {}
+            Victim::Freed => freed += 1,
+            Victim::Busy | Victim::Swap { .. } => touch(&list, index),
+        }
+    }
+    freed
+}
+
+/// Evicts at most `count` pages, swapping out the anonymous ones if any swap
+/// area is available, returning the number of the frames freed.
+pub async fn reclaim(count: usize) -> usize {
+    let mut swap = true;
+    let mut freed = 0;
+    for _ in 0..len() {
+        if freed >= count {
+            break;
+        }
+        let Some(((_, index), list)) = pop_lru() else {
+            break
+        };
+        let Some(list) = list.upgrade() else {
+            continue
+        };
+        let outcome = match ksync::critical(|| select(&mut list.lock(), index, swap)) {
+            Victim::Gone => Outcome::Gone,
+            Victim::Freed => Outcome::Freed,
+            Victim::Busy => Outcome::Kept,
+            Victim::Swap { frame, len, dirty } => swap_out(&list, index, frame, len, dirty).await,
+        };
+        match outcome {
+            Outcome::Gone => {}
+            Outcome::Freed => freed += 1,
+            Outcome::Kept => touch(&list, index),
+            Outcome::NoSpace => {
+                swap = false;
+                touch(&list, index)
+            }
+        }
+    }
+    freed
+}
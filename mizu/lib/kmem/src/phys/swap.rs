@@ -0,0 +1,260 @@
+//! The swap areas, where the anonymous pages are written out under memory
+//! pressure.
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicIsize, Ordering::SeqCst},
+};
+
+use ksc_core::Error::{self, EBUSY, EINVAL};
+use ksync::event::Event;
+use rv39_paging::PAGE_SHIFT;
+use spin::Mutex;
+use umio::{Io, IoExt};
+
+/// The swap areas in the descending order of their priorities.
+static AREAS: Mutex<Vec<Arc<Area>>> = Mutex::new(Vec::new());
+
+/// The priority of the next area added without one, decreasing from -1.
+static NEXT_PRIORITY: AtomicIsize = AtomicIsize::new(-1);
+
+/// The bitmap of the slots in a swap area.
+struct Slots {
+    bits: Vec<u64>,
+    used: usize,
+    /// The index of the word to start searching from.
+    next: usize,
+}
+
+impl Slots {
+    fn new(len: usize) -> Self {
+        let mut bits = vec![0; (len + 63) / 64];
+        // The header is never allocated.
+        bits[0] |= 1;
+        // Neither are the bits past the end.
+        if len % 64 != 0 {
+            *bits.last_mut().unwrap() |= !0 << (len % 64);
+        }
+        Slots {
+            bits,
+            used: 0,
+            next: 0,
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let words = self.bits.len();
+        let index = ((self.next..words).chain(0..self.next)).find(|&w| self.bits[w] != !0)?;
+        let bit = self.bits[index].trailing_ones() as usize;
+        self.bits[index] |= 1 << bit;
+        self.used += 1;
+        self.next = index;
+        Some(index * 64 + bit)
+    }
+
+    fn free(&mut self, index: usize) {
+        let (word, bit) = (index / 64, index % 64);
+        debug_assert!(self.bits[word] & (1 << bit) != 0);
+        self.bits[word] &= !(1 << bit);
+        self.used -= 1;
+    }
+}
+
+pub(super) struct Area {
+    name: String,
+    partition: bool,
+    io: Arc<dyn Io>,
+    /// The number of the slots, including the header.
+    len: usize,
+    priority: isize,
+    slots: Mutex<Slots>,
+    /// Whether new slots can be allocated from the area.
+    active: AtomicBool,
+    /// Notified whenever a slot is freed.
+    pub(super) freed: Event,
+}
+
+impl Area {
+    pub(super) fn used(&self) -> usize {
+        ksync::critical(|| self.slots.lock().used)
+    }
+}
+
+/// The location of a slot in a swap area.
+#[derive(Clone)]
+pub(super) struct Location {
+    area: Arc<Area>,
+    index: usize,
+}
+
+impl PartialEq for Location {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.area, &other.area) && self.index == other.index
+    }
+}
+
+impl Location {
+    pub(super) fn is_in(&self, area: &Arc<Area>) -> bool {
+        Arc::ptr_eq(&self.area, area)
+    }
+
+    pub(super) async fn read(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        let offset = self.index << PAGE_SHIFT;
+        self.area.io.read_exact_at(offset, buffer).await
+    }
+
+    pub(super) async fn write(&self, buffer: &[u8]) -> Result<(), Error> {
+        let offset = self.index << PAGE_SHIFT;
+        self.area.io.write_all_at(offset, buffer).await
+    }
+}
+
+/// An allocated slot in a swap area, freed on drop.
+pub(super) struct Slot(Location);
+
+impl fmt::Debug for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Slot")
+            .field(&self.0.area.name)
+            .field(&self.0.index)
+            .finish()
+    }
+}
+
+impl Slot {
+    pub(super) fn location(&self) -> &Location {
+        &self.0
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let area = &self.0.area;
+        ksync::critical(|| area.slots.lock().free(self.0.index));
+        area.freed.notify(usize::MAX);
+    }
+}
+
+/// Returns whether there is an active area, without which the pages only in
+/// the memory can't be reclaimed.
+pub(crate) fn swap_active() -> bool {
+    ksync::critical(|| AREAS.lock().iter().any(|area| area.active.load(SeqCst)))
+}
+
+/// Allocates a slot from the active area with the highest priority.
+pub(super) fn alloc() -> Option<Slot> {
+    ksync::critical(|| {
+        let areas = AREAS.lock();
+        let mut iter = areas.iter().filter(|area| area.active.load(SeqCst));
+        iter.find_map(|area| {
+            let index = area.slots.lock().alloc()?;
+            Some(Slot(Location {
+                area: area.clone(),
+                index,
+            }))
+        })
+    })
+}
+
+/// The snapshot of a swap area.
+#[derive(Debug, Clone)]
+pub struct SwapInfo {
+    pub name: String,
+    /// Whether the area is a block device rather than a file.
+    pub partition: bool,
+    /// The number of the pages available for swapping.
+    pub pages: usize,
+    pub used: usize,
+    pub priority: isize,
+}
+
+/// Adds a swap area named `name` with `pages` pages in `io`, where the first
+/// page is the header and is never used.
+///
+/// The areas with higher priorities are used first, and the ones added
+/// without a priority get decreasing negative ones.
+pub fn swap_on(
+    name: String,
+    partition: bool,
+    io: Arc<dyn Io>,
+    pages: usize,
+    priority: Option<isize>,
+) -> Result<(), Error> {
+    if pages < 2 {
+        return Err(EINVAL);
+    }
+    ksync::critical(|| {
+        let mut areas = AREAS.lock();
+        if areas.iter().any(|area| area.name == name) {
+            return Err(EBUSY);
+        }
+        let priority = priority.unwrap_or_else(|| NEXT_PRIORITY.fetch_sub(1, SeqCst));
+        let area = Arc::new(Area {
+            name,
+            partition,
+            io,
+            len: pages,
+            priority,
+            slots: Mutex::new(Slots::new(pages)),
+            active: AtomicBool::new(true),
+            freed: Event::new(),
+        });
+        let index = areas.partition_point(|a| a.priority >= priority);
+        areas.insert(index, area);
+        Ok(())
+    })
+}
+
+/// Removes the swap area named `name`, swapping all its pages in.
+pub async fn swap_off(name: &str) -> Result<(), Error> {
+    let area = ksync::critical(|| {
+        let areas = AREAS.lock();
+        areas.iter().find(|area| area.name == name).cloned()
+    });
+    let area = area.ok_or(EINVAL)?;
+    if !area.active.swap(false, SeqCst) {
+        return Err(EBUSY);
+    }
+    if let Err(err) = super::reclaim::drain(&area).await {
+        area.active.store(true, SeqCst);
+        return Err(err);
+    }
+    ksync::critical(|| AREAS.lock().retain(|a| !Arc::ptr_eq(a, &area)));
+    Ok(())
+}
+
+/// Returns the snapshots of all the swap areas in the order they are used.
+pub fn swaps() -> Vec<SwapInfo> {
+    ksync::critical(|| {
+        let areas = AREAS.lock();
+        let iter = areas.iter().map(|area| SwapInfo {
+            name: area.name.clone(),
+            partition: area.partition,
+            pages: area.len - 1,
+            used: area.slots.lock().used,
+            priority: area.priority,
+        });
+        iter.collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Slots;
+
+    #[test]
+    fn test_slots() {
+        let mut slots = Slots::new(130);
+        let indices = (0..129).map(|_| slots.alloc().unwrap()).collect::<Vec<_>>();
+        assert_eq!(indices, (1..130).collect::<Vec<_>>());
+        assert_eq!(slots.alloc(), None);
+        assert_eq!(slots.used, 129);
+
+        slots.free(64);
+        slots.free(3);
+        assert_eq!(slots.alloc(), Some(3));
+        assert_eq!(slots.alloc(), Some(64));
+        assert_eq!(slots.alloc(), None);
+    }
+}
@@ -1,19 +1,23 @@
 mod tlb;
 
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, vec::Vec};
 use core::{
+    alloc::Layout,
     marker::PhantomPinned,
     mem,
     num::NonZeroUsize,
     ops::{Deref, DerefMut, Range},
-    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
 };
 
 use arsc_rs::Arsc;
-use ksc_core::Error::{self, EFAULT, EINVAL, ENOSPC, EPERM};
+use ksc_core::Error::{self, EACCES, EAGAIN, EFAULT, EINVAL, ENOMEM, ENOSPC, EPERM};
 use ksync::{Mutex, RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
+use rand_riscv::{rand_core::SeedableRng, Rng};
 use range_map::{AslrKey, RangeMap};
-use rv39_paging::{Attr, LAddr, Table, ID_OFFSET, PAGE_LAYOUT, PAGE_MASK, PAGE_SHIFT, PAGE_SIZE};
+use rv39_paging::{
+    Attr, LAddr, Level, Table, ID_OFFSET, PAGE_LAYOUT, PAGE_MASK, PAGE_SHIFT, PAGE_SIZE,
+};
 use static_assertions::const_assert_eq;
 
 pub use self::tlb::unset_virt;
@@ -21,10 +25,41 @@ use crate::{frame::frames, Frame, Phys};
 
 const ASLR_BIT: u32 = 30;
 
+/// The level of the large leaf entries that anonymous mappings are
+/// transparently committed with.
+const TRANSPARENT_LEVEL: Level = Level::new(1);
+
+/// The gap kept between a mapping growing down and the one beneath it.
+const GUARD_GAP: usize = 256 << PAGE_SHIFT;
+
 struct Mapping {
     phys: Arsc<Phys>,
     start_index: usize,
     attr: Attr,
+    /// The highest level of the leaf entries the mapping may be committed
+    /// with, if its aligned blocks are anonymous and untouched.
+    huge: Level,
+    /// Whether the pages are committed in advance and never aged.
+    locked: bool,
+    /// Whether the mapping is extended on faults right below it.
+    grows_down: bool,
+    /// Whether the mapping may never be made executable, such as the ones of
+    /// files on `noexec` mounts.
+    noexec: bool,
+    /// The frames mapped in the page table by their indices, held so that they
+    /// are not reclaimed.
+    frames: BTreeMap<usize, Arsc<Frame>>,
+}
+
+/// A snapshot of a mapping in an address space.
+#[derive(Debug, Clone)]
+pub struct MappingInfo {
+    pub range: Range<LAddr>,
+    pub attr: Attr,
+    /// The offset of the mapping in its backing object, in bytes.
+    pub offset: usize,
+    /// Whether the modifications are private to the mapping.
+    pub private: bool,
 }
 
 #[derive(Debug)]
@@ -124,9 +159,17 @@ impl<'a> VirtCommitGuard<'a> {
             let count = len >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = this.virt.cpu_mask.load(SeqCst);
+                let range = *addr.start..*addr.end;
                 mapping
-                    .commit(start, offset, count, table.as_table(), cpu_mask, this.attr)
+                    .commit(
+                        &range,
+                        start,
+                        offset,
+                        count,
+                        table.as_table(),
+                        this.virt,
+                        this.attr,
+                    )
                     .await?;
             }
         }
@@ -152,7 +195,22 @@ impl<'a> VirtCommitGuard<'a> {
 pub struct Virt {
     root: Mutex<Frame>,
     map: RwLock<RangeMap<LAddr, Mapping>>,
+    /// The harts that may cache the TLB entries of this address space.
     cpu_mask: AtomicUsize,
+    /// The hardware ASID tagged with its generation, allocated in `tlb`.
+    asid: AtomicUsize,
+    /// The address from which the pages are aged next.
+    hand: AtomicUsize,
+    /// The number of the pages mapped in the page table, which can be read
+    /// without locking the mappings.
+    resident: AtomicUsize,
+    /// Whether the mappings created from now on are locked.
+    lock_future: AtomicBool,
+    /// The maximum size of the locked mappings created from now on.
+    lock_limit: AtomicUsize,
+    /// Whether the addresses of the mappings allocated from now on are
+    /// randomized.
+    randomized: AtomicBool,
 
     _marker: PhantomPinned,
 }
@@ -160,25 +218,34 @@ pub struct Virt {
 unsafe impl Send for Virt {}
 unsafe impl Sync for Virt {}
 
-struct TlbFlushOnDrop {
-    cpu_mask: usize,
-    addr: LAddr,
-    count: usize,
+/// Collects the modified pages and flushes them in one range on drop.
+struct TlbFlushOnDrop<'a> {
+    virt: &'a Virt,
+    range: Option<Range<LAddr>>,
 }
 
-impl TlbFlushOnDrop {
-    fn new(cpu_mask: usize, addr: LAddr) -> Self {
-        TlbFlushOnDrop {
-            cpu_mask,
-            addr,
-            count: 0,
-        }
+impl<'a> TlbFlushOnDrop<'a> {
+    fn new(virt: &'a Virt) -> Self {
+        TlbFlushOnDrop { virt, range: None }
+    }
+
+    fn push(&mut self, addr: LAddr) {
+        self.push_range(addr..(addr + PAGE_SIZE))
+    }
+
+    fn push_range(&mut self, new: Range<LAddr>) {
+        self.range = Some(match self.range.take() {
+            Some(range) => range.start.min(new.start)..range.end.max(new.end),
+            None => new,
+        });
     }
 }
 
-impl Drop for TlbFlushOnDrop {
+impl Drop for TlbFlushOnDrop<'_> {
     fn drop(&mut self) {
-        tlb::flush(self.cpu_mask, self.addr, self.count)
+        if let Some(range) = self.range.take() {
+            tlb::flush(self.virt, range)
+        }
     }
 }
 
@@ -195,75 +262,301 @@ impl Mapping {
                 return Err(EPERM);
             }
 
-            if !table.la2pte(addr, ID_OFFSET).map_or(false, |e| e.is_set()) {
+            if !table
+                .la2leaf(addr, ID_OFFSET)
+                .map_or(false, |(e, _)| e.is_set())
+            {
                 return Ok(false);
             }
         }
         Ok(true)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn commit(
         &mut self,
+        range: &Range<LAddr>,
         addr: LAddr,
         offset: usize,
         count: NonZeroUsize,
         table: &mut Table,
-        cpu_mask: usize,
+        virt: &Virt,
         expect_attr: Attr,
     ) -> Result<(), Error> {
         let writable = self.attr.contains(Attr::WRITABLE);
 
-        let mut flush = TlbFlushOnDrop::new(cpu_mask, addr);
+        let mut flush = TlbFlushOnDrop::new(virt);
 
-        for (index, addr) in
-            (0..count.get()).map(|c| (c + self.start_index + offset, addr + (c << PAGE_SHIFT)))
-        {
+        let (start, end) = (addr, addr + (count.get() << PAGE_SHIFT));
+        let mut addr = addr;
+        while addr < end {
+            let index = self.start_index + offset + ((addr.val() - start.val()) >> PAGE_SHIFT);
             if !self.attr.contains(expect_attr | Attr::USER_ACCESS) {
                 return Err(EPERM);
             }
 
+            // Pages in large leaf entries are committed as a whole.
+            if let Ok((entry, level)) = table.la2leaf(addr, ID_OFFSET) {
+                if entry.is_set() {
+                    let block = LAddr::from(addr.val() & !level.page_mask());
+                    let next = block + level.page_size();
+                    // Aging cleared the accessed bit, which some harts fault on
+                    // instead of setting it.
+                    let (paddr, attr) = entry.get(level);
+                    if !attr.contains(Attr::ACCESSED) {
+                        *entry = rv39_paging::Entry::new(paddr, attr | Attr::ACCESSED, level);
+                        flush.push_range(block..next);
+                    }
+                    addr = next;
+                    continue;
+                }
+            }
+            if writable {
+                if let Some(next) = self.commit_huge(range, addr, table, virt, &mut flush)? {
+                    addr = next;
+                    continue;
+                }
+            }
+
             let entry = table.la2pte_alloc(addr, frames(), ID_OFFSET)?;
             if !entry.is_set() {
                 let writable = writable.then_some(PAGE_SIZE);
                 let (frame, _) = self.phys.commit(index, writable).await?;
                 let base = frame.base();
-                *entry = rv39_paging::Entry::new(base, self.attr, rv39_paging::Level::pt());
-                flush.count += 1;
+                *entry = rv39_paging::Entry::new(base, self.attr, Level::pt());
+                self.frames.insert(index, frame);
+                virt.resident.fetch_add(1, Relaxed);
+                flush.push(addr);
             }
+            addr += PAGE_SIZE;
         }
         Ok(())
     }
 
+    /// Commits the largest untouched block in the mapping `range` containing
+    /// `addr` with a large leaf entry, returning the end of the block.
+    fn commit_huge(
+        &mut self,
+        range: &Range<LAddr>,
+        addr: LAddr,
+        table: &mut Table,
+        virt: &Virt,
+        flush: &mut TlbFlushOnDrop<'_>,
+    ) -> Result<Option<LAddr>, Error> {
+        let mut level = self.huge;
+        while level > Level::pt() {
+            let start = LAddr::from(addr.val() & !level.page_mask());
+            let end = start + level.page_size();
+            if range.start <= start && end <= range.end {
+                let entry = table.la2pte_alloc_at(start, level, frames(), ID_OFFSET)?;
+                if !entry.is_set() {
+                    let count = NonZeroUsize::new(level.page_size() >> PAGE_SHIFT).unwrap();
+                    let index =
+                        self.start_index + ((start.val() - range.start.val()) >> PAGE_SHIFT);
+
+                    let committed = self.phys.commit_contiguous(index, count, level.page_size());
+                    if let Some(committed) = committed {
+                        let base = committed[0].base();
+                        *entry = rv39_paging::Entry::new(base, self.attr, level);
+                        self.frames.extend((index..).zip(committed));
+                        virt.resident.fetch_add(count.get(), Relaxed);
+                        flush.push_range(start..end);
+                        return Ok(Some(end));
+                    }
+                }
+            }
+            level = level.decrease().unwrap();
+        }
+        Ok(None)
+    }
+
     async fn decommit(
         &mut self,
         addr: LAddr,
         offset: usize,
         count: NonZeroUsize,
         table: &mut Table,
-        cpu_mask: usize,
+        virt: &Virt,
     ) -> Result<(), Error> {
-        let mut flush = TlbFlushOnDrop::new(cpu_mask, addr);
-
-        for (index, addr) in
-            (0..count.get()).map(|c| (c + self.start_index + offset, addr + (c << PAGE_SHIFT)))
-        {
-            if let Ok(entry) = table.la2pte(addr, ID_OFFSET) {
-                let dirty = entry.get(rv39_paging::Level::pt()).1.contains(Attr::DIRTY);
-                self.phys.flush(index, Some(dirty)).await?;
-                entry.reset();
-                flush.count += 1;
-            } else {
-                flush = TlbFlushOnDrop::new(cpu_mask, addr + PAGE_SIZE);
+        // The frames are released after the TLB entries are flushed.
+        let mut released = Vec::new();
+        let mut flush = TlbFlushOnDrop::new(virt);
+
+        let (start, end) = (addr, addr + (count.get() << PAGE_SHIFT));
+        let mut addr = addr;
+        while addr < end {
+            let index = self.start_index + offset + ((addr.val() - start.val()) >> PAGE_SHIFT);
+
+            // Large leaf entries are unmapped as a whole if they are entirely in
+            // the range, or split otherwise.
+            if let Ok((entry, level)) = table.la2leaf(addr, ID_OFFSET) {
+                let next = addr + level.page_size();
+                if level > Level::pt()
+                    && entry.is_set()
+                    && addr.val() & level.page_mask() == 0
+                    && next <= end
+                {
+                    let len = self
+                        .decommit_block(entry, level, addr, index, &mut flush, &mut released)
+                        .await?;
+                    virt.resident.fetch_sub(len, Relaxed);
+                    addr = next;
+                    continue;
+                }
             }
+            match table.la2pte_split(addr, frames(), ID_OFFSET) {
+                Ok(entry) => {
+                    let dirty = entry.get(Level::pt()).1.contains(Attr::DIRTY);
+                    self.phys.flush(index, Some(dirty)).await?;
+                    entry.reset();
+                    flush.push(addr);
+                }
+                Err(rv39_paging::Error::EntryExistent(false)) => {}
+                Err(err) => return Err(err.into()),
+            }
+            if let Some(frame) = self.frames.remove(&index) {
+                virt.resident.fetch_sub(1, Relaxed);
+                released.push(frame);
+            }
+            addr += PAGE_SIZE;
         }
         Ok(())
     }
 
+    /// Unmaps the large leaf `entry` at `level` mapping the block at `addr`,
+    /// whose first page is at `index`, returning the number of the frames
+    /// released.
+    async fn decommit_block(
+        &mut self,
+        entry: &mut rv39_paging::Entry,
+        level: Level,
+        addr: LAddr,
+        index: usize,
+        flush: &mut TlbFlushOnDrop<'_>,
+        released: &mut Vec<Arsc<Frame>>,
+    ) -> Result<usize, Error> {
+        let indices = index..(index + (level.page_size() >> PAGE_SHIFT));
+        let dirty = entry.get(level).1.contains(Attr::DIRTY);
+        for index in indices.clone() {
+            self.phys.flush(index, Some(dirty)).await?;
+        }
+        entry.reset();
+        flush.push_range(addr..(addr + level.page_size()));
+
+        let len = released.len();
+        released.extend(indices.filter_map(|index| self.frames.remove(&index)));
+        Ok(released.len() - len)
+    }
+
+    /// Visits the committed pages in `range` of the mapping starting at
+    /// `base`, at most `max` of them, clearing the accessed bits of the ones
+    /// used since the last visit and unmapping the others.
+    ///
+    /// Returns the number of the pages unmapped and the address next to the
+    /// last one visited. The pages unmapped are pushed to `written`, to be
+    /// written back after the locks are released. Large leaf entries are
+    /// visited as a whole.
+    #[allow(clippy::too_many_arguments)]
+    fn age(
+        &mut self,
+        base: LAddr,
+        range: Range<LAddr>,
+        max: usize,
+        table: &mut Table,
+        flush: &mut TlbFlushOnDrop<'_>,
+        released: &mut Vec<Arsc<Frame>>,
+        written: &mut Vec<(Arsc<Phys>, Range<usize>, bool)>,
+    ) -> (usize, Option<LAddr>) {
+        let index_of = |addr: LAddr| self.start_index + ((addr.val() - base.val()) >> PAGE_SHIFT);
+        let (start, end) = (index_of(range.start), index_of(range.end));
+        let indices = self.frames.range(start..end).map(|(&index, _)| index);
+        let indices = indices.take(max).collect::<Vec<_>>();
+
+        let mut aged = 0;
+        let mut next = None;
+        for &index in &indices {
+            // The page may have been unmapped along with its block.
+            if !self.frames.contains_key(&index) {
+                continue;
+            }
+            let addr = base + ((index - self.start_index) << PAGE_SHIFT);
+            let unmapped = match table.la2leaf(addr, ID_OFFSET) {
+                Ok((entry, level)) if entry.is_set() => {
+                    let block = LAddr::from(addr.val() & !level.page_mask());
+                    let first = index - ((addr.val() - block.val()) >> PAGE_SHIFT);
+                    let block = block..(block + level.page_size());
+                    next = Some(block.end);
+
+                    let (paddr, attr) = entry.get(level);
+                    flush.push_range(block);
+                    if attr.contains(Attr::ACCESSED) {
+                        *entry = rv39_paging::Entry::new(paddr, attr - Attr::ACCESSED, level);
+                        continue;
+                    }
+                    entry.reset();
+
+                    let unmapped = first..(first + (level.page_size() >> PAGE_SHIFT));
+                    let dirty = attr.contains(Attr::DIRTY);
+                    written.push((self.phys.clone(), unmapped.clone(), dirty));
+                    unmapped
+                }
+                _ => {
+                    next = Some(addr + PAGE_SIZE);
+                    index..(index + 1)
+                }
+            };
+            let len = released.len();
+            released.extend(unmapped.filter_map(|index| self.frames.remove(&index)));
+            aged += released.len() - len;
+        }
+        (aged, next)
+    }
+
     fn deep_fork(&self) -> Mapping {
         Mapping {
             phys: Arsc::new(self.phys.clone_as(self.phys.is_cow(), 0, None)),
             start_index: self.start_index,
             attr: self.attr,
+            huge: self.huge,
+            // Locks are not inherited by the child.
+            locked: false,
+            grows_down: self.grows_down,
+            noexec: self.noexec,
+            frames: BTreeMap::new(),
+        }
+    }
+
+    /// Splits the mapping at `offset` pages in two, returning the latter one.
+    fn split_off(&mut self, offset: usize) -> Mapping {
+        let start_index = self.start_index + offset;
+        Mapping {
+            phys: self.phys.clone(),
+            start_index,
+            attr: self.attr,
+            huge: self.huge,
+            locked: self.locked,
+            grows_down: self.grows_down,
+            noexec: self.noexec,
+            frames: self.frames.split_off(&start_index),
+        }
+    }
+
+    /// Commits all the pages of the mapping at `range`.
+    async fn populate(
+        &mut self,
+        range: Range<LAddr>,
+        table: &mut Table,
+        virt: &Virt,
+    ) -> Result<(), Error> {
+        let count = (range.end.val() - range.start.val()) >> PAGE_SHIFT;
+        match NonZeroUsize::new(count) {
+            // Inaccessible pages cannot be mapped with leaf entries.
+            Some(count) if !self.attr.has_table() => {
+                let start = range.start;
+                let commit = self.commit(&range, start, 0, count, table, virt, Attr::empty());
+                commit.await
+            }
+            _ => Ok(()),
         }
     }
 }
@@ -274,10 +567,34 @@ impl Virt {
             root: Mutex::new(init_root.into()),
             map: RwLock::new(RangeMap::new(range)),
             cpu_mask: AtomicUsize::new(0),
+            asid: AtomicUsize::new(0),
+            hand: AtomicUsize::new(0),
+            resident: AtomicUsize::new(0),
+            lock_future: AtomicBool::new(false),
+            lock_limit: AtomicUsize::new(usize::MAX),
+            randomized: AtomicBool::new(true),
             _marker: PhantomPinned,
         })
     }
 
+    /// Sets whether the addresses of the mappings allocated from now on are
+    /// randomized, or chosen in the same order for every address space
+    /// otherwise.
+    pub fn set_randomized(&self, randomized: bool) {
+        self.randomized.store(randomized, Relaxed);
+    }
+
+    fn aslr_key(&self, layout: Layout) -> AslrKey<Rng> {
+        // A fixed seed still spreads the mappings out instead of packing them
+        // at the lowest addresses, where the heap is placed.
+        let rng = if self.randomized.load(Relaxed) {
+            rand_riscv::rng()
+        } else {
+            Rng::seed_from_u64(0)
+        };
+        AslrKey::new(ASLR_BIT, rng, layout)
+    }
+
     /// # Safety
     ///
     /// The caller must ensure that the current executing address is mapped
@@ -294,13 +611,75 @@ impl Virt {
         start_index: usize,
         count: usize,
         attr: Attr,
+    ) -> Result<LAddr, Error> {
+        // Large anonymous mappings are aligned so that they can be committed
+        // with large leaf entries.
+        let align = if phys.is_anonymous() && (count << PAGE_SHIFT) >= TRANSPARENT_LEVEL.page_size()
+        {
+            TRANSPARENT_LEVEL.page_size()
+        } else {
+            PAGE_SIZE
+        };
+        self.map_impl(
+            addr,
+            phys,
+            start_index,
+            count,
+            attr,
+            TRANSPARENT_LEVEL,
+            align,
+        )
+        .await
+    }
+
+    /// Maps `phys` preferring the large leaf entries at `level`, whose page
+    /// size the address and the length are aligned to.
+    ///
+    /// The pages fall back to smaller entries if no contiguous frames are
+    /// available.
+    pub async fn map_huge(
+        &self,
+        addr: Option<LAddr>,
+        phys: Phys,
+        start_index: usize,
+        count: usize,
+        attr: Attr,
+        level: Level,
+    ) -> Result<LAddr, Error> {
+        let align = level.page_size();
+        if addr.map_or(false, |addr| addr.val() & (align - 1) != 0)
+            || (count << PAGE_SHIFT) & (align - 1) != 0
+        {
+            return Err(EINVAL);
+        }
+        self.map_impl(addr, phys, start_index, count, attr, level, align)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn map_impl(
+        &self,
+        addr: Option<LAddr>,
+        phys: Phys,
+        start_index: usize,
+        count: usize,
+        attr: Attr,
+        huge: Level,
+        align: usize,
     ) -> Result<LAddr, Error> {
         log::trace!(
             "Virt::map at {addr:?}, start_index = {start_index}, count = {count}, attr = {attr:?}"
         );
 
         let mut map = self.map.write().await;
-        match addr {
+        let locked = self.lock_future.load(Relaxed);
+        if locked && !attr.has_table() {
+            let len = Self::locked_len(&map).saturating_add(count.saturating_mul(PAGE_SIZE));
+            if len > self.lock_limit.load(Relaxed) {
+                return Err(EAGAIN);
+            }
+        }
+        let range = match addr {
             Some(start) => {
                 if start.val() & PAGE_MASK != 0 {
                     return Err(EINVAL);
@@ -314,26 +693,44 @@ impl Virt {
                     phys: Arsc::new(phys),
                     start_index,
                     attr: attr | Attr::VALID,
+                    huge,
+                    locked,
+                    grows_down: false,
+                    noexec: false,
+                    frames: BTreeMap::new(),
                 };
                 log::trace!("Virt::map result = {start:?}..{end:?}");
                 map.try_insert(start..end, mapping).map_err(|_| ENOSPC)?;
-                Ok(start)
+                start..end
             }
             None => {
-                let layout = PAGE_LAYOUT.repeat(count)?.0;
-                let aslr_key = AslrKey::new(ASLR_BIT, rand_riscv::rng(), layout);
+                let layout = PAGE_LAYOUT.repeat(count)?.0.align_to(align)?;
+                let aslr_key = self.aslr_key(layout);
 
                 let ent = map.allocate_with_aslr(aslr_key, LAddr::val).ok_or(ENOSPC)?;
-                let addr = *ent.key().start;
-                log::trace!("Virt::map result = {:?}", ent.key());
+                let range = *ent.key().start..*ent.key().end;
+                log::trace!("Virt::map result = {range:?}");
                 ent.insert(Mapping {
                     phys: Arsc::new(phys),
                     start_index,
                     attr: attr | Attr::VALID,
+                    huge,
+                    locked,
+                    grows_down: false,
+                    noexec: false,
+                    frames: BTreeMap::new(),
                 });
-                Ok(addr)
+                range
             }
+        };
+
+        if locked {
+            let mut table = self.root.lock().await;
+            let populate =
+                self.populate_added(&mut map, table.as_table(), range.clone(), range.clone());
+            populate.await?;
         }
+        Ok(range.start)
     }
 
     pub async fn find_free(
@@ -342,7 +739,7 @@ impl Virt {
         count: usize,
     ) -> Result<Range<LAddr>, Error> {
         let layout = PAGE_LAYOUT.repeat(count)?.0;
-        let aslr_key = AslrKey::new(ASLR_BIT, rand_riscv::rng(), layout);
+        let aslr_key = self.aslr_key(layout);
 
         let map = self.map.read().await;
         match start {
@@ -373,6 +770,9 @@ impl Virt {
         let mut map = self.map.write().await;
         let mut table = self.root.lock().await;
 
+        if !map.intersects(aligned_range.clone()) {
+            Self::grow_down(&mut map, aligned_range.start)?;
+        }
         if let Some((addr, mapping)) = map.intersection_mut(aligned_range.clone()).next() {
             log::trace!("Virt::commit found {addr:?}");
             let start = aligned_range.start.max(*addr.start);
@@ -382,14 +782,15 @@ impl Virt {
             let count = len >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
+                let range = *addr.start..*addr.end;
                 mapping
                     .commit(
+                        &range,
                         start,
                         offset,
                         count,
                         table.as_table(),
-                        cpu_mask,
+                        self,
                         expect_attr,
                     )
                     .await?;
@@ -413,9 +814,8 @@ impl Virt {
             let count = (end.val() - start.val()) >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
                 mapping
-                    .decommit(start, offset, count, table.as_table(), cpu_mask)
+                    .decommit(start, offset, count, table.as_table(), self)
                     .await?;
             }
         }
@@ -431,15 +831,21 @@ impl Virt {
         let attr = attr | Attr::VALID;
 
         let mut map = self.map.write().await;
+        if attr.contains(Attr::EXECUTABLE)
+            && map
+                .intersection(range.clone())
+                .any(|(_, mapping)| mapping.noexec)
+        {
+            return Err(EACCES);
+        }
         let mut table = self.root.lock().await;
 
         for (addr, mapping) in map.range_mut(range.clone()) {
             let count = (addr.end.val() - addr.start.val()) >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
                 mapping
-                    .decommit(*addr.start, 0, count, table.as_table(), cpu_mask)
+                    .decommit(*addr.start, 0, count, table.as_table(), self)
                     .await?;
             }
             mapping.attr = attr;
@@ -451,9 +857,8 @@ impl Virt {
             let count = (addr.end.val() - range.start.val()) >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
                 mapping
-                    .decommit(range.start, offset, count, table.as_table(), cpu_mask)
+                    .decommit(range.start, offset, count, table.as_table(), self)
                     .await?;
             }
 
@@ -461,6 +866,11 @@ impl Virt {
                 phys: mapping.phys.clone(),
                 start_index: mapping.start_index + offset,
                 attr,
+                huge: mapping.huge,
+                locked: mapping.locked,
+                grows_down: mapping.grows_down,
+                noexec: mapping.noexec,
+                frames: BTreeMap::new(),
             };
 
             entry.set_former(mapping);
@@ -471,9 +881,8 @@ impl Virt {
             let count = (range.end.val() - addr.start.val()) >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
                 mapping
-                    .decommit(range.end, 0, count, table.as_table(), cpu_mask)
+                    .decommit(range.end, 0, count, table.as_table(), self)
                     .await?;
             }
 
@@ -481,6 +890,11 @@ impl Virt {
                 phys: mapping.phys.clone(),
                 start_index: mapping.start_index,
                 attr,
+                huge: mapping.huge,
+                locked: mapping.locked,
+                grows_down: mapping.grows_down,
+                noexec: mapping.noexec,
+                frames: BTreeMap::new(),
             };
 
             mapping.start_index += count;
@@ -498,19 +912,41 @@ impl Virt {
         }
         let mut map = self.map.write().await;
         let mut table = self.root.lock().await;
+        self.unmap_locked(&mut map, table.as_table(), range).await
+    }
 
+    /// Commits the mapping at `range` at once if it is locked, and takes the
+    /// pages `added` to it back if that fails.
+    async fn populate_added(
+        &self,
+        map: &mut RangeMap<LAddr, Mapping>,
+        table: &mut Table,
+        range: Range<LAddr>,
+        added: Range<LAddr>,
+    ) -> Result<(), Error> {
+        let mapping = map.get_mut(&range.start).unwrap();
+        if !mapping.locked {
+            return Ok(());
+        }
+        match mapping.populate(range, table, self).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                self.unmap_locked(map, table, added).await?;
+                Err(err)
+            }
+        }
+    }
+
+    async fn unmap_locked(
+        &self,
+        map: &mut RangeMap<LAddr, Mapping>,
+        table: &mut Table,
+        range: Range<LAddr>,
+    ) -> Result<(), Error> {
         for (addr, mut mapping) in map.drain(range.clone()) {
             let count = (addr.end.val() - addr.start.val()) >> PAGE_SHIFT;
             if let Some(count) = NonZeroUsize::new(count) {
-                mapping
-                    .decommit(
-                        addr.start,
-                        0,
-                        count,
-                        table.as_table(),
-                        self.cpu_mask.load(SeqCst),
-                    )
-                    .await?;
+                mapping.decommit(addr.start, 0, count, table, self).await?;
             }
         }
 
@@ -520,9 +956,8 @@ impl Virt {
             let count = (addr.end.val() - range.start.val()) >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
                 mapping
-                    .decommit(range.start, offset, count, table.as_table(), cpu_mask)
+                    .decommit(range.start, offset, count, table, self)
                     .await?;
             }
             entry.set_former(mapping);
@@ -532,10 +967,7 @@ impl Virt {
             let count = (range.end.val() - addr.start.val()) >> PAGE_SHIFT;
 
             if let Some(count) = NonZeroUsize::new(count) {
-                let cpu_mask = self.cpu_mask.load(SeqCst);
-                mapping
-                    .decommit(range.end, 0, count, table.as_table(), cpu_mask)
-                    .await?;
+                mapping.decommit(range.end, 0, count, table, self).await?;
             }
             mapping.start_index += count;
             entry.set_latter(mapping);
@@ -543,6 +975,378 @@ impl Virt {
         Ok(())
     }
 
+    /// Splits the mapping across `addr` in two without unmapping any page, so
+    /// that no large leaf entry is shared by both of them.
+    fn split(
+        map: &mut RangeMap<LAddr, Mapping>,
+        table: &mut Table,
+        addr: LAddr,
+    ) -> Result<(), Error> {
+        if let Ok((entry, level)) = table.la2leaf(addr, ID_OFFSET) {
+            if level > Level::pt() && entry.is_set() && addr.val() & level.page_mask() != 0 {
+                table.la2pte_split(addr, frames(), ID_OFFSET)?;
+            }
+        }
+        if let Some((mut mapping, mut entry)) = map.split_entry(addr) {
+            let offset = (addr.val() - entry.old_key().start.val()) >> PAGE_SHIFT;
+            let latter = mapping.split_off(offset);
+            entry.set_former(mapping);
+            entry.set_latter(latter);
+        }
+        Ok(())
+    }
+
+    /// Returns whether every page in `range` is mapped.
+    fn is_covered(map: &RangeMap<LAddr, Mapping>, range: Range<LAddr>) -> bool {
+        let len = map.intersection(range.clone()).fold(0, |len, (addr, _)| {
+            len + (range.end.min(*addr.end).val() - range.start.max(*addr.start).val())
+        });
+        len == range.end.val() - range.start.val()
+    }
+
+    /// Returns the size of the pages in `range` whose mappings satisfy
+    /// `predicate`, leaving out the inaccessible ones, which are never
+    /// committed.
+    fn len_of(
+        map: &RangeMap<LAddr, Mapping>,
+        range: Range<LAddr>,
+        predicate: impl Fn(&Mapping) -> bool,
+    ) -> usize {
+        let iter = map
+            .intersection(range.clone())
+            .filter(|(_, m)| !m.attr.has_table() && predicate(m));
+        iter.fold(0, |len, (addr, _)| {
+            len + (range.end.min(*addr.end).val() - range.start.max(*addr.start).val())
+        })
+    }
+
+    /// Returns the size of all the locked pages.
+    fn locked_len(map: &RangeMap<LAddr, Mapping>) -> usize {
+        let root = map.root_range();
+        Self::len_of(map, *root.start..*root.end, |mapping| mapping.locked)
+    }
+
+    /// Extends the mapping growing down right above `addr` to cover it, as
+    /// long as the guard gap beneath is kept.
+    ///
+    /// The pages below the original mapping are backed by a new anonymous
+    /// object.
+    fn grow_down(map: &mut RangeMap<LAddr, Mapping>, addr: LAddr) -> Result<(), Error> {
+        let root = map.root_range();
+        let (root_start, root_end) = (*root.start, *root.end);
+        let (start, mapping) = match map.range(addr..root_end).next() {
+            Some((key, mapping)) if mapping.grows_down => (*key.start, mapping),
+            _ => return Err(EFAULT),
+        };
+        let gap = LAddr::from(addr.val().saturating_sub(GUARD_GAP)).max(root_start);
+        if map.intersects(gap..addr) {
+            return Err(EFAULT);
+        }
+        log::trace!("Virt::grow_down {start:?} => {addr:?}");
+
+        let below = Mapping {
+            phys: Arsc::new(Phys::new(mapping.phys.is_cow())),
+            start_index: 0,
+            attr: mapping.attr,
+            huge: mapping.huge,
+            locked: mapping.locked,
+            grows_down: true,
+            noexec: mapping.noexec,
+            frames: BTreeMap::new(),
+        };
+        map.try_insert(addr..start, below).map_err(|_| EFAULT)
+    }
+
+    /// Lets the mapping starting at `start` grow down to the addresses faulted
+    /// right below it.
+    pub async fn set_grows_down(&self, start: LAddr) -> Result<(), Error> {
+        let mut map = self.map.write().await;
+        let mapping = map.get_mut(&start).ok_or(EINVAL)?;
+        mapping.grows_down = true;
+        Ok(())
+    }
+
+    /// Forbids the mapping starting at `start` from being made executable.
+    pub async fn set_noexec(&self, start: LAddr) -> Result<(), Error> {
+        let mut map = self.map.write().await;
+        let mapping = map.get_mut(&start).ok_or(EINVAL)?;
+        mapping.noexec = true;
+        Ok(())
+    }
+
+    /// Resizes the pages in `range`, which must be in one mapping, and returns
+    /// their new address.
+    ///
+    /// The pages grow in place if possible, and are moved otherwise if
+    /// `may_move` is set, or to `fixed` if specified. The pages moved keep
+    /// their backing objects, and are committed again on faults.
+    pub async fn remap(
+        &self,
+        range: Range<LAddr>,
+        new_count: usize,
+        may_move: bool,
+        fixed: Option<LAddr>,
+    ) -> Result<LAddr, Error> {
+        log::trace!("Virt::remap {range:?}, new_count = {new_count}, fixed = {fixed:?}");
+
+        if range.start.val() & PAGE_MASK != 0
+            || range.end.val() & PAGE_MASK != 0
+            || range.start >= range.end
+            || fixed.map_or(false, |fixed| fixed.val() & PAGE_MASK != 0)
+        {
+            return Err(EINVAL);
+        }
+        let new_len = new_count
+            .checked_shl(PAGE_SHIFT)
+            .filter(|&l| l != 0)
+            .ok_or(EINVAL)?;
+        let old_len = range.end.val() - range.start.val();
+
+        let mut map = self.map.write().await;
+        let mut table = self.root.lock().await;
+
+        let key = match map
+            .intersection(range.start..(range.start + PAGE_SIZE))
+            .next()
+        {
+            Some((key, _)) if range.end <= *key.end => *key.start..*key.end,
+            _ => return Err(EFAULT),
+        };
+        if map.get(&key.start).map_or(false, |mapping| mapping.locked) && new_len > old_len {
+            let len = Self::locked_len(&map) + (new_len - old_len);
+            if len > self.lock_limit.load(Relaxed) {
+                return Err(EAGAIN);
+            }
+        }
+
+        if fixed.is_none() {
+            if new_len <= old_len {
+                let tail = (range.start + new_len)..range.end;
+                if tail.start < tail.end {
+                    self.unmap_locked(&mut map, table.as_table(), tail).await?;
+                }
+                return Ok(range.start);
+            }
+            let end = LAddr::from(range.start.val().checked_add(new_len).ok_or(EINVAL)?);
+            if range.end == key.end
+                && end <= *map.root_range().end
+                && !map.intersects(range.end..end)
+            {
+                let (_, mapping) = map.remove_entry(&key.start).unwrap();
+                map.try_insert(key.start..end, mapping)
+                    .map_err(|_| ENOMEM)?;
+                let (whole, added) = (key.start..end, range.end..end);
+                let populate = self.populate_added(&mut map, table.as_table(), whole, added);
+                populate.await?;
+                return Ok(range.start);
+            }
+            if !may_move {
+                return Err(ENOMEM);
+            }
+        }
+
+        let dest = match fixed {
+            Some(dest) => {
+                let end = LAddr::from(dest.val().checked_add(new_len).ok_or(EINVAL)?);
+                if dest < range.end && range.start < end {
+                    return Err(EINVAL);
+                }
+                self.unmap_locked(&mut map, table.as_table(), dest..end)
+                    .await?;
+                dest
+            }
+            None => {
+                let layout = PAGE_LAYOUT.repeat(new_count)?.0;
+                let aslr_key = self.aslr_key(layout);
+                let free = map.find_free_with_aslr(aslr_key, LAddr::val);
+                free.ok_or(ENOMEM)?.start
+            }
+        };
+
+        Self::split(&mut map, table.as_table(), range.start)?;
+        Self::split(&mut map, table.as_table(), range.end)?;
+        let (_, mut mapping) = map.remove_entry(&range.start).ok_or(EFAULT)?;
+        if let Some(count) = NonZeroUsize::new(old_len >> PAGE_SHIFT) {
+            mapping
+                .decommit(range.start, 0, count, table.as_table(), self)
+                .await?;
+        }
+        let dest = dest..(dest + new_len);
+        map.try_insert(dest.clone(), mapping).map_err(|_| ENOMEM)?;
+        let populate = self.populate_added(&mut map, table.as_table(), dest.clone(), dest.clone());
+        populate.await?;
+        Ok(dest.start)
+    }
+
+    /// Returns whether each page in `range` is mapped in the page table.
+    pub async fn residency(&self, range: Range<LAddr>) -> Result<Vec<bool>, Error> {
+        if range.start.val() & PAGE_MASK != 0 {
+            return Err(EINVAL);
+        }
+        let end = LAddr::from((range.end.val() + PAGE_MASK) & !PAGE_MASK);
+
+        let map = self.map.read().await;
+        let mut table = self.root.lock().await;
+        if !Self::is_covered(&map, range.start..end) {
+            return Err(ENOMEM);
+        }
+        let iter = (range.start.val()..end.val()).step_by(PAGE_SIZE);
+        let iter = iter.map(|addr| {
+            let leaf = table.as_table().la2leaf(addr.into(), ID_OFFSET);
+            leaf.map_or(false, |(entry, _)| entry.is_set())
+        });
+        Ok(iter.collect())
+    }
+
+    /// Locks or unlocks the pages in `range`. The pages locked are committed
+    /// at once and never aged.
+    ///
+    /// Locking fails if the locked pages would exceed `limit` bytes.
+    pub async fn lock(&self, range: Range<LAddr>, locked: bool, limit: usize) -> Result<(), Error> {
+        log::trace!("Virt::lock {range:?}, locked = {locked}");
+
+        if range.start.val() & PAGE_MASK != 0 || range.end.val() & PAGE_MASK != 0 {
+            return Err(EINVAL);
+        }
+        let mut map = self.map.write().await;
+        let mut table = self.root.lock().await;
+        if !Self::is_covered(&map, range.clone()) {
+            return Err(ENOMEM);
+        }
+        if locked {
+            let unlocked = Self::len_of(&map, range.clone(), |mapping| !mapping.locked);
+            if Self::locked_len(&map) + unlocked > limit {
+                return Err(ENOMEM);
+            }
+        }
+
+        Self::split(&mut map, table.as_table(), range.start)?;
+        Self::split(&mut map, table.as_table(), range.end)?;
+        for (addr, mapping) in map.range_mut(range) {
+            mapping.locked = locked;
+            if locked {
+                let range = *addr.start..*addr.end;
+                mapping.populate(range, table.as_table(), self).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Locks all the current mappings if `current` is set, and the ones
+    /// created from now on if `future` is set, as long as the locked pages
+    /// don't exceed `limit` bytes.
+    pub async fn lock_all(&self, current: bool, future: bool, limit: usize) -> Result<(), Error> {
+        let mut map = self.map.write().await;
+        let mut table = self.root.lock().await;
+
+        if current {
+            let root = map.root_range();
+            if Self::len_of(&map, *root.start..*root.end, |_| true) > limit {
+                return Err(ENOMEM);
+            }
+        }
+        if future {
+            self.lock_limit.store(limit, Relaxed);
+            self.lock_future.store(true, Relaxed);
+        }
+        if current {
+            for (addr, mapping) in map.iter_mut() {
+                mapping.locked = true;
+                let range = *addr.start..*addr.end;
+                mapping.populate(range, table.as_table(), self).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn unlock_all(&self) {
+        let mut map = self.map.write().await;
+        self.lock_future.store(false, Relaxed);
+        map.iter_mut()
+            .for_each(|(_, mapping)| mapping.locked = false);
+    }
+
+    /// Returns the snapshots of all the mappings in ascending order.
+    pub async fn mappings(&self) -> Vec<MappingInfo> {
+        let map = self.map.read().await;
+        let iter = map.iter().map(|(range, mapping)| MappingInfo {
+            range: *range.start..*range.end,
+            attr: mapping.attr,
+            offset: mapping.start_index << PAGE_SHIFT,
+            private: mapping.phys.is_cow(),
+        });
+        iter.collect()
+    }
+
+    /// Returns the number of the pages mapped in the page table, without
+    /// waiting for the mappings to be available.
+    pub fn resident(&self) -> usize {
+        self.resident.load(Relaxed)
+    }
+
+    /// Unmaps at most `count` committed pages, continuing from where the last
+    /// call stopped, and returns the number of them.
+    ///
+    /// The pages accessed since the last visit are only marked as not accessed
+    /// and kept this time. The frames unmapped may be reclaimed once they are
+    /// used nowhere else, and the pages accessed again are committed back
+    /// on faults.
+    pub async fn age(&self, count: usize) -> usize {
+        let swap_active = crate::phys::swap_active();
+        let mut released = Vec::new();
+        let mut written = Vec::new();
+        let mut map = self.map.write().await;
+        let mut table = self.root.lock().await;
+        let mut flush = TlbFlushOnDrop::new(self);
+
+        let root = map.root_range();
+        let (start, end) = (*root.start, *root.end);
+        let hand = LAddr::from(self.hand.load(Relaxed)).clamp(start, end);
+
+        let mut aged = 0;
+        // The pages after the hand are visited first, and then the ones before.
+        for range in [hand..end, start..hand] {
+            for (addr, mapping) in map.intersection_mut(range.clone()) {
+                if aged >= count {
+                    break;
+                }
+                if mapping.locked {
+                    continue;
+                }
+                // Private pages can only be written out to swap areas.
+                let private = mapping.phys.is_anonymous() || mapping.phys.is_cow();
+                if private && !swap_active {
+                    continue;
+                }
+                let range = range.start.max(*addr.start)..range.end.min(*addr.end);
+                let (len, next) = mapping.age(
+                    *addr.start,
+                    range,
+                    count - aged,
+                    table.as_table(),
+                    &mut flush,
+                    &mut released,
+                    &mut written,
+                );
+                aged += len;
+                self.resident.fetch_sub(len, Relaxed);
+                if let Some(next) = next {
+                    self.hand.store(next.val(), Relaxed);
+                }
+            }
+        }
+        // The pages are written back after they are unmapped from all the harts,
+        // without blocking the faults on the address space.
+        drop(flush);
+        drop((table, map));
+        for (phys, indices, dirty) in written {
+            for index in indices {
+                let _ = phys.flush(index, Some(dirty)).await;
+            }
+        }
+        aged
+    }
+
     pub async fn clear(&self) {
         log::trace!("Virt::clear table = {:p}", self.root.as_ptr());
 
@@ -552,10 +1356,10 @@ impl Virt {
         let range = map.root_range();
         let range = *range.start..*range.end;
         let old = mem::replace(&mut *map, RangeMap::new(range.clone()));
+        self.resident.store(0, Relaxed);
 
-        let count = (range.end.val() - range.start.val()) >> PAGE_SHIFT;
         table.as_table().unmap(range.clone(), frames(), ID_OFFSET);
-        tlb::flush(self.cpu_mask.load(SeqCst), range.start, count);
+        tlb::flush(self, range.clone());
 
         for (addr, mapping) in old {
             let count: usize = (addr.end.val() - addr.start.val()) >> PAGE_SHIFT;
@@ -578,9 +1382,8 @@ impl Virt {
             if mapping.attr.contains(Attr::WRITABLE) {
                 let count = (addr.end.val() - addr.start.val()) >> PAGE_SHIFT;
                 if let Some(count) = NonZeroUsize::new(count) {
-                    let cpu_mask = self.cpu_mask.load(SeqCst);
                     mapping
-                        .decommit(*addr.start, 0, count, table.as_table(), cpu_mask)
+                        .decommit(*addr.start, 0, count, table.as_table(), self)
                         .await?;
                 }
             }
@@ -592,6 +1395,12 @@ impl Virt {
             root: Mutex::new(init_root.into()),
             map: RwLock::new(new_map),
             cpu_mask: AtomicUsize::new(0),
+            asid: AtomicUsize::new(0),
+            hand: AtomicUsize::new(0),
+            resident: AtomicUsize::new(0),
+            lock_future: AtomicBool::new(false),
+            lock_limit: AtomicUsize::new(usize::MAX),
+            randomized: AtomicBool::new(self.randomized.load(Relaxed)),
             _marker: PhantomPinned,
         }))
     }
@@ -602,11 +1411,11 @@ impl Drop for Virt {
         log::trace!("Virt::drop table = {:p}", self.root.as_ptr());
 
         let range = self.map.get_mut().root_range();
-        let count = (range.end.val() - range.start.val()) >> PAGE_SHIFT;
+        let range = *range.start..*range.end;
         self.root
             .get_mut()
             .as_table()
-            .unmap(*range.start..*range.end, frames(), ID_OFFSET);
-        tlb::flush(self.cpu_mask.load(SeqCst), *range.start, count);
+            .unmap(range.clone(), frames(), ID_OFFSET);
+        tlb::flush(self, range);
     }
 }
@@ -1,5 +1,6 @@
 use core::{
     mem,
+    ops::Range,
     ptr::{self, NonNull},
     sync::atomic::Ordering::SeqCst,
 };
@@ -9,31 +10,168 @@ use riscv::{
     asm::{sfence_vma, sfence_vma_all},
     register::{satp, satp::Mode::Sv39},
 };
-use rv39_paging::{LAddr, PAddr, ID_OFFSET, PAGE_SHIFT};
+use rv39_paging::{LAddr, PAddr, ID_OFFSET, PAGE_SHIFT, PAGE_SIZE};
+use spin::{Mutex, Once};
 
 use crate::Virt;
 
+/// The width of the ASID field in `satp` of Sv39.
+const ASID_BITS: u32 = 16;
+const ASID_MASK: usize = (1 << ASID_BITS) - 1;
+
+/// The maximum number of pages flushed one by one, beyond which the whole
+/// address space is flushed instead.
+const FLUSH_THRESHOLD: usize = 32;
+
+const MAX_HARTS: usize = usize::BITS as usize;
+
+/// The allocator of hardware address space identifiers.
+///
+/// ASIDs are tagged with the generations they are allocated in, and handed out
+/// sequentially. When they run out, a new generation begins: the ASIDs active
+/// on each hart are kept, and every hart flushes its entire TLB before loading
+/// another address space.
+struct Asids {
+    /// The largest available ASID, or 0 if the hardware does not support them.
+    max: usize,
+    generation: usize,
+    next: usize,
+    /// The tagged ASIDs active on each hart, or 0 if the hart is running with
+    /// the kernel page table.
+    active: [usize; MAX_HARTS],
+    /// The tagged ASIDs active when the current generation began.
+    reserved: [usize; MAX_HARTS],
+    /// The harts that should flush their entire TLBs before loading an address
+    /// space.
+    stale: usize,
+}
+
+impl Asids {
+    fn new(max: usize) -> Self {
+        Asids {
+            // Too few ASIDs cannot even cover the active ones.
+            max: if max < MAX_HARTS * 2 { 0 } else { max },
+            generation: 1,
+            next: 1,
+            active: [0; MAX_HARTS],
+            reserved: [0; MAX_HARTS],
+            stale: 0,
+        }
+    }
+
+    fn rollover(&mut self) {
+        self.generation += 1;
+        self.next = 1;
+        self.reserved = self.active;
+        self.stale = usize::MAX;
+    }
+
+    fn alloc(&mut self) -> usize {
+        loop {
+            if self.next > self.max {
+                self.rollover();
+            }
+            let asid = self.next;
+            self.next += 1;
+            if !self.reserved.iter().any(|&r| r & ASID_MASK == asid) {
+                break (self.generation << ASID_BITS) | asid;
+            }
+        }
+    }
+
+    /// Returns the tagged ASID for an address space with `cur` as its old one
+    /// to be loaded on `hart`, and whether the hart should flush its entire
+    /// TLB first.
+    fn activate(&mut self, hart: usize, cur: usize) -> (usize, bool) {
+        if self.max == 0 {
+            return (0, true);
+        }
+        let asid = if cur >> ASID_BITS == self.generation {
+            cur
+        } else if cur != 0 && self.reserved.contains(&cur) {
+            (self.generation << ASID_BITS) | (cur & ASID_MASK)
+        } else {
+            self.alloc()
+        };
+        self.active[hart] = asid;
+        let stale = mem::replace(&mut self.stale, self.stale & !(1 << hart));
+        (asid, stale & (1 << hart) != 0)
+    }
+
+    /// Marks `hart` as running with the kernel page table, returning whether
+    /// the hart should flush its entire TLB.
+    fn deactivate(&mut self, hart: usize) -> bool {
+        if self.max == 0 {
+            return true;
+        }
+        self.active[hart] = 0;
+        let stale = mem::replace(&mut self.stale, self.stale & !(1 << hart));
+        stale & (1 << hart) != 0
+    }
+}
+
+static ASIDS: Once<Mutex<Asids>> = Once::new();
+
+fn asids() -> &'static Mutex<Asids> {
+    ASIDS.call_once(|| {
+        // The unsupported high bits of the ASID field are hardwired to zero.
+        let max = unsafe {
+            let old = satp::read();
+            satp::set(Sv39, ASID_MASK, old.ppn());
+            let max = satp::read().asid();
+            satp::set(Sv39, old.asid(), old.ppn());
+            sfence_vma_all();
+            max
+        };
+        log::debug!("tlb: max ASID = {max}");
+        Mutex::new(Asids::new(max))
+    })
+}
+
+/// Invalidates all the entries of `asid` in the local TLB.
+unsafe fn sfence_vma_asid(asid: usize) {
+    #[cfg(target_arch = "riscv64")]
+    core::arch::asm!("sfence.vma zero, {}", in(reg) asid);
+    #[cfg(not(target_arch = "riscv64"))]
+    let _ = asid;
+}
+
 #[thread_local]
 static mut CUR_VIRT: *const Virt = ptr::null();
 
 pub fn set_virt(virt: Arsc<Virt>) {
     let addr = unsafe { (*virt.root.as_ptr()).as_mut_ptr() };
+    let hart = hart_id::hart_id();
 
-    virt.cpu_mask.fetch_or(1 << hart_id::hart_id(), SeqCst);
+    virt.cpu_mask.fetch_or(1 << hart, SeqCst);
     let new = Arsc::into_raw(virt);
     let old = unsafe { mem::replace(&mut CUR_VIRT, new) };
 
     let ret = NonNull::new(old.cast_mut()).map(|old| unsafe { Arsc::from_raw(old.as_ptr()) });
 
     if old != new {
+        let virt = unsafe { &*new };
+        let (asid, flush_all) = ksync::critical(|| {
+            let mut asids = asids().lock();
+            let ret = asids.activate(hart, virt.asid.load(SeqCst));
+            virt.asid.store(ret.0, SeqCst);
+            ret
+        });
+
         let paddr = *LAddr::from(addr).to_paddr(ID_OFFSET);
         unsafe {
-            satp::set(Sv39, 0, paddr >> PAGE_SHIFT);
-            sfence_vma_all()
+            satp::set(Sv39, asid & ASID_MASK, paddr >> PAGE_SHIFT);
+            if flush_all {
+                sfence_vma_all()
+            }
         }
         if let Some(old) = ret {
             log::debug!("tlb::set_virt: {:p} => {:p}", old.root.as_ptr(), addr);
-            old.cpu_mask.fetch_and(!(1 << hart_id::hart_id()), SeqCst);
+            // Without ASIDs, the TLB entries of the old address space are already
+            // gone with the flush above.
+            if asid == 0 {
+                old.cpu_mask.fetch_and(!(1 << hart), SeqCst);
+            }
         } else {
             log::debug!("tlb::set_virt: K => {:p}", addr);
         }
@@ -45,36 +183,105 @@ pub fn set_virt(virt: Arsc<Virt>) {
 /// The caller must ensure the validity of the page tables contained in
 /// `default_pt`.
 pub unsafe fn unset_virt(default_pt: PAddr) {
+    let hart = hart_id::hart_id();
     let old = unsafe { mem::replace(&mut CUR_VIRT, ptr::null()) };
 
     let ret = NonNull::new(old.cast_mut()).map(|old| unsafe { Arsc::from_raw(old.as_ptr()) });
 
+    let (flush_all, has_asid) = ksync::critical(|| {
+        let mut asids = asids().lock();
+        (asids.deactivate(hart), asids.max != 0)
+    });
     unsafe {
         satp::set(Sv39, 0, *default_pt >> PAGE_SHIFT);
-        sfence_vma_all()
+        if flush_all {
+            sfence_vma_all()
+        }
     }
     if let Some(ref old) = ret {
         log::debug!("tlb::set_virt: {:p} => K", old.root.as_ptr());
-        old.cpu_mask.fetch_and(!(1 << hart_id::hart_id()), SeqCst);
+        if !has_asid {
+            old.cpu_mask.fetch_and(!(1 << hart), SeqCst);
+        }
     }
 }
 
-pub fn flush(cpu_mask: usize, addr: LAddr, count: usize) {
-    if count == 0 {
+/// Invalidates the TLB entries of `range` in `virt` on all the harts that may
+/// cache them.
+pub fn flush(virt: &Virt, range: Range<LAddr>) {
+    if range.start >= range.end {
         return;
     }
-    log::trace!("tlb::flush cpu_mask = {cpu_mask:#b}, addr = {addr:?}, count = {count}");
+    let cpu_mask = virt.cpu_mask.load(SeqCst);
+    let asid = virt.asid.load(SeqCst) & ASID_MASK;
+    let count = (range.end.val() - range.start.val()) >> PAGE_SHIFT;
+    log::trace!("tlb::flush cpu_mask = {cpu_mask:#b}, asid = {asid}, range = {range:?}");
+
     let others = cpu_mask & !(1 << hart_id::hart_id());
     if others != 0 {
-        let _ = sbi_rt::remote_sfence_vma(others, 0, addr.val(), count << PAGE_SHIFT);
+        let size = range.end.val() - range.start.val();
+        let _ = sbi_rt::remote_sfence_vma_asid(others, 0, range.start.val(), size, asid);
     }
     if cpu_mask != others {
         unsafe {
-            if count == 1 {
-                sfence_vma(0, addr.val())
+            if count <= FLUSH_THRESHOLD {
+                for addr in (range.start.val()..range.end.val()).step_by(PAGE_SIZE) {
+                    sfence_vma(asid, addr)
+                }
+            } else if asid != 0 {
+                sfence_vma_asid(asid)
             } else {
                 sfence_vma_all()
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asid_alloc() {
+        let mut asids = Asids::new(MAX_HARTS * 2);
+
+        let (a, flush) = asids.activate(0, 0);
+        assert!(!flush);
+        assert_eq!(asids.activate(0, a), (a, false));
+        let (b, _) = asids.activate(1, 0);
+        assert_ne!(a & ASID_MASK, b & ASID_MASK);
+    }
+
+    #[test]
+    fn test_asid_rollover() {
+        let max = MAX_HARTS * 2;
+        let mut asids = Asids::new(max);
+
+        let (running, _) = asids.activate(0, 0);
+        let (idle, _) = asids.activate(1, 0);
+        asids.deactivate(1);
+        for _ in 2..max {
+            asids.activate(2, 0);
+        }
+        asids.deactivate(2);
+
+        // The new generation begins.
+        let (new, flush) = asids.activate(1, 0);
+        assert!(flush);
+        assert_eq!(new >> ASID_BITS, 2);
+        assert_ne!(new & ASID_MASK, running & ASID_MASK);
+
+        // The ASID active on a hart is kept.
+        let (kept, flush) = asids.activate(0, running);
+        assert!(flush);
+        assert_eq!(kept & ASID_MASK, running & ASID_MASK);
+        assert_eq!(kept >> ASID_BITS, 2);
+
+        // The inactive one is reallocated.
+        let (realloc, flush) = asids.activate(3, idle);
+        assert!(flush);
+        assert_eq!(realloc >> ASID_BITS, 2);
+        assert_ne!(realloc & ASID_MASK, running & ASID_MASK);
+        assert_ne!(realloc, new);
+    }
+}
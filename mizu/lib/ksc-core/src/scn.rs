@@ -14,12 +14,17 @@ pub enum Scn {
     __TEST2 = 2,
 
     GETCWD = 17,
+    EPOLL_CREATE1 = 20,
+    EPOLL_CTL = 21,
+    EPOLL_PWAIT = 22,
     DUP = 23,
     DUP3 = 24,
     FCNTL = 25,
     IOCTL = 29,
     MKDIRAT = 34,
     UNLINKAT = 35,
+    SYMLINKAT = 36,
+    LINKAT = 37,
     UMOUNT2 = 39,
     MOUNT = 40,
     STATFS = 43,
@@ -29,6 +34,7 @@ pub enum Scn {
     CHDIR = 49,
     FCHMOD = 52,
     FCHMODAT = 53,
+    FCHOWNAT = 54,
     FCHOWN = 55,
     OPENAT = 56,
     CLOSE = 57,
@@ -60,6 +66,7 @@ pub enum Scn {
     GET_ROBUST_LIST = 100,
     NANOSLEEP = 101,
     SETITIMER = 103,
+    CLOCK_SETTIME = 112,
     CLOCK_GETTIME = 113,
     CLOCK_GETRES = 114,
     CLOCK_NANOSLEEP = 115,
@@ -81,14 +88,27 @@ pub enum Scn {
     RT_SIGTIMEDWAIT = 137,
     RT_SIGQUEUEINFO = 138,
     RT_SIGRETURN = 139,
+    REBOOT = 142,
+    SETREGID = 143,
+    SETGID = 144,
+    SETREUID = 145,
+    SETUID = 146,
+    SETRESUID = 147,
+    GETRESUID = 148,
+    SETRESGID = 149,
+    GETRESGID = 150,
     TIMES = 153,
     SETPGID = 154,
     GETPGID = 155,
+    GETSID = 156,
     SETSID = 157,
+    GETGROUPS = 158,
+    SETGROUPS = 159,
     UNAME = 160,
     GETRUSAGE = 165,
     UMASK = 166,
     GETTIMEOFDAY = 169,
+    SETTIMEOFDAY = 170,
     GETPID = 172,
     GETPPID = 173,
     GETUID = 174,
@@ -114,13 +134,23 @@ pub enum Scn {
     SETSOCKOPT = 208,
     GETSOCKOPT = 209,
     SHUTDOWN = 210,
+    SENDMSG = 211,
+    RECVMSG = 212,
     BRK = 214,
     MUNMAP = 215,
+    MREMAP = 216,
     CLONE = 220,
     EXECVE = 221,
     MMAP = 222,
+    SWAPON = 224,
+    SWAPOFF = 225,
     MPROTECT = 226,
     MSYNC = 227,
+    MLOCK = 228,
+    MUNLOCK = 229,
+    MLOCKALL = 230,
+    MUNLOCKALL = 231,
+    MINCORE = 232,
     MADVISE = 233,
     WAIT4 = 260,
     PRLIMIT64 = 261,
@@ -8,8 +8,8 @@ use bitflags::bitflags;
 use static_assertions::const_assert;
 
 use crate::{
-    Error, LAddr, Level, PAddr, PageAlloc, BLANK_BEGIN, BLANK_END, ENTRY_SIZE_SHIFT, ID_OFFSET,
-    NR_ENTRIES, PAGE_SIZE,
+    Error, LAddr, Level, PAddr, PageAlloc, BLANK_BEGIN, BLANK_END, ENTRY_SIZE_SHIFT, NR_ENTRIES,
+    PAGE_SIZE,
 };
 
 bitflags! {
@@ -240,25 +240,26 @@ impl Entry {
         &mut self,
         level: Level,
         alloc: &impl PageAlloc,
+        id_offset: usize,
     ) -> Result<&mut Table, Error> {
         let (addr, attr) = self.get(Level::pt());
         if !attr.contains(Attr::VALID) || level == Level::pt() {
             return Err(Error::EntryExistent(false));
         }
         Ok(if attr.has_table() {
-            let ptr = addr.to_laddr(ID_OFFSET);
+            let ptr = addr.to_laddr(id_offset);
             unsafe { &mut *ptr.cast() }
         } else {
             let mut ptr = alloc.alloc().ok_or(Error::OutOfMemory)?;
 
             let item_level = level.decrease().expect("Item level");
             let table = unsafe { ptr.as_mut() };
-            let addrs = (0..NR_ENTRIES).map(|n| PAddr::new(*addr + n * PAGE_SIZE));
+            let addrs = (0..NR_ENTRIES).map(|n| PAddr::new(*addr + n * item_level.page_size()));
             for (item, addr) in table.iter_mut().zip(addrs) {
                 *item = Self::new(addr, attr, item_level);
             }
 
-            let table_addr = LAddr::from(ptr).to_paddr(ID_OFFSET);
+            let table_addr = LAddr::from(ptr).to_paddr(id_offset);
             *self = Self::new(table_addr, Attr::VALID, Level::pt());
             table
         })
@@ -341,6 +342,44 @@ impl Table {
         Ok(&mut t[Level::pt().addr_idx(la.val(), false)])
     }
 
+    /// Look up the leaf entry mapping `la`, which may be a large one, along
+    /// with its level.
+    ///
+    /// # Error
+    ///
+    /// If any table on the path is absent, `Error::EntryExistent(false)`.
+    pub fn la2leaf(&mut self, la: LAddr, id_offset: usize) -> Result<(&mut Entry, Level), Error> {
+        let mut t: &mut Table = self;
+        for l in (1..=2u8).rev() {
+            let level = Level::new(l);
+            let pte = &mut t[level.addr_idx(la.val(), false)];
+            if pte.is_set() && !pte.get(Level::pt()).1.has_table() {
+                return Ok((pte, level));
+            }
+            t = pte
+                .table_mut(level, id_offset)
+                .ok_or(Error::EntryExistent(false))?;
+        }
+        Ok((&mut t[Level::pt().addr_idx(la.val(), false)], Level::pt()))
+    }
+
+    /// Same as [`Table::la2pte`], except that the large leaf entries on the
+    /// path are split into tables of smaller ones.
+    pub fn la2pte_split(
+        &mut self,
+        la: LAddr,
+        alloc_func: &impl PageAlloc,
+        id_offset: usize,
+    ) -> Result<&mut Entry, Error> {
+        let mut t: &mut Table = self;
+        for l in (1..=2u8).rev() {
+            let level = Level::new(l);
+            let pte = &mut t[level.addr_idx(la.val(), false)];
+            t = pte.table_or_split(level, alloc_func, id_offset)?;
+        }
+        Ok(&mut t[Level::pt().addr_idx(la.val(), false)])
+    }
+
     pub fn la2pte_alloc(
         &mut self,
         la: LAddr,
@@ -360,6 +399,25 @@ impl Table {
         Ok(&mut t[Level::pt().addr_idx(la.val(), false)])
     }
 
+    /// Same as [`Table::la2pte_alloc`], except that the entry is looked up at
+    /// `level`, which may be a large leaf one.
+    pub fn la2pte_alloc_at(
+        &mut self,
+        la: LAddr,
+        level: Level,
+        alloc_func: &impl PageAlloc,
+        id_offset: usize,
+    ) -> Result<&mut Entry, Error> {
+        let mut t: &mut Table = self;
+        let mut l = Level::max();
+        while l > level {
+            let pte = &mut t[l.addr_idx(la.val(), false)];
+            t = pte.table_or_create(l, alloc_func, id_offset)?;
+            l = l.decrease().expect("Item level");
+        }
+        Ok(&mut t[level.addr_idx(la.val(), false)])
+    }
+
     /// Look up in the pgtbl and retrun corresponding `pa` with given `la`.
     ///
     /// # Arguments
@@ -482,8 +540,11 @@ impl Table {
     ) -> bool {
         let page_size = level.page_size();
 
-        let indices = level.addr_idx(range.start.max(base).val(), false)
-            ..level.addr_idx(range.end.min(base + page_size * NR_ENTRIES).val(), true);
+        // Partially covered entries are visited as well, so that the split
+        // large pages inside them can be unmapped.
+        let end = range.end.min(base + page_size * NR_ENTRIES).val();
+        let indices =
+            level.addr_idx(range.start.max(base).val(), false)..level.addr_idx(end - 1, false) + 1;
         let entries = self.iter_mut().enumerate();
         let count = entries.fold(0, |count, (index, entry)| {
             let is_empty = if indices.contains(&index) {
@@ -502,6 +563,9 @@ impl Table {
     }
 
     pub fn unmap(&mut self, range: Range<LAddr>, alloc: &impl PageAlloc, id_offset: usize) {
+        if range.start >= range.end {
+            return;
+        }
         self.unmap_impl(range, 0usize.into(), Level::max(), alloc, id_offset);
     }
 }
@@ -556,7 +620,7 @@ mod tests {
         ptr::NonNull,
     };
 
-    use crate::{Error, LAddr, PageAlloc, Table, ID_OFFSET, PAGE_SIZE};
+    use crate::{Attr, Entry, Error, LAddr, Level, PAddr, PageAlloc, Table, ID_OFFSET, PAGE_SIZE};
 
     #[test]
     fn test_la2pa() {
@@ -579,6 +643,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_split() {
+        let a = Alloc();
+        let mut tb = Table::new();
+        let level = Level::new(1);
+        let la = LAddr::from(0x4020_0000usize);
+        let pa = PAddr::new(0x8020_0000);
+
+        let root = &mut tb[Level::max().addr_idx(la.val(), false)];
+        let table = root.table_or_create(Level::max(), &a, 0).unwrap();
+        table[level.addr_idx(la.val(), false)] = Entry::new(pa, Attr::USER_RW, level);
+
+        let (entry, l) = tb.la2leaf(la + 3 * PAGE_SIZE, 0).unwrap();
+        assert_eq!(l, level);
+        assert_eq!(entry.addr(level), pa);
+
+        let entry = tb.la2pte_split(la + 3 * PAGE_SIZE, &a, 0).unwrap();
+        assert_eq!(
+            *entry,
+            Entry::new(pa + 3 * PAGE_SIZE, Attr::USER_RW, Level::pt())
+        );
+        let (_, l) = tb.la2leaf(la, 0).unwrap();
+        assert_eq!(l, Level::pt());
+
+        tb.unmap(la..(la + level.page_size()), &a, 0);
+        assert!(tb.la2leaf(la, 0).is_err());
+    }
+
     // #[test]
     // fn test_mapfuncs() {
     //     let a = Alloc();
@@ -34,7 +34,9 @@ impl Level {
 
     /// If `self.0 = n`, return
     ///
-    ///     0...011...1  /* (12+9n)  1s */
+    /// ```text
+    /// 0...011...1  /* (12+9n)  1s */
+    /// ```
     #[inline]
     pub const fn page_mask(&self) -> usize {
         self.page_size() - 1
@@ -42,7 +44,9 @@ impl Level {
 
     /// If `self.0 = n`, return
     ///
-    ///     1...100...0 /* (12+9n)  0s */
+    /// ```text
+    /// 1...100...0 /* (12+9n)  0s */
+    /// ```
     #[inline]
     pub const fn paddr_mask(&self) -> usize {
         ((1 << 56) - 1) & !self.page_mask()
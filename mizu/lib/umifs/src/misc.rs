@@ -58,7 +58,10 @@ impl Entry for Null {
             ty: FileType::CHR,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
@@ -119,7 +122,10 @@ impl Entry for Zero {
             ty: FileType::CHR,
             len: 0,
             offset: 0,
+            link_count: 1,
             perm: Permissions::all_same(true, true, false),
+            uid: 0,
+            gid: 0,
             block_size: 0,
             block_count: 0,
             times: Default::default(),
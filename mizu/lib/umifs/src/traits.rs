@@ -2,12 +2,12 @@ use alloc::{boxed::Box, sync::Arc};
 
 use arsc_rs::Arsc;
 use async_trait::async_trait;
-use ksc_core::Error;
+use ksc_core::Error::{self, EINVAL, EPERM};
 use umio::IoPoll;
 pub use umio::{IntoAny, IntoAnyExt, Io, IoExt, ToIo};
 
 use crate::{
-    path::Path,
+    path::{Path, PathBuf},
     types::{DirEntry, FsStat, Metadata, OpenOptions, Permissions, SetMetadata},
 };
 
@@ -36,6 +36,12 @@ pub trait Entry: IntoAny + Send + ToIo + IoPoll + Sync + 'static {
         Ok(())
     }
 
+    /// Reads the target of the entry if it is a symbolic link, or returns
+    /// `EINVAL` otherwise.
+    async fn read_link(&self) -> Result<PathBuf, Error> {
+        Err(EINVAL)
+    }
+
     fn to_dir(self: Arc<Self>) -> Option<Arc<dyn Directory>> {
         None
     }
@@ -70,4 +76,10 @@ pub trait DirectoryMut: Directory {
     ) -> Result<(), Error>;
 
     async fn unlink(&self, path: &Path, expect_dir: Option<bool>) -> Result<(), Error>;
+
+    /// Creates a symbolic link at `path` pointing to `target`.
+    async fn symlink(&self, path: &Path, target: &Path) -> Result<(), Error> {
+        let _ = (path, target);
+        Err(EPERM)
+    }
 }
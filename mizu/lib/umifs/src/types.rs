@@ -38,6 +38,9 @@ bitflags! {
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
     pub struct Permissions: u32 {
+        const SET_UID =  1 << 11;
+        const SET_GID =  1 << 10;
+        const STICKY =   1 << 9;
         const SELF_R =   1 << 8;
         const SELF_W =   1 << 7;
         const SELF_X =   1 << 6;
@@ -105,7 +108,10 @@ pub struct Metadata {
     pub ty: FileType,
     pub len: usize,
     pub offset: u64,
+    pub link_count: usize,
     pub perm: Permissions,
+    pub uid: u32,
+    pub gid: u32,
     pub block_size: usize,
     pub block_count: usize,
     pub times: Times,
@@ -115,6 +121,8 @@ pub struct Metadata {
 pub struct SetMetadata {
     pub len: Option<usize>,
     pub perm: Option<Permissions>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
     pub times: Times,
 }
 
@@ -1,6 +1,7 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, sync::Weak, vec::Vec};
 
 use async_trait::async_trait;
+use spin::Mutex;
 
 use crate::IntoAny;
 
@@ -22,4 +23,73 @@ pub trait IoPoll: IntoAny {
         let _ = expected;
         Some(Event::READABLE | Event::WRITABLE)
     }
+
+    /// Returns the current events of the object without waiting.
+    fn poll(&self) -> Event {
+        Event::READABLE | Event::WRITABLE
+    }
+
+    /// Registers `watcher` to be notified whenever the result of
+    /// [`IoPoll::poll`] may change, until the watcher is dropped.
+    ///
+    /// Returns `false` if the object does not push its readiness, in which
+    /// case it cannot be watched.
+    fn watch(&self, watcher: Weak<dyn Watcher>, exclusive: bool) -> bool {
+        let _ = (watcher, exclusive);
+        false
+    }
+}
+
+/// The receiver of the readiness changes of I/O objects.
+pub trait Watcher: Send + Sync {
+    /// Called when `events` of the watched object may have become ready,
+    /// returning whether the watcher is interested in them.
+    ///
+    /// The locks of the object may be held, so the watcher must neither block
+    /// nor access the object here.
+    fn notify(&self, events: Event) -> bool;
+}
+
+/// The watchers of an I/O object.
+#[derive(Default)]
+pub struct Watchers {
+    list: Mutex<Vec<(Weak<dyn Watcher>, bool)>>,
+}
+
+impl Watchers {
+    pub const fn new() -> Self {
+        Watchers {
+            list: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn push(&self, watcher: Weak<dyn Watcher>, exclusive: bool) {
+        ksync_core::critical(|| {
+            let mut list = self.list.lock();
+            list.retain(|(w, _)| w.strong_count() > 0);
+            list.push((watcher, exclusive));
+        })
+    }
+
+    /// Notifies all the non-exclusive watchers and the first interested
+    /// exclusive one.
+    pub fn notify(&self, events: Event) {
+        // The watchers are only upgraded outside the lock so that none of them
+        // is dropped with the lock held.
+        let list = ksync_core::critical(|| {
+            let list = self.list.lock();
+            (!list.is_empty()).then(|| list.clone())
+        });
+        let mut woken = false;
+        for (watcher, exclusive) in list.into_iter().flatten() {
+            let Some(watcher) = watcher.upgrade() else {
+                continue;
+            };
+            if !exclusive {
+                watcher.notify(events);
+            } else if !woken {
+                woken = watcher.notify(events);
+            }
+        }
+    }
 }